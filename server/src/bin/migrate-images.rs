@@ -0,0 +1,95 @@
+use mise::{
+    config,
+    core::{self, image::MigrateOutcome},
+    datastore, imagestore, sqlite,
+};
+
+// Copies all images from one configured image backend to another.
+//
+// usage: migrate-images <from> <to>, where each backend is "file" or "s3"
+#[tokio::main]
+async fn main() {
+    let args: Vec<String> = std::env::args().collect();
+    let [_, from, to] = args.as_slice() else {
+        println!("usage: migrate-images <file|s3> <file|s3>");
+        std::process::exit(2);
+    };
+
+    if from == to {
+        println!("source and destination backends must be different");
+        std::process::exit(2);
+    }
+
+    let config = match config::from_filesystem() {
+        Ok(config) => config,
+        Err(err) => {
+            println!("error with config: {:?}", err);
+            std::process::exit(1);
+        }
+    };
+
+    let (from_store, to_store) = match (image_store(from).await, image_store(to).await) {
+        (Ok(from_store), Ok(to_store)) => (from_store, to_store),
+        (Err(err), _) | (_, Err(err)) => {
+            println!("error with image backend: {:?}", err);
+            std::process::exit(1);
+        }
+    };
+
+    let (_worker_pool, senders) = match sqlite::datastore_handler(
+        &config.sqlite.db_path,
+        &sqlite::DatastoreConfig {
+            recipe_page_size: 20,
+            recipe_dump_page_size: 250,
+            image_dump_page_size: 250,
+        },
+    ) {
+        Ok(pool) => pool,
+        Err(err) => {
+            println!("error with pool: {:?}", err);
+            std::process::exit(1);
+        }
+    };
+    let pool = datastore::Pool::new(senders);
+
+    println!("migrating images from {from} to {to}...");
+
+    let result = core::image::migrate(&pool, &from_store, &to_store, |progress| {
+        let status = match progress.outcome {
+            MigrateOutcome::Copied { bytes } => format!("copied {bytes} bytes"),
+            MigrateOutcome::AlreadyCopied => "already copied".to_owned(),
+            MigrateOutcome::MissingFromSource => "missing from source".to_owned(),
+        };
+        println!(
+            "[{}/{}] {}: {status}",
+            progress.position, progress.total, progress.id
+        );
+    })
+    .await;
+
+    match result {
+        Ok(summary) => {
+            println!(
+                "migration complete. {} copied, {} already copied, {} missing from source.",
+                summary.copied,
+                summary.already_copied,
+                summary.missing_from_source.len()
+            );
+
+            if !summary.missing_from_source.is_empty() {
+                std::process::exit(1);
+            }
+        }
+        Err(err) => {
+            println!("migration failed, run again to resume: {:?}", err);
+            std::process::exit(1);
+        }
+    }
+}
+
+async fn image_store(name: &str) -> anyhow::Result<imagestore::ImageStore> {
+    let backend_config = config::image_backend_from_filesystem(name)?;
+    let backend = imagestore::backend(&backend_config).await?;
+
+    Ok(imagestore::ImageStore::new(backend))
+}
@@ -1,10 +1,10 @@
 use anyhow::Context;
 use mise::{
-    config, datastore, file,
+    config, core, datastore,
     http::Server,
+    image_cache,
     image_processing::ImageProcessor,
-    imagestore::{self, ImageBackend},
-    oidc, s3,
+    imagestore, oidc,
     search::Backend,
     session_store::{self, SessionStore},
     sqlite,
@@ -26,6 +26,7 @@ async fn main() {
         &sqlite::DatastoreConfig {
             recipe_page_size: 20,
             recipe_dump_page_size: 250,
+            image_dump_page_size: 250,
         },
     ) {
         Ok(pool) => pool,
@@ -61,26 +62,117 @@ async fn main() {
         }
     });
 
+    let session_encryption_keys = if config.session_encryption_keys.is_empty() {
+        let path = format!("{}.key", config.sqlite.session_db_path);
+        match session_store::generated_key(&path) {
+            Ok(key) => {
+                println!("sealing sessions with the key generated in {path}.");
+                vec![key]
+            }
+            Err(err) => {
+                println!("error with generated session encryption key: {:?}", err);
+                return;
+            }
+        }
+    } else {
+        config.session_encryption_keys.clone()
+    };
+
+    let cipher = match session_store::Cipher::new(&session_encryption_keys) {
+        Ok(cipher) => cipher,
+        Err(err) => {
+            println!("error with session encryption keys: {:?}", err);
+            return;
+        }
+    };
+
     let pool = datastore::Pool::new(senders);
-    let cache = SessionStore::new(session_store_sender, background_result_sender);
+    let cache = SessionStore::new(session_store_sender, background_result_sender, cipher);
+
+    if config.local_auth.is_some() {
+        println!("local sign in is enabled, only use it for single user or LAN deployments.");
+    }
+
+    if !config.trusted_proxies.is_empty() {
+        println!(
+            "trusting the X-Forwarded-For header of proxies in {:?}.",
+            config.trusted_proxies
+        );
+    }
 
-    let oidc_provider = oidc::Provider::new((&config).try_into().unwrap())
+    if let Some(proxy_auth) = &config.proxy_auth {
+        println!(
+            "trusting the {} header of proxies in {:?}.",
+            proxy_auth.user_header, config.trusted_proxies
+        );
+    }
+
+    match &config.rate_limit {
+        Some(_) if !config.trusted_proxies.is_empty() => {
+            println!("rate limiting clients by their forwarded address.");
+        }
+        Some(_) => println!(
+            "rate limiting clients by the address they connect from, behind a reverse proxy set trusted_proxies or every client shares the proxy's limit."
+        ),
+        None => println!("rate limiting is disabled, configure rate_limit to enable it."),
+    }
+
+    let oidc_providers = oidc::Providers::new(oidc::configs(&config).unwrap())
         .await
         .unwrap();
 
-    let image_backend: Box<dyn ImageBackend + Send + Sync> = match &config.image_backend {
-        config::ImageBackend::S3(config) => {
-            let backend = s3::imagebackend::ImageBackend::new(config.try_into().unwrap()).unwrap();
+    match core::user::link_unlinked(
+        &pool,
+        &oidc_providers,
+        config.legacy_oidc_provider.as_deref(),
+    )
+    .await
+    {
+        Ok(0) => {}
+        Ok(linked) => println!("linked {linked} users to the legacy OIDC provider."),
+        Err(err) => {
+            println!("error linking users: {:?}", err);
+            return;
+        }
+    }
 
-            Box::from(backend)
+    // prune the audit log daily
+    let audit_pool = pool.clone();
+    let audit_retention_days = config.audit_log.retention_days;
+    tokio::spawn(async move {
+        loop {
+            match core::audit::prune(&audit_pool, audit_retention_days).await {
+                Ok(0) => {}
+                Ok(pruned) => println!("pruned {pruned} audit log entries."),
+                Err(err) => println!("Failed to prune audit log: {:?}.", err),
+            }
+
+            tokio::time::sleep(std::time::Duration::from_secs(60 * 60 * 24)).await;
         }
-        config::ImageBackend::File(config) => {
-            let backend = file::ImageBackend::new(&config.directory).await.unwrap();
-            Box::from(backend)
+    });
+
+    let image_backend = match imagestore::backend(&config.image_backend).await {
+        Ok(backend) => backend,
+        Err(err) => {
+            println!("error with image backend: {:?}", err);
+            return;
+        }
+    };
+
+    let image_backend = match &config.image_cache {
+        Some(cache_config) => {
+            match image_cache::ImageBackend::new(image_backend, cache_config).await {
+                Ok(cached) => Box::from(cached),
+                Err(err) => {
+                    println!("error with image cache: {:?}", err);
+                    return;
+                }
+            }
         }
+        None => image_backend,
     };
 
-    let image_processor = ImageProcessor::new()
+    let image_processor = ImageProcessor::new(&config.image_processing)
         .await
         .context("Initialize image processor.")
         .unwrap();
@@ -94,7 +186,7 @@ async fn main() {
         config,
         pool,
         cache,
-        oidc_provider,
+        oidc_providers,
         imagestore::ImageStore::new(image_backend),
         image_processor,
         sb,
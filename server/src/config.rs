@@ -1,6 +1,9 @@
-use std::{env, fs};
+use std::{collections::HashMap, env, fs};
 
 use anyhow::anyhow;
+use base64::Engine;
+
+use crate::domain::household::Role;
 
 #[derive(Clone)]
 pub enum ImageBackend {
@@ -9,8 +12,12 @@ pub enum ImageBackend {
 }
 
 mod internal {
+    use std::collections::{BTreeMap, HashMap};
+
     use serde::Deserialize;
 
+    use crate::domain::household::Role;
+
     #[derive(Deserialize)]
     pub struct Config {
         pub http_port: Option<u16>,
@@ -18,31 +25,140 @@ mod internal {
         pub insecure_cookies: Option<bool>,
         pub search_index_directory: Option<String>,
 
-        pub oidc: Oidc,
+        pub oidc: Option<Oidc>,
+        pub local_auth: Option<LocalAuth>,
+        pub proxy_auth: Option<ProxyAuth>,
         pub sqlite: Sqlite,
+        pub session_encryption: Option<SessionEncryption>,
+        pub cookie_signing: Option<CookieSigning>,
         pub images: Images,
+        pub image_processing: Option<ImageProcessing>,
+        pub image_import: Option<ImageImport>,
+        pub audit_log: Option<AuditLog>,
+        pub rate_limit: Option<RateLimit>,
+        pub trusted_proxies: Option<Vec<String>>,
+    }
+
+    #[derive(Deserialize)]
+    pub struct RateLimit {
+        pub enabled: Option<bool>,
+        pub auth: Option<RateLimitBucket>,
+        pub image_upload: Option<RateLimitBucket>,
+        pub write: Option<RateLimitBucket>,
+        pub read: Option<RateLimitBucket>,
+    }
+
+    #[derive(Deserialize)]
+    pub struct RateLimitBucket {
+        pub burst: u32,
+        pub per_minute: u32,
+    }
+
+    #[derive(Deserialize)]
+    pub struct AuditLog {
+        pub retention_days: Option<u64>,
+    }
+
+    #[derive(Deserialize)]
+    pub struct ImageImport {
+        pub max_size_bytes: Option<u64>,
+        pub timeout_seconds: Option<u64>,
+        pub allow_private_addresses: Option<bool>,
+    }
+
+    #[derive(Deserialize)]
+    pub struct ImageProcessing {
+        pub parallelism: Option<usize>,
+        pub queue_size: Option<usize>,
+        pub timeout_seconds: Option<u64>,
+        pub max_pixels: Option<u64>,
     }
 
     #[derive(Deserialize)]
     pub struct Images {
         pub s3: Option<S3>,
         pub file: Option<File>,
+        pub cache: Option<Cache>,
 
         pub backend: ImageBackend,
     }
 
-    #[derive(Deserialize)]
+    #[derive(Deserialize, Clone, Copy)]
     #[serde(rename_all = "snake_case")]
     pub enum ImageBackend {
         S3,
         File,
     }
 
+    // A provider configured directly in the section is named "default".
     #[derive(Deserialize)]
     pub struct Oidc {
+        pub issuer_url: Option<String>,
+        pub client_id: Option<String>,
+        pub client_secret: Option<String>,
+        pub access: Option<OidcAccess>,
+
+        pub providers: Option<BTreeMap<String, OidcProvider>>,
+        pub legacy_provider: Option<String>,
+    }
+
+    #[derive(Deserialize)]
+    pub struct OidcProvider {
         pub issuer_url: String,
         pub client_id: String,
         pub client_secret: String,
+        pub access: Option<OidcAccess>,
+    }
+
+    #[derive(Deserialize)]
+    pub struct OidcAccess {
+        pub allowed_subjects: Option<Vec<String>>,
+        pub allowed_emails: Option<Vec<String>>,
+        pub allowed_groups: Option<Vec<String>>,
+        pub groups_claim: Option<String>,
+        pub scopes: Option<Vec<String>>,
+        pub group_roles: Option<HashMap<String, Role>>,
+        pub default_role: Option<Role>,
+    }
+
+    #[derive(Deserialize)]
+    pub struct LocalAuth {
+        pub enabled: bool,
+        pub users: Vec<LocalUser>,
+    }
+
+    #[derive(Deserialize)]
+    pub struct LocalUser {
+        pub username: String,
+        pub name: Option<String>,
+        pub password_hash: String,
+    }
+
+    #[derive(Deserialize)]
+    pub struct ProxyAuth {
+        pub enabled: bool,
+        pub user_header: Option<String>,
+        pub name_header: Option<String>,
+    }
+
+    #[derive(Deserialize)]
+    pub struct CookieSigning {
+        // base64 encoded
+        pub keys: Option<Vec<String>>,
+        // a file with one base64 encoded key per line
+        pub key_file: Option<String>,
+    }
+
+    #[derive(Deserialize)]
+    pub struct SessionEncryption {
+        pub keys: Vec<SessionEncryptionKey>,
+    }
+
+    #[derive(Deserialize)]
+    pub struct SessionEncryptionKey {
+        pub id: String,
+        // base64 encoded
+        pub key: String,
     }
 
     #[derive(Deserialize)]
@@ -58,12 +174,19 @@ mod internal {
         pub region: String,
         pub secret_access_key: String,
         pub secret_access_key_id: String,
+        pub presigned_url_expires_in: Option<u64>,
     }
 
     #[derive(Deserialize)]
     pub struct File {
         pub directory: String,
     }
+
+    #[derive(Deserialize)]
+    pub struct Cache {
+        pub directory: String,
+        pub max_size_bytes: u64,
+    }
 }
 
 #[derive(Clone)]
@@ -75,16 +198,151 @@ pub struct Config {
 
     pub search_index_directory: String,
 
-    pub oidc: Oidc,
+    // users choose one of these to sign in with
+    pub oidc_providers: Vec<Oidc>,
+    // the provider users registered before users were linked by issuer signed in with. When
+    // unset, the default provider or the only provider.
+    pub legacy_oidc_provider: Option<String>,
+    // sign in with a username and password instead of a provider, only for single user or LAN
+    // deployments and development
+    pub local_auth: Option<LocalAuth>,
+    // trust the user signed in by an authenticating reverse proxy
+    pub proxy_auth: Option<ProxyAuth>,
     pub sqlite: Sqlite,
+    // the first key seals new sessions, the others are only used to open existing sessions. When
+    // empty, a key is generated on first start and kept next to the session database.
+    pub session_encryption_keys: Vec<SessionEncryptionKey>,
+    // the first key signs new cookies, the others are only used to verify cookies signed before
+    // a rotation. When empty, a key is generated on start which only works with insecure cookies.
+    pub cookie_signing_keys: Vec<Vec<u8>>,
     pub image_backend: ImageBackend,
+    pub image_cache: Option<ImageCache>,
+    pub image_processing: ImageProcessing,
+    pub image_import: ImageImport,
+    pub audit_log: AuditLog,
+    // limits how fast each client address and each user may make requests, off when None
+    pub rate_limit: Option<RateLimit>,
+    // reverse proxies in front of the server. Requests from them are trusted to forward the
+    // client address in X-Forwarded-For, and with proxy_auth the signed in user.
+    pub trusted_proxies: Vec<ipnet::IpNet>,
+}
+
+#[derive(Clone)]
+pub struct AuditLog {
+    // entries older than this are deleted
+    pub retention_days: u64,
+}
+
+#[derive(Clone)]
+pub struct RateLimit {
+    // signing in, which starts OIDC flows and checks passwords
+    pub auth: RateLimitBucket,
+    // uploading and importing images, which are processed in the background
+    pub image_upload: RateLimitBucket,
+    // any other request that changes something
+    pub write: RateLimitBucket,
+    pub read: RateLimitBucket,
+}
+
+// A token bucket, which allows a burst of requests and then refills at a steady rate.
+#[derive(Clone, Copy)]
+pub struct RateLimitBucket {
+    pub burst: u32,
+    pub per_minute: u32,
+}
+
+#[derive(Clone)]
+pub struct ImageProcessing {
+    // number of images processed at once
+    pub parallelism: usize,
+    // number of images that may wait for processing before uploads are rejected
+    pub queue_size: usize,
+    pub timeout_seconds: u64,
+    // largest width * height accepted, to reject decompression bombs
+    pub max_pixels: u64,
 }
 
+#[derive(Clone)]
+pub struct ImageImport {
+    // largest image downloaded when importing by url
+    pub max_size_bytes: u64,
+    pub timeout_seconds: u64,
+    // allows importing from loopback and private networks, only meant for development
+    pub allow_private_addresses: bool,
+}
+
+// The name of the provider configured directly in the oidc section. Users registered before
+// users were linked by issuer are linked to the issuer of this provider.
+pub const DEFAULT_OIDC_PROVIDER: &str = "default";
+
 #[derive(Clone)]
 pub struct Oidc {
+    // chosen with /auth/init?provider=name
+    pub name: String,
     pub issuer_url: String,
     pub client_id: String,
     pub client_secret: String,
+    pub access: OidcAccess,
+}
+
+// Who may sign in, and what they may do, based on the claims of the provider.
+#[derive(Clone)]
+pub struct OidcAccess {
+    // when any of these are set, only users matching one of them, or in a group of group_roles,
+    // may sign in
+    pub allowed_subjects: Vec<String>,
+    // only verified emails are matched
+    pub allowed_emails: Vec<String>,
+    pub allowed_groups: Vec<String>,
+    // the claim listing the groups of a user
+    pub groups_claim: String,
+    // requested in addition to the standard scopes, for providers that only include groups when
+    // asked for them
+    pub scopes: Vec<String>,
+    // the most a member of a group may do in any household, a user in several groups gets the
+    // most privileged role. Applied when the user signs in.
+    pub group_roles: HashMap<String, Role>,
+    // the most a user in none of those groups may do, not limited when unset
+    pub default_role: Option<Role>,
+}
+
+impl Default for OidcAccess {
+    fn default() -> Self {
+        OidcAccess {
+            allowed_subjects: vec![],
+            allowed_emails: vec![],
+            allowed_groups: vec![],
+            groups_claim: "groups".to_owned(),
+            scopes: vec![],
+            group_roles: HashMap::new(),
+            default_role: None,
+        }
+    }
+}
+
+// The name sessions started with a local password are recorded with, which is why no OIDC
+// provider may use it.
+pub const LOCAL_AUTH_PROVIDER: &str = "local";
+
+#[derive(Clone)]
+pub struct LocalAuth {
+    pub users: Vec<LocalUser>,
+}
+
+#[derive(Clone)]
+pub struct LocalUser {
+    pub username: String,
+    pub name: String,
+    // an argon2 hash in the PHC string format, such as the output of the argon2 cli
+    pub password_hash: String,
+}
+
+#[derive(Clone)]
+pub struct ProxyAuth {
+    // the username of the signed in user, such as Remote-User
+    pub user_header: String,
+    // the display name of the signed in user, the username is used when it is missing
+    pub name_header: String,
 }
 
 #[derive(Clone)]
@@ -94,6 +352,8 @@ pub struct ImageBackendS3 {
     pub region: String,
     pub secret_access_key: String,
     pub secret_access_key_id: String,
+    // when set, image downloads are redirected to presigned urls valid for this many seconds
+    pub presigned_url_expires_in: Option<u64>,
 }
 
 #[derive(Clone)]
@@ -101,6 +361,19 @@ pub struct ImageBackendFile {
     pub directory: String,
 }
 
+#[derive(Clone)]
+pub struct ImageCache {
+    pub directory: String,
+    pub max_size_bytes: u64,
+}
+
+#[derive(Clone)]
+pub struct SessionEncryptionKey {
+    pub id: String,
+    // 32 bytes for AES-256-GCM
+    pub key: Vec<u8>,
+}
+
 #[derive(Clone)]
 pub struct Sqlite {
     pub db_path: String,
@@ -119,57 +392,503 @@ pub enum Error {
     Malformed(#[from] anyhow::Error),
 }
 
+#[allow(clippy::too_many_lines)]
 pub fn from_filesystem() -> Result<Config, Error> {
-    let config_path = env::var("MISE_CONFIG")
-        .ok()
-        .unwrap_or("mise.toml".to_owned());
+    let mut parsed = read()?;
 
-    let raw_config = fs::read_to_string(config_path)?;
+    let cache = parsed.images.cache.take();
+    let backend = parsed.images.backend;
+    let image_backend = image_backend(parsed.images, backend)?;
+    let image_cache = image_cache(cache, &image_backend)?;
+
+    let image_processing = parsed.image_processing.as_ref();
+    let image_processing = ImageProcessing {
+        parallelism: image_processing
+            .and_then(|config| config.parallelism)
+            .unwrap_or(2),
+        queue_size: image_processing
+            .and_then(|config| config.queue_size)
+            .unwrap_or(16),
+        timeout_seconds: image_processing
+            .and_then(|config| config.timeout_seconds)
+            .unwrap_or(60),
+        max_pixels: image_processing
+            .and_then(|config| config.max_pixels)
+            .unwrap_or(100_000_000),
+    };
+    if image_processing.parallelism == 0 || image_processing.timeout_seconds == 0 {
+        return Err(Error::Malformed(anyhow!(
+            "Image processing parallelism and timeout must be greater than zero."
+        )));
+    }
+
+    let session_encryption_keys = session_encryption_keys(parsed.session_encryption.as_ref())?;
 
-    let parsed: internal::Config = toml::from_str(&raw_config)?;
+    let insecure_cookies = parsed.insecure_cookies.unwrap_or(false);
+    let cookie_signing_keys = cookie_signing_keys(parsed.cookie_signing.as_ref())?;
+    if cookie_signing_keys.is_empty() && !insecure_cookies {
+        return Err(Error::Malformed(anyhow!(
+            "A cookie signing key is required, configure cookie_signing.keys or cookie_signing.key_file."
+        )));
+    }
+
+    let trusted_proxies = trusted_proxies(&parsed.trusted_proxies.unwrap_or_default())?;
+    let local_auth = parsed.local_auth.map(local_auth).transpose()?;
+    let proxy_auth = parsed
+        .proxy_auth
+        .map(|config| proxy_auth(config, &trusted_proxies))
+        .transpose()?;
+    let (oidc_providers, legacy_oidc_provider) = match parsed.oidc {
+        Some(config) => oidc_providers(config)?,
+        None => (vec![], None),
+    };
+    if oidc_providers.is_empty() && local_auth.is_none() && proxy_auth.is_none() {
+        return Err(Error::Malformed(anyhow!(
+            "Configure an OIDC provider in the oidc section, or enable local_auth or proxy_auth."
+        )));
+    }
+
+    let image_import = parsed.image_import.as_ref();
+    let image_import = ImageImport {
+        max_size_bytes: image_import
+            .and_then(|config| config.max_size_bytes)
+            .unwrap_or(1024 * 1024 * 10),
+        timeout_seconds: image_import
+            .and_then(|config| config.timeout_seconds)
+            .unwrap_or(15),
+        allow_private_addresses: image_import
+            .and_then(|config| config.allow_private_addresses)
+            .unwrap_or(false),
+    };
+    if image_import.timeout_seconds == 0 {
+        return Err(Error::Malformed(anyhow!(
+            "Image import timeout must be greater than zero."
+        )));
+    }
+
+    let audit_log = AuditLog {
+        retention_days: parsed
+            .audit_log
+            .and_then(|config| config.retention_days)
+            .unwrap_or(365),
+    };
+    if audit_log.retention_days == 0 {
+        return Err(Error::Malformed(anyhow!(
+            "Audit log retention must be at least one day."
+        )));
+    }
 
     Ok(Config {
         http_port: parsed.http_port.unwrap_or(3000),
         origin: parsed.origin,
-        insecure_cookies: parsed.insecure_cookies.unwrap_or(false),
+        insecure_cookies,
         static_build_path: env::var("MISE_STATIC_BUILD")
             .ok()
             .unwrap_or("../ui/build".to_owned()),
         search_index_directory: parsed
             .search_index_directory
             .unwrap_or("search-index".to_owned()),
-        oidc: Oidc {
-            issuer_url: parsed.oidc.issuer_url,
-            client_id: parsed.oidc.client_id,
-            client_secret: parsed.oidc.client_secret,
-        },
+        oidc_providers,
+        legacy_oidc_provider,
+        local_auth,
+        proxy_auth,
         sqlite: Sqlite {
             db_path: parsed.sqlite.db_path,
             session_db_path: parsed.sqlite.session_db_path,
         },
-        image_backend: match parsed.images.backend {
-            internal::ImageBackend::S3 => {
-                let config = parsed.images.s3.ok_or(Error::Malformed(anyhow!(
-                    "Image backend is s3, but missing s3 config."
-                )))?;
-
-                ImageBackend::S3(ImageBackendS3 {
-                    host: config.host,
-                    bucket: config.bucket,
-                    region: config.region,
-                    secret_access_key: config.secret_access_key,
-                    secret_access_key_id: config.secret_access_key_id,
-                })
-            }
-            internal::ImageBackend::File => {
-                let config = parsed.images.file.ok_or(Error::Malformed(anyhow!(
-                    "Image backend is file, but missing file config."
-                )))?;
-
-                ImageBackend::File(ImageBackendFile {
-                    directory: config.directory,
-                })
-            }
-        },
+        session_encryption_keys,
+        cookie_signing_keys,
+        image_backend,
+        image_cache,
+        image_processing,
+        image_import,
+        audit_log,
+        rate_limit: rate_limit(parsed.rate_limit)?,
+        trusted_proxies,
+    })
+}
+
+// Reads an image backend by name ("s3" or "file"), even if it is not the selected backend.
+pub fn image_backend_from_filesystem(name: &str) -> Result<ImageBackend, Error> {
+    let parsed = read()?;
+
+    let backend = match name {
+        "s3" => internal::ImageBackend::S3,
+        "file" => internal::ImageBackend::File,
+        _ => {
+            return Err(Error::Malformed(anyhow!(
+                "Unknown image backend {name}, expected s3 or file."
+            )));
+        }
+    };
+
+    image_backend(parsed.images, backend)
+}
+
+fn oidc_providers(config: internal::Oidc) -> Result<(Vec<Oidc>, Option<String>), Error> {
+    let mut providers = vec![];
+
+    match (config.issuer_url, config.client_id, config.client_secret) {
+        (Some(issuer_url), Some(client_id), Some(client_secret)) => providers.push(Oidc {
+            name: DEFAULT_OIDC_PROVIDER.to_owned(),
+            issuer_url,
+            client_id,
+            client_secret,
+            access: config.access.map(oidc_access).unwrap_or_default(),
+        }),
+        (None, None, None) if config.access.is_none() => {}
+        _ => {
+            return Err(Error::Malformed(anyhow!(
+                "Configure all of oidc.issuer_url, oidc.client_id and oidc.client_secret, or none of them."
+            )));
+        }
+    }
+
+    for (name, provider) in config.providers.unwrap_or_default() {
+        if name == LOCAL_AUTH_PROVIDER {
+            return Err(Error::Malformed(anyhow!(
+                "OIDC provider name {name} is reserved for local sign in."
+            )));
+        }
+
+        if providers.iter().any(|existing| existing.name == name) {
+            return Err(Error::Malformed(anyhow!(
+                "OIDC provider {name} is configured more than once."
+            )));
+        }
+
+        providers.push(Oidc {
+            name,
+            issuer_url: provider.issuer_url,
+            client_id: provider.client_id,
+            client_secret: provider.client_secret,
+            access: provider.access.map(oidc_access).unwrap_or_default(),
+        });
+    }
+
+    if providers.is_empty() {
+        return Err(Error::Malformed(anyhow!(
+            "At least one OIDC provider is required."
+        )));
+    }
+
+    let unknown = |name: &&String| !providers.iter().any(|provider| &provider.name == *name);
+    if let Some(name) = config.legacy_provider.as_ref().filter(unknown) {
+        return Err(Error::Malformed(anyhow!(
+            "The oidc.legacy_provider {name} is not a configured OIDC provider."
+        )));
+    }
+
+    Ok((providers, config.legacy_provider))
+}
+
+// Local sign in has to be enabled explicitly, an empty or leftover section is rejected rather than
+// ignored so that nobody believes it is off when it is not, or the other way around.
+fn local_auth(config: internal::LocalAuth) -> Result<LocalAuth, Error> {
+    if !config.enabled {
+        return Err(Error::Malformed(anyhow!(
+            "Set local_auth.enabled = true to sign in with local passwords, or remove the local_auth section."
+        )));
+    }
+
+    if config.users.is_empty() {
+        return Err(Error::Malformed(anyhow!(
+            "Local sign in is enabled, but no local_auth.users are configured."
+        )));
+    }
+
+    let mut users: Vec<LocalUser> = vec![];
+    for user in config.users {
+        if users
+            .iter()
+            .any(|existing| existing.username == user.username)
+        {
+            return Err(Error::Malformed(anyhow!(
+                "Local user {} is configured more than once.",
+                user.username
+            )));
+        }
+
+        // plain text passwords are not accepted
+        let is_argon2 = argon2::PasswordHash::new(&user.password_hash)
+            .is_ok_and(|hash| hash.algorithm.as_str().starts_with("argon2"));
+        if !is_argon2 {
+            return Err(Error::Malformed(anyhow!(
+                "The password of local user {} must be an argon2 hash.",
+                user.username
+            )));
+        }
+
+        users.push(LocalUser {
+            name: user.name.unwrap_or_else(|| user.username.clone()),
+            username: user.username,
+            password_hash: user.password_hash,
+        });
+    }
+
+    Ok(LocalAuth { users })
+}
+
+// Like local sign in, trusting a proxy has to be enabled explicitly. Anyone who can reach mise
+// without going through a trusted proxy could otherwise sign in as anyone by setting the header.
+fn proxy_auth(
+    config: internal::ProxyAuth,
+    trusted_proxies: &[ipnet::IpNet],
+) -> Result<ProxyAuth, Error> {
+    if !config.enabled {
+        return Err(Error::Malformed(anyhow!(
+            "Set proxy_auth.enabled = true to trust a reverse proxy, or remove the proxy_auth section."
+        )));
+    }
+
+    if trusted_proxies.is_empty() {
+        return Err(Error::Malformed(anyhow!(
+            "Proxy authentication is enabled, but no trusted_proxies are configured."
+        )));
+    }
+
+    let user_header = config.user_header.unwrap_or("Remote-User".to_owned());
+    let name_header = config.name_header.unwrap_or("Remote-Name".to_owned());
+    for header in [&user_header, &name_header] {
+        if axum::http::HeaderName::try_from(header.as_str()).is_err() {
+            return Err(Error::Malformed(anyhow!(
+                "Proxy authentication header {header} is not a valid header name."
+            )));
+        }
+    }
+
+    Ok(ProxyAuth {
+        user_header,
+        name_header,
     })
 }
+
+// Rate limiting is only on once configured, as behind a reverse proxy every client shares the
+// proxy's address until trusted_proxies is set. The default limits are meant to be well above
+// what a household reaches, but they are not measured.
+fn rate_limit(config: Option<internal::RateLimit>) -> Result<Option<RateLimit>, Error> {
+    let Some(config) = config.filter(|config| config.enabled.unwrap_or(true)) else {
+        return Ok(None);
+    };
+
+    let bucket = |bucket: Option<internal::RateLimitBucket>, default: RateLimitBucket| {
+        let bucket = bucket.map_or(default, |bucket| RateLimitBucket {
+            burst: bucket.burst,
+            per_minute: bucket.per_minute,
+        });
+        if bucket.burst == 0 || bucket.per_minute == 0 {
+            return Err(Error::Malformed(anyhow!(
+                "Rate limit burst and per_minute must be greater than zero."
+            )));
+        }
+
+        Ok(bucket)
+    };
+
+    Ok(Some(RateLimit {
+        auth: bucket(config.auth, RATE_LIMIT_AUTH)?,
+        image_upload: bucket(config.image_upload, RATE_LIMIT_IMAGE_UPLOAD)?,
+        write: bucket(config.write, RATE_LIMIT_WRITE)?,
+        read: bucket(config.read, RATE_LIMIT_READ)?,
+    }))
+}
+
+const RATE_LIMIT_AUTH: RateLimitBucket = RateLimitBucket {
+    burst: 10,
+    per_minute: 10,
+};
+const RATE_LIMIT_IMAGE_UPLOAD: RateLimitBucket = RateLimitBucket {
+    burst: 20,
+    per_minute: 30,
+};
+const RATE_LIMIT_WRITE: RateLimitBucket = RateLimitBucket {
+    burst: 60,
+    per_minute: 120,
+};
+const RATE_LIMIT_READ: RateLimitBucket = RateLimitBucket {
+    burst: 300,
+    per_minute: 600,
+};
+
+fn trusted_proxies(networks: &[String]) -> Result<Vec<ipnet::IpNet>, Error> {
+    networks
+        .iter()
+        .map(|network| {
+            network
+                .parse::<ipnet::IpNet>()
+                // a single address is a network of one
+                .or_else(|_| network.parse::<std::net::IpAddr>().map(ipnet::IpNet::from))
+                .map_err(|_| {
+                    Error::Malformed(anyhow!(
+                        "Trusted proxy {network} is not an address or CIDR network."
+                    ))
+                })
+        })
+        .collect()
+}
+
+fn oidc_access(config: internal::OidcAccess) -> OidcAccess {
+    let default = OidcAccess::default();
+
+    OidcAccess {
+        allowed_subjects: config.allowed_subjects.unwrap_or_default(),
+        allowed_emails: config.allowed_emails.unwrap_or_default(),
+        allowed_groups: config.allowed_groups.unwrap_or_default(),
+        groups_claim: config.groups_claim.unwrap_or(default.groups_claim),
+        scopes: config.scopes.unwrap_or_default(),
+        group_roles: config.group_roles.unwrap_or_default(),
+        default_role: config.default_role,
+    }
+}
+
+fn session_encryption_keys(
+    config: Option<&internal::SessionEncryption>,
+) -> Result<Vec<SessionEncryptionKey>, Error> {
+    let Some(config) = config else {
+        return Ok(vec![]);
+    };
+
+    if config.keys.is_empty() {
+        return Err(Error::Malformed(anyhow!(
+            "At least one session encryption key is required."
+        )));
+    }
+
+    let mut keys: Vec<SessionEncryptionKey> = vec![];
+    for key in &config.keys {
+        if keys.iter().any(|existing| existing.id == key.id) {
+            return Err(Error::Malformed(anyhow!(
+                "Session encryption key id {} is used more than once.",
+                key.id
+            )));
+        }
+
+        let decoded = base64::engine::general_purpose::STANDARD
+            .decode(&key.key)
+            .ok()
+            .filter(|decoded| decoded.len() == 32)
+            .ok_or(Error::Malformed(anyhow!(
+                "Session encryption key {} must be 32 bytes encoded as base64.",
+                key.id
+            )))?;
+
+        keys.push(SessionEncryptionKey {
+            id: key.id.clone(),
+            key: decoded,
+        });
+    }
+
+    Ok(keys)
+}
+
+fn cookie_signing_keys(config: Option<&internal::CookieSigning>) -> Result<Vec<Vec<u8>>, Error> {
+    let encoded = match config {
+        None => vec![],
+        Some(internal::CookieSigning {
+            keys: Some(keys),
+            key_file: None,
+        }) => keys.clone(),
+        Some(internal::CookieSigning {
+            keys: None,
+            key_file: Some(key_file),
+        }) => fs::read_to_string(key_file)?
+            .lines()
+            .map(str::trim)
+            .filter(|line| !line.is_empty())
+            .map(str::to_owned)
+            .collect(),
+        Some(_) => {
+            return Err(Error::Malformed(anyhow!(
+                "Configure exactly one of cookie_signing.keys and cookie_signing.key_file."
+            )));
+        }
+    };
+
+    if config.is_some() && encoded.is_empty() {
+        return Err(Error::Malformed(anyhow!(
+            "No cookie signing keys configured."
+        )));
+    }
+
+    encoded
+        .iter()
+        .map(|key| {
+            base64::engine::general_purpose::STANDARD
+                .decode(key)
+                .ok()
+                .filter(|decoded| decoded.len() >= 32)
+                .ok_or(Error::Malformed(anyhow!(
+                    "Cookie signing keys must be at least 32 bytes encoded as base64."
+                )))
+        })
+        .collect()
+}
+
+fn read() -> Result<internal::Config, Error> {
+    let config_path = env::var("MISE_CONFIG")
+        .ok()
+        .unwrap_or("mise.toml".to_owned());
+
+    let raw_config = fs::read_to_string(config_path)?;
+
+    Ok(toml::from_str(&raw_config)?)
+}
+
+// The cache removes files from its directory, so it cannot share a directory with the images
+// themselves.
+fn image_cache(
+    config: Option<internal::Cache>,
+    backend: &ImageBackend,
+) -> Result<Option<ImageCache>, Error> {
+    let Some(config) = config else {
+        return Ok(None);
+    };
+
+    if let ImageBackend::File(file) = backend {
+        let cache = std::path::absolute(&config.directory)?;
+        let images = std::path::absolute(&file.directory)?;
+        if cache.starts_with(&images) || images.starts_with(&cache) {
+            return Err(Error::Malformed(anyhow!(
+                "The image cache directory {} cannot be inside the images directory {} or contain it.",
+                config.directory,
+                file.directory
+            )));
+        }
+    }
+
+    Ok(Some(ImageCache {
+        directory: config.directory,
+        max_size_bytes: config.max_size_bytes,
+    }))
+}
+
+fn image_backend(
+    images: internal::Images,
+    backend: internal::ImageBackend,
+) -> Result<ImageBackend, Error> {
+    match backend {
+        internal::ImageBackend::S3 => {
+            let config = images.s3.ok_or(Error::Malformed(anyhow!(
+                "Image backend is s3, but missing s3 config."
+            )))?;
+
+            Ok(ImageBackend::S3(ImageBackendS3 {
+                host: config.host,
+                bucket: config.bucket,
+                region: config.region,
+                secret_access_key: config.secret_access_key,
+                secret_access_key_id: config.secret_access_key_id,
+                presigned_url_expires_in: config.presigned_url_expires_in,
+            }))
+        }
+        internal::ImageBackend::File => {
+            let config = images.file.ok_or(Error::Malformed(anyhow!(
+                "Image backend is file, but missing file config."
+            )))?;
+
+            Ok(ImageBackend::File(ImageBackendFile {
+                directory: config.directory,
+            }))
+        }
+    }
+}
@@ -1,9 +1,15 @@
-use crate::{domain, image_processing, session_store};
+use anyhow::anyhow;
 
+use crate::{domain, image_import, image_processing, rate_limit, session_store};
+
+pub mod audit;
+pub mod household;
 pub mod image;
 pub mod recipe;
 pub mod session;
+pub mod share;
 pub mod tag;
+pub mod token;
 pub mod user;
 
 #[derive(thiserror::Error, Debug)]
@@ -14,16 +20,46 @@ pub enum Error {
     #[error("Unauthenticated")]
     Unauthenticated(#[source] anyhow::Error),
 
+    #[error("Forbidden")]
+    Forbidden(#[source] anyhow::Error),
+
     #[error("Not a valid request.")]
     Invalid(#[source] anyhow::Error),
 
     #[error(transparent)]
     DomainValidation(#[from] domain::ValidationError),
 
+    #[error("Range not satisfiable.")]
+    RangeNotSatisfiable { size: u64 },
+
+    #[error("{message}")]
+    Unavailable { message: String, retry_after: u64 },
+
+    #[error("Too many requests.")]
+    TooManyRequests { retry_after: u64 },
+
     #[error(transparent)]
     Other(#[from] anyhow::Error),
 }
 
+// Fails unless the user has at least the given role in the household they are acting in.
+fn authorize(
+    user: &domain::user::Authenticated,
+    role: domain::household::Role,
+) -> Result<(), Error> {
+    if user.role < role {
+        return Err(Error::Forbidden(anyhow!(
+            "user {} is {} but must be {} in household {}",
+            user.id,
+            user.role.as_str(),
+            role.as_str(),
+            user.household_id
+        )));
+    }
+
+    Ok(())
+}
+
 impl From<session_store::Error> for Error {
     fn from(value: session_store::Error) -> Self {
         Error::Other(value.into())
@@ -36,6 +72,16 @@ impl From<postcard::Error> for Error {
     }
 }
 
+impl From<rate_limit::Limited> for Error {
+    fn from(value: rate_limit::Limited) -> Self {
+        // in whole seconds, rounded up so that retrying then is allowed
+        let retry_after =
+            value.retry_after.as_secs() + u64::from(value.retry_after.subsec_nanos() > 0);
+
+        Error::TooManyRequests { retry_after }
+    }
+}
+
 impl From<base64::DecodeError> for Error {
     fn from(value: base64::DecodeError) -> Self {
         Error::Other(value.into())
@@ -44,6 +90,28 @@ impl From<base64::DecodeError> for Error {
 
 impl From<image_processing::Error> for Error {
     fn from(value: image_processing::Error) -> Self {
-        Error::Other(value.into())
+        match value {
+            image_processing::Error::Busy => Error::Unavailable {
+                message: "Too many images are being processed, try again later.".into(),
+                retry_after: image_processing::RETRY_AFTER_SECONDS,
+            },
+            // most likely the server was busy with other images, so it may work later
+            image_processing::Error::TimedOut => Error::Unavailable {
+                message: "Processing the image took too long, try again later.".into(),
+                retry_after: image_processing::RETRY_AFTER_SECONDS,
+            },
+            image_processing::Error::Invalid(message) => Error::Invalid(anyhow!(message)),
+            image_processing::Error::Other(err) => Error::Other(err),
+        }
+    }
+}
+
+impl From<image_import::Error> for Error {
+    fn from(value: image_import::Error) -> Self {
+        match value {
+            image_import::Error::Invalid(message) => Error::Invalid(anyhow!(message)),
+            image_import::Error::Fetch(_) => Error::Invalid(anyhow!(value.to_string())),
+            image_import::Error::Other(err) => Error::Other(err),
+        }
     }
 }
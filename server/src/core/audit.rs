@@ -0,0 +1,63 @@
+use anyhow::anyhow;
+
+use crate::{
+    core::Error,
+    datastore::Pool,
+    domain::{self, audit::Recording},
+};
+
+const DEFAULT_LIMIT: u32 = 100;
+const MAX_LIMIT: u32 = 500;
+
+// Appends to the audit log. It is called once the change has happened, so an entry that cannot be
+// written is printed instead of failing the change.
+pub async fn record(datastore: &Pool, recording: Recording) {
+    let id = domain::audit::Id::new();
+    let action = recording.action;
+    let user_id = recording.user_id.clone();
+
+    if let Err(err) = datastore.record_audit(&id, recording).await {
+        println!(
+            "Failed to record {} by user {user_id} in the audit log: {err}.",
+            action.as_str()
+        );
+    }
+}
+
+// What happened in the household the admin is acting in, newest first.
+pub async fn list(
+    datastore: &Pool,
+    user: &domain::user::Authenticated,
+    filter: domain::audit::Filter,
+    limit: Option<u32>,
+) -> Result<Vec<domain::audit::Entry>, Error> {
+    super::authorize(user, domain::household::Role::Admin)?;
+
+    let limit = limit.unwrap_or(DEFAULT_LIMIT);
+    if limit == 0 || limit > MAX_LIMIT {
+        return Err(Error::Invalid(anyhow!(
+            "Limit must be between 1 and {MAX_LIMIT}."
+        )));
+    }
+
+    datastore
+        .list_audit(&user.household_id, filter, limit)
+        .await
+        .map_err(|err| Error::Other(err.into()))
+}
+
+// Deletes entries older than the retention period, returning how many were deleted.
+pub async fn prune(datastore: &Pool, retention_days: u64) -> Result<usize, Error> {
+    let before = i64::try_from(retention_days)
+        .ok()
+        .and_then(chrono::TimeDelta::try_days)
+        .and_then(|retention| chrono::Utc::now().checked_sub_signed(retention))
+        .ok_or(Error::Other(anyhow!(
+            "audit log retention of {retention_days} days is out of bounds"
+        )))?;
+
+    datastore
+        .prune_audit(before)
+        .await
+        .map_err(|err| Error::Other(err.into()))
+}
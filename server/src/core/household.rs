@@ -0,0 +1,295 @@
+use anyhow::anyhow;
+
+use crate::{
+    core::Error,
+    datastore::{self, Pool},
+    domain::{self, audit, household::Role},
+    session_store::SessionStore,
+};
+
+// The household the user acts in by default, and their role there. Sessions start in it and users
+// signed in by a proxy act in it.
+pub async fn active(
+    datastore: &Pool,
+    user_id: &str,
+) -> Result<domain::household::Membership, Error> {
+    datastore
+        .get_active_membership(user_id.to_owned())
+        .await
+        .map_err(|err| match err {
+            datastore::Error::NotFound => {
+                Error::Unauthenticated(anyhow!("user {user_id} has no household"))
+            }
+            _ => Error::Other(err.into()),
+        })
+}
+
+// The user's role in a household they act in, Forbidden once they are no longer a member.
+pub async fn member(
+    datastore: &Pool,
+    user_id: &str,
+    household_id: &domain::household::Id,
+) -> Result<domain::household::Membership, Error> {
+    let role = datastore
+        .get_household_role(household_id, user_id.to_owned())
+        .await
+        .map_err(|err| match err {
+            datastore::Error::NotFound => Error::Forbidden(anyhow!(
+                "user {user_id} is not a member of household {household_id}"
+            )),
+            _ => Error::Other(err.into()),
+        })?;
+
+    Ok(domain::household::Membership {
+        household_id: household_id.clone(),
+        role,
+    })
+}
+
+// The households of the user, marking the one the request acts in as active.
+pub async fn list(
+    datastore: &Pool,
+    user_id: &str,
+    active_household_id: &domain::household::Id,
+) -> Result<Vec<domain::household::Household>, Error> {
+    let households = datastore
+        .list_households(user_id.to_owned())
+        .await
+        .map_err(|err| Error::Other(err.into()))?;
+
+    Ok(households
+        .into_iter()
+        .map(|household| domain::household::Household {
+            active: household.id == *active_household_id,
+            ..household
+        })
+        .collect())
+}
+
+pub async fn create(
+    datastore: &Pool,
+    user: domain::user::Authenticated,
+    household: domain::household::Creating,
+) -> Result<domain::household::Id, Error> {
+    let id = domain::household::Id::new();
+
+    datastore
+        .create_household(&id, user.id.clone(), household.name.into())
+        .await
+        .map_err(|err| Error::Other(err.into()))?;
+
+    super::audit::record(
+        datastore,
+        audit::Recording {
+            user_id: user.id,
+            household_id: Some(id.clone()),
+            action: audit::Action::CreateHousehold,
+            entity_id: id.to_string(),
+            detail: None,
+        },
+    )
+    .await;
+
+    Ok(id)
+}
+
+// Switches the household of the session, other sessions and tokens of the user keep theirs. The
+// household also becomes the user's default, which sessions start in and users signed in by a
+// proxy act in.
+pub async fn activate(
+    datastore: &Pool,
+    session_store: &SessionStore,
+    user_id: &str,
+    session_id: Option<&str>,
+    household_id: &domain::household::Id,
+) -> Result<(), Error> {
+    datastore
+        .set_active_household(user_id.to_owned(), household_id)
+        .await
+        .map_err(|err| match err {
+            datastore::Error::NotFound => {
+                Error::NotFound(format!("household {household_id} does not exist"))
+            }
+            _ => Error::Other(err.into()),
+        })?;
+
+    if let Some(session_id) = session_id {
+        session_store
+            .set_household_for_user(
+                user_id.to_owned(),
+                session_id.to_owned(),
+                household_id.to_string(),
+            )
+            .await?;
+    }
+
+    super::audit::record(
+        datastore,
+        audit::Recording {
+            user_id: user_id.to_owned(),
+            household_id: Some(household_id.clone()),
+            action: audit::Action::ActivateHousehold,
+            entity_id: household_id.to_string(),
+            detail: None,
+        },
+    )
+    .await;
+
+    Ok(())
+}
+
+pub async fn add_member(
+    datastore: &Pool,
+    user_id: &str,
+    household_id: &domain::household::Id,
+    member_id: &str,
+    role: Role,
+) -> Result<(), Error> {
+    authorize(datastore, user_id, household_id, Role::Admin).await?;
+
+    datastore
+        .add_household_member(household_id, member_id.to_owned(), role)
+        .await
+        .map_err(|err| match err {
+            datastore::Error::NotFound => {
+                Error::NotFound(format!("user {member_id} does not exist"))
+            }
+            _ => Error::Other(err.into()),
+        })?;
+
+    record_member(
+        datastore,
+        user_id,
+        household_id,
+        member_id,
+        audit::Action::AddMember,
+        Some(role),
+    )
+    .await;
+
+    Ok(())
+}
+
+pub async fn set_member_role(
+    datastore: &Pool,
+    user_id: &str,
+    household_id: &domain::household::Id,
+    member_id: &str,
+    role: Role,
+) -> Result<(), Error> {
+    authorize(datastore, user_id, household_id, Role::Admin).await?;
+
+    datastore
+        .set_household_member_role(household_id, member_id.to_owned(), role)
+        .await
+        .map_err(|err| match err {
+            datastore::Error::NotFound => Error::NotFound(format!(
+                "user {member_id} is not a member of household {household_id}"
+            )),
+            datastore::Error::Conflict => {
+                Error::Invalid(anyhow!("A household must keep at least one admin."))
+            }
+            _ => Error::Other(err.into()),
+        })?;
+
+    record_member(
+        datastore,
+        user_id,
+        household_id,
+        member_id,
+        audit::Action::SetMemberRole,
+        Some(role),
+    )
+    .await;
+
+    Ok(())
+}
+
+// Admins remove other members, anyone can leave a household on their own.
+pub async fn remove_member(
+    datastore: &Pool,
+    user_id: &str,
+    household_id: &domain::household::Id,
+    member_id: &str,
+) -> Result<(), Error> {
+    let required = if user_id == member_id {
+        Role::Viewer
+    } else {
+        Role::Admin
+    };
+    authorize(datastore, user_id, household_id, required).await?;
+
+    datastore
+        .remove_household_member(household_id, member_id.to_owned())
+        .await
+        .map_err(|err| match err {
+            datastore::Error::NotFound => Error::NotFound(format!(
+                "user {member_id} is not a member of household {household_id}"
+            )),
+            datastore::Error::Conflict => Error::Invalid(anyhow!(
+                "User {member_id} must belong to at least one household, and the household must keep an admin."
+            )),
+            _ => Error::Other(err.into()),
+        })?;
+
+    record_member(
+        datastore,
+        user_id,
+        household_id,
+        member_id,
+        audit::Action::RemoveMember,
+        None,
+    )
+    .await;
+
+    Ok(())
+}
+
+async fn record_member(
+    datastore: &Pool,
+    user_id: &str,
+    household_id: &domain::household::Id,
+    member_id: &str,
+    action: audit::Action,
+    role: Option<Role>,
+) {
+    super::audit::record(
+        datastore,
+        audit::Recording {
+            user_id: user_id.to_owned(),
+            household_id: Some(household_id.clone()),
+            action,
+            entity_id: member_id.to_owned(),
+            detail: role.map(|role| role.as_str().to_owned()),
+        },
+    )
+    .await;
+}
+
+// Households other than the active one are managed here, so the role is looked up rather than
+// taken from the authenticated user.
+async fn authorize(
+    datastore: &Pool,
+    user_id: &str,
+    household_id: &domain::household::Id,
+    required: Role,
+) -> Result<(), Error> {
+    let role = datastore
+        .get_household_role(household_id, user_id.to_owned())
+        .await
+        .map_err(|err| match err {
+            datastore::Error::NotFound => {
+                Error::NotFound(format!("household {household_id} does not exist"))
+            }
+            _ => Error::Other(err.into()),
+        })?;
+
+    super::authorize(
+        &domain::user::Authenticated {
+            id: user_id.to_owned(),
+            household_id: household_id.clone(),
+            role,
+        },
+        required,
+    )
+}
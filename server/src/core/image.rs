@@ -3,7 +3,8 @@ use anyhow::{Context, anyhow};
 use crate::{
     core::Error,
     datastore::{self, Pool},
-    domain,
+    domain::{self, audit},
+    image_import::ImageImporter,
     image_processing::ImageProcessor,
     imagestore::{self, ImageStore},
 };
@@ -12,46 +13,494 @@ pub async fn upload(
     datastore: &Pool,
     image_store: &ImageStore,
     image_processor: &ImageProcessor,
+    user: &domain::user::Authenticated,
     file: Vec<u8>,
 ) -> Result<domain::image::Id, Error> {
+    super::authorize(user, domain::household::Role::Editor)?;
+
+    let id = store(datastore, image_store, image_processor, user, file).await?;
+
+    record(datastore, user, audit::Action::UploadImage, &id, None).await;
+
+    Ok(id)
+}
+
+// Downloads the image at url and stores it like an upload.
+pub async fn import(
+    datastore: &Pool,
+    image_store: &ImageStore,
+    image_processor: &ImageProcessor,
+    image_importer: &ImageImporter,
+    user: &domain::user::Authenticated,
+    url: &str,
+) -> Result<domain::image::Id, Error> {
+    super::authorize(user, domain::household::Role::Editor)?;
+    let file = image_importer.fetch(url).await?;
+
+    let id = store(datastore, image_store, image_processor, user, file).await?;
+
+    record(
+        datastore,
+        user,
+        audit::Action::ImportImage,
+        &id,
+        Some(url.to_owned()),
+    )
+    .await;
+
+    Ok(id)
+}
+
+async fn store(
+    datastore: &Pool,
+    image_store: &ImageStore,
+    image_processor: &ImageProcessor,
+    user: &domain::user::Authenticated,
+    file: Vec<u8>,
+) -> Result<domain::image::Id, Error> {
+    let household_id = &user.household_id;
+
+    let processed = image_processor.process_image(file).await?;
+    let content_hash = sha256::digest(&processed);
+
+    // reuse the existing image if the same content was uploaded to the household before
+    match datastore
+        .get_image_by_content_hash(household_id, &content_hash)
+        .await
+    {
+        Ok(id) => return Ok(id),
+        Err(datastore::Error::NotFound) => {}
+        Err(err) => return Err(Error::Other(anyhow!(err).context("Could not find image."))),
+    }
+
     let id = domain::image::Id::new();
+    let path = original_path(String::from(&id).as_ref());
 
     image_store
-        .upload(
-            &original_path(String::from(&id).as_ref()),
-            image_processor.process_image(file).await?,
-        )
+        .upload(&path, processed)
         .await
         .context("Could not upload image.")?;
 
-    datastore
-        .create_image(&id)
+    // if a concurrent upload of the same content won, its id is returned and the object stored
+    // above is removed, as nothing refers to it.
+    let stored = datastore
+        .create_image(household_id, &id, &content_hash)
         .await
         .context("Could not persist image.")?;
 
-    Ok(id)
+    if stored != id {
+        let _ = image_store.delete(&path).await.inspect_err(|err| {
+            println!("Failed to remove unreferenced image {path}: {err:?}.");
+        });
+    }
+
+    Ok(stored)
+}
+
+async fn record(
+    datastore: &Pool,
+    user: &domain::user::Authenticated,
+    action: audit::Action,
+    id: &domain::image::Id,
+    detail: Option<String>,
+) {
+    super::audit::record(
+        datastore,
+        audit::Recording {
+            user_id: user.id.clone(),
+            household_id: Some(user.household_id.clone()),
+            action,
+            entity_id: id.to_string(),
+            detail,
+        },
+    )
+    .await;
 }
 
-pub async fn get(image_store: &ImageStore, image_id: &str) -> Result<Vec<u8>, Error> {
+// The sizes cover and tile images are generated at, the cover shown on a recipe and the tile in
+// recipe lists.
+pub const COVER_SIZE: (u32, u32) = (960, 672);
+pub const TILE_SIZE: (u32, u32) = (192, 192);
+
+// A stored version of an image.
+#[derive(Debug, Clone, Copy)]
+pub enum Variant<'a> {
+    Original,
+    // the image cropped to the crop with this key
+    Cropped(&'a str),
+    // the image framed with the framing with this key, at the cover or tile size
+    Cover(&'a str),
+    Tile(&'a str),
+}
+
+impl<'a> Variant<'a> {
+    // A generated variant by the name it is served under.
+    pub fn generated(name: &str, key: &'a str) -> Result<Self, Error> {
+        match name {
+            "cropped" => Ok(Variant::Cropped(key)),
+            "cover" => Ok(Variant::Cover(key)),
+            "tile" => Ok(Variant::Tile(key)),
+            _ => Err(Error::NotFound("Image not found.".into())),
+        }
+    }
+
+    fn path(&self, image_id: &str) -> Result<String, Error> {
+        let (name, key) = match self {
+            Variant::Original => return Ok(original_path(image_id)),
+            Variant::Cropped(key) => ("cropped", key),
+            Variant::Cover(key) => ("cover", key),
+            Variant::Tile(key) => ("tile", key),
+        };
+
+        // keys are generated by the server, anything else cannot exist
+        if key.len() != 16 || !key.chars().all(|c| c.is_ascii_hexdigit()) {
+            return Err(Error::NotFound("Image not found.".into()));
+        }
+
+        Ok(generated_path(image_id, name, key))
+    }
+}
+
+pub async fn get(
+    image_store: &ImageStore,
+    image_id: &str,
+    variant: Variant<'_>,
+    range: Option<imagestore::Range>,
+) -> Result<imagestore::Object, Error> {
     image_store
-        .get(&original_path(image_id))
+        .get(&variant.path(image_id)?, range)
         .await
         .map_err(|err| match err {
             imagestore::Error::NotFound(_) => Error::NotFound("Image not found.".into()),
+            imagestore::Error::RangeNotSatisfiable { size } => Error::RangeNotSatisfiable { size },
             _ => Error::Other(anyhow!(err).context("Could not get image.")),
         })
 }
 
-pub async fn exists(datastore: &Pool, image_id: &str) -> Result<(), Error> {
+pub fn presigned_url(
+    image_store: &ImageStore,
+    image_id: &str,
+    variant: Variant<'_>,
+) -> Result<Option<String>, Error> {
+    image_store
+        .presigned_url(&variant.path(image_id)?)
+        .map_err(|err| Error::Other(anyhow!(err).context("Could not presign image url.")))
+}
+
+pub async fn exists(
+    datastore: &Pool,
+    household_id: &domain::household::Id,
+    image_id: &str,
+) -> Result<(), Error> {
     let id = domain::image::Id::try_from(image_id)?;
-    datastore.get_image(&id).await.map_err(|err| match err {
-        datastore::Error::NotFound => Error::NotFound("Image not found.".into()),
-        _ => Error::Other(anyhow!(err).context("Could not get image.")),
-    })?;
+    datastore
+        .get_image(household_id, &id)
+        .await
+        .map_err(|err| match err {
+            datastore::Error::NotFound => Error::NotFound("Image not found.".into()),
+            _ => Error::Other(anyhow!(err).context("Could not get image.")),
+        })?;
 
     Ok(())
 }
 
+pub async fn get_framing(
+    datastore: &Pool,
+    household_id: &domain::household::Id,
+    recipe_id: &str,
+    image_id: &str,
+) -> Result<domain::image::Framing, Error> {
+    let recipe_id = domain::recipe::Id::try_from(recipe_id)?;
+    let id = domain::image::Id::try_from(image_id)?;
+
+    datastore
+        .get_image_framing(household_id, &recipe_id, &id)
+        .await
+        .map_err(|err| match err {
+            datastore::Error::NotFound => Error::NotFound("Recipe image not found.".into()),
+            _ => Error::Other(anyhow!(err).context("Could not get image framing.")),
+        })
+}
+
+// Stores the crop and focal point of an image where a recipe uses it, generating the cropped
+// image if there is a crop, and the cover and tile images framed around the focal point. Other
+// recipes using the same image keep their own framing.
+pub async fn update_framing(
+    datastore: &Pool,
+    image_store: &ImageStore,
+    image_processor: &ImageProcessor,
+    user: &domain::user::Authenticated,
+    recipe_id: &str,
+    image_id: &str,
+    framing: domain::image::Framing,
+) -> Result<(), Error> {
+    super::authorize(user, domain::household::Role::Editor)?;
+    let household_id = &user.household_id;
+
+    // fails unless the recipe uses the image
+    get_framing(datastore, household_id, recipe_id, image_id).await?;
+    let recipe_id = domain::recipe::Id::try_from(recipe_id)?;
+    let id = domain::image::Id::try_from(image_id)?;
+
+    generate(image_store, image_processor, image_id, &framing).await?;
+
+    datastore
+        .set_image_framing(household_id, &recipe_id, &id, framing)
+        .await
+        .context("Could not persist image framing.")?;
+
+    record(
+        datastore,
+        user,
+        audit::Action::UpdateImageFraming,
+        &id,
+        Some(format!("recipe {recipe_id}")),
+    )
+    .await;
+
+    Ok(())
+}
+
+// How a generated image is made from the original.
+enum Generate {
+    Crop(domain::image::Crop),
+    Frame((u32, u32)),
+}
+
+// The paths of the images generated for a framing of an image, and how to make them. There are
+// none until a crop or focal point is set.
+fn generated(image_id: &str, framing: &domain::image::Framing) -> Vec<(String, Generate)> {
+    if *framing == domain::image::Framing::default() {
+        return vec![];
+    }
+
+    let mut generated = vec![];
+    if let Some(crop) = framing.crop() {
+        generated.push((
+            generated_path(image_id, "cropped", &crop.key()),
+            Generate::Crop(*crop),
+        ));
+    }
+    generated.push((
+        generated_path(image_id, "cover", &framing.key()),
+        Generate::Frame(COVER_SIZE),
+    ));
+    generated.push((
+        generated_path(image_id, "tile", &framing.key()),
+        Generate::Frame(TILE_SIZE),
+    ));
+
+    generated
+}
+
+// Generates the images of the framing that were not generated before.
+async fn generate(
+    image_store: &ImageStore,
+    image_processor: &ImageProcessor,
+    image_id: &str,
+    framing: &domain::image::Framing,
+) -> Result<(), Error> {
+    let mut missing = vec![];
+    for (path, generate) in generated(image_id, framing) {
+        // already generated for the same framing
+        if !image_store
+            .exists(&path)
+            .await
+            .context("Could not check for generated image.")?
+        {
+            missing.push((path, generate));
+        }
+    }
+
+    if missing.is_empty() {
+        return Ok(());
+    }
+
+    let original = get(image_store, image_id, Variant::Original, None)
+        .await?
+        .into_bytes()
+        .await
+        .context("Could not read image.")?;
+
+    for (path, generate) in missing {
+        let image = match generate {
+            Generate::Crop(crop) => image_processor.crop(original.clone(), &crop).await?,
+            Generate::Frame(size) => {
+                image_processor
+                    .frame(original.clone(), framing, size)
+                    .await?
+            }
+        };
+
+        match image_store.upload(&path, image).await {
+            // a concurrent update generated the same image
+            Ok(()) | Err(imagestore::Error::DuplicatePath(_)) => {}
+            Err(err) => {
+                return Err(Error::Other(
+                    anyhow!(err).context("Could not upload generated image."),
+                ));
+            }
+        }
+    }
+
+    Ok(())
+}
+
+pub enum MigrateOutcome {
+    Copied { bytes: u64 },
+    AlreadyCopied,
+    MissingFromSource,
+}
+
+pub struct MigrateProgress<'a> {
+    pub id: &'a domain::image::Id,
+    pub position: u64,
+    pub total: u64,
+    pub outcome: &'a MigrateOutcome,
+}
+
+#[derive(Default)]
+pub struct MigrateSummary {
+    pub copied: u64,
+    pub already_copied: u64,
+    pub missing_from_source: Vec<domain::image::Id>,
+}
+
+// Copies every image in the datastore from one store to another. Images already present in the
+// destination are checked and skipped, so an interrupted migration can be run again to resume.
+pub async fn migrate(
+    datastore: &Pool,
+    from: &ImageStore,
+    to: &ImageStore,
+    mut on_progress: impl FnMut(MigrateProgress),
+) -> Result<MigrateSummary, Error> {
+    let total = datastore
+        .count_images()
+        .await
+        .context("Could not count images.")?;
+
+    let mut summary = MigrateSummary::default();
+    let mut position = 0;
+    let mut cursor = None;
+    loop {
+        let page = datastore
+            .list_images(cursor)
+            .await
+            .context("Could not list images.")?;
+
+        for image in page.items {
+            position += 1;
+
+            let id = image.id;
+            let image_id = String::from(&id);
+            let outcome = migrate_image(from, to, &id, &original_path(&image_id)).await?;
+
+            // the images generated for every recipe using it are copied along with the original
+            let framings = datastore
+                .list_image_framings(&id)
+                .await
+                .context(format!("Could not list framings of image {id}."))?;
+            for framing in &framings {
+                for (path, _) in generated(&image_id, framing) {
+                    migrate_image(from, to, &id, &path).await?;
+                }
+            }
+
+            on_progress(MigrateProgress {
+                id: &id,
+                position,
+                total,
+                outcome: &outcome,
+            });
+
+            match outcome {
+                MigrateOutcome::Copied { .. } => summary.copied += 1,
+                MigrateOutcome::AlreadyCopied => summary.already_copied += 1,
+                MigrateOutcome::MissingFromSource => summary.missing_from_source.push(id),
+            }
+        }
+
+        if page.next.is_none() {
+            break;
+        }
+
+        cursor = page.next;
+    }
+
+    Ok(summary)
+}
+
+async fn migrate_image(
+    from: &ImageStore,
+    to: &ImageStore,
+    id: &domain::image::Id,
+    path: &str,
+) -> Result<MigrateOutcome, Error> {
+    let source = match from.get(path, None).await {
+        Ok(object) => object
+            .into_bytes()
+            .await
+            .context(format!("Could not read image {id} from source."))?,
+        Err(imagestore::Error::NotFound(_)) => return Ok(MigrateOutcome::MissingFromSource),
+        Err(err) => {
+            return Err(Error::Other(
+                anyhow!(err).context(format!("Could not get image {id} from source.")),
+            ));
+        }
+    };
+    let source_hash = sha256::digest(&source);
+
+    match to.get(path, None).await {
+        Ok(object) => {
+            let existing = object
+                .into_bytes()
+                .await
+                .context(format!("Could not read image {id} from destination."))?;
+
+            return if sha256::digest(&existing) == source_hash {
+                Ok(MigrateOutcome::AlreadyCopied)
+            } else {
+                Err(Error::Other(anyhow!(
+                    "Image {id} already exists in destination with different content."
+                )))
+            };
+        }
+        Err(imagestore::Error::NotFound(_)) => {}
+        Err(err) => {
+            return Err(Error::Other(
+                anyhow!(err).context(format!("Could not get image {id} from destination.")),
+            ));
+        }
+    }
+
+    let bytes = u64::try_from(source.len()).context("Image size out of bounds.")?;
+    to.upload(path, source)
+        .await
+        .context(format!("Could not upload image {id} to destination."))?;
+
+    // read the image back to verify it was stored intact
+    let copied = to
+        .get(path, None)
+        .await
+        .context(format!("Could not get copied image {id}."))?
+        .into_bytes()
+        .await
+        .context(format!("Could not read copied image {id}."))?;
+
+    if sha256::digest(&copied) != source_hash {
+        return Err(Error::Other(anyhow!(
+            "Checksum mismatch after copying image {id}."
+        )));
+    }
+
+    Ok(MigrateOutcome::Copied { bytes })
+}
+
 fn original_path(id: &str) -> String {
     format!("{id}-original.jpg")
 }
+
+// An image generated from the original, such as "cropped" or "cover".
+fn generated_path(id: &str, name: &str, key: &str) -> String {
+    format!("{id}-{name}-{key}.jpg")
+}
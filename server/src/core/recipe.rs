@@ -1,10 +1,10 @@
-use anyhow::Context;
+use anyhow::{Context, anyhow};
 
 use crate::{
     core::Error,
-    datastore::{self, Pool, RecipeDocument},
+    datastore::{self, DocumentImage, DocumentStepImage, Pool, RecipeDocument},
     domain::{
-        self, CreatingRecipe, ListedRecipe, Recipe, UpdatingRecipe, recipe::StringifiedBlock,
+        self, CreatingRecipe, ListedRecipe, Recipe, UpdatingRecipe, audit, recipe::StringifiedBlock,
     },
     search::Backend,
 };
@@ -15,14 +15,15 @@ pub async fn create(
     user: domain::user::Authenticated,
     recipe: CreatingRecipe,
 ) -> Result<domain::recipe::Id, Error> {
+    super::authorize(&user, domain::household::Role::Editor)?;
+
+    let (instructions, step_images) = instructions_to_document(recipe.instructions);
     let document = RecipeDocument {
         title: recipe.title.into(),
         image_id: recipe.image_id,
-        instructions: recipe
-            .instructions
-            .into_iter()
-            .map(StringifiedBlock::from)
-            .collect(),
+        images: images_to_document(recipe.images),
+        instructions,
+        step_images,
         ingredients: recipe
             .ingredients
             .into_iter()
@@ -35,9 +36,19 @@ pub async fn create(
     let id = domain::recipe::Id::new();
 
     datastore
-        .create_recipe(id.clone().into(), user.id, document)
+        .create_recipe(
+            &user.household_id,
+            id.clone().into(),
+            user.id.clone(),
+            document,
+        )
         .await
-        .map_err(|err| Error::Other(err.into()))?;
+        .map_err(|err| match err {
+            datastore::Error::MissingReference => missing_reference(),
+            _ => Error::Other(err.into()),
+        })?;
+
+    record(datastore, user, audit::Action::CreateRecipe, &id).await;
 
     search_backend
         .index_recipes()
@@ -53,14 +64,15 @@ pub async fn update(
     user: domain::user::Authenticated,
     recipe: UpdatingRecipe,
 ) -> Result<(), Error> {
+    super::authorize(&user, domain::household::Role::Editor)?;
+
+    let (instructions, step_images) = instructions_to_document(recipe.instructions);
     let document = RecipeDocument {
         title: recipe.title.into(),
         image_id: recipe.image_id,
-        instructions: recipe
-            .instructions
-            .into_iter()
-            .map(StringifiedBlock::from)
-            .collect(),
+        images: images_to_document(recipe.images),
+        instructions,
+        step_images,
         ingredients: recipe
             .ingredients
             .into_iter()
@@ -72,8 +84,9 @@ pub async fn update(
 
     datastore
         .update_recipe(
+            &user.household_id,
             recipe.id.clone().into(),
-            user.id,
+            user.id.clone(),
             document,
             recipe.previous_hash,
         )
@@ -82,9 +95,12 @@ pub async fn update(
             datastore::Error::NotFound => {
                 Error::NotFound(format!("recipe {} does not exist", recipe.id))
             }
+            datastore::Error::MissingReference => missing_reference(),
             _ => Error::Other(err.into()),
         })?;
 
+    record(datastore, user, audit::Action::UpdateRecipe, &recipe.id).await;
+
     search_backend
         .index_recipes()
         .await
@@ -93,9 +109,13 @@ pub async fn update(
     Ok(())
 }
 
-pub async fn get(datastore: &Pool, id: domain::recipe::Id) -> Result<Recipe, Error> {
+pub async fn get(
+    datastore: &Pool,
+    household_id: &domain::household::Id,
+    id: domain::recipe::Id,
+) -> Result<Recipe, Error> {
     datastore
-        .get_recipe(id.clone().into())
+        .get_recipe(household_id, id.clone().into())
         .await
         .map_err(|err| match err {
             datastore::Error::NotFound => Error::NotFound(format!("recipe {id} does not exist")),
@@ -106,19 +126,20 @@ pub async fn get(datastore: &Pool, id: domain::recipe::Id) -> Result<Recipe, Err
 pub async fn list(
     datastore: &Pool,
     search_backend: &Backend,
+    household_id: &domain::household::Id,
     query: Option<String>,
     filter: domain::filter::Recipe,
     cursor: Option<domain::page::cursor::Recipe>,
 ) -> Result<domain::page::Recipe, Error> {
     if let Some(search) = query {
         let recipe_ids = search_backend
-            .search(search, filter)
+            .search(search, household_id, filter)
             .await
             .map_err(|err| Error::Other(err.into()))?;
 
         let recipes = futures::future::try_join_all(recipe_ids.into_iter().map(|id| async move {
             let recipe = datastore
-                .get_recipe(id.clone().into())
+                .get_recipe(household_id, id.clone().into())
                 .await
                 .context(format!("get recipe: {id}"))?;
 
@@ -137,8 +158,67 @@ pub async fn list(
         })
     } else {
         datastore
-            .list_recipes(filter, cursor)
+            .list_recipes(household_id, filter, cursor)
             .await
             .map_err(|err| Error::Other(err.into()))
     }
 }
+
+async fn record(
+    datastore: &Pool,
+    user: domain::user::Authenticated,
+    action: audit::Action,
+    id: &domain::recipe::Id,
+) {
+    super::audit::record(
+        datastore,
+        audit::Recording {
+            user_id: user.id,
+            household_id: Some(user.household_id),
+            action,
+            entity_id: id.to_string(),
+            detail: None,
+        },
+    )
+    .await;
+}
+
+fn missing_reference() -> Error {
+    Error::Invalid(anyhow!(
+        "Recipe refers to a tag or image that does not exist."
+    ))
+}
+
+fn images_to_document(images: Vec<domain::recipe::Image>) -> Vec<DocumentImage> {
+    images
+        .into_iter()
+        .map(|image| DocumentImage {
+            image_id: image.id,
+            caption: image.caption.map(Into::into),
+        })
+        .collect()
+}
+
+fn instructions_to_document(
+    blocks: Vec<domain::recipe::InstructionBlock>,
+) -> (Vec<StringifiedBlock>, Vec<DocumentStepImage>) {
+    let step_images = blocks
+        .iter()
+        .enumerate()
+        .flat_map(|(block, instructions)| {
+            instructions
+                .step_images
+                .iter()
+                .map(move |image| DocumentStepImage {
+                    block,
+                    step: image.step,
+                    image_id: image.image_id.clone(),
+                })
+        })
+        .collect();
+
+    (
+        blocks.into_iter().map(StringifiedBlock::from).collect(),
+        step_images,
+    )
+}
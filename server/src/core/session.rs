@@ -1,10 +1,13 @@
+use std::net::IpAddr;
+
 use base64::Engine;
 use ring::rand::SecureRandom;
 
 use crate::{
-    core,
-    domain::{Session, SessionKey, SessionStatus, User},
-    oidc,
+    config, core,
+    datastore::Pool,
+    domain::{self, Session, SessionInfo, SessionKey, SessionStatus, User, audit},
+    local_auth, oidc,
     session_store::{self, SessionStore},
 };
 
@@ -16,6 +19,9 @@ enum Error {
     #[error("session is expired")]
     Expired,
 
+    #[error("local user was removed or changed their password")]
+    LocalUserChanged,
+
     #[error("time out of bounds")]
     TimeOutOfBounds,
 }
@@ -23,7 +29,7 @@ enum Error {
 impl From<Error> for core::Error {
     fn from(value: Error) -> Self {
         match value {
-            Error::Expired => core::Error::Unauthenticated(value.into()),
+            Error::Expired | Error::LocalUserChanged => core::Error::Unauthenticated(value.into()),
             _ => core::Error::Other(value.into()),
         }
     }
@@ -32,32 +38,58 @@ impl From<Error> for core::Error {
 // maximum number of seconds a session may last before refresh
 pub const SESSION_EXPIRES_IN: i64 = 60 * 60 * 24 * 90;
 
+// how often the last use of a session or token is recorded, so not every request writes to a store
+pub(super) const LAST_USED_RESOLUTION: i64 = 60 * 5;
+
+const MAX_USER_AGENT_LENGTH: usize = 256;
+
 pub struct Active {
     pub key: SessionKey,
+    pub id: String,
     pub user_id: String,
     pub expires_at: chrono::DateTime<chrono::Utc>,
+    // None for sessions started before households were kept per session
+    pub household_id: Option<String>,
+}
+
+// Where a session is started from.
+pub struct Client {
+    pub user_agent: Option<String>,
+    pub address: Option<IpAddr>,
 }
 
 pub async fn begin(
     store: &SessionStore,
     user: &User,
+    household_id: &domain::household::Id,
     authenticated: &oidc::Authenticated,
+    client: &Client,
 ) -> Result<SessionKey, core::Error> {
-    let expires_at = chrono::Utc::now()
+    let now = chrono::Utc::now();
+    let expires_at = now
         .checked_add_signed(chrono::TimeDelta::seconds(SESSION_EXPIRES_IN))
         .ok_or(Error::TimeOutOfBounds)?;
 
     let session = Session {
         key: new_session_id()?,
+        id: ulid::Ulid::new().to_string(),
         user_id: user.id.clone(),
+        provider: authenticated.provider.clone(),
         refresh_token: authenticated.refresh_token.clone(),
         revalidate_at: authenticated.expires_at,
         expires_at,
+        created_at: now,
+        last_used_at: now,
+        user_agent: client
+            .user_agent
+            .as_ref()
+            .map(|user_agent| user_agent.chars().take(MAX_USER_AGENT_LENGTH).collect()),
+        client: client.address.map(approximate_address),
+        household_id: Some(household_id.to_string()),
     };
 
     let session_key = session.key.clone();
 
-    // TODO - also encrypt session value before inserting
     store.set(session).await?;
 
     Ok(SessionKey(session_key))
@@ -65,17 +97,47 @@ pub async fn begin(
 
 pub async fn get(
     store: &SessionStore,
-    oidc: &oidc::Provider,
+    datastore: &Pool,
+    oidc: &oidc::Providers,
+    local_auth: Option<&config::LocalAuth>,
     key: SessionKey,
 ) -> Result<Active, core::Error> {
     let session = find_session(store, key.clone()).await?;
 
+    // local users are only known from the configuration, so the session ends when they are
+    // removed from it or their password is changed
+    if session.provider == config::LOCAL_AUTH_PROVIDER
+        && !local_auth::is_current(local_auth, &session.refresh_token)
+    {
+        store.delete(key).await?;
+        record(
+            datastore,
+            &session.user_id,
+            audit::Action::RefreshFailed,
+            &session.user_id,
+            Some(session.provider.clone()),
+        )
+        .await;
+        return Err(Error::LocalUserChanged.into());
+    }
+
     match session.status() {
-        SessionStatus::Ok => Ok(Active {
-            key: SessionKey(session.key),
-            user_id: session.user_id,
-            expires_at: session.expires_at,
-        }),
+        SessionStatus::Ok => {
+            let last_used_before = chrono::Utc::now()
+                .checked_sub_signed(chrono::TimeDelta::seconds(LAST_USED_RESOLUTION))
+                .ok_or(Error::TimeOutOfBounds)?;
+            if session.last_used_at < last_used_before {
+                store.touch(&session).await?;
+            }
+
+            Ok(Active {
+                key: SessionKey(session.key),
+                id: session.id,
+                user_id: session.user_id,
+                expires_at: session.expires_at,
+                household_id: session.household_id,
+            })
+        }
         SessionStatus::Expired => Err(Error::Expired.into()),
         SessionStatus::MustRevalidate => {
             // A refresh token can only be used once, therefore only one request to refresh must
@@ -83,7 +145,7 @@ pub async fn get(
             // the same refresh token.
             store.lock_refresh(key.clone()).await?;
 
-            let result = refresh_session(store, oidc, key.clone()).await;
+            let result = refresh_session(store, datastore, oidc, key.clone()).await;
 
             store.unlock_refresh(key).await?;
 
@@ -92,6 +154,37 @@ pub async fn get(
     }
 }
 
+// Ends a session, so its key can no longer be used. Returns the name of the provider the session
+// was started with, if the session still existed.
+pub async fn end(
+    store: &SessionStore,
+    datastore: &Pool,
+    key: SessionKey,
+) -> Result<Option<String>, core::Error> {
+    let session = match store.get(key.clone()).await {
+        Ok(session) => Some(session),
+        Err(session_store::Error::NotFound(_)) => None,
+        Err(err) => return Err(err.into()),
+    };
+
+    store.delete(key).await?;
+
+    let Some(session) = session else {
+        return Ok(None);
+    };
+
+    record(
+        datastore,
+        &session.user_id,
+        audit::Action::SignOut,
+        &session.user_id,
+        Some(session.provider.clone()),
+    )
+    .await;
+
+    Ok(Some(session.provider))
+}
+
 async fn find_session(store: &SessionStore, key: SessionKey) -> Result<Session, core::Error> {
     store.get(key).await.map_err(|err| match err {
         session_store::Error::NotFound(err) => {
@@ -103,7 +196,8 @@ async fn find_session(store: &SessionStore, key: SessionKey) -> Result<Session,
 
 async fn refresh_session(
     store: &SessionStore,
-    oidc: &oidc::Provider,
+    datastore: &Pool,
+    oidc: &oidc::Providers,
     key: SessionKey,
 ) -> Result<Active, core::Error> {
     let session = find_session(store, key.clone()).await?;
@@ -111,32 +205,54 @@ async fn refresh_session(
     match session.status() {
         SessionStatus::Ok => Ok(Active {
             key: SessionKey(session.key),
+            id: session.id,
             user_id: session.user_id,
             expires_at: session.expires_at,
+            household_id: session.household_id,
         }),
         SessionStatus::Expired => Err(Error::Expired.into()),
         SessionStatus::MustRevalidate => {
-            let authenticated = match oidc::refresh_auth(oidc, session.refresh_token.clone()).await
-            {
+            // the provider may no longer be configured
+            let refreshed = match oidc.get(Some(&session.provider)) {
+                Ok(provider) => oidc::refresh_auth(provider, session.refresh_token.clone()).await,
+                Err(err) => Err(err),
+            };
+            let authenticated = match refreshed {
                 Ok(authenticated) => Ok(authenticated),
                 Err(err) => {
                     store.delete(key.clone()).await?;
+                    record(
+                        datastore,
+                        &session.user_id,
+                        audit::Action::RefreshFailed,
+                        &session.user_id,
+                        Some(session.provider.clone()),
+                    )
+                    .await;
                     Err(core::Error::Unauthenticated(err.into()))
                 }
             }?;
 
             // build a new session
-            let expires_at = chrono::Utc::now()
+            let now = chrono::Utc::now();
+            let expires_at = now
                 .checked_add_signed(chrono::TimeDelta::seconds(SESSION_EXPIRES_IN))
                 .ok_or(Error::TimeOutOfBounds)?;
 
             let new_key = new_session_id()?;
             let new_session = Session {
                 key: new_key.clone(),
+                id: session.id.clone(),
                 user_id: session.user_id.clone(),
+                provider: session.provider.clone(),
                 refresh_token: authenticated.refresh_token,
                 revalidate_at: authenticated.expires_at,
                 expires_at,
+                created_at: session.created_at,
+                last_used_at: now,
+                user_agent: session.user_agent.clone(),
+                client: session.client.clone(),
+                household_id: session.household_id.clone(),
             };
 
             // the old session should be kept alive for a short grace period to allow any requests
@@ -145,22 +261,115 @@ async fn refresh_session(
                 .checked_add_signed(chrono::TimeDelta::seconds(15))
                 .ok_or(Error::TimeOutOfBounds)?;
 
+            let active = Active {
+                key: SessionKey(new_key),
+                id: session.id.clone(),
+                user_id: session.user_id.clone(),
+                expires_at,
+                household_id: session.household_id.clone(),
+            };
+
             let original_session = Session {
                 key: key.to_string(),
-                user_id: session.user_id.clone(),
-                refresh_token: session.refresh_token,
                 revalidate_at: original_session_grace,
                 expires_at: original_session_grace,
+                ..session
             };
 
             store.set(original_session).await?;
             store.set(new_session).await?;
 
-            Ok(Active {
-                key: SessionKey(new_key),
-                user_id: session.user_id.clone(),
-                expires_at,
-            })
+            Ok(active)
+        }
+    }
+}
+
+pub async fn list(store: &SessionStore, user_id: &str) -> Result<Vec<SessionInfo>, core::Error> {
+    Ok(store.list_for_user(user_id.to_owned()).await?)
+}
+
+pub async fn revoke(
+    store: &SessionStore,
+    datastore: &Pool,
+    user_id: &str,
+    id: &str,
+) -> Result<(), core::Error> {
+    store
+        .delete_for_user(user_id.to_owned(), id.to_owned())
+        .await
+        .map_err(|err| match err {
+            session_store::Error::NotFound(_) => core::Error::NotFound("Session not found.".into()),
+            _ => err.into(),
+        })?;
+
+    record(datastore, user_id, audit::Action::RevokeSession, id, None).await;
+
+    Ok(())
+}
+
+// Revokes every session of the user except the current one. Requests made with a token have no
+// session of their own, so every session is revoked.
+pub async fn revoke_others(
+    store: &SessionStore,
+    datastore: &Pool,
+    user_id: &str,
+    current_id: Option<&str>,
+) -> Result<(), core::Error> {
+    store
+        .delete_others_for_user(
+            user_id.to_owned(),
+            current_id.unwrap_or_default().to_owned(),
+        )
+        .await?;
+
+    record(
+        datastore,
+        user_id,
+        audit::Action::RevokeOtherSessions,
+        user_id,
+        None,
+    )
+    .await;
+
+    Ok(())
+}
+
+// Sessions belong to the user rather than a household.
+pub(super) async fn record(
+    datastore: &Pool,
+    user_id: &str,
+    action: audit::Action,
+    entity_id: &str,
+    detail: Option<String>,
+) {
+    core::audit::record(
+        datastore,
+        audit::Recording {
+            user_id: user_id.to_owned(),
+            household_id: None,
+            action,
+            entity_id: entity_id.to_owned(),
+            detail,
+        },
+    )
+    .await;
+}
+
+// Reduces an address to its network, which is enough to recognise where a session was started
+// without keeping the exact address.
+fn approximate_address(address: IpAddr) -> String {
+    match address {
+        IpAddr::V4(address) => {
+            let [a, b, c, _] = address.octets();
+            format!("{a}.{b}.{c}.0/24")
+        }
+        IpAddr::V6(address) => {
+            if let Some(address) = address.to_ipv4_mapped() {
+                return approximate_address(IpAddr::V4(address));
+            }
+
+            let [a, b, c, ..] = address.segments();
+            format!("{}/48", std::net::Ipv6Addr::new(a, b, c, 0, 0, 0, 0, 0))
         }
     }
 }
@@ -173,3 +382,21 @@ fn new_session_id() -> Result<String, Error> {
 
     Ok(base64::engine::general_purpose::URL_SAFE.encode(bytes))
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_approximate_address() {
+        let table = [
+            ("192.168.1.27", "192.168.1.0/24"),
+            ("2001:db8:85a3:8d3:1319:8a2e:370:7348", "2001:db8:85a3::/48"),
+            ("::ffff:10.0.0.1", "10.0.0.0/24"),
+        ];
+
+        for (address, expected) in table {
+            assert_eq!(expected, approximate_address(address.parse().unwrap()));
+        }
+    }
+}
@@ -0,0 +1,160 @@
+use anyhow::anyhow;
+use base64::Engine;
+use ring::rand::SecureRandom;
+
+use crate::{
+    core::Error,
+    datastore::{self, Pool},
+    domain::{self, Recipe, audit},
+};
+
+// A newly created share, the only time the token for its link is known.
+pub struct Created {
+    pub id: domain::share::Id,
+    pub token: String,
+}
+
+pub async fn create(
+    datastore: &Pool,
+    user: &domain::user::Authenticated,
+    recipe_id: &domain::recipe::Id,
+    share: domain::share::Creating,
+) -> Result<Created, Error> {
+    super::authorize(user, domain::household::Role::Editor)?;
+
+    if share
+        .expires_at
+        .is_some_and(|expires_at| expires_at <= chrono::Utc::now())
+    {
+        return Err(Error::Invalid(anyhow!("Share must expire in the future.")));
+    }
+
+    let id = domain::share::Id::new();
+    let token = new_share_token()?;
+
+    datastore
+        .create_share(
+            &id,
+            &user.household_id,
+            recipe_id,
+            sha256::digest(&token),
+            share,
+        )
+        .await
+        .map_err(|err| match err {
+            datastore::Error::NotFound => {
+                Error::NotFound(format!("recipe {recipe_id} does not exist"))
+            }
+            _ => Error::Other(err.into()),
+        })?;
+
+    record(datastore, user, audit::Action::CreateShare, recipe_id, &id).await;
+
+    Ok(Created { id, token })
+}
+
+pub async fn list(
+    datastore: &Pool,
+    household_id: &domain::household::Id,
+    recipe_id: &domain::recipe::Id,
+) -> Result<Vec<domain::share::Share>, Error> {
+    datastore
+        .list_shares(household_id, recipe_id)
+        .await
+        .map_err(|err| Error::Other(err.into()))
+}
+
+pub async fn revoke(
+    datastore: &Pool,
+    user: &domain::user::Authenticated,
+    recipe_id: &domain::recipe::Id,
+    id: &domain::share::Id,
+) -> Result<(), Error> {
+    super::authorize(user, domain::household::Role::Editor)?;
+
+    datastore
+        .delete_share(&user.household_id, recipe_id, id)
+        .await
+        .map_err(|err| match err {
+            datastore::Error::NotFound => Error::NotFound("Share not found.".into()),
+            _ => Error::Other(err.into()),
+        })?;
+
+    record(datastore, user, audit::Action::RevokeShare, recipe_id, id).await;
+
+    Ok(())
+}
+
+async fn record(
+    datastore: &Pool,
+    user: &domain::user::Authenticated,
+    action: audit::Action,
+    recipe_id: &domain::recipe::Id,
+    id: &domain::share::Id,
+) {
+    super::audit::record(
+        datastore,
+        audit::Recording {
+            user_id: user.id.clone(),
+            household_id: Some(user.household_id.clone()),
+            action,
+            entity_id: id.to_string(),
+            detail: Some(format!("recipe {recipe_id}")),
+        },
+    )
+    .await;
+}
+
+// The recipe a share link points to, for anyone holding the link.
+pub async fn open(datastore: &Pool, token: &str) -> Result<Recipe, Error> {
+    let stored = datastore
+        .get_share_by_hash(sha256::digest(token))
+        .await
+        .map_err(|err| match err {
+            datastore::Error::NotFound => Error::NotFound("Share not found.".into()),
+            _ => Error::Other(err.into()),
+        })?;
+
+    if stored
+        .expires_at
+        .is_some_and(|expires_at| expires_at <= chrono::Utc::now())
+    {
+        return Err(Error::NotFound("Share not found.".into()));
+    }
+
+    datastore
+        .get_recipe(&stored.household_id, stored.recipe_id.clone().into())
+        .await
+        .map_err(|err| Error::Other(err.into()))
+}
+
+// Fails unless the image is shown on the shared recipe, so a link never exposes anything else.
+pub async fn image(datastore: &Pool, token: &str, image_id: &str) -> Result<(), Error> {
+    let recipe = open(datastore, token).await?;
+
+    let is_shown = recipe
+        .image_id
+        .iter()
+        .chain(recipe.images.iter().map(|image| &image.id))
+        .chain(
+            recipe
+                .instructions
+                .iter()
+                .flat_map(|block| block.step_images.iter().map(|image| &image.image_id)),
+        )
+        .any(|id| id.to_string() == image_id);
+    if !is_shown {
+        return Err(Error::NotFound("Image not found.".into()));
+    }
+
+    Ok(())
+}
+
+fn new_share_token() -> Result<String, Error> {
+    let mut bytes: [u8; 32] = [0; 32];
+    ring::rand::SystemRandom::new()
+        .fill(&mut bytes)
+        .map_err(|_| Error::Other(anyhow!("crypto random error")))?;
+
+    Ok(base64::engine::general_purpose::URL_SAFE_NO_PAD.encode(bytes))
+}
@@ -1,21 +1,42 @@
-use crate::{core::Error, datastore::Pool, domain};
+use crate::{
+    core::Error,
+    datastore::Pool,
+    domain::{self, audit},
+};
 
 pub async fn create(
     datastore: &Pool,
     user: domain::user::Authenticated,
     tag: domain::tag::Creating,
 ) -> Result<domain::tag::Id, Error> {
+    super::authorize(&user, domain::household::Role::Editor)?;
+
     let id = datastore
-        .create_tag(user.id, tag.name.into())
+        .create_tag(&user.household_id, user.id.clone(), tag.name.into())
         .await
         .map_err(|err| Error::Other(err.into()))?;
 
+    super::audit::record(
+        datastore,
+        audit::Recording {
+            user_id: user.id,
+            household_id: Some(user.household_id),
+            action: audit::Action::CreateTag,
+            entity_id: id.to_string(),
+            detail: None,
+        },
+    )
+    .await;
+
     Ok(id)
 }
 
-pub async fn get_all(datastore: &Pool) -> Result<Vec<domain::tag::Tag>, Error> {
+pub async fn get_all(
+    datastore: &Pool,
+    household_id: &domain::household::Id,
+) -> Result<Vec<domain::tag::Tag>, Error> {
     datastore
-        .get_tags()
+        .get_tags(household_id)
         .await
         .map_err(|err| Error::Other(err.into()))
 }
@@ -0,0 +1,137 @@
+use anyhow::anyhow;
+use base64::Engine;
+use ring::rand::SecureRandom;
+
+use crate::{
+    core::Error,
+    datastore::{self, Pool},
+    domain::{self, audit},
+};
+
+use super::session::LAST_USED_RESOLUTION;
+
+// Tokens are recognisable by their prefix, for example by secret scanners.
+const TOKEN_PREFIX: &str = "mise_";
+
+// A newly created token, the only time the token itself is known.
+pub struct Created {
+    pub id: domain::token::Id,
+    pub token: String,
+}
+
+// The user a token acts for, and the household it acts in.
+pub struct Authenticated {
+    pub user_id: String,
+    pub household_id: domain::household::Id,
+    pub read_only: bool,
+}
+
+pub async fn create(
+    datastore: &Pool,
+    user_id: &str,
+    token: domain::token::Creating,
+) -> Result<Created, Error> {
+    if token
+        .expires_at
+        .is_some_and(|expires_at| expires_at <= chrono::Utc::now())
+    {
+        return Err(Error::Invalid(anyhow!("Token must expire in the future.")));
+    }
+
+    let id = domain::token::Id::new();
+    let secret = new_token()?;
+
+    datastore
+        .create_token(&id, user_id.to_owned(), sha256::digest(&secret), token)
+        .await
+        .map_err(|err| Error::Other(err.into()))?;
+
+    record(datastore, user_id, audit::Action::CreateToken, &id).await;
+
+    Ok(Created { id, token: secret })
+}
+
+pub async fn list(datastore: &Pool, user_id: &str) -> Result<Vec<domain::token::Token>, Error> {
+    datastore
+        .list_tokens(user_id.to_owned())
+        .await
+        .map_err(|err| Error::Other(err.into()))
+}
+
+pub async fn revoke(datastore: &Pool, user_id: &str, id: &domain::token::Id) -> Result<(), Error> {
+    datastore
+        .delete_token(user_id.to_owned(), id)
+        .await
+        .map_err(|err| match err {
+            datastore::Error::NotFound => Error::NotFound("Token not found.".into()),
+            _ => Error::Other(err.into()),
+        })?;
+
+    record(datastore, user_id, audit::Action::RevokeToken, id).await;
+
+    Ok(())
+}
+
+// Tokens belong to the user rather than a household.
+async fn record(datastore: &Pool, user_id: &str, action: audit::Action, id: &domain::token::Id) {
+    super::audit::record(
+        datastore,
+        audit::Recording {
+            user_id: user_id.to_owned(),
+            household_id: None,
+            action,
+            entity_id: id.to_string(),
+            detail: None,
+        },
+    )
+    .await;
+}
+
+pub async fn authenticate(datastore: &Pool, token: &str) -> Result<Authenticated, Error> {
+    let stored = datastore
+        .get_token_by_hash(sha256::digest(token))
+        .await
+        .map_err(|err| match err {
+            datastore::Error::NotFound => Error::Unauthenticated(anyhow!("token not found")),
+            _ => Error::Other(err.into()),
+        })?;
+
+    let now = chrono::Utc::now();
+    if stored
+        .expires_at
+        .is_some_and(|expires_at| expires_at <= now)
+    {
+        return Err(Error::Unauthenticated(anyhow!(
+            "token {} is expired",
+            stored.id
+        )));
+    }
+
+    let is_recently_used = stored.last_used_at.is_some_and(|last_used_at| {
+        now.signed_duration_since(last_used_at).num_seconds() < LAST_USED_RESOLUTION
+    });
+    if !is_recently_used {
+        datastore
+            .touch_token(&stored.id)
+            .await
+            .map_err(|err| Error::Other(err.into()))?;
+    }
+
+    Ok(Authenticated {
+        user_id: stored.user_id,
+        household_id: stored.household_id,
+        read_only: stored.read_only,
+    })
+}
+
+fn new_token() -> Result<String, Error> {
+    let mut bytes: [u8; 32] = [0; 32];
+    ring::rand::SystemRandom::new()
+        .fill(&mut bytes)
+        .map_err(|_| Error::Other(anyhow!("crypto random error")))?;
+
+    Ok(format!(
+        "{TOKEN_PREFIX}{}",
+        base64::engine::general_purpose::URL_SAFE_NO_PAD.encode(bytes)
+    ))
+}
@@ -1,42 +1,211 @@
+use anyhow::anyhow;
+
 use crate::{
+    config,
     core::Error,
     datastore::{self, Pool},
-    domain::{self, RegisteringUser, SessionKey},
-    oidc,
+    domain::{self, RegisteringUser, SessionKey, User, audit},
+    local_auth, oidc, proxy_auth,
     session_store::SessionStore,
 };
 
 use super::session;
 
-pub async fn get(datasource: &Pool, id: &str) -> Result<String, Error> {
-    let user = datasource
+pub async fn get(datasource: &Pool, id: &str) -> Result<User, Error> {
+    datasource
         .get_user(id.to_owned())
         .await
         .map_err(|err| match err {
             datastore::Error::NotFound => Error::NotFound(format!("user {id} does not exist")),
             _ => Error::Other(err.into()),
+        })
+}
+
+// Updates the profile of the user. The avatar must be an image the household they are acting in
+// can see, uploaded like any other image.
+pub async fn update(
+    datasource: &Pool,
+    user: domain::user::Authenticated,
+    updating: domain::user::Updating,
+) -> Result<User, Error> {
+    if let Some(avatar_image_id) = &updating.avatar_image_id {
+        datasource
+            .get_image(&user.household_id, avatar_image_id)
+            .await
+            .map_err(|err| match err {
+                datastore::Error::NotFound => {
+                    Error::Invalid(anyhow!("Avatar image {avatar_image_id} does not exist."))
+                }
+                _ => Error::Other(err.into()),
+            })?;
+    }
+
+    let updated = datasource
+        .update_user(user.id.clone(), updating)
+        .await
+        .map_err(|err| match err {
+            datastore::Error::NotFound => {
+                Error::NotFound(format!("user {} does not exist", user.id))
+            }
+            _ => Error::Other(err.into()),
         })?;
 
-    Ok(user.name)
+    session::record(
+        datasource,
+        &user.id,
+        audit::Action::UpdateProfile,
+        &user.id,
+        None,
+    )
+    .await;
+
+    Ok(updated)
 }
 
 pub async fn on_authenticated(
     datasource: &Pool,
     session_store: &SessionStore,
     authenticated: &oidc::Authenticated,
+    client: &session::Client,
 ) -> Result<SessionKey, Error> {
     let registering = RegisteringUser {
         potential_id: domain::user::Id::new().into(),
-        oauth_id: format!("custom|{}", authenticated.subject),
+        issuer: authenticated.issuer.clone(),
+        subject: authenticated.subject.clone(),
         name: authenticated.name.to_string(),
+        max_role: authenticated.max_role,
     };
 
     let user = datasource
-        .upsert_user_by_oauth_id(registering)
+        .upsert_user_by_identity(registering)
         .await
         .map_err(|err| Error::Other(err.into()))?;
 
-    let session_key = session::begin(session_store, &user, authenticated).await?;
+    let membership = super::household::active(datasource, &user.id).await?;
+    let session_key = session::begin(
+        session_store,
+        &user,
+        &membership.household_id,
+        authenticated,
+        client,
+    )
+    .await?;
+
+    session::record(
+        datasource,
+        &user.id,
+        audit::Action::SignIn,
+        &user.id,
+        Some(authenticated.provider.clone()),
+    )
+    .await;
 
     Ok(session_key)
 }
+
+// Signs in with a password of a user configured for local sign in, which starts the same kind of
+// session as signing in with a provider.
+pub async fn sign_in_locally(
+    datasource: &Pool,
+    session_store: &SessionStore,
+    config: config::LocalAuth,
+    username: String,
+    password: String,
+    client: &session::Client,
+) -> Result<SessionKey, Error> {
+    let user = tokio::task::spawn_blocking(move || {
+        local_auth::verify(&config, &username, &password).cloned()
+    })
+    .await
+    .map_err(|err| Error::Other(err.into()))?
+    .map_err(|err| Error::Unauthenticated(err.into()))?;
+
+    // there is no provider to revalidate with, so the session lasts until it expires, unless the
+    // user's configuration changes
+    let authenticated = oidc::Authenticated {
+        provider: config::LOCAL_AUTH_PROVIDER.to_owned(),
+        issuer: local_auth::ISSUER.to_owned(),
+        refresh_token: local_auth::fingerprint(&user),
+        subject: user.username,
+        name: user.name,
+        expires_at: chrono::Utc::now()
+            .checked_add_signed(chrono::TimeDelta::seconds(session::SESSION_EXPIRES_IN))
+            .ok_or(Error::Other(anyhow!("time out of bounds")))?,
+        max_role: None,
+    };
+
+    on_authenticated(datasource, session_store, &authenticated, client).await
+}
+
+// Finds the id of the user a trusted proxy signed in, registering them on their first request.
+pub async fn on_proxy_authenticated(
+    datasource: &Pool,
+    users: &proxy_auth::Users,
+    identity: proxy_auth::Identity,
+) -> Result<String, Error> {
+    if let Some(id) = users.get(&identity) {
+        return Ok(id);
+    }
+
+    let user = datasource
+        .upsert_user_by_identity(RegisteringUser {
+            potential_id: domain::user::Id::new().into(),
+            issuer: proxy_auth::ISSUER.to_owned(),
+            subject: identity.username.clone(),
+            name: identity.name.clone(),
+            max_role: None,
+        })
+        .await
+        .map_err(|err| Error::Other(err.into()))?;
+
+    users.insert(identity, user.id.clone());
+
+    // the proxy keeps the session, so this is the first request since mise started rather than
+    // when the user signed in
+    session::record(
+        datasource,
+        &user.id,
+        audit::Action::SignIn,
+        &user.id,
+        Some(proxy_auth::ISSUER.to_owned()),
+    )
+    .await;
+
+    Ok(user.id)
+}
+
+// Users registered before users were linked by issuer signed in with the legacy provider, which
+// is the default provider or the only provider unless configured. When it is not clear which
+// provider that was, starting is refused rather than letting those users sign in to new, empty
+// accounts.
+pub async fn link_unlinked(
+    datasource: &Pool,
+    providers: &oidc::Providers,
+    legacy_provider: Option<&str>,
+) -> Result<usize, Error> {
+    let provider = match legacy_provider {
+        Some(name) => providers.get(Some(name)),
+        None => providers
+            .get(Some(config::DEFAULT_OIDC_PROVIDER))
+            .or_else(|_| providers.get(None)),
+    };
+
+    let Ok(provider) = provider else {
+        let unlinked = datasource
+            .count_unlinked_users()
+            .await
+            .map_err(|err| Error::Other(err.into()))?;
+        if unlinked == 0 {
+            return Ok(0);
+        }
+
+        return Err(Error::Invalid(anyhow!(
+            "{unlinked} users signed in before users were linked by provider, set oidc.legacy_provider to the provider they used"
+        )));
+    };
+
+    datasource
+        .link_unlinked_users(provider.issuer().to_owned())
+        .await
+        .map_err(|err| Error::Other(err.into()))
+}
@@ -21,6 +21,22 @@ pub struct RecipeDocument {
     pub notes: Option<String>,
     pub tag_ids: Vec<domain::tag::Id>,
     pub image_id: Option<domain::image::Id>,
+    pub images: Vec<DocumentImage>,
+    pub step_images: Vec<DocumentStepImage>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DocumentImage {
+    pub image_id: domain::image::Id,
+    pub caption: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DocumentStepImage {
+    // index of the instruction block, and of the instruction within it
+    pub block: usize,
+    pub step: usize,
+    pub image_id: domain::image::Id,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -33,6 +49,16 @@ pub enum VersionedRecipeDocument {
         tag_ids: Vec<domain::tag::Id>,
         image_id: Option<domain::image::Id>,
     },
+    V2 {
+        title: String,
+        ingredients: Vec<StringifiedBlock>,
+        instructions: Vec<StringifiedBlock>,
+        notes: Option<String>,
+        tag_ids: Vec<domain::tag::Id>,
+        image_id: Option<domain::image::Id>,
+        images: Vec<DocumentImage>,
+        step_images: Vec<DocumentStepImage>,
+    },
 }
 
 impl From<VersionedRecipeDocument> for RecipeDocument {
@@ -52,6 +78,27 @@ impl From<VersionedRecipeDocument> for RecipeDocument {
                 notes,
                 tag_ids,
                 image_id,
+                images: vec![],
+                step_images: vec![],
+            },
+            VersionedRecipeDocument::V2 {
+                title,
+                ingredients,
+                instructions,
+                notes,
+                tag_ids,
+                image_id,
+                images,
+                step_images,
+            } => RecipeDocument {
+                title,
+                ingredients,
+                instructions,
+                notes,
+                tag_ids,
+                image_id,
+                images,
+                step_images,
             },
         }
     }
@@ -59,24 +106,38 @@ impl From<VersionedRecipeDocument> for RecipeDocument {
 
 impl From<RecipeDocument> for VersionedRecipeDocument {
     fn from(value: RecipeDocument) -> Self {
-        VersionedRecipeDocument::V1 {
+        VersionedRecipeDocument::V2 {
             title: value.title,
             ingredients: value.ingredients,
             instructions: value.instructions,
             notes: value.notes,
             tag_ids: value.tag_ids,
             image_id: value.image_id,
+            images: value.images,
+            step_images: value.step_images,
         }
     }
 }
 
 impl RecipeDocument {
+    // Every image the document refers to, which may contain duplicates.
+    #[must_use]
+    pub fn image_ids(&self) -> Vec<&domain::image::Id> {
+        self.image_id
+            .iter()
+            .chain(self.images.iter().map(|image| &image.image_id))
+            .chain(self.step_images.iter().map(|image| &image.image_id))
+            .collect()
+    }
+
     pub fn to_dumped_indexable_recipe(
         id: domain::recipe::Id,
+        household_id: domain::household::Id,
         value: RecipeDocument,
     ) -> Result<domain::DumpedIndexableRecipe, domain::ValidationError> {
         Ok(domain::DumpedIndexableRecipe {
             id,
+            household_id,
             title: value.title.try_into()?,
             ingredients: value
                 .ingredients
@@ -115,6 +176,9 @@ pub enum Error {
     #[error("invalid state when mutating record")]
     Conflict,
 
+    #[error("referenced record not found")]
+    MissingReference,
+
     #[error("no available connections")]
     NoConnections,
 
@@ -204,10 +268,10 @@ impl Pool {
         rx.await?
     }
 
-    pub async fn upsert_user_by_oauth_id(&self, user: RegisteringUser) -> Result<User, Error> {
+    pub async fn upsert_user_by_identity(&self, user: RegisteringUser) -> Result<User, Error> {
         let conn = self.conn().await?;
         let (tx, rx) = oneshot::channel();
-        let msg = Message::UpsertUserByOauthId {
+        let msg = Message::UpsertUserByIdentity {
             user,
             respond_to: tx,
         };
@@ -216,12 +280,367 @@ impl Pool {
         rx.await?
     }
 
+    // Replaces the profile of the user, returning the updated user.
+    pub async fn update_user(
+        &self,
+        id: String,
+        user: domain::user::Updating,
+    ) -> Result<User, Error> {
+        let (tx, rx) = oneshot::channel();
+        let msg = Message::UpdateUser {
+            id,
+            user,
+            respond_to: tx,
+        };
+
+        self.send_message(rx, msg).await
+    }
+
+    // Links users that are only known by their subject to the issuer, returning how many were
+    // linked.
+    pub async fn link_unlinked_users(&self, issuer: String) -> Result<usize, Error> {
+        let (tx, rx) = oneshot::channel();
+        let msg = Message::LinkUnlinkedUsers {
+            issuer,
+            respond_to: tx,
+        };
+
+        self.send_message(rx, msg).await
+    }
+
+    // Counts users that are only known by their subject.
+    pub async fn count_unlinked_users(&self) -> Result<usize, Error> {
+        let (tx, rx) = oneshot::channel();
+        let msg = Message::CountUnlinkedUsers { respond_to: tx };
+
+        self.send_message(rx, msg).await
+    }
+
+    // households
+
+    pub async fn get_active_membership(
+        &self,
+        user_id: String,
+    ) -> Result<domain::household::Membership, Error> {
+        let (tx, rx) = oneshot::channel();
+        let msg = Message::GetActiveMembership {
+            user_id,
+            respond_to: tx,
+        };
+
+        self.send_message(rx, msg).await
+    }
+
+    // Fails with NotFound unless the user is a member of the household.
+    pub async fn get_household_role(
+        &self,
+        household_id: &domain::household::Id,
+        user_id: String,
+    ) -> Result<domain::household::Role, Error> {
+        let (tx, rx) = oneshot::channel();
+        let msg = Message::GetHouseholdRole {
+            household_id: household_id.into(),
+            user_id,
+            respond_to: tx,
+        };
+
+        self.send_message(rx, msg).await
+    }
+
+    pub async fn list_households(
+        &self,
+        user_id: String,
+    ) -> Result<Vec<domain::household::Household>, Error> {
+        let (tx, rx) = oneshot::channel();
+        let msg = Message::ListHouseholds {
+            user_id,
+            respond_to: tx,
+        };
+
+        self.send_message(rx, msg).await
+    }
+
+    pub async fn create_household(
+        &self,
+        id: &domain::household::Id,
+        user_id: String,
+        name: String,
+    ) -> Result<(), Error> {
+        let (tx, rx) = oneshot::channel();
+        let msg = Message::CreateHousehold {
+            id: id.into(),
+            user_id,
+            name,
+            respond_to: tx,
+        };
+
+        self.send_message(rx, msg).await
+    }
+
+    // Fails with NotFound unless the user is a member of the household.
+    pub async fn set_active_household(
+        &self,
+        user_id: String,
+        household_id: &domain::household::Id,
+    ) -> Result<(), Error> {
+        let (tx, rx) = oneshot::channel();
+        let msg = Message::SetActiveHousehold {
+            user_id,
+            household_id: household_id.into(),
+            respond_to: tx,
+        };
+
+        self.send_message(rx, msg).await
+    }
+
+    // Fails with NotFound if the household or the user do not exist. Adding an existing member
+    // leaves their role unchanged.
+    pub async fn add_household_member(
+        &self,
+        household_id: &domain::household::Id,
+        member_id: String,
+        role: domain::household::Role,
+    ) -> Result<(), Error> {
+        let (tx, rx) = oneshot::channel();
+        let msg = Message::AddHouseholdMember {
+            household_id: household_id.into(),
+            member_id,
+            role,
+            respond_to: tx,
+        };
+
+        self.send_message(rx, msg).await
+    }
+
+    // Fails with Conflict if it would leave the household without an admin.
+    pub async fn set_household_member_role(
+        &self,
+        household_id: &domain::household::Id,
+        member_id: String,
+        role: domain::household::Role,
+    ) -> Result<(), Error> {
+        let (tx, rx) = oneshot::channel();
+        let msg = Message::SetHouseholdMemberRole {
+            household_id: household_id.into(),
+            member_id,
+            role,
+            respond_to: tx,
+        };
+
+        self.send_message(rx, msg).await
+    }
+
+    // Fails with Conflict if it is the last household of the member, since every user must be
+    // acting in a household, or if it would leave the household without an admin.
+    pub async fn remove_household_member(
+        &self,
+        household_id: &domain::household::Id,
+        member_id: String,
+    ) -> Result<(), Error> {
+        let (tx, rx) = oneshot::channel();
+        let msg = Message::RemoveHouseholdMember {
+            household_id: household_id.into(),
+            member_id,
+            respond_to: tx,
+        };
+
+        self.send_message(rx, msg).await
+    }
+
+    // tokens
+
+    pub async fn create_token(
+        &self,
+        id: &domain::token::Id,
+        user_id: String,
+        token_hash: String,
+        token: domain::token::Creating,
+    ) -> Result<(), Error> {
+        let (tx, rx) = oneshot::channel();
+        let msg = Message::CreateToken {
+            id: id.into(),
+            user_id,
+            token_hash,
+            token,
+            respond_to: tx,
+        };
+
+        self.send_message(rx, msg).await
+    }
+
+    pub async fn list_tokens(&self, user_id: String) -> Result<Vec<domain::token::Token>, Error> {
+        let (tx, rx) = oneshot::channel();
+        let msg = Message::ListTokens {
+            user_id,
+            respond_to: tx,
+        };
+
+        self.send_message(rx, msg).await
+    }
+
+    // Fails with NotFound unless the token belongs to the user.
+    pub async fn delete_token(&self, user_id: String, id: &domain::token::Id) -> Result<(), Error> {
+        let (tx, rx) = oneshot::channel();
+        let msg = Message::DeleteToken {
+            user_id,
+            id: id.into(),
+            respond_to: tx,
+        };
+
+        self.send_message(rx, msg).await
+    }
+
+    pub async fn get_token_by_hash(
+        &self,
+        token_hash: String,
+    ) -> Result<domain::token::Stored, Error> {
+        let (tx, rx) = oneshot::channel();
+        let msg = Message::GetTokenByHash {
+            token_hash,
+            respond_to: tx,
+        };
+
+        self.send_message(rx, msg).await
+    }
+
+    pub async fn touch_token(&self, id: &domain::token::Id) -> Result<(), Error> {
+        let (tx, rx) = oneshot::channel();
+        let msg = Message::TouchToken {
+            id: id.into(),
+            respond_to: tx,
+        };
+
+        self.send_message(rx, msg).await
+    }
+
+    // shares
+
+    // Fails with NotFound unless the recipe belongs to the household.
+    pub async fn create_share(
+        &self,
+        id: &domain::share::Id,
+        household_id: &domain::household::Id,
+        recipe_id: &domain::recipe::Id,
+        token_hash: String,
+        share: domain::share::Creating,
+    ) -> Result<(), Error> {
+        let (tx, rx) = oneshot::channel();
+        let msg = Message::CreateShare {
+            id: id.into(),
+            household_id: household_id.into(),
+            recipe_id: recipe_id.into(),
+            token_hash,
+            share,
+            respond_to: tx,
+        };
+
+        self.send_message(rx, msg).await
+    }
+
+    pub async fn list_shares(
+        &self,
+        household_id: &domain::household::Id,
+        recipe_id: &domain::recipe::Id,
+    ) -> Result<Vec<domain::share::Share>, Error> {
+        let (tx, rx) = oneshot::channel();
+        let msg = Message::ListShares {
+            household_id: household_id.into(),
+            recipe_id: recipe_id.into(),
+            respond_to: tx,
+        };
+
+        self.send_message(rx, msg).await
+    }
+
+    pub async fn delete_share(
+        &self,
+        household_id: &domain::household::Id,
+        recipe_id: &domain::recipe::Id,
+        id: &domain::share::Id,
+    ) -> Result<(), Error> {
+        let (tx, rx) = oneshot::channel();
+        let msg = Message::DeleteShare {
+            household_id: household_id.into(),
+            recipe_id: recipe_id.into(),
+            id: id.into(),
+            respond_to: tx,
+        };
+
+        self.send_message(rx, msg).await
+    }
+
+    // audit log
+
+    pub async fn record_audit(
+        &self,
+        id: &domain::audit::Id,
+        recording: domain::audit::Recording,
+    ) -> Result<(), Error> {
+        let (tx, rx) = oneshot::channel();
+        let msg = Message::RecordAudit {
+            id: id.into(),
+            recording,
+            respond_to: tx,
+        };
+
+        self.send_message(rx, msg).await
+    }
+
+    pub async fn list_audit(
+        &self,
+        household_id: &domain::household::Id,
+        filter: domain::audit::Filter,
+        limit: u32,
+    ) -> Result<Vec<domain::audit::Entry>, Error> {
+        let (tx, rx) = oneshot::channel();
+        let msg = Message::ListAudit {
+            household_id: household_id.into(),
+            filter,
+            limit,
+            respond_to: tx,
+        };
+
+        self.send_message(rx, msg).await
+    }
+
+    // Deletes entries that occurred before the given time, returning how many were deleted.
+    pub async fn prune_audit(&self, before: chrono::DateTime<chrono::Utc>) -> Result<usize, Error> {
+        let (tx, rx) = oneshot::channel();
+        let msg = Message::PruneAudit {
+            before,
+            respond_to: tx,
+        };
+
+        self.send_message(rx, msg).await
+    }
+
+    pub async fn get_share_by_hash(
+        &self,
+        token_hash: String,
+    ) -> Result<domain::share::Stored, Error> {
+        let (tx, rx) = oneshot::channel();
+        let msg = Message::GetShareByHash {
+            token_hash,
+            respond_to: tx,
+        };
+
+        self.send_message(rx, msg).await
+    }
+
     // recipe
 
-    pub async fn get_recipe(&self, id: String) -> Result<domain::Recipe, Error> {
+    pub async fn get_recipe(
+        &self,
+        household_id: &domain::household::Id,
+        id: String,
+    ) -> Result<domain::Recipe, Error> {
         let conn = self.conn().await?;
         let (tx, rx) = oneshot::channel();
-        let msg = Message::GetRecipe { id, respond_to: tx };
+        let msg = Message::GetRecipe {
+            household_id: household_id.into(),
+            id,
+            respond_to: tx,
+        };
 
         let _ = conn.sender.send(msg);
         rx.await?
@@ -229,6 +648,7 @@ impl Pool {
 
     pub async fn create_recipe(
         &self,
+        household_id: &domain::household::Id,
         id: String,
         user_id: String,
         recipe: RecipeDocument,
@@ -236,6 +656,7 @@ impl Pool {
         let conn = self.conn().await?;
         let (tx, rx) = oneshot::channel();
         let msg = Message::CreateRecipe {
+            household_id: household_id.into(),
             id,
             user_id,
             recipe,
@@ -248,6 +669,7 @@ impl Pool {
 
     pub async fn update_recipe(
         &self,
+        household_id: &domain::household::Id,
         id: String,
         user_id: String,
         recipe: RecipeDocument,
@@ -256,6 +678,7 @@ impl Pool {
         let conn = self.conn().await?;
         let (tx, rx) = oneshot::channel();
         let msg = Message::UpdateRecipe {
+            household_id: household_id.into(),
             id,
             user_id,
             recipe,
@@ -269,11 +692,13 @@ impl Pool {
 
     pub async fn list_recipes(
         &self,
+        household_id: &domain::household::Id,
         filter: domain::filter::Recipe,
         cursor: Option<domain::page::cursor::Recipe>,
     ) -> Result<domain::page::Recipe, Error> {
         let (tx, rx) = oneshot::channel();
         let msg = Message::ListRecipes {
+            household_id: household_id.into(),
             filter,
             cursor,
             respond_to: tx,
@@ -284,11 +709,13 @@ impl Pool {
 
     pub async fn get_recipe_revisions(
         &self,
+        household_id: &domain::household::Id,
         recipe_id: String,
     ) -> Result<Vec<RecipeRevision>, Error> {
         let conn = self.conn().await?;
         let (tx, rx) = oneshot::channel();
         let msg = Message::GetRevisions {
+            household_id: household_id.into(),
             recipe_id,
             respond_to: tx,
         };
@@ -299,12 +726,14 @@ impl Pool {
 
     pub async fn get_recipe_revision(
         &self,
+        household_id: &domain::household::Id,
         recipe_id: String,
         revision: usize,
     ) -> Result<domain::Recipe, Error> {
         let conn = self.conn().await?;
         let (tx, rx) = oneshot::channel();
         let msg = Message::GetRevision {
+            household_id: household_id.into(),
             recipe_id,
             revision,
             respond_to: tx,
@@ -314,6 +743,8 @@ impl Pool {
         rx.await?
     }
 
+    // Dumps the recipes of every household, the index keeps track of which household each
+    // recipe belongs to.
     pub async fn dump_recipes_for_index(
         &self,
         cursor: Option<domain::page::cursor::DumpedIndexableRecipe>,
@@ -330,11 +761,13 @@ impl Pool {
     // tags
     pub async fn create_tag(
         &self,
+        household_id: &domain::household::Id,
         user_id: String,
         name: String,
     ) -> Result<domain::tag::Id, Error> {
         let (tx, rx) = oneshot::channel();
         let msg = Message::CreateTag {
+            household_id: household_id.into(),
             user_id,
             name,
             respond_to: tx,
@@ -343,27 +776,134 @@ impl Pool {
         self.send_message(rx, msg).await
     }
 
-    pub async fn get_tags(&self) -> Result<Vec<domain::tag::Tag>, Error> {
+    pub async fn get_tags(
+        &self,
+        household_id: &domain::household::Id,
+    ) -> Result<Vec<domain::tag::Tag>, Error> {
         let (tx, rx) = oneshot::channel();
-        let msg = Message::GetTags { respond_to: tx };
+        let msg = Message::GetTags {
+            household_id: household_id.into(),
+            respond_to: tx,
+        };
 
         self.send_message(rx, msg).await
     }
 
     // images
-    pub async fn create_image(&self, id: &domain::image::Id) -> Result<(), Error> {
+    // Returns the id of the image that owns the content, which is not `id` if an image with the
+    // same content hash already exists in the household.
+    pub async fn create_image(
+        &self,
+        household_id: &domain::household::Id,
+        id: &domain::image::Id,
+        content_hash: &str,
+    ) -> Result<domain::image::Id, Error> {
         let (tx, rx) = oneshot::channel();
         let msg = Message::CreateImage {
+            household_id: household_id.into(),
             id: id.into(),
+            content_hash: content_hash.to_owned(),
             respond_to: tx,
         };
 
         self.send_message(rx, msg).await
     }
 
-    pub async fn get_image(&self, id: &domain::image::Id) -> Result<(), Error> {
+    pub async fn get_image(
+        &self,
+        household_id: &domain::household::Id,
+        id: &domain::image::Id,
+    ) -> Result<(), Error> {
         let (tx, rx) = oneshot::channel();
         let msg = Message::GetImage {
+            household_id: household_id.into(),
+            id: id.into(),
+            respond_to: tx,
+        };
+
+        self.send_message(rx, msg).await
+    }
+
+    pub async fn get_image_by_content_hash(
+        &self,
+        household_id: &domain::household::Id,
+        content_hash: &str,
+    ) -> Result<domain::image::Id, Error> {
+        let (tx, rx) = oneshot::channel();
+        let msg = Message::GetImageByContentHash {
+            household_id: household_id.into(),
+            content_hash: content_hash.to_owned(),
+            respond_to: tx,
+        };
+
+        self.send_message(rx, msg).await
+    }
+
+    // Lists the images of every household, for maintenance across the whole store.
+    pub async fn list_images(
+        &self,
+        cursor: Option<domain::page::cursor::Image>,
+    ) -> Result<domain::page::Image, Error> {
+        let (tx, rx) = oneshot::channel();
+        let msg = Message::ListImages {
+            cursor,
+            respond_to: tx,
+        };
+
+        self.send_message(rx, msg).await
+    }
+
+    pub async fn count_images(&self) -> Result<u64, Error> {
+        let (tx, rx) = oneshot::channel();
+        let msg = Message::CountImages { respond_to: tx };
+
+        self.send_message(rx, msg).await
+    }
+
+    // The framing of an image where a recipe uses it.
+    pub async fn get_image_framing(
+        &self,
+        household_id: &domain::household::Id,
+        recipe_id: &domain::recipe::Id,
+        id: &domain::image::Id,
+    ) -> Result<domain::image::Framing, Error> {
+        let (tx, rx) = oneshot::channel();
+        let msg = Message::GetImageFraming {
+            household_id: household_id.into(),
+            recipe_id: recipe_id.into(),
+            id: id.into(),
+            respond_to: tx,
+        };
+
+        self.send_message(rx, msg).await
+    }
+
+    pub async fn set_image_framing(
+        &self,
+        household_id: &domain::household::Id,
+        recipe_id: &domain::recipe::Id,
+        id: &domain::image::Id,
+        framing: domain::image::Framing,
+    ) -> Result<(), Error> {
+        let (tx, rx) = oneshot::channel();
+        let msg = Message::SetImageFraming {
+            household_id: household_id.into(),
+            recipe_id: recipe_id.into(),
+            id: id.into(),
+            framing,
+            respond_to: tx,
+        };
+
+        self.send_message(rx, msg).await
+    }
+
+    // Every stored framing of an image, by any recipe using it.
+    pub async fn list_image_framings(
+        &self,
+        id: &domain::image::Id,
+    ) -> Result<Vec<domain::image::Framing>, Error> {
+        let (tx, rx) = oneshot::channel();
+        let msg = Message::ListImageFramings {
             id: id.into(),
             respond_to: tx,
         };
@@ -392,28 +932,153 @@ pub enum Message {
         id: String,
         respond_to: oneshot::Sender<Result<User, Error>>,
     },
-    UpsertUserByOauthId {
+    UpdateUser {
+        id: String,
+        user: domain::user::Updating,
+        respond_to: oneshot::Sender<Result<User, Error>>,
+    },
+    UpsertUserByIdentity {
         user: RegisteringUser,
         respond_to: oneshot::Sender<Result<User, Error>>,
     },
+    LinkUnlinkedUsers {
+        issuer: String,
+        respond_to: oneshot::Sender<Result<usize, Error>>,
+    },
+    CountUnlinkedUsers {
+        respond_to: oneshot::Sender<Result<usize, Error>>,
+    },
+
+    // households
+    GetActiveMembership {
+        user_id: String,
+        respond_to: oneshot::Sender<Result<domain::household::Membership, Error>>,
+    },
+    GetHouseholdRole {
+        household_id: String,
+        user_id: String,
+        respond_to: oneshot::Sender<Result<domain::household::Role, Error>>,
+    },
+    ListHouseholds {
+        user_id: String,
+        respond_to: oneshot::Sender<Result<Vec<domain::household::Household>, Error>>,
+    },
+    CreateHousehold {
+        id: String,
+        user_id: String,
+        name: String,
+        respond_to: oneshot::Sender<Result<(), Error>>,
+    },
+    SetActiveHousehold {
+        user_id: String,
+        household_id: String,
+        respond_to: oneshot::Sender<Result<(), Error>>,
+    },
+    AddHouseholdMember {
+        household_id: String,
+        member_id: String,
+        role: domain::household::Role,
+        respond_to: oneshot::Sender<Result<(), Error>>,
+    },
+    SetHouseholdMemberRole {
+        household_id: String,
+        member_id: String,
+        role: domain::household::Role,
+        respond_to: oneshot::Sender<Result<(), Error>>,
+    },
+    RemoveHouseholdMember {
+        household_id: String,
+        member_id: String,
+        respond_to: oneshot::Sender<Result<(), Error>>,
+    },
+
+    // tokens
+    CreateToken {
+        id: String,
+        user_id: String,
+        token_hash: String,
+        token: domain::token::Creating,
+        respond_to: oneshot::Sender<Result<(), Error>>,
+    },
+    ListTokens {
+        user_id: String,
+        respond_to: oneshot::Sender<Result<Vec<domain::token::Token>, Error>>,
+    },
+    DeleteToken {
+        user_id: String,
+        id: String,
+        respond_to: oneshot::Sender<Result<(), Error>>,
+    },
+    GetTokenByHash {
+        token_hash: String,
+        respond_to: oneshot::Sender<Result<domain::token::Stored, Error>>,
+    },
+    TouchToken {
+        id: String,
+        respond_to: oneshot::Sender<Result<(), Error>>,
+    },
+
+    // shares
+    CreateShare {
+        id: String,
+        household_id: String,
+        recipe_id: String,
+        token_hash: String,
+        share: domain::share::Creating,
+        respond_to: oneshot::Sender<Result<(), Error>>,
+    },
+    RecordAudit {
+        id: String,
+        recording: domain::audit::Recording,
+        respond_to: oneshot::Sender<Result<(), Error>>,
+    },
+    ListAudit {
+        household_id: String,
+        filter: domain::audit::Filter,
+        limit: u32,
+        respond_to: oneshot::Sender<Result<Vec<domain::audit::Entry>, Error>>,
+    },
+    PruneAudit {
+        before: chrono::DateTime<chrono::Utc>,
+        respond_to: oneshot::Sender<Result<usize, Error>>,
+    },
+    ListShares {
+        household_id: String,
+        recipe_id: String,
+        respond_to: oneshot::Sender<Result<Vec<domain::share::Share>, Error>>,
+    },
+    DeleteShare {
+        household_id: String,
+        recipe_id: String,
+        id: String,
+        respond_to: oneshot::Sender<Result<(), Error>>,
+    },
+    GetShareByHash {
+        token_hash: String,
+        respond_to: oneshot::Sender<Result<domain::share::Stored, Error>>,
+    },
 
     // recipe
     GetRecipe {
+        household_id: String,
         id: String,
         respond_to: oneshot::Sender<Result<Recipe, Error>>,
     },
     ListRecipes {
+        household_id: String,
         filter: domain::filter::Recipe,
         cursor: Option<domain::page::cursor::Recipe>,
         respond_to: oneshot::Sender<Result<domain::page::Recipe, Error>>,
     },
     CreateRecipe {
+        household_id: String,
         id: String,
         user_id: String,
         recipe: RecipeDocument,
         respond_to: oneshot::Sender<Result<(), Error>>,
     },
     UpdateRecipe {
+        household_id: String,
         id: String,
         user_id: String,
         recipe: RecipeDocument,
@@ -421,10 +1086,12 @@ pub enum Message {
         respond_to: oneshot::Sender<Result<(), Error>>,
     },
     GetRevisions {
+        household_id: String,
         recipe_id: String,
         respond_to: oneshot::Sender<Result<Vec<RecipeRevision>, Error>>,
     },
     GetRevision {
+        household_id: String,
         recipe_id: String,
         revision: usize,
         respond_to: oneshot::Sender<Result<Recipe, Error>>,
@@ -436,9 +1103,11 @@ pub enum Message {
 
     // tags
     GetTags {
+        household_id: String,
         respond_to: oneshot::Sender<Result<Vec<domain::tag::Tag>, Error>>,
     },
     CreateTag {
+        household_id: String,
         user_id: String,
         name: String,
         respond_to: oneshot::Sender<Result<domain::tag::Id, Error>>,
@@ -446,11 +1115,43 @@ pub enum Message {
 
     // images
     CreateImage {
+        household_id: String,
         id: String,
-        respond_to: oneshot::Sender<Result<(), Error>>,
+        content_hash: String,
+        respond_to: oneshot::Sender<Result<domain::image::Id, Error>>,
     },
     GetImage {
+        household_id: String,
         id: String,
         respond_to: oneshot::Sender<Result<(), Error>>,
     },
+    GetImageByContentHash {
+        household_id: String,
+        content_hash: String,
+        respond_to: oneshot::Sender<Result<domain::image::Id, Error>>,
+    },
+    ListImages {
+        cursor: Option<domain::page::cursor::Image>,
+        respond_to: oneshot::Sender<Result<domain::page::Image, Error>>,
+    },
+    CountImages {
+        respond_to: oneshot::Sender<Result<u64, Error>>,
+    },
+    GetImageFraming {
+        household_id: String,
+        recipe_id: String,
+        id: String,
+        respond_to: oneshot::Sender<Result<domain::image::Framing, Error>>,
+    },
+    SetImageFraming {
+        household_id: String,
+        recipe_id: String,
+        id: String,
+        framing: domain::image::Framing,
+        respond_to: oneshot::Sender<Result<(), Error>>,
+    },
+    ListImageFramings {
+        id: String,
+        respond_to: oneshot::Sender<Result<Vec<domain::image::Framing>, Error>>,
+    },
 }
@@ -5,15 +5,21 @@ use serde::{Deserialize, Serialize};
 #[derive(Debug, Clone)]
 pub struct User {
     pub id: String,
-    pub oauth_id: String,
+    // the display name chosen by the user, otherwise the name given by their provider
     pub name: String,
+    pub avatar_image_id: Option<String>,
+    pub preferences: user::Preferences,
 }
 
 #[derive(Clone)]
 pub struct RegisteringUser {
     pub potential_id: String,
-    pub oauth_id: String,
+    // users are known by the issuer of their provider and their subject there
+    pub issuer: String,
+    pub subject: String,
     pub name: String,
+    // the most the user may do in any household, not limited when None
+    pub max_role: Option<household::Role>,
 }
 
 #[derive(Debug, Clone)]
@@ -36,10 +42,33 @@ impl From<SessionKey> for String {
 #[derive(Clone, Serialize, Deserialize)]
 pub struct Session {
     pub key: String,
+    // stays the same when the key is replaced on refresh, so a session can be referred to
+    // without exposing its key
+    pub id: String,
     pub user_id: String,
+    // name of the OIDC provider the session is refreshed with
+    pub provider: String,
     pub refresh_token: String,
     pub revalidate_at: chrono::DateTime<chrono::Utc>,
     pub expires_at: chrono::DateTime<chrono::Utc>,
+    pub created_at: chrono::DateTime<chrono::Utc>,
+    pub last_used_at: chrono::DateTime<chrono::Utc>,
+    pub user_agent: Option<String>,
+    // approximate network the session was started from
+    pub client: Option<String>,
+    // household the session acts in, None for sessions started before households were kept per
+    // session, which act in the user's default household
+    pub household_id: Option<String>,
+}
+
+// A session as shown to the user it belongs to.
+#[derive(Debug, Clone)]
+pub struct SessionInfo {
+    pub id: String,
+    pub created_at: chrono::DateTime<chrono::Utc>,
+    pub last_used_at: chrono::DateTime<chrono::Utc>,
+    pub user_agent: Option<String>,
+    pub client: Option<String>,
 }
 
 pub enum SessionStatus {
@@ -72,6 +101,7 @@ pub struct RecipeRevision {
 pub struct CreatingRecipe {
     pub title: recipe::Title,
     pub image_id: Option<image::Id>,
+    pub images: Vec<recipe::Image>,
     pub ingredients: Vec<recipe::IngredientBlock>,
     pub instructions: Vec<recipe::InstructionBlock>,
     pub notes: Option<recipe::Notes>,
@@ -85,6 +115,7 @@ pub struct UpdatingRecipe {
 
     pub title: recipe::Title,
     pub image_id: Option<image::Id>,
+    pub images: Vec<recipe::Image>,
     pub ingredients: Vec<recipe::IngredientBlock>,
     pub instructions: Vec<recipe::InstructionBlock>,
     pub notes: Option<recipe::Notes>,
@@ -97,6 +128,7 @@ pub struct Recipe {
     pub hash: String,
     pub title: recipe::Title,
     pub image_id: Option<image::Id>,
+    pub images: Vec<recipe::Image>,
     pub ingredients: Vec<recipe::IngredientBlock>,
     pub instructions: Vec<recipe::InstructionBlock>,
     pub notes: Option<recipe::Notes>,
@@ -113,6 +145,7 @@ pub struct ListedRecipe {
 #[derive(Debug, Clone)]
 pub struct DumpedIndexableRecipe {
     pub id: recipe::Id,
+    pub household_id: household::Id,
     pub title: recipe::Title,
     pub ingredients: Vec<recipe::IngredientBlock>,
     pub instructions: Vec<recipe::InstructionBlock>,
@@ -151,6 +184,12 @@ pub mod page {
         pub next: Option<cursor::DumpedIndexableRecipe>,
     }
 
+    #[derive(Debug, Clone)]
+    pub struct Image {
+        pub items: Vec<super::image::Stored>,
+        pub next: Option<cursor::Image>,
+    }
+
     pub mod cursor {
         use serde::{Deserialize, Serialize};
 
@@ -164,6 +203,11 @@ pub mod page {
         pub struct DumpedIndexableRecipe {
             pub id: String,
         }
+
+        #[derive(Debug, Clone)]
+        pub struct Image {
+            pub id: String,
+        }
     }
 }
 
@@ -245,9 +289,109 @@ mod id {
 }
 
 pub mod user {
+    use serde::{Deserialize, Serialize};
+
+    use super::ValidationError;
+
     #[derive(Debug, Clone)]
     pub struct Authenticated {
         pub id: String,
+        // the household the user is acting in, which everything they do is scoped to
+        pub household_id: super::household::Id,
+        // the role of the user in that household
+        pub role: super::household::Role,
+    }
+
+    // Settings that only apply to the user, in every household they are in.
+    #[derive(Debug, Clone, Default, PartialEq, Eq)]
+    pub struct Preferences {
+        pub units: Option<Units>,
+        pub default_servings: Option<Servings>,
+    }
+
+    // A user updating their own profile. Each field replaces the previous value, a name of None
+    // goes back to the name given by their provider.
+    #[derive(Debug, Clone)]
+    pub struct Updating {
+        pub name: Option<Name>,
+        pub avatar_image_id: Option<super::image::Id>,
+        pub preferences: Preferences,
+    }
+
+    #[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+    #[serde(rename_all = "lowercase")]
+    pub enum Units {
+        Metric,
+        Imperial,
+    }
+
+    impl Units {
+        #[must_use]
+        pub fn as_str(&self) -> &'static str {
+            match self {
+                Units::Metric => "metric",
+                Units::Imperial => "imperial",
+            }
+        }
+    }
+
+    impl TryFrom<&str> for Units {
+        type Error = ValidationError;
+        fn try_from(value: &str) -> Result<Self, Self::Error> {
+            match value {
+                "metric" => Ok(Units::Metric),
+                "imperial" => Ok(Units::Imperial),
+                _ => Err(ValidationError::Constraint(format!(
+                    r#"Units "{value}" must be one of metric or imperial."#
+                ))),
+            }
+        }
+    }
+
+    #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+    pub struct Servings(u32);
+
+    impl TryFrom<u32> for Servings {
+        type Error = ValidationError;
+        fn try_from(value: u32) -> Result<Self, Self::Error> {
+            if (1..=100).contains(&value) {
+                Ok(Servings(value))
+            } else {
+                Err(ValidationError::Constraint(format!(
+                    "Default servings {value} must be between 1 and 100."
+                )))
+            }
+        }
+    }
+
+    impl From<Servings> for u32 {
+        fn from(value: Servings) -> Self {
+            value.0
+        }
+    }
+
+    #[derive(Debug, Clone)]
+    pub struct Name(String);
+
+    impl TryFrom<String> for Name {
+        type Error = ValidationError;
+        fn try_from(value: String) -> Result<Self, Self::Error> {
+            let trimmed = value.trim();
+            let char_count = trimmed.chars().count();
+            if char_count < 1 {
+                Err(ValidationError::Constraint(format!(
+                    r#"Name "{value}" must contain at least one character."#
+                )))
+            } else {
+                Ok(Name(trimmed.to_string()))
+            }
+        }
+    }
+
+    impl From<Name> for String {
+        fn from(value: Name) -> Self {
+            value.0
+        }
     }
 
     pub use super::id::Id;
@@ -261,7 +405,7 @@ pub mod recipe {
 
     use crate::required_and_trimmed_string;
 
-    use super::ValidationError;
+    use super::{ValidationError, image};
 
     pub use super::id::Id;
 
@@ -400,6 +544,39 @@ pub mod recipe {
     pub struct InstructionBlock {
         pub title: Option<InstructionBlockTitle>,
         pub instructions: Vec<Instruction>,
+        pub step_images: Vec<StepImage>,
+    }
+
+    // An image shown beside one instruction of a block.
+    #[derive(Debug, Clone)]
+    pub struct StepImage {
+        // index of the instruction within its block
+        pub step: usize,
+        pub image_id: image::Id,
+    }
+
+    impl InstructionBlock {
+        // Step images must point at an instruction in the block, with at most one image per step.
+        pub fn validate_step_images(&self) -> Result<(), ValidationError> {
+            let mut steps = std::collections::HashSet::new();
+            for step_image in &self.step_images {
+                if step_image.step >= self.instructions.len() {
+                    return Err(ValidationError::Constraint(format!(
+                        "Step image is attached to step {}, but the block only has {} steps.",
+                        step_image.step + 1,
+                        self.instructions.len()
+                    )));
+                }
+                if !steps.insert(step_image.step) {
+                    return Err(ValidationError::Constraint(format!(
+                        "Step {} has more than one image.",
+                        step_image.step + 1
+                    )));
+                }
+            }
+
+            Ok(())
+        }
     }
 
     impl TryFrom<StringifiedBlock> for InstructionBlock {
@@ -415,10 +592,18 @@ pub mod recipe {
                     .into_iter()
                     .map(Instruction::try_from)
                     .collect::<Result<Vec<Instruction>, ValidationError>>()?,
+                step_images: vec![],
             })
         }
     }
 
+    // An image in a recipe's gallery.
+    #[derive(Debug, Clone)]
+    pub struct Image {
+        pub id: image::Id,
+        pub caption: Option<ImageCaption>,
+    }
+
     impl From<InstructionBlock> for StringifiedBlock {
         fn from(value: InstructionBlock) -> Self {
             StringifiedBlock {
@@ -443,6 +628,7 @@ pub mod recipe {
 
     required_and_trimmed_string!(IngredientBlockTitle);
     required_and_trimmed_string!(Ingredient);
+    required_and_trimmed_string!(ImageCaption);
 }
 
 mod common {
@@ -523,6 +709,534 @@ pub mod tag {
     }
 }
 
+pub mod household {
+    use serde::{Deserialize, Serialize};
+
+    use super::ValidationError;
+
+    pub use super::id::Id;
+
+    #[derive(Debug, Clone)]
+    pub struct Household {
+        pub id: Id,
+        pub name: Name,
+        // the role of the user in the household
+        pub role: Role,
+        // whether this is the household the user is currently acting in
+        pub active: bool,
+    }
+
+    // The household a user is acting in, and what they are allowed to do there.
+    #[derive(Debug, Clone)]
+    pub struct Membership {
+        pub household_id: Id,
+        pub role: Role,
+    }
+
+    // Ordered from least to most privileged, every role can do what the ones before it can.
+    #[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+    #[serde(rename_all = "lowercase")]
+    pub enum Role {
+        // can browse recipes, tags and images
+        Viewer,
+        // can also create and update recipes, tags and images
+        Editor,
+        // can also manage the members of the household
+        Admin,
+    }
+
+    impl Role {
+        #[must_use]
+        pub fn as_str(&self) -> &'static str {
+            match self {
+                Role::Viewer => "viewer",
+                Role::Editor => "editor",
+                Role::Admin => "admin",
+            }
+        }
+    }
+
+    impl TryFrom<&str> for Role {
+        type Error = ValidationError;
+        fn try_from(value: &str) -> Result<Self, Self::Error> {
+            match value {
+                "viewer" => Ok(Role::Viewer),
+                "editor" => Ok(Role::Editor),
+                "admin" => Ok(Role::Admin),
+                _ => Err(ValidationError::Constraint(format!(
+                    r#"Role "{value}" must be one of viewer, editor or admin."#
+                ))),
+            }
+        }
+    }
+
+    #[derive(Debug, Clone)]
+    pub struct Creating {
+        pub name: Name,
+    }
+
+    #[derive(Debug, Clone)]
+    pub struct Name(String);
+
+    impl TryFrom<String> for Name {
+        type Error = ValidationError;
+        fn try_from(value: String) -> Result<Self, Self::Error> {
+            let trimmed = value.trim();
+            let char_count = trimmed.chars().count();
+            if char_count < 1 {
+                Err(ValidationError::Constraint(format!(
+                    r#"Household "{value}" must contain at least one character."#
+                )))
+            } else {
+                Ok(Name(trimmed.to_string()))
+            }
+        }
+    }
+
+    impl From<Name> for String {
+        fn from(value: Name) -> Self {
+            value.0
+        }
+    }
+}
+
+pub mod token {
+    use super::{ValidationError, household};
+
+    pub use super::id::Id;
+
+    // A personal access token as shown to its owner, the token itself is only shown once.
+    #[derive(Debug, Clone)]
+    pub struct Token {
+        pub id: Id,
+        pub name: Name,
+        // only allows requests that do not change anything
+        pub read_only: bool,
+        pub expires_at: Option<chrono::DateTime<chrono::Utc>>,
+        pub created_at: chrono::DateTime<chrono::Utc>,
+        pub last_used_at: Option<chrono::DateTime<chrono::Utc>>,
+        // the household the token acts in
+        pub household_id: household::Id,
+    }
+
+    #[derive(Debug, Clone)]
+    pub struct Creating {
+        pub name: Name,
+        pub read_only: bool,
+        pub expires_at: Option<chrono::DateTime<chrono::Utc>>,
+        // tokens act in the household they are created in, whichever the user switches to later
+        pub household_id: household::Id,
+    }
+
+    // A stored token found by its hash.
+    #[derive(Debug, Clone)]
+    pub struct Stored {
+        pub id: Id,
+        pub user_id: String,
+        pub household_id: household::Id,
+        pub read_only: bool,
+        pub expires_at: Option<chrono::DateTime<chrono::Utc>>,
+        pub last_used_at: Option<chrono::DateTime<chrono::Utc>>,
+    }
+
+    #[derive(Debug, Clone)]
+    pub struct Name(String);
+
+    impl TryFrom<String> for Name {
+        type Error = ValidationError;
+        fn try_from(value: String) -> Result<Self, Self::Error> {
+            let trimmed = value.trim();
+            let char_count = trimmed.chars().count();
+            if char_count < 1 {
+                Err(ValidationError::Constraint(format!(
+                    r#"Token name "{value}" must contain at least one character."#
+                )))
+            } else {
+                Ok(Name(trimmed.to_string()))
+            }
+        }
+    }
+
+    impl From<Name> for String {
+        fn from(value: Name) -> Self {
+            value.0
+        }
+    }
+}
+
+pub mod share {
+    pub use super::id::Id;
+
+    // A public link to a recipe as shown to the household, the token in it is only shown once.
+    #[derive(Debug, Clone)]
+    pub struct Share {
+        pub id: Id,
+        pub recipe_id: super::recipe::Id,
+        pub expires_at: Option<chrono::DateTime<chrono::Utc>>,
+        pub created_at: chrono::DateTime<chrono::Utc>,
+    }
+
+    #[derive(Debug, Clone)]
+    pub struct Creating {
+        pub expires_at: Option<chrono::DateTime<chrono::Utc>>,
+    }
+
+    // A stored share found by the hash of its token.
+    #[derive(Debug, Clone)]
+    pub struct Stored {
+        pub id: Id,
+        pub household_id: super::household::Id,
+        pub recipe_id: super::recipe::Id,
+        pub expires_at: Option<chrono::DateTime<chrono::Utc>>,
+    }
+}
+
+pub mod audit {
+    use serde::{Deserialize, Serialize};
+
+    use super::ValidationError;
+
+    pub use super::id::Id;
+
+    // Something a user did, as recorded in the audit log.
+    #[derive(Debug, Clone)]
+    pub struct Entry {
+        pub id: Id,
+        pub occurred_at: chrono::DateTime<chrono::Utc>,
+        pub user_id: String,
+        pub household_id: Option<super::household::Id>,
+        pub action: Action,
+        pub entity_id: String,
+        pub detail: Option<String>,
+    }
+
+    #[derive(Debug, Clone)]
+    pub struct Recording {
+        pub user_id: String,
+        // None for what is not about one household, such as signing in
+        pub household_id: Option<super::household::Id>,
+        pub action: Action,
+        // the id of the entity of the action
+        pub entity_id: String,
+        // such as the provider signed in with, or the role given to a member
+        pub detail: Option<String>,
+    }
+
+    // Which entries to list, newest first.
+    #[derive(Debug, Clone, Default)]
+    pub struct Filter {
+        pub user_id: Option<String>,
+        pub entity: Option<Entity>,
+        pub entity_id: Option<String>,
+        pub from: Option<chrono::DateTime<chrono::Utc>>,
+        pub to: Option<chrono::DateTime<chrono::Utc>>,
+    }
+
+    #[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+    #[serde(rename_all = "snake_case")]
+    pub enum Entity {
+        User,
+        Session,
+        Token,
+        Household,
+        Recipe,
+        Share,
+        Tag,
+        Image,
+    }
+
+    impl Entity {
+        #[must_use]
+        pub fn as_str(&self) -> &'static str {
+            match self {
+                Entity::User => "user",
+                Entity::Session => "session",
+                Entity::Token => "token",
+                Entity::Household => "household",
+                Entity::Recipe => "recipe",
+                Entity::Share => "share",
+                Entity::Tag => "tag",
+                Entity::Image => "image",
+            }
+        }
+    }
+
+    #[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+    #[serde(rename_all = "snake_case")]
+    pub enum Action {
+        SignIn,
+        SignOut,
+        // the provider no longer accepted the session, so the user was signed out
+        RefreshFailed,
+        UpdateProfile,
+        RevokeSession,
+        RevokeOtherSessions,
+        CreateToken,
+        RevokeToken,
+        CreateHousehold,
+        ActivateHousehold,
+        AddMember,
+        SetMemberRole,
+        RemoveMember,
+        CreateRecipe,
+        UpdateRecipe,
+        CreateShare,
+        RevokeShare,
+        CreateTag,
+        UploadImage,
+        ImportImage,
+        UpdateImageFraming,
+    }
+
+    const ACTIONS: [Action; 21] = [
+        Action::SignIn,
+        Action::SignOut,
+        Action::RefreshFailed,
+        Action::UpdateProfile,
+        Action::RevokeSession,
+        Action::RevokeOtherSessions,
+        Action::CreateToken,
+        Action::RevokeToken,
+        Action::CreateHousehold,
+        Action::ActivateHousehold,
+        Action::AddMember,
+        Action::SetMemberRole,
+        Action::RemoveMember,
+        Action::CreateRecipe,
+        Action::UpdateRecipe,
+        Action::CreateShare,
+        Action::RevokeShare,
+        Action::CreateTag,
+        Action::UploadImage,
+        Action::ImportImage,
+        Action::UpdateImageFraming,
+    ];
+
+    impl Action {
+        #[must_use]
+        pub fn as_str(&self) -> &'static str {
+            match self {
+                Action::SignIn => "sign_in",
+                Action::SignOut => "sign_out",
+                Action::RefreshFailed => "refresh_failed",
+                Action::UpdateProfile => "update_profile",
+                Action::RevokeSession => "revoke_session",
+                Action::RevokeOtherSessions => "revoke_other_sessions",
+                Action::CreateToken => "create_token",
+                Action::RevokeToken => "revoke_token",
+                Action::CreateHousehold => "create_household",
+                Action::ActivateHousehold => "activate_household",
+                Action::AddMember => "add_member",
+                Action::SetMemberRole => "set_member_role",
+                Action::RemoveMember => "remove_member",
+                Action::CreateRecipe => "create_recipe",
+                Action::UpdateRecipe => "update_recipe",
+                Action::CreateShare => "create_share",
+                Action::RevokeShare => "revoke_share",
+                Action::CreateTag => "create_tag",
+                Action::UploadImage => "upload_image",
+                Action::ImportImage => "import_image",
+                Action::UpdateImageFraming => "update_image_framing",
+            }
+        }
+
+        // The kind of entity the id recorded with the action refers to.
+        #[must_use]
+        pub fn entity(&self) -> Entity {
+            match self {
+                Action::SignIn
+                | Action::SignOut
+                | Action::RefreshFailed
+                | Action::UpdateProfile
+                | Action::RevokeOtherSessions
+                | Action::AddMember
+                | Action::SetMemberRole
+                | Action::RemoveMember => Entity::User,
+                Action::RevokeSession => Entity::Session,
+                Action::CreateToken | Action::RevokeToken => Entity::Token,
+                Action::CreateHousehold | Action::ActivateHousehold => Entity::Household,
+                Action::CreateRecipe | Action::UpdateRecipe => Entity::Recipe,
+                Action::CreateShare | Action::RevokeShare => Entity::Share,
+                Action::CreateTag => Entity::Tag,
+                Action::UploadImage | Action::ImportImage | Action::UpdateImageFraming => {
+                    Entity::Image
+                }
+            }
+        }
+    }
+
+    impl TryFrom<&str> for Action {
+        type Error = ValidationError;
+        fn try_from(value: &str) -> Result<Self, Self::Error> {
+            ACTIONS
+                .into_iter()
+                .find(|action| action.as_str() == value)
+                .ok_or_else(|| {
+                    ValidationError::Constraint(format!(r#"Unknown audit action "{value}"."#))
+                })
+        }
+    }
+}
+
 pub mod image {
+    use super::ValidationError;
+
     pub use super::id::Id;
+
+    // An image along with the household it belongs to.
+    #[derive(Debug, Clone)]
+    pub struct Stored {
+        pub id: Id,
+        pub household_id: super::household::Id,
+    }
+
+    // A rectangle within an image, as fractions of the image's width and height.
+    #[derive(Debug, Clone, Copy, PartialEq)]
+    pub struct Crop {
+        x: f64,
+        y: f64,
+        width: f64,
+        height: f64,
+    }
+
+    impl Crop {
+        pub fn new(x: f64, y: f64, width: f64, height: f64) -> Result<Self, ValidationError> {
+            let is_fraction = |value: f64| (0.0..=1.0).contains(&value);
+            if !is_fraction(x)
+                || !is_fraction(y)
+                || !is_fraction(width)
+                || !is_fraction(height)
+                || width <= 0.0
+                || height <= 0.0
+                || x + width > 1.0
+                || y + height > 1.0
+            {
+                return Err(ValidationError::Constraint(
+                    "Crop must be a non-empty rectangle within the image.".into(),
+                ));
+            }
+
+            Ok(Crop {
+                x,
+                y,
+                width,
+                height,
+            })
+        }
+
+        #[must_use]
+        pub fn x(&self) -> f64 {
+            self.x
+        }
+
+        #[must_use]
+        pub fn y(&self) -> f64 {
+            self.y
+        }
+
+        #[must_use]
+        pub fn width(&self) -> f64 {
+            self.width
+        }
+
+        #[must_use]
+        pub fn height(&self) -> f64 {
+            self.height
+        }
+
+        // Identifies the image generated for this crop.
+        #[must_use]
+        pub fn key(&self) -> String {
+            let digest = sha256::digest(format!(
+                "{}:{}:{}:{}",
+                self.x, self.y, self.width, self.height
+            ));
+            digest[..16].to_owned()
+        }
+
+        fn contains(&self, point: &FocalPoint) -> bool {
+            point.x >= self.x
+                && point.x <= self.x + self.width
+                && point.y >= self.y
+                && point.y <= self.y + self.height
+        }
+    }
+
+    // The most important point of an image, as fractions of the image's width and height.
+    #[derive(Debug, Clone, Copy, PartialEq)]
+    pub struct FocalPoint {
+        x: f64,
+        y: f64,
+    }
+
+    impl FocalPoint {
+        pub fn new(x: f64, y: f64) -> Result<Self, ValidationError> {
+            if !(0.0..=1.0).contains(&x) || !(0.0..=1.0).contains(&y) {
+                return Err(ValidationError::Constraint(
+                    "Focal point must be within the image.".into(),
+                ));
+            }
+
+            Ok(FocalPoint { x, y })
+        }
+
+        #[must_use]
+        pub fn x(&self) -> f64 {
+            self.x
+        }
+
+        #[must_use]
+        pub fn y(&self) -> f64 {
+            self.y
+        }
+    }
+
+    #[derive(Debug, Clone, Default, PartialEq)]
+    pub struct Framing {
+        crop: Option<Crop>,
+        focal_point: Option<FocalPoint>,
+    }
+
+    impl Framing {
+        pub fn new(
+            crop: Option<Crop>,
+            focal_point: Option<FocalPoint>,
+        ) -> Result<Self, ValidationError> {
+            if crop
+                .as_ref()
+                .zip(focal_point.as_ref())
+                .is_some_and(|(crop, focal_point)| !crop.contains(focal_point))
+            {
+                return Err(ValidationError::Constraint(
+                    "Focal point must be within the crop.".into(),
+                ));
+            }
+
+            Ok(Framing { crop, focal_point })
+        }
+
+        #[must_use]
+        pub fn crop(&self) -> Option<&Crop> {
+            self.crop.as_ref()
+        }
+
+        #[must_use]
+        pub fn focal_point(&self) -> Option<&FocalPoint> {
+            self.focal_point.as_ref()
+        }
+
+        // Identifies the cover and tile images generated for this framing.
+        #[must_use]
+        pub fn key(&self) -> String {
+            let crop = self.crop.map_or("none".to_owned(), |crop| {
+                format!("{}:{}:{}:{}", crop.x, crop.y, crop.width, crop.height)
+            });
+            let focal_point = self.focal_point.map_or("none".to_owned(), |point| {
+                format!("{}:{}", point.x, point.y)
+            });
+
+            let digest = sha256::digest(format!("{crop}/{focal_point}"));
+            digest[..16].to_owned()
+        }
+    }
 }
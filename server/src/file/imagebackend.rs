@@ -1,6 +1,9 @@
 use async_trait::async_trait;
+use futures::TryStreamExt;
+use tokio::io::{AsyncReadExt, AsyncSeekExt};
+use tokio_util::io::ReaderStream;
 
-use crate::imagestore::Error;
+use crate::imagestore::{Error, Object, Range};
 
 pub struct ImageBackend {
     base_path: std::path::PathBuf,
@@ -26,13 +29,35 @@ impl ImageBackend {
 
 #[async_trait]
 impl crate::imagestore::ImageBackend for ImageBackend {
-    async fn get(&self, path: &str) -> Result<Vec<u8>, Error> {
-        tokio::fs::read(self.base_path.join(path))
+    async fn get(&self, path: &str, range: Option<Range>) -> Result<Object, Error> {
+        let mut file = tokio::fs::File::open(self.base_path.join(path))
             .await
             .map_err(|err| match err.kind() {
                 std::io::ErrorKind::NotFound => Error::NotFound(err.into()),
                 _ => Error::Other(err.into()),
-            })
+            })?;
+        let size = file.metadata().await?.len();
+
+        match range {
+            None => Ok(Object {
+                size,
+                range: None,
+                stream: Box::pin(ReaderStream::new(file).map_err(Error::from)),
+            }),
+            Some(range) => {
+                let content_range = range.resolve(size)?;
+                file.seek(std::io::SeekFrom::Start(content_range.start))
+                    .await?;
+
+                Ok(Object {
+                    size,
+                    range: Some(content_range),
+                    stream: Box::pin(
+                        ReaderStream::new(file.take(content_range.length())).map_err(Error::from),
+                    ),
+                })
+            }
+        }
     }
 
     async fn upload(&self, path: &str, file: Vec<u8>) -> Result<(), Error> {
@@ -45,4 +70,15 @@ impl crate::imagestore::ImageBackend for ImageBackend {
 
         Ok(())
     }
+
+    async fn exists(&self, path: &str) -> Result<bool, Error> {
+        Ok(tokio::fs::try_exists(self.base_path.join(path)).await?)
+    }
+
+    async fn delete(&self, path: &str) -> Result<(), Error> {
+        match tokio::fs::remove_file(self.base_path.join(path)).await {
+            Err(err) if err.kind() != std::io::ErrorKind::NotFound => Err(err.into()),
+            _ => Ok(()),
+        }
+    }
 }
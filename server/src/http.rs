@@ -1,8 +1,14 @@
+mod audit;
 mod auth;
+mod household;
 mod image;
 mod recipe;
 mod responses;
 mod server;
+mod session;
+mod share;
 mod tag;
+mod token;
+mod user;
 
 pub use server::Server;
@@ -0,0 +1,71 @@
+use axum::{
+    Extension,
+    extract::{Query, State},
+};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    core::{self, Error},
+    domain,
+};
+
+use super::{
+    responses,
+    server::{AppState, AuthenticatedUser},
+};
+
+#[derive(Serialize)]
+pub struct Entry {
+    id: domain::audit::Id,
+    occurred_at: chrono::DateTime<chrono::Utc>,
+    user_id: String,
+    household_id: Option<domain::household::Id>,
+    action: domain::audit::Action,
+    entity: domain::audit::Entity,
+    entity_id: String,
+    detail: Option<String>,
+}
+
+// Every filter is optional, from is inclusive and to is exclusive so the occurred_at of the last
+// entry can be passed as to for the next page.
+#[derive(Deserialize)]
+pub struct ListParams {
+    user_id: Option<String>,
+    entity: Option<domain::audit::Entity>,
+    entity_id: Option<String>,
+    from: Option<chrono::DateTime<chrono::Utc>>,
+    to: Option<chrono::DateTime<chrono::Utc>>,
+    limit: Option<u32>,
+}
+
+pub async fn list(
+    State(state): State<AppState>,
+    Extension(user): Extension<AuthenticatedUser>,
+    Query(params): Query<ListParams>,
+) -> Result<axum::response::Json<responses::Data<Vec<Entry>>>, Error> {
+    let filter = domain::audit::Filter {
+        user_id: params.user_id,
+        entity: params.entity,
+        entity_id: params.entity_id,
+        from: params.from,
+        to: params.to,
+    };
+
+    let entries = core::audit::list(&state.datasource, &user.into(), filter, params.limit).await?;
+
+    Ok(axum::response::Json(responses::Data {
+        data: entries
+            .into_iter()
+            .map(|entry| Entry {
+                id: entry.id,
+                occurred_at: entry.occurred_at,
+                user_id: entry.user_id,
+                household_id: entry.household_id,
+                entity: entry.action.entity(),
+                action: entry.action,
+                entity_id: entry.entity_id,
+                detail: entry.detail,
+            })
+            .collect(),
+    }))
+}
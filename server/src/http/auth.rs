@@ -1,6 +1,10 @@
+use std::net::SocketAddr;
+
 use anyhow::anyhow;
 use axum::{
-    extract::{Query, State},
+    Json,
+    extract::{ConnectInfo, Query, State},
+    http::{HeaderMap, StatusCode, header::USER_AGENT},
     response::Redirect,
 };
 use axum_extra::extract::{
@@ -10,36 +14,133 @@ use axum_extra::extract::{
 use serde::{Deserialize, Serialize};
 
 use crate::{
+    config::{self, Config},
     core::{self, Error},
-    oidc,
+    domain::SessionKey,
+    oidc, proxy_auth,
 };
 
-use super::server::AppState;
+use super::{responses, server::AppState};
 
 #[derive(Deserialize)]
 pub struct InitParams {
+    // may be left out when only one provider is configured
+    provider: Option<String>,
     redirect_target: Option<String>,
 }
 
+#[derive(Deserialize)]
+pub struct LocalSignIn {
+    username: String,
+    password: String,
+}
+
 #[derive(Serialize, Deserialize)]
 struct SignedCookie {
     value: String,
     tag: Vec<u8>,
 }
 
+// Signs cookies with the first key and verifies them with any key, so a new key can be put in
+// front while cookies signed with the previous key remain valid.
+pub struct CookieKeys {
+    keys: Vec<ring::hmac::Key>,
+}
+
+impl CookieKeys {
+    pub fn new(keys: &[Vec<u8>]) -> Result<Self, Error> {
+        if keys.is_empty() {
+            return Err(Error::Other(anyhow!("No cookie signing key.")));
+        }
+
+        Ok(CookieKeys {
+            keys: keys
+                .iter()
+                .map(|key| ring::hmac::Key::new(ring::hmac::HMAC_SHA256, key))
+                .collect(),
+        })
+    }
+
+    // Without configured keys, a key is generated that only lives as long as the process. That
+    // is only allowed with insecure cookies, so only for development.
+    pub fn from_config(config: &Config) -> Result<Self, Error> {
+        if !config.cookie_signing_keys.is_empty() {
+            return CookieKeys::new(&config.cookie_signing_keys);
+        }
+
+        if !config.insecure_cookies {
+            return Err(Error::Other(anyhow!("No cookie signing key configured.")));
+        }
+
+        println!("No cookie signing key configured, logins will not survive a restart.");
+        CookieKeys::generate()
+    }
+
+    fn generate() -> Result<Self, Error> {
+        let rng = ring::rand::SystemRandom::new();
+        let key = ring::hmac::Key::generate(ring::hmac::HMAC_SHA256, &rng)
+            .map_err(|_| Error::Other(anyhow!("ring key generation error.")))?;
+
+        Ok(CookieKeys { keys: vec![key] })
+    }
+
+    fn sign(&self, value: &[u8]) -> ring::hmac::Tag {
+        ring::hmac::sign(&self.keys[0], value)
+    }
+
+    fn verify(&self, value: &[u8], tag: &[u8]) -> Result<(), Error> {
+        if self
+            .keys
+            .iter()
+            .any(|key| ring::hmac::verify(key, value, tag).is_ok())
+        {
+            Ok(())
+        } else {
+            Err(Error::Unauthenticated(anyhow!(
+                "Cookie signature validation failed."
+            )))
+        }
+    }
+}
+
+// The names of the providers users may sign in with. Local sign in is listed as "local" when it
+// is enabled, and is done with /auth/local instead of /auth/init.
+pub async fn providers(State(state): State<AppState>) -> Json<responses::Data<Vec<String>>> {
+    let mut names: Vec<String> = state
+        .oidc_providers
+        .names()
+        .into_iter()
+        .map(str::to_owned)
+        .collect();
+    if state.config.local_auth.is_some() {
+        names.push(config::LOCAL_AUTH_PROVIDER.to_owned());
+    }
+
+    Json(responses::Data { data: names })
+}
+
 pub async fn init(
     State(state): State<AppState>,
     jar: CookieJar,
     params: Query<InitParams>,
 ) -> Result<(CookieJar, Redirect), Error> {
-    let (auth_url, oidc_state) =
-        oidc::begin_auth(&state.oidc_provider, params.redirect_target.clone())
-            .map_err(|err| Error::Unauthenticated(err.into()))?;
+    let provider = state
+        .oidc_providers
+        .get(params.provider.as_deref())
+        .map_err(|err| match err {
+            oidc::Error::UnknownProvider(name) => {
+                Error::NotFound(format!("OIDC provider {name} does not exist"))
+            }
+            _ => Error::Invalid(err.into()),
+        })?;
+
+    let (auth_url, oidc_state) = oidc::begin_auth(provider, params.redirect_target.clone())
+        .map_err(|err| Error::Unauthenticated(err.into()))?;
 
     let cookie_value =
         serde_json::to_string(&oidc_state).map_err(|err| Error::Other(err.into()))?;
 
-    let tag = ring::hmac::sign(&state.key, cookie_value.as_bytes());
+    let tag = state.cookie_keys.sign(cookie_value.as_bytes());
     let signed_cookie = serde_json::to_string(&SignedCookie {
         value: cookie_value,
         tag: tag.as_ref().to_vec(),
@@ -69,6 +170,8 @@ pub struct CallbackParams {
 pub async fn callback(
     jar: CookieJar,
     State(state): State<AppState>,
+    ConnectInfo(address): ConnectInfo<SocketAddr>,
+    headers: HeaderMap,
     params: Query<CallbackParams>,
 ) -> Result<(CookieJar, Redirect), Error> {
     let signed_cookie = serde_json::from_str::<SignedCookie>(
@@ -80,12 +183,9 @@ pub async fn callback(
     )
     .map_err(|err| Error::Other(err.into()))?;
 
-    ring::hmac::verify(
-        &state.key,
-        signed_cookie.value.as_bytes(),
-        signed_cookie.tag.as_ref(),
-    )
-    .map_err(|_| Error::Unauthenticated(anyhow!("Cookie signature validation failed.")))?;
+    state
+        .cookie_keys
+        .verify(signed_cookie.value.as_bytes(), signed_cookie.tag.as_ref())?;
 
     let oidc_state = serde_json::from_str::<oidc::AuthState>(&signed_cookie.value)
         .map_err(|err| Error::Other(err.into()))?;
@@ -95,7 +195,7 @@ pub async fn callback(
 
     // exchange with oidc provider
     let (authenticated, redirect_target) = oidc::complete_auth(
-        &state.oidc_provider,
+        &state.oidc_providers,
         oidc_state,
         oidc::CallbackParams {
             state: &params.state,
@@ -103,26 +203,130 @@ pub async fn callback(
         },
     )
     .await
-    .map_err(|err| Error::Unauthenticated(err.into()))?;
+    .map_err(|err| match err {
+        // rejected before the user is persisted
+        oidc::Error::NotAllowed(_) => Error::Forbidden(err.into()),
+        _ => Error::Unauthenticated(err.into()),
+    })?;
 
     // persist user and create session
-    let session_key =
-        core::user::on_authenticated(&state.datasource, &state.session_store, &authenticated)
-            .await?;
+    let session_key = core::user::on_authenticated(
+        &state.datasource,
+        &state.session_store,
+        &authenticated,
+        &client(&state.config, &headers, address),
+    )
+    .await?;
 
-    let jar = jar.add(
-        Cookie::build(("id", session_key.to_string()))
-            .path("/")
-            .http_only(true)
-            .secure(!state.config.insecure_cookies)
-            .same_site(SameSite::Strict)
-            .max_age(cookie::time::Duration::seconds(
-                core::session::SESSION_EXPIRES_IN,
-            )),
-    );
+    let jar = jar.add(session_cookie(&state.config, &session_key));
 
     Ok((
         jar,
         Redirect::temporary(redirect_target.as_ref().map_or("/", |s| s.as_str())),
     ))
 }
+
+// Signs in with a username and password when local sign in is enabled.
+pub async fn local(
+    jar: CookieJar,
+    State(state): State<AppState>,
+    ConnectInfo(address): ConnectInfo<SocketAddr>,
+    headers: HeaderMap,
+    Json(params): Json<LocalSignIn>,
+) -> Result<(CookieJar, StatusCode), Error> {
+    let local_auth = state
+        .config
+        .local_auth
+        .clone()
+        .ok_or(Error::NotFound("Local sign in is not enabled.".into()))?;
+
+    let session_key = core::user::sign_in_locally(
+        &state.datasource,
+        &state.session_store,
+        local_auth,
+        params.username,
+        params.password,
+        &client(&state.config, &headers, address),
+    )
+    .await?;
+
+    let jar = jar.add(session_cookie(&state.config, &session_key));
+
+    Ok((jar, StatusCode::NO_CONTENT))
+}
+
+// Behind trusted proxies, the session records the address of the client rather than the proxy's.
+fn client(config: &Config, headers: &HeaderMap, address: SocketAddr) -> core::session::Client {
+    core::session::Client {
+        user_agent: headers
+            .get(USER_AGENT)
+            .and_then(|user_agent| user_agent.to_str().ok())
+            .map(str::to_owned),
+        address: Some(proxy_auth::client_address(
+            &config.trusted_proxies,
+            address.ip(),
+            headers,
+        )),
+    }
+}
+
+fn session_cookie(config: &Config, session_key: &SessionKey) -> Cookie<'static> {
+    Cookie::build(("id", session_key.to_string()))
+        .path("/")
+        .http_only(true)
+        .secure(!config.insecure_cookies)
+        .same_site(SameSite::Strict)
+        .max_age(cookie::time::Duration::seconds(
+            core::session::SESSION_EXPIRES_IN,
+        ))
+        .build()
+}
+
+pub async fn logout(
+    jar: CookieJar,
+    State(state): State<AppState>,
+) -> Result<(CookieJar, Redirect), Error> {
+    let provider = match jar.get("id") {
+        Some(cookie) => {
+            core::session::end(
+                &state.session_store,
+                &state.datasource,
+                SessionKey(cookie.value().to_string()),
+            )
+            .await?
+        }
+        None => None,
+    };
+
+    let jar = jar.remove(Cookie::build("id").path("/"));
+
+    // also end the session with the provider it was started with, when it supports it
+    let redirect_target = provider
+        .and_then(|name| state.oidc_providers.get(Some(&name)).ok())
+        .and_then(oidc::logout_url)
+        .map_or_else(|| "/login".to_string(), |url| url.to_string());
+
+    // see other, so the browser follows the form post with a get
+    Ok((jar, Redirect::to(&redirect_target)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_verifies_with_previous_keys() {
+        let old = CookieKeys::new(&[vec![1; 32]]).unwrap();
+        let rotated = CookieKeys::new(&[vec![2; 32], vec![1; 32]]).unwrap();
+        let replaced = CookieKeys::new(&[vec![2; 32]]).unwrap();
+
+        let tag = old.sign(b"value");
+        assert!(rotated.verify(b"value", tag.as_ref()).is_ok());
+        assert!(replaced.verify(b"value", tag.as_ref()).is_err());
+
+        let tag = rotated.sign(b"value");
+        assert!(replaced.verify(b"value", tag.as_ref()).is_ok());
+        assert!(old.verify(b"value", tag.as_ref()).is_err());
+        assert!(rotated.verify(b"other", tag.as_ref()).is_err());
+    }
+}
@@ -0,0 +1,142 @@
+use anyhow::anyhow;
+use axum::{
+    Extension, Json,
+    extract::{Path, State},
+    http::StatusCode,
+};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    core::{self, Error},
+    domain,
+};
+
+use super::{
+    responses,
+    server::{AppState, AuthenticatedUser},
+};
+
+#[derive(Serialize)]
+pub struct Household {
+    id: domain::household::Id,
+    name: String,
+    role: domain::household::Role,
+    // whether this is the household the user is acting in
+    active: bool,
+}
+
+impl From<domain::household::Household> for Household {
+    fn from(household: domain::household::Household) -> Self {
+        Household {
+            id: household.id,
+            name: household.name.into(),
+            role: household.role,
+            active: household.active,
+        }
+    }
+}
+
+#[derive(Deserialize)]
+pub struct CreateParams {
+    name: String,
+}
+
+#[derive(Deserialize)]
+pub struct AddMemberParams {
+    user_id: String,
+    // new members can only browse unless given another role
+    role: Option<domain::household::Role>,
+}
+
+#[derive(Deserialize)]
+pub struct SetMemberRoleParams {
+    role: domain::household::Role,
+}
+
+pub async fn list(
+    State(state): State<AppState>,
+    Extension(user): Extension<AuthenticatedUser>,
+) -> Result<axum::response::Json<responses::Data<Vec<Household>>>, Error> {
+    let households = core::household::list(&state.datasource, &user.id, &user.household_id).await?;
+
+    Ok(axum::response::Json(responses::Data {
+        data: households.into_iter().map(Household::from).collect(),
+    }))
+}
+
+pub async fn create(
+    State(state): State<AppState>,
+    Extension(user): Extension<AuthenticatedUser>,
+    Json(request): Json<CreateParams>,
+) -> Result<axum::response::Json<responses::Data<domain::household::Id>>, Error> {
+    let creating = domain::household::Creating {
+        name: request.name.try_into()?,
+    };
+
+    let id = core::household::create(&state.datasource, user.into(), creating).await?;
+
+    Ok(axum::response::Json(responses::Data { data: id }))
+}
+
+pub async fn activate(
+    State(state): State<AppState>,
+    Extension(user): Extension<AuthenticatedUser>,
+    Path(id): Path<domain::household::Id>,
+) -> Result<StatusCode, Error> {
+    if user.token {
+        return Err(Error::Invalid(anyhow!(
+            "A token acts in the household it was created in."
+        )));
+    }
+
+    core::household::activate(
+        &state.datasource,
+        &state.session_store,
+        &user.id,
+        user.session_id.as_deref(),
+        &id,
+    )
+    .await?;
+
+    Ok(StatusCode::NO_CONTENT)
+}
+
+pub async fn add_member(
+    State(state): State<AppState>,
+    Extension(user): Extension<AuthenticatedUser>,
+    Path(id): Path<domain::household::Id>,
+    Json(request): Json<AddMemberParams>,
+) -> Result<StatusCode, Error> {
+    core::household::add_member(
+        &state.datasource,
+        &user.id,
+        &id,
+        &request.user_id,
+        request.role.unwrap_or(domain::household::Role::Viewer),
+    )
+    .await?;
+
+    Ok(StatusCode::NO_CONTENT)
+}
+
+pub async fn set_member_role(
+    State(state): State<AppState>,
+    Extension(user): Extension<AuthenticatedUser>,
+    Path((id, member_id)): Path<(domain::household::Id, String)>,
+    Json(request): Json<SetMemberRoleParams>,
+) -> Result<StatusCode, Error> {
+    core::household::set_member_role(&state.datasource, &user.id, &id, &member_id, request.role)
+        .await?;
+
+    Ok(StatusCode::NO_CONTENT)
+}
+
+pub async fn remove_member(
+    State(state): State<AppState>,
+    Extension(user): Extension<AuthenticatedUser>,
+    Path((id, member_id)): Path<(domain::household::Id, String)>,
+) -> Result<StatusCode, Error> {
+    core::household::remove_member(&state.datasource, &user.id, &id, &member_id).await?;
+
+    Ok(StatusCode::NO_CONTENT)
+}
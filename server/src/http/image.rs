@@ -1,18 +1,28 @@
 use anyhow::{Context, anyhow};
 use axum::{
+    Extension, Json,
+    body::Body,
     extract::{Multipart, Path, State},
     http::HeaderMap,
     response::IntoResponse,
 };
 use reqwest::StatusCode;
+use serde::{Deserialize, Serialize};
 
-use super::{responses, server::AppState};
-use crate::core::{self, Error};
+use super::{
+    responses,
+    server::{AppState, AuthenticatedUser},
+};
+use crate::{
+    core::{self, Error},
+    domain, imagestore,
+};
 
 const CACHE_CONTROL: &str = "private, immutable, max-age=31536000";
 
 pub async fn upload(
     State(state): State<AppState>,
+    Extension(user): Extension<AuthenticatedUser>,
     mut multipart: Multipart,
 ) -> Result<axum::response::Json<responses::Data<String>>, Error> {
     if let Some(field) = multipart
@@ -31,6 +41,7 @@ pub async fn upload(
             &state.datasource,
             &state.image_store,
             &state.image_processor,
+            &user.into(),
             bytes.to_vec(),
         )
         .await?;
@@ -41,38 +52,284 @@ pub async fn upload(
     }
 }
 
+#[derive(Deserialize)]
+pub struct ImportParams {
+    url: String,
+}
+
+pub async fn import(
+    State(state): State<AppState>,
+    Extension(user): Extension<AuthenticatedUser>,
+    Json(request): Json<ImportParams>,
+) -> Result<axum::response::Json<responses::Data<String>>, Error> {
+    let id = core::image::import(
+        &state.datasource,
+        &state.image_store,
+        &state.image_processor,
+        &state.image_importer,
+        &user.into(),
+        &request.url,
+    )
+    .await?;
+
+    Ok(axum::response::Json(responses::Data { data: id.into() }))
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct Crop {
+    x: f64,
+    y: f64,
+    width: f64,
+    height: f64,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct FocalPoint {
+    x: f64,
+    y: f64,
+}
+
+#[derive(Deserialize)]
+pub struct UpdateFramingParams {
+    crop: Option<Crop>,
+    focal_point: Option<FocalPoint>,
+}
+
+#[derive(Serialize)]
+pub struct Framing {
+    crop: Option<Crop>,
+    focal_point: Option<FocalPoint>,
+    // key of the cropped image, served at /images/{id}/cropped/{key}
+    cropped_image: Option<String>,
+    // key of the cover and tile images framed around the focal point, served at
+    // /images/{id}/cover/{key} and /images/{id}/tile/{key}
+    framed_image: Option<String>,
+}
+
+impl From<domain::image::Framing> for Framing {
+    fn from(value: domain::image::Framing) -> Self {
+        let framed_image = (value != domain::image::Framing::default()).then(|| value.key());
+
+        Framing {
+            crop: value.crop().map(|crop| Crop {
+                x: crop.x(),
+                y: crop.y(),
+                width: crop.width(),
+                height: crop.height(),
+            }),
+            focal_point: value.focal_point().map(|focal_point| FocalPoint {
+                x: focal_point.x(),
+                y: focal_point.y(),
+            }),
+            cropped_image: value.crop().map(domain::image::Crop::key),
+            framed_image,
+        }
+    }
+}
+
+pub async fn get_framing(
+    State(state): State<AppState>,
+    Extension(user): Extension<AuthenticatedUser>,
+    Path((recipe_id, id)): Path<(String, String)>,
+) -> Result<axum::response::Json<responses::Data<Framing>>, Error> {
+    let framing =
+        core::image::get_framing(&state.datasource, &user.household_id, &recipe_id, &id).await?;
+
+    Ok(axum::response::Json(responses::Data {
+        data: framing.into(),
+    }))
+}
+
+pub async fn update_framing(
+    State(state): State<AppState>,
+    Extension(user): Extension<AuthenticatedUser>,
+    Path((recipe_id, id)): Path<(String, String)>,
+    Json(request): Json<UpdateFramingParams>,
+) -> Result<axum::response::Json<responses::Data<Framing>>, Error> {
+    let framing = domain::image::Framing::new(
+        request
+            .crop
+            .map(|crop| domain::image::Crop::new(crop.x, crop.y, crop.width, crop.height))
+            .transpose()?,
+        request
+            .focal_point
+            .map(|focal_point| domain::image::FocalPoint::new(focal_point.x, focal_point.y))
+            .transpose()?,
+    )?;
+
+    core::image::update_framing(
+        &state.datasource,
+        &state.image_store,
+        &state.image_processor,
+        &user.into(),
+        &recipe_id,
+        &id,
+        framing.clone(),
+    )
+    .await?;
+
+    Ok(axum::response::Json(responses::Data {
+        data: framing.into(),
+    }))
+}
+
 pub async fn get(
     headers: HeaderMap,
     State(state): State<AppState>,
+    Extension(user): Extension<AuthenticatedUser>,
     Path(id): Path<String>,
 ) -> Result<axum::response::Response, Error> {
-    // verify image exists first
-    core::image::exists(&state.datasource, &id).await?;
-
     let etag = format!(r#""{id}""#);
 
+    // verify image exists in the household first
+    core::image::exists(&state.datasource, &user.household_id, &id).await?;
+
+    serve(&headers, &state, &id, core::image::Variant::Original, &etag).await
+}
+
+pub async fn get_generated(
+    headers: HeaderMap,
+    State(state): State<AppState>,
+    Extension(user): Extension<AuthenticatedUser>,
+    Path((id, variant, key)): Path<(String, String, String)>,
+) -> Result<axum::response::Response, Error> {
+    let etag = format!(r#""{id}-{variant}-{key}""#);
+    let variant = core::image::Variant::generated(&variant, &key)?;
+
+    // verify image exists in the household first
+    core::image::exists(&state.datasource, &user.household_id, &id).await?;
+
+    serve(&headers, &state, &id, variant, &etag).await
+}
+
+// Sends an image the caller has already checked may be seen.
+pub(super) async fn serve(
+    headers: &HeaderMap,
+    state: &AppState,
+    id: &str,
+    variant: core::image::Variant<'_>,
+    etag: &str,
+) -> Result<axum::response::Response, Error> {
     if let Some(if_none_match) = headers.get("if-none-match") {
         if let Ok(if_none_match) = if_none_match.to_str() {
             if if_none_match == etag {
                 return Ok((
                     StatusCode::NOT_MODIFIED,
-                    [("Cache-Control", CACHE_CONTROL), ("ETag", &etag)],
+                    [("Cache-Control", CACHE_CONTROL), ("ETag", etag)],
                 )
                     .into_response());
             }
         }
     }
 
-    let img = core::image::get(&state.image_store, &id).await?;
+    // let the client download straight from the backend if it supports it
+    if let Some(url) = core::image::presigned_url(&state.image_store, id, variant)? {
+        return Ok((
+            StatusCode::TEMPORARY_REDIRECT,
+            [
+                ("Location", url.as_str()),
+                ("ETag", etag),
+                ("Cache-Control", "private, no-store"),
+            ],
+        )
+            .into_response());
+    }
+
+    // a range only applies if the client's copy is still current
+    let range = match headers.get("if-range") {
+        Some(if_range) if if_range.to_str().ok() != Some(etag) => None,
+        _ => headers
+            .get("range")
+            .and_then(|range| range.to_str().ok())
+            .and_then(parse_range),
+    };
 
-    Ok((
-        StatusCode::OK,
-        [
-            ("Content-Type", "image/jpeg"),
-            ("ETag", &etag),
-            ("Cache-Control", CACHE_CONTROL),
-        ],
-        img,
-    )
-        .into_response())
+    let img = core::image::get(&state.image_store, id, variant, range).await?;
+    let body = Body::from_stream(img.stream);
+
+    match img.range {
+        None => Ok((
+            StatusCode::OK,
+            [
+                ("Content-Type", "image/jpeg"),
+                ("Content-Length", &img.size.to_string()),
+                ("Accept-Ranges", "bytes"),
+                ("ETag", etag),
+                ("Cache-Control", CACHE_CONTROL),
+            ],
+            body,
+        )
+            .into_response()),
+        Some(range) => Ok((
+            StatusCode::PARTIAL_CONTENT,
+            [
+                ("Content-Type", "image/jpeg"),
+                ("Content-Length", &range.length().to_string()),
+                (
+                    "Content-Range",
+                    &format!("bytes {}-{}/{}", range.start, range.end, img.size),
+                ),
+                ("Accept-Ranges", "bytes"),
+                ("ETag", etag),
+                ("Cache-Control", CACHE_CONTROL),
+            ],
+            body,
+        )
+            .into_response()),
+    }
+}
+
+// Parses a single range such as "bytes=0-499", "bytes=500-" or "bytes=-500". Anything else,
+// including multiple ranges, is ignored and the whole image is sent.
+fn parse_range(value: &str) -> Option<imagestore::Range> {
+    let (start, end) = value.trim().strip_prefix("bytes=")?.split_once('-')?;
+
+    match (start.trim(), end.trim()) {
+        ("", "") => None,
+        ("", length) => Some(imagestore::Range::Suffix {
+            length: length.parse().ok()?,
+        }),
+        (start, "") => Some(imagestore::Range::From {
+            start: start.parse().ok()?,
+        }),
+        (start, end) => {
+            let start = start.parse().ok()?;
+            let end = end.parse().ok()?;
+            if start > end {
+                return None;
+            }
+
+            Some(imagestore::Range::Bounded { start, end })
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_range() {
+        let test_cases = vec![
+            // (header, expected)
+            (
+                "bytes=0-499",
+                Some(imagestore::Range::Bounded { start: 0, end: 499 }),
+            ),
+            ("bytes=500-", Some(imagestore::Range::From { start: 500 })),
+            (
+                "bytes=-500",
+                Some(imagestore::Range::Suffix { length: 500 }),
+            ),
+            ("bytes=-", None),
+            ("bytes=5-1", None),
+            ("bytes=0-1,5-9", None),
+            ("items=0-1", None),
+            ("bytes=a-b", None),
+        ];
+
+        for (header, expected) in test_cases {
+            assert_eq!(expected, parse_range(header), "header: {header}");
+        }
+    }
 }
@@ -19,6 +19,8 @@ use super::{
 pub struct CreateParams {
     title: String,
     image_id: Option<String>,
+    #[serde(default)]
+    images: Vec<RecipeImage>,
     ingredients: Vec<IngredientBlock>,
     instructions: Vec<InstructionBlock>,
     notes: Option<String>,
@@ -31,6 +33,7 @@ pub struct Recipe {
     hash: String,
     title: String,
     image_id: Option<String>,
+    images: Vec<RecipeImage>,
     ingredient_blocks: Vec<IngredientBlock>,
     instruction_blocks: Vec<RichInstructionBlock>,
     notes: Option<String>,
@@ -61,6 +64,8 @@ pub struct Page {
 pub struct InstructionBlock {
     title: Option<String>,
     instructions: Vec<String>,
+    #[serde(default)]
+    step_images: Vec<StepImage>,
 }
 
 #[derive(Serialize)]
@@ -68,6 +73,19 @@ pub struct RichInstructionBlock {
     title: Option<String>,
     instructions: Vec<String>,
     rich_instructions: Vec<String>,
+    step_images: Vec<StepImage>,
+}
+
+#[derive(Deserialize, Serialize)]
+pub struct StepImage {
+    step: usize,
+    image_id: String,
+}
+
+#[derive(Deserialize, Serialize)]
+pub struct RecipeImage {
+    image_id: String,
+    caption: Option<String>,
 }
 
 #[derive(Deserialize, Serialize)]
@@ -77,7 +95,7 @@ pub struct IngredientBlock {
 }
 
 fn parse_instructions(block: InstructionBlock) -> Result<domain::recipe::InstructionBlock, Error> {
-    Ok(domain::recipe::InstructionBlock {
+    let block = domain::recipe::InstructionBlock {
         title: match block.title {
             None => None,
             Some(n) => Some(n.try_into()?),
@@ -87,6 +105,29 @@ fn parse_instructions(block: InstructionBlock) -> Result<domain::recipe::Instruc
             .into_iter()
             .map(domain::recipe::Instruction::try_from)
             .collect::<Result<Vec<domain::recipe::Instruction>, domain::ValidationError>>()?,
+        step_images: block
+            .step_images
+            .into_iter()
+            .map(|image| -> Result<domain::recipe::StepImage, Error> {
+                Ok(domain::recipe::StepImage {
+                    step: image.step,
+                    image_id: image.image_id.as_str().try_into()?,
+                })
+            })
+            .collect::<Result<Vec<domain::recipe::StepImage>, Error>>()?,
+    };
+    block.validate_step_images()?;
+
+    Ok(block)
+}
+
+fn parse_image(image: RecipeImage) -> Result<domain::recipe::Image, Error> {
+    Ok(domain::recipe::Image {
+        id: image.image_id.as_str().try_into()?,
+        caption: match image.caption {
+            None => None,
+            Some(n) => Some(n.try_into()?),
+        },
     })
 }
 
@@ -115,6 +156,11 @@ pub async fn create(
             None => None,
             Some(n) => Some(n.as_str().try_into()?),
         },
+        images: request
+            .images
+            .into_iter()
+            .map(parse_image)
+            .collect::<Result<Vec<domain::recipe::Image>, Error>>()?,
         ingredients: request
             .ingredients
             .into_iter()
@@ -143,18 +189,21 @@ pub async fn create(
     Ok(axum::response::Json(responses::Data { data: id.into() }))
 }
 
-pub async fn get(
-    State(state): State<AppState>,
-    Path(id): Path<domain::recipe::Id>,
-) -> Result<axum::response::Json<responses::Data<Recipe>>, Error> {
-    let recipe = core::recipe::get(&state.datasource, id).await?;
-
-    Ok(axum::response::Json(responses::Data {
-        data: Recipe {
+impl From<domain::Recipe> for Recipe {
+    fn from(recipe: domain::Recipe) -> Self {
+        Recipe {
             id: recipe.id.to_string(),
             hash: recipe.hash,
             title: recipe.title.into(),
             image_id: recipe.image_id.map(Into::into),
+            images: recipe
+                .images
+                .into_iter()
+                .map(|image| RecipeImage {
+                    image_id: image.id.into(),
+                    caption: image.caption.map(String::from),
+                })
+                .collect(),
             ingredient_blocks: recipe
                 .ingredients
                 .into_iter()
@@ -179,6 +228,14 @@ pub async fn get(
                         .into_iter()
                         .map(|instruction| instruction.into_html())
                         .collect(),
+                    step_images: block
+                        .step_images
+                        .into_iter()
+                        .map(|image| StepImage {
+                            step: image.step,
+                            image_id: image.image_id.into(),
+                        })
+                        .collect(),
                 })
                 .collect(),
             notes: recipe.notes.clone().map(Into::into),
@@ -191,7 +248,19 @@ pub async fn get(
                     name: tag.name.into(),
                 })
                 .collect(),
-        },
+        }
+    }
+}
+
+pub async fn get(
+    State(state): State<AppState>,
+    Extension(user): Extension<AuthenticatedUser>,
+    Path(id): Path<domain::recipe::Id>,
+) -> Result<axum::response::Json<responses::Data<Recipe>>, Error> {
+    let recipe = core::recipe::get(&state.datasource, &user.household_id, id).await?;
+
+    Ok(axum::response::Json(responses::Data {
+        data: recipe.into(),
     }))
 }
 
@@ -204,6 +273,7 @@ pub struct ListParams {
 
 pub async fn list(
     State(state): State<AppState>,
+    Extension(user): Extension<AuthenticatedUser>,
     Query(params): Query<ListParams>,
 ) -> Result<axum::response::Json<Page>, Error> {
     let base64_engine = base64::engine::general_purpose::URL_SAFE;
@@ -229,6 +299,7 @@ pub async fn list(
     let page = core::recipe::list(
         &state.datasource,
         &state.search_backend,
+        &user.household_id,
         params.title,
         filter,
         cursor,
@@ -257,6 +328,8 @@ pub struct UpdateParams {
     previous_hash: String,
     title: String,
     image_id: Option<String>,
+    #[serde(default)]
+    images: Vec<RecipeImage>,
     ingredients: Vec<IngredientBlock>,
     instructions: Vec<InstructionBlock>,
     notes: Option<String>,
@@ -277,6 +350,11 @@ pub async fn update(
             None => None,
             Some(n) => Some(n.as_str().try_into()?),
         },
+        images: request
+            .images
+            .into_iter()
+            .map(parse_image)
+            .collect::<Result<Vec<domain::recipe::Image>, Error>>()?,
         ingredients: request
             .ingredients
             .into_iter()
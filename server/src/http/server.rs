@@ -1,10 +1,13 @@
-use std::sync::Arc;
+use std::{net::SocketAddr, sync::Arc};
 
 use anyhow::anyhow;
 use axum::{
-    Extension, Router,
-    extract::{DefaultBodyLimit, Request, State},
-    http::{HeaderValue, StatusCode},
+    Router,
+    extract::{ConnectInfo, DefaultBodyLimit, Request, State},
+    http::{
+        HeaderMap, HeaderValue, Method, StatusCode,
+        header::{AUTHORIZATION, ORIGIN},
+    },
     middleware::{self, Next},
     response::{IntoResponse, Redirect, Response},
 };
@@ -18,9 +21,11 @@ use crate::{
     datastore::Pool,
     domain::{self, SessionKey},
     http,
+    image_import::ImageImporter,
     image_processing::ImageProcessor,
     imagestore::ImageStore,
-    oidc,
+    oidc, proxy_auth,
+    rate_limit::{self, Group},
     search::Backend,
     session_store::SessionStore,
 };
@@ -32,7 +37,7 @@ pub struct Server {
     datasource: Pool,
     session_store: SessionStore,
     search_backend: Backend,
-    oidc_provider: Arc<oidc::Provider>,
+    oidc_providers: Arc<oidc::Providers>,
     image_store: Arc<ImageStore>,
     image_processor: Arc<ImageProcessor>,
 }
@@ -43,10 +48,14 @@ pub struct AppState {
     pub config: Config,
     pub session_store: SessionStore,
     pub search_backend: Backend,
-    pub key: ring::hmac::Key,
-    pub oidc_provider: Arc<oidc::Provider>,
+    pub cookie_keys: Arc<http::auth::CookieKeys>,
+    pub oidc_providers: Arc<oidc::Providers>,
     pub image_store: Arc<ImageStore>,
     pub image_processor: Arc<ImageProcessor>,
+    pub image_importer: Arc<ImageImporter>,
+    pub proxy_users: Arc<proxy_auth::Users>,
+    // None when rate limiting is off
+    pub rate_limiter: Option<Arc<rate_limit::Limiter>>,
 }
 
 impl Server {
@@ -55,7 +64,7 @@ impl Server {
         config: Config,
         datasource: Pool,
         session_store: SessionStore,
-        oidc_provider: oidc::Provider,
+        oidc_providers: oidc::Providers,
         image_store: ImageStore,
         image_processor: ImageProcessor,
         search_backend: Backend,
@@ -64,7 +73,7 @@ impl Server {
             config,
             datasource,
             session_store,
-            oidc_provider: Arc::new(oidc_provider),
+            oidc_providers: Arc::new(oidc_providers),
             image_store: Arc::new(image_store),
             image_processor: Arc::new(image_processor),
             search_backend,
@@ -77,53 +86,39 @@ impl Server {
             self.config.http_port
         );
 
-        let rng = ring::rand::SystemRandom::new();
-        let key = ring::hmac::Key::generate(ring::hmac::HMAC_SHA256, &rng)
-            .map_err(|_| Error::Other(anyhow!("ring key generation error.")))?;
+        let cookie_keys = http::auth::CookieKeys::from_config(&self.config)?;
+
+        let image_importer = ImageImporter::new(&self.config.image_import)?;
 
         let state = AppState {
-            key,
+            cookie_keys: Arc::new(cookie_keys),
             config: self.config.clone(),
             session_store: self.session_store.clone(),
             datasource: self.datasource.clone(),
-            oidc_provider: self.oidc_provider.clone(),
+            oidc_providers: self.oidc_providers.clone(),
             image_store: self.image_store.clone(),
             image_processor: self.image_processor.clone(),
+            image_importer: Arc::new(image_importer),
+            proxy_users: Arc::new(proxy_auth::Users::default()),
+            rate_limiter: self
+                .config
+                .rate_limit
+                .clone()
+                .map(|config| Arc::new(rate_limit::Limiter::new(config))),
             search_backend: self.search_backend.clone(),
         };
 
         let router: Router = Router::new()
             .route("/health-check", axum::routing::get(|| async { "ok" }))
             //
-            // OIDC routes
-            .route("/auth/init", axum::routing::get(http::auth::init))
-            .route("/auth/complete", axum::routing::get(http::auth::callback))
+            // Sign in routes
+            .nest("/auth", auth_routes(&state))
+            //
+            // Public share links
+            .nest("/api/v1/shared", shared_routes(&state))
             //
             // Authenticated routes
-            .nest(
-                "/api/v1",
-                Router::new()
-                    .route("/auth/me", axum::routing::get(get_me))
-                    .route("/recipes", axum::routing::get(http::recipe::list))
-                    .route("/recipes", axum::routing::post(http::recipe::create))
-                    .route("/recipes/{id}", axum::routing::get(http::recipe::get))
-                    .route("/recipes/{id}", axum::routing::put(http::recipe::update))
-                    .route("/tags", axum::routing::post(http::tag::create))
-                    .route("/tags", axum::routing::get(http::tag::get_all))
-                    //
-                    // Nested /images router with large max body size
-                    .nest(
-                        "/images",
-                        Router::new()
-                            .route("/", axum::routing::post(http::image::upload))
-                            .route("/{id}", axum::routing::get(http::image::get))
-                            .layer(DefaultBodyLimit::max(MAX_IMAGE_BODY_SIZE)),
-                    )
-                    .layer(middleware::from_fn_with_state(
-                        state.clone(),
-                        auth_middleware,
-                    )),
-            )
+            .nest("/api/v1", api_routes(&state))
             //
             // Base path redirect
             .route("/", axum::routing::get(handle_base_redirect))
@@ -153,14 +148,153 @@ impl Server {
 
         let listener =
             tokio::net::TcpListener::bind(format!("0.0.0.0:{}", self.config.http_port)).await?;
-        axum::serve(listener, router)
-            .with_graceful_shutdown(shutdown_signal())
-            .await?;
+        axum::serve(
+            listener,
+            router.into_make_service_with_connect_info::<SocketAddr>(),
+        )
+        .with_graceful_shutdown(shutdown_signal())
+        .await?;
 
         Ok(())
     }
 }
 
+// Routes to sign in and out, which are rate limited by client address as nobody is signed in yet.
+fn auth_routes(state: &AppState) -> Router<AppState> {
+    Router::new()
+        .route("/providers", axum::routing::get(http::auth::providers))
+        .route("/init", axum::routing::get(http::auth::init))
+        .route("/complete", axum::routing::get(http::auth::callback))
+        .route("/local", axum::routing::post(http::auth::local))
+        .route("/logout", axum::routing::post(http::auth::logout))
+        .layer(middleware::from_fn_with_state(
+            (state.clone(), Some(Group::Auth)),
+            limit_addresses,
+        ))
+}
+
+// Routes that require an authenticated user.
+fn api_routes(state: &AppState) -> Router<AppState> {
+    Router::new()
+        .route("/auth/me", axum::routing::get(http::user::get_me))
+        .route("/auth/me", axum::routing::put(http::user::update_me))
+        .route("/auth/sessions", axum::routing::get(http::session::list))
+        .route(
+            "/auth/sessions",
+            axum::routing::delete(http::session::revoke_others),
+        )
+        .route(
+            "/auth/sessions/{id}",
+            axum::routing::delete(http::session::revoke),
+        )
+        .route("/auth/tokens", axum::routing::get(http::token::list))
+        .route("/auth/tokens", axum::routing::post(http::token::create))
+        .route(
+            "/auth/tokens/{id}",
+            axum::routing::delete(http::token::revoke),
+        )
+        .route("/audit", axum::routing::get(http::audit::list))
+        .route("/households", axum::routing::get(http::household::list))
+        .route("/households", axum::routing::post(http::household::create))
+        .route(
+            "/households/{id}/active",
+            axum::routing::put(http::household::activate),
+        )
+        .route(
+            "/households/{id}/members",
+            axum::routing::post(http::household::add_member),
+        )
+        .route(
+            "/households/{id}/members/{user_id}",
+            axum::routing::put(http::household::set_member_role),
+        )
+        .route(
+            "/households/{id}/members/{user_id}",
+            axum::routing::delete(http::household::remove_member),
+        )
+        .route("/recipes", axum::routing::get(http::recipe::list))
+        .route("/recipes", axum::routing::post(http::recipe::create))
+        .route("/recipes/{id}", axum::routing::get(http::recipe::get))
+        .route("/recipes/{id}", axum::routing::put(http::recipe::update))
+        .route(
+            "/recipes/{id}/images/{image_id}/framing",
+            axum::routing::get(http::image::get_framing),
+        )
+        .route(
+            "/recipes/{id}/images/{image_id}/framing",
+            axum::routing::put(http::image::update_framing),
+        )
+        .route(
+            "/recipes/{id}/shares",
+            axum::routing::get(http::share::list),
+        )
+        .route(
+            "/recipes/{id}/shares",
+            axum::routing::post(http::share::create),
+        )
+        .route(
+            "/recipes/{id}/shares/{share_id}",
+            axum::routing::delete(http::share::revoke),
+        )
+        .route("/tags", axum::routing::post(http::tag::create))
+        .route("/tags", axum::routing::get(http::tag::get_all))
+        //
+        // Nested /images router with large max body size
+        .nest(
+            "/images",
+            Router::new()
+                .route("/", axum::routing::post(http::image::upload))
+                .route("/import", axum::routing::post(http::image::import))
+                // uploads are limited on top of being writes, the layers only apply to the routes
+                // above
+                .route_layer(middleware::from_fn_with_state(
+                    (state.clone(), Some(Group::ImageUpload)),
+                    limit_users,
+                ))
+                .route_layer(middleware::from_fn_with_state(
+                    (state.clone(), Some(Group::ImageUpload)),
+                    limit_addresses,
+                ))
+                .route("/{id}", axum::routing::get(http::image::get))
+                .route(
+                    "/{id}/{variant}/{key}",
+                    axum::routing::get(http::image::get_generated),
+                )
+                .layer(DefaultBodyLimit::max(MAX_IMAGE_BODY_SIZE)),
+        )
+        .layer(middleware::from_fn_with_state(
+            (state.clone(), None),
+            limit_users,
+        ))
+        .layer(middleware::from_fn_with_state(
+            state.clone(),
+            auth_middleware,
+        ))
+        // addresses are limited before authenticating, so that failed attempts count too
+        .layer(middleware::from_fn_with_state(
+            (state.clone(), None),
+            limit_addresses,
+        ))
+}
+
+// Routes for share links, which bypass authentication and only expose the shared recipe.
+fn shared_routes(state: &AppState) -> Router<AppState> {
+    Router::new()
+        .route("/{token}", axum::routing::get(http::share::get))
+        .route(
+            "/{token}/images/{id}",
+            axum::routing::get(http::share::get_image),
+        )
+        .route(
+            "/{token}/images/{id}/{variant}/{key}",
+            axum::routing::get(http::share::get_generated_image),
+        )
+        .layer(middleware::from_fn_with_state(
+            (state.clone(), None),
+            limit_addresses,
+        ))
+}
+
 // from axum graceful shutdown example
 async fn shutdown_signal() {
     let ctrl_c = async {
@@ -188,54 +322,79 @@ async fn shutdown_signal() {
 
 async fn handle_base_redirect(
     State(state): State<AppState>,
+    ConnectInfo(address): ConnectInfo<SocketAddr>,
+    headers: HeaderMap,
     jar: CookieJar,
 ) -> (CookieJar, Redirect) {
+    if let Some(Ok(_)) = check_proxy_user(&state, address, &headers).await {
+        return (jar, Redirect::temporary("/recipes"));
+    }
+
     let previous_jar = jar.clone();
 
-    match check_if_authenticated(
-        &state.config,
-        &state.session_store,
-        &state.oidc_provider,
-        jar,
-    )
-    .await
-    {
+    match check_if_authenticated(&state, jar).await {
         Ok((jar, _)) => (jar, Redirect::temporary("/recipes")),
         Err(_) => (previous_jar, Redirect::temporary("/login")),
     }
 }
 
-async fn get_me(Extension(user): Extension<AuthenticatedUser>) -> Result<String, Error> {
-    Ok(format!("you are: {}", user.id))
-}
-
 #[derive(Clone)]
 pub struct AuthenticatedUser {
-    id: String,
+    pub(super) id: String,
+    // None when authenticated with a token or by a proxy
+    pub(super) session_id: Option<String>,
+    // tokens act in the household they were created in and cannot switch
+    pub(super) token: bool,
+    pub(super) household_id: domain::household::Id,
+    pub(super) role: domain::household::Role,
 }
 
 impl From<AuthenticatedUser> for domain::user::Authenticated {
     fn from(val: AuthenticatedUser) -> Self {
-        Self { id: val.id }
+        Self {
+            id: val.id,
+            household_id: val.household_id,
+            role: val.role,
+        }
     }
 }
 
 async fn auth_middleware(
     State(state): State<AppState>,
+    ConnectInfo(address): ConnectInfo<SocketAddr>,
     jar: CookieJar,
     mut req: Request,
     next: Next,
 ) -> Result<(CookieJar, Response), Error> {
+    // scripts authenticate with a token instead of the session cookie
+    if let Some(token) = bearer_token(req.headers()) {
+        let user = match check_token(&state, &token, req.method()).await {
+            Ok(user) => user,
+            Err(err) => return Ok((jar, err.into_response())),
+        };
+
+        req.extensions_mut().insert(user);
+
+        return Ok((jar, next.run(req).await));
+    }
+
+    if let Some(user) = check_proxy_user(&state, address, req.headers()).await {
+        let user = match user.and_then(|user| {
+            check_origin(&state.config, req.method(), req.headers())?;
+            Ok(user)
+        }) {
+            Ok(user) => user,
+            Err(err) => return Ok((jar, err.into_response())),
+        };
+
+        req.extensions_mut().insert(user);
+
+        return Ok((jar, next.run(req).await));
+    }
+
     let previous_jar = jar.clone();
 
-    let (jar, user) = match check_if_authenticated(
-        &state.config,
-        &state.session_store,
-        &state.oidc_provider,
-        jar,
-    )
-    .await
-    {
+    let (jar, user) = match check_if_authenticated(&state, jar).await {
         Ok(r) => r,
         Err(err) => {
             let jar = previous_jar.remove(Cookie::from("id"));
@@ -248,6 +407,42 @@ async fn auth_middleware(
     Ok((jar, next.run(req).await))
 }
 
+// Rate limits requests by the address of the client. Routes without a group of their own are
+// reads or writes by method.
+async fn limit_addresses(
+    State((state, group)): State<(AppState, Option<Group>)>,
+    ConnectInfo(address): ConnectInfo<SocketAddr>,
+    req: Request,
+    next: Next,
+) -> Result<Response, Error> {
+    if let Some(limiter) = &state.rate_limiter {
+        let group = group.unwrap_or_else(|| Group::of(req.method()));
+        let address =
+            proxy_auth::client_address(&state.config.trusted_proxies, address.ip(), req.headers());
+
+        limiter.check(group, rate_limit::Key::Address(address))?;
+    }
+
+    Ok(next.run(req).await)
+}
+
+// Rate limits requests by the authenticated user, so that spreading requests over several
+// addresses or tokens does not get around the limit.
+async fn limit_users(
+    State((state, group)): State<(AppState, Option<Group>)>,
+    req: Request,
+    next: Next,
+) -> Result<Response, Error> {
+    let user = req.extensions().get::<AuthenticatedUser>();
+    if let (Some(limiter), Some(user)) = (&state.rate_limiter, user) {
+        let group = group.unwrap_or_else(|| Group::of(req.method()));
+
+        limiter.check(group, rate_limit::Key::User(user.id.clone()))?;
+    }
+
+    Ok(next.run(req).await)
+}
+
 async fn static_cache_middleware(request: Request, next: Next) -> Response {
     let mut response = next.run(request).await;
     if !response.headers().contains_key("Cache-Control") {
@@ -271,9 +466,7 @@ async fn no_cache_middleware(request: Request, next: Next) -> Response {
 }
 
 async fn check_if_authenticated(
-    config: &Config,
-    session_store: &SessionStore,
-    oidc_provider: &oidc::Provider,
+    state: &AppState,
     jar: CookieJar,
 ) -> Result<(CookieJar, AuthenticatedUser), Error> {
     let session_key = jar
@@ -283,12 +476,18 @@ async fn check_if_authenticated(
 
     // try to fetch the session
     let session = core::session::get(
-        session_store,
-        oidc_provider,
+        &state.session_store,
+        &state.datasource,
+        &state.oidc_providers,
+        state.config.local_auth.as_ref(),
         SessionKey(session_key.to_string()),
     )
     .await?;
 
+    // looked up on every request, so that switching households or changing roles applies right
+    // away
+    let membership = session_membership(state, &session).await?;
+
     // Update the cookie if the session key changed.
     let jar = if session_key == session.key.to_string() {
         jar
@@ -296,7 +495,7 @@ async fn check_if_authenticated(
         jar.add(
             Cookie::build(("id", session.key.to_string()))
                 .http_only(true)
-                .secure(!config.insecure_cookies)
+                .secure(!state.config.insecure_cookies)
                 .path("/")
                 .same_site(cookie::SameSite::Strict)
                 .max_age(cookie::time::Duration::seconds(
@@ -307,11 +506,122 @@ async fn check_if_authenticated(
 
     let user = AuthenticatedUser {
         id: session.user_id,
+        session_id: Some(session.id),
+        token: false,
+        household_id: membership.household_id,
+        role: membership.role,
     };
 
     Ok((jar, user))
 }
 
+// The household the session acts in, or the user's default household for sessions started before
+// households were kept per session and once the user leaves the household of the session.
+async fn session_membership(
+    state: &AppState,
+    session: &core::session::Active,
+) -> Result<domain::household::Membership, Error> {
+    if let Some(household_id) = &session.household_id {
+        let household_id = household_id.as_str().try_into()?;
+        match core::household::member(&state.datasource, &session.user_id, &household_id).await {
+            Err(Error::Forbidden(_)) => {}
+            result => return result,
+        }
+    }
+
+    core::household::active(&state.datasource, &session.user_id).await
+}
+
+// The user signed in by a trusted proxy, None when proxy authentication is off or the request
+// did not come through a trusted proxy with a signed in user.
+async fn check_proxy_user(
+    state: &AppState,
+    address: SocketAddr,
+    headers: &HeaderMap,
+) -> Option<Result<AuthenticatedUser, Error>> {
+    let identity = proxy_auth::identity(
+        state.config.proxy_auth.as_ref()?,
+        &state.config.trusted_proxies,
+        address.ip(),
+        headers,
+    )?;
+
+    let user = async {
+        let user_id =
+            core::user::on_proxy_authenticated(&state.datasource, &state.proxy_users, identity)
+                .await?;
+        let membership = core::household::active(&state.datasource, &user_id).await?;
+
+        Ok(AuthenticatedUser {
+            id: user_id,
+            // the proxy keeps the session
+            session_id: None,
+            token: false,
+            household_id: membership.household_id,
+            role: membership.role,
+        })
+    };
+
+    Some(user.await)
+}
+
+// The proxy signs the user in on every request that reaches it, including ones started by other
+// sites, so changes are only accepted from pages of the server's own origin.
+fn check_origin(config: &Config, method: &Method, headers: &HeaderMap) -> Result<(), Error> {
+    if method.is_safe() {
+        return Ok(());
+    }
+
+    let origin = headers.get(ORIGIN).and_then(|origin| origin.to_str().ok());
+    if origin != Some(config.origin.trim_end_matches('/')) {
+        return Err(Error::Forbidden(anyhow!(
+            "{method} from origin {origin:?} is not allowed for users signed in by a proxy"
+        )));
+    }
+
+    Ok(())
+}
+
+fn bearer_token(headers: &HeaderMap) -> Option<String> {
+    headers
+        .get(AUTHORIZATION)?
+        .to_str()
+        .ok()?
+        .strip_prefix("Bearer ")
+        .map(|token| token.trim().to_owned())
+}
+
+async fn check_token(
+    state: &AppState,
+    token: &str,
+    method: &Method,
+) -> Result<AuthenticatedUser, Error> {
+    let token = core::token::authenticate(&state.datasource, token).await?;
+
+    // read-only tokens can only make requests that do not change anything
+    if token.read_only && !method.is_safe() {
+        return Err(Error::Forbidden(anyhow!(
+            "read-only token of user {} cannot {method}",
+            token.user_id
+        )));
+    }
+
+    let membership =
+        core::household::member(&state.datasource, &token.user_id, &token.household_id).await?;
+
+    Ok(AuthenticatedUser {
+        id: token.user_id,
+        session_id: None,
+        token: true,
+        household_id: membership.household_id,
+        role: if token.read_only {
+            membership.role.min(domain::household::Role::Viewer)
+        } else {
+            membership.role
+        },
+    })
+}
+
 impl IntoResponse for Error {
     fn into_response(self) -> Response {
         match self {
@@ -321,6 +631,10 @@ impl IntoResponse for Error {
                 println!("error: {err:?}");
                 (StatusCode::UNAUTHORIZED, "Unauthenticated.").into_response()
             }
+            Error::Forbidden(err) => {
+                println!("error: {err:?}");
+                (StatusCode::FORBIDDEN, "Forbidden.").into_response()
+            }
             Error::DomainValidation(err) => {
                 println!("error: {err:?}");
                 (StatusCode::UNPROCESSABLE_ENTITY, err.to_string()).into_response()
@@ -329,6 +643,26 @@ impl IntoResponse for Error {
                 println!("error: {err:?}");
                 (StatusCode::UNPROCESSABLE_ENTITY, err.to_string()).into_response()
             }
+            Error::RangeNotSatisfiable { size } => (
+                StatusCode::RANGE_NOT_SATISFIABLE,
+                [("Content-Range", format!("bytes */{size}"))],
+            )
+                .into_response(),
+            Error::Unavailable {
+                message,
+                retry_after,
+            } => (
+                StatusCode::SERVICE_UNAVAILABLE,
+                [("Retry-After", retry_after.to_string())],
+                message,
+            )
+                .into_response(),
+            Error::TooManyRequests { retry_after } => (
+                StatusCode::TOO_MANY_REQUESTS,
+                [("Retry-After", retry_after.to_string())],
+                "Too many requests.",
+            )
+                .into_response(),
             Error::Other(err) => {
                 println!("error: {err:?}");
                 (StatusCode::INTERNAL_SERVER_ERROR, "Internal error").into_response()
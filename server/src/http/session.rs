@@ -0,0 +1,70 @@
+use axum::{
+    Extension,
+    extract::{Path, State},
+    http::StatusCode,
+};
+use serde::Serialize;
+
+use crate::core::{self, Error};
+
+use super::{
+    responses,
+    server::{AppState, AuthenticatedUser},
+};
+
+#[derive(Serialize)]
+pub struct Session {
+    id: String,
+    created_at: chrono::DateTime<chrono::Utc>,
+    last_used_at: chrono::DateTime<chrono::Utc>,
+    user_agent: Option<String>,
+    client: Option<String>,
+    // whether this is the session making the request
+    current: bool,
+}
+
+pub async fn list(
+    State(state): State<AppState>,
+    Extension(user): Extension<AuthenticatedUser>,
+) -> Result<axum::response::Json<responses::Data<Vec<Session>>>, Error> {
+    let sessions = core::session::list(&state.session_store, &user.id).await?;
+
+    Ok(axum::response::Json(responses::Data {
+        data: sessions
+            .into_iter()
+            .map(|session| Session {
+                current: user.session_id.as_ref() == Some(&session.id),
+                id: session.id,
+                created_at: session.created_at,
+                last_used_at: session.last_used_at,
+                user_agent: session.user_agent,
+                client: session.client,
+            })
+            .collect(),
+    }))
+}
+
+pub async fn revoke(
+    State(state): State<AppState>,
+    Extension(user): Extension<AuthenticatedUser>,
+    Path(id): Path<String>,
+) -> Result<StatusCode, Error> {
+    core::session::revoke(&state.session_store, &state.datasource, &user.id, &id).await?;
+
+    Ok(StatusCode::NO_CONTENT)
+}
+
+pub async fn revoke_others(
+    State(state): State<AppState>,
+    Extension(user): Extension<AuthenticatedUser>,
+) -> Result<StatusCode, Error> {
+    core::session::revoke_others(
+        &state.session_store,
+        &state.datasource,
+        &user.id,
+        user.session_id.as_deref(),
+    )
+    .await?;
+
+    Ok(StatusCode::NO_CONTENT)
+}
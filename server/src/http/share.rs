@@ -0,0 +1,121 @@
+use axum::{
+    Extension, Json,
+    extract::{Path, State},
+    http::{HeaderMap, StatusCode},
+};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    core::{self, Error},
+    domain,
+};
+
+use super::{
+    image, recipe, responses,
+    server::{AppState, AuthenticatedUser},
+};
+
+#[derive(Serialize)]
+pub struct Share {
+    id: domain::share::Id,
+    expires_at: Option<chrono::DateTime<chrono::Utc>>,
+    created_at: chrono::DateTime<chrono::Utc>,
+}
+
+#[derive(Deserialize)]
+pub struct CreateParams {
+    expires_at: Option<chrono::DateTime<chrono::Utc>>,
+}
+
+#[derive(Serialize)]
+pub struct Created {
+    id: domain::share::Id,
+    // only returned when the share is created
+    token: String,
+}
+
+pub async fn list(
+    State(state): State<AppState>,
+    Extension(user): Extension<AuthenticatedUser>,
+    Path(recipe_id): Path<domain::recipe::Id>,
+) -> Result<axum::response::Json<responses::Data<Vec<Share>>>, Error> {
+    let shares = core::share::list(&state.datasource, &user.household_id, &recipe_id).await?;
+
+    Ok(axum::response::Json(responses::Data {
+        data: shares
+            .into_iter()
+            .map(|share| Share {
+                id: share.id,
+                expires_at: share.expires_at,
+                created_at: share.created_at,
+            })
+            .collect(),
+    }))
+}
+
+pub async fn create(
+    State(state): State<AppState>,
+    Extension(user): Extension<AuthenticatedUser>,
+    Path(recipe_id): Path<domain::recipe::Id>,
+    Json(request): Json<CreateParams>,
+) -> Result<axum::response::Json<responses::Data<Created>>, Error> {
+    let creating = domain::share::Creating {
+        expires_at: request.expires_at,
+    };
+
+    let created =
+        core::share::create(&state.datasource, &user.into(), &recipe_id, creating).await?;
+
+    Ok(axum::response::Json(responses::Data {
+        data: Created {
+            id: created.id,
+            token: created.token,
+        },
+    }))
+}
+
+pub async fn revoke(
+    State(state): State<AppState>,
+    Extension(user): Extension<AuthenticatedUser>,
+    Path((recipe_id, id)): Path<(domain::recipe::Id, domain::share::Id)>,
+) -> Result<StatusCode, Error> {
+    core::share::revoke(&state.datasource, &user.into(), &recipe_id, &id).await?;
+
+    Ok(StatusCode::NO_CONTENT)
+}
+
+// Public, anyone with the link can see the recipe.
+pub async fn get(
+    State(state): State<AppState>,
+    Path(token): Path<String>,
+) -> Result<axum::response::Json<responses::Data<recipe::Recipe>>, Error> {
+    let recipe = core::share::open(&state.datasource, &token).await?;
+
+    Ok(axum::response::Json(responses::Data {
+        data: recipe.into(),
+    }))
+}
+
+pub async fn get_image(
+    headers: HeaderMap,
+    State(state): State<AppState>,
+    Path((token, id)): Path<(String, String)>,
+) -> Result<axum::response::Response, Error> {
+    core::share::image(&state.datasource, &token, &id).await?;
+
+    let etag = format!(r#""{id}""#);
+    image::serve(&headers, &state, &id, core::image::Variant::Original, &etag).await
+}
+
+pub async fn get_generated_image(
+    headers: HeaderMap,
+    State(state): State<AppState>,
+    Path((token, id, variant, key)): Path<(String, String, String, String)>,
+) -> Result<axum::response::Response, Error> {
+    let etag = format!(r#""{id}-{variant}-{key}""#);
+    let variant = core::image::Variant::generated(&variant, &key)?;
+
+    core::share::image(&state.datasource, &token, &id).await?;
+
+    image::serve(&headers, &state, &id, variant, &etag).await
+}
@@ -38,8 +38,9 @@ pub async fn create(
 
 pub async fn get_all(
     State(state): State<AppState>,
+    Extension(user): Extension<AuthenticatedUser>,
 ) -> Result<axum::response::Json<responses::Data<Vec<Tag>>>, Error> {
-    let result = core::tag::get_all(&state.datasource).await?;
+    let result = core::tag::get_all(&state.datasource, &user.household_id).await?;
 
     Ok(axum::response::Json(responses::Data {
         data: result
@@ -0,0 +1,114 @@
+use anyhow::anyhow;
+use axum::{
+    Extension, Json,
+    extract::{Path, State},
+    http::StatusCode,
+};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    core::{self, Error},
+    domain,
+};
+
+use super::{
+    responses,
+    server::{AppState, AuthenticatedUser},
+};
+
+#[derive(Serialize)]
+pub struct Token {
+    id: domain::token::Id,
+    name: String,
+    read_only: bool,
+    expires_at: Option<chrono::DateTime<chrono::Utc>>,
+    created_at: chrono::DateTime<chrono::Utc>,
+    last_used_at: Option<chrono::DateTime<chrono::Utc>>,
+    // the household the token acts in
+    household_id: domain::household::Id,
+}
+
+#[derive(Deserialize)]
+pub struct CreateParams {
+    name: String,
+    read_only: Option<bool>,
+    expires_at: Option<chrono::DateTime<chrono::Utc>>,
+}
+
+#[derive(Serialize)]
+pub struct Created {
+    id: domain::token::Id,
+    // only returned when the token is created
+    token: String,
+}
+
+pub async fn list(
+    State(state): State<AppState>,
+    Extension(user): Extension<AuthenticatedUser>,
+) -> Result<axum::response::Json<responses::Data<Vec<Token>>>, Error> {
+    let tokens = core::token::list(&state.datasource, &user.id).await?;
+
+    Ok(axum::response::Json(responses::Data {
+        data: tokens
+            .into_iter()
+            .map(|token| Token {
+                id: token.id,
+                name: token.name.into(),
+                read_only: token.read_only,
+                expires_at: token.expires_at,
+                created_at: token.created_at,
+                last_used_at: token.last_used_at,
+                household_id: token.household_id,
+            })
+            .collect(),
+    }))
+}
+
+pub async fn create(
+    State(state): State<AppState>,
+    Extension(user): Extension<AuthenticatedUser>,
+    Json(request): Json<CreateParams>,
+) -> Result<axum::response::Json<responses::Data<Created>>, Error> {
+    signed_in(&user)?;
+
+    let creating = domain::token::Creating {
+        name: request.name.try_into()?,
+        read_only: request.read_only.unwrap_or(false),
+        expires_at: request.expires_at,
+        household_id: user.household_id.clone(),
+    };
+
+    let created = core::token::create(&state.datasource, &user.id, creating).await?;
+
+    Ok(axum::response::Json(responses::Data {
+        data: Created {
+            id: created.id,
+            token: created.token,
+        },
+    }))
+}
+
+pub async fn revoke(
+    State(state): State<AppState>,
+    Extension(user): Extension<AuthenticatedUser>,
+    Path(id): Path<domain::token::Id>,
+) -> Result<StatusCode, Error> {
+    signed_in(&user)?;
+
+    core::token::revoke(&state.datasource, &user.id, &id).await?;
+
+    Ok(StatusCode::NO_CONTENT)
+}
+
+// Tokens are managed by a signed in user, so that a token cannot be used to mint a longer lived one
+// or to revoke the others.
+fn signed_in(user: &AuthenticatedUser) -> Result<(), Error> {
+    if user.token {
+        return Err(Error::Forbidden(anyhow!(
+            "token of user {} cannot manage tokens",
+            user.id
+        )));
+    }
+
+    Ok(())
+}
@@ -0,0 +1,97 @@
+use axum::{Extension, Json, extract::State};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    core::{self, Error},
+    domain,
+};
+
+use super::{
+    household::Household,
+    responses,
+    server::{AppState, AuthenticatedUser},
+};
+
+#[derive(Serialize)]
+pub struct Me {
+    id: String,
+    name: String,
+    avatar_image_id: Option<String>,
+    // the household the user is acting in, and what they may do there
+    household_id: domain::household::Id,
+    role: domain::household::Role,
+    households: Vec<Household>,
+    preferences: Preferences,
+}
+
+#[derive(Serialize, Deserialize, Default)]
+pub struct Preferences {
+    units: Option<domain::user::Units>,
+    default_servings: Option<u32>,
+}
+
+// Replaces the profile, a name of null goes back to the name given by the provider.
+#[derive(Deserialize)]
+pub struct UpdateParams {
+    name: Option<String>,
+    avatar_image_id: Option<String>,
+    #[serde(default)]
+    preferences: Preferences,
+}
+
+pub async fn get_me(
+    State(state): State<AppState>,
+    Extension(user): Extension<AuthenticatedUser>,
+) -> Result<Json<responses::Data<Me>>, Error> {
+    let profile = core::user::get(&state.datasource, &user.id).await?;
+
+    Ok(Json(responses::Data {
+        data: me(&state, user, profile).await?,
+    }))
+}
+
+pub async fn update_me(
+    State(state): State<AppState>,
+    Extension(user): Extension<AuthenticatedUser>,
+    Json(request): Json<UpdateParams>,
+) -> Result<Json<responses::Data<Me>>, Error> {
+    let updating = domain::user::Updating {
+        name: request.name.map(TryInto::try_into).transpose()?,
+        avatar_image_id: request
+            .avatar_image_id
+            .as_deref()
+            .map(TryInto::try_into)
+            .transpose()?,
+        preferences: domain::user::Preferences {
+            units: request.preferences.units,
+            default_servings: request
+                .preferences
+                .default_servings
+                .map(TryInto::try_into)
+                .transpose()?,
+        },
+    };
+
+    let profile = core::user::update(&state.datasource, user.clone().into(), updating).await?;
+
+    Ok(Json(responses::Data {
+        data: me(&state, user, profile).await?,
+    }))
+}
+
+async fn me(state: &AppState, user: AuthenticatedUser, profile: domain::User) -> Result<Me, Error> {
+    let households = core::household::list(&state.datasource, &user.id, &user.household_id).await?;
+
+    Ok(Me {
+        id: profile.id,
+        name: profile.name,
+        avatar_image_id: profile.avatar_image_id,
+        household_id: user.household_id,
+        role: user.role,
+        households: households.into_iter().map(Household::from).collect(),
+        preferences: Preferences {
+            units: profile.preferences.units,
+            default_servings: profile.preferences.default_servings.map(u32::from),
+        },
+    })
+}
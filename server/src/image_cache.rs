@@ -0,0 +1,378 @@
+use std::{
+    collections::{BTreeMap, HashMap},
+    path::PathBuf,
+    sync::{Arc, Mutex},
+};
+
+use anyhow::anyhow;
+use async_trait::async_trait;
+use futures::StreamExt;
+use tokio::io::AsyncWriteExt;
+
+use crate::{
+    config, file,
+    imagestore::{self, ByteStream, Error, Object, Range},
+};
+
+// Cached images are stored with their own extensions, so that only files the cache created are
+// ever removed from its directory.
+const CACHED_EXTENSION: &str = "cached";
+const TEMPORARY_EXTENSION: &str = "caching";
+
+fn cached_name(path: &str) -> String {
+    format!("{path}.{CACHED_EXTENSION}")
+}
+
+// A read-through cache that keeps recently read images on local disk, in front of another backend.
+// Entries are keyed by path, so every stored object (originals and any derived sizes or formats)
+// is cached the same way. Once the cache grows past its size limit, the least recently used
+// entries are removed.
+pub struct ImageBackend {
+    inner: Box<dyn imagestore::ImageBackend + Send + Sync>,
+    cached: file::ImageBackend,
+    cache: Arc<Cache>,
+}
+
+// The cached files, shared with the streams writing images into the cache as they are served.
+struct Cache {
+    directory: PathBuf,
+    max_size_bytes: u64,
+    entries: Mutex<Entries>,
+}
+
+#[derive(Default)]
+struct Entries {
+    by_path: HashMap<String, Entry>,
+    // paths by the last time they were used, the first being the least recently used
+    by_use: BTreeMap<u64, String>,
+    clock: u64,
+    size: u64,
+}
+
+struct Entry {
+    size: u64,
+    last_used: u64,
+}
+
+impl Entries {
+    fn touch(&mut self, path: &str) -> bool {
+        self.clock += 1;
+        let clock = self.clock;
+
+        let Some(entry) = self.by_path.get_mut(path) else {
+            return false;
+        };
+        self.by_use.remove(&entry.last_used);
+        self.by_use.insert(clock, path.to_owned());
+        entry.last_used = clock;
+
+        true
+    }
+
+    fn insert(&mut self, path: &str, size: u64) {
+        self.remove(path);
+        self.clock += 1;
+
+        self.by_path.insert(
+            path.to_owned(),
+            Entry {
+                size,
+                last_used: self.clock,
+            },
+        );
+        self.by_use.insert(self.clock, path.to_owned());
+        self.size += size;
+    }
+
+    fn remove(&mut self, path: &str) {
+        if let Some(entry) = self.by_path.remove(path) {
+            self.by_use.remove(&entry.last_used);
+            self.size -= entry.size;
+        }
+    }
+
+    // Removes least recently used entries until the cache fits within max_size_bytes, returning
+    // the paths of the removed entries.
+    fn evict(&mut self, max_size_bytes: u64) -> Vec<String> {
+        let mut evicted = vec![];
+        while self.size > max_size_bytes {
+            let Some((_, path)) = self.by_use.pop_first() else {
+                break;
+            };
+            if let Some(entry) = self.by_path.remove(&path) {
+                self.size -= entry.size;
+            }
+            evicted.push(path);
+        }
+
+        evicted
+    }
+}
+
+impl ImageBackend {
+    pub async fn new(
+        inner: Box<dyn imagestore::ImageBackend + Send + Sync>,
+        config: &config::ImageCache,
+    ) -> Result<Self, Error> {
+        if config.max_size_bytes == 0 {
+            return Err(Error::Config(anyhow!(
+                "Image cache max size must be greater than zero."
+            )));
+        }
+
+        let cached = file::ImageBackend::new(&config.directory).await?;
+        let directory = PathBuf::from(&config.directory);
+
+        // pick up entries left by a previous run, oldest first
+        let mut existing = vec![];
+        let mut dir = tokio::fs::read_dir(&directory).await?;
+        while let Some(item) = dir.next_entry().await? {
+            let path = item.path();
+            let metadata = item.metadata().await?;
+            if !metadata.is_file() {
+                continue;
+            }
+
+            let extension = path.extension().and_then(|extension| extension.to_str());
+            if extension == Some(TEMPORARY_EXTENSION) {
+                tokio::fs::remove_file(&path).await?;
+                continue;
+            }
+
+            if let Some(name) = item
+                .file_name()
+                .to_str()
+                .and_then(|name| name.strip_suffix(&format!(".{CACHED_EXTENSION}")))
+            {
+                existing.push((metadata.modified()?, name.to_owned(), metadata.len()));
+            }
+        }
+        existing.sort();
+
+        let mut entries = Entries::default();
+        for (_, name, size) in existing {
+            entries.insert(&name, size);
+        }
+        let evicted = entries.evict(config.max_size_bytes);
+
+        let cache = Arc::new(Cache {
+            directory,
+            max_size_bytes: config.max_size_bytes,
+            entries: Mutex::new(entries),
+        });
+        cache.remove_files(evicted).await;
+
+        Ok(ImageBackend {
+            inner,
+            cached,
+            cache,
+        })
+    }
+}
+
+impl Cache {
+    fn entries(&self) -> std::sync::MutexGuard<'_, Entries> {
+        // the entries are always left consistent, so they are safe to use after a panic
+        self.entries
+            .lock()
+            .unwrap_or_else(std::sync::PoisonError::into_inner)
+    }
+
+    // Moves a completely written temporary file into place, evicting entries to make room for it.
+    async fn insert(
+        &self,
+        path: &str,
+        temporary: &std::path::Path,
+        size: u64,
+    ) -> Result<(), Error> {
+        tokio::fs::rename(temporary, self.directory.join(cached_name(path))).await?;
+
+        let evicted = {
+            let mut entries = self.entries();
+            entries.insert(path, size);
+            entries.evict(self.max_size_bytes)
+        };
+        self.remove_files(evicted).await;
+
+        Ok(())
+    }
+
+    async fn remove_files(&self, paths: Vec<String>) {
+        for path in paths {
+            match tokio::fs::remove_file(self.directory.join(cached_name(&path))).await {
+                Err(err) if err.kind() != std::io::ErrorKind::NotFound => {
+                    println!("Failed to remove cached image {path}: {err}.");
+                }
+                _ => {}
+            }
+        }
+    }
+}
+
+// An image being written to a temporary file in the cache as it is read from the inner backend,
+// so a partially written image is never served. The temporary file is removed if the image is not
+// completely written, for example when the client goes away.
+struct Filling {
+    cache: Arc<Cache>,
+    path: String,
+    temporary: PathBuf,
+    file: tokio::fs::File,
+    written: u64,
+    // moved into place, so there is no temporary file left to remove
+    finished: bool,
+}
+
+impl Filling {
+    async fn new(cache: Arc<Cache>, path: &str) -> Result<Self, Error> {
+        let temporary = cache.directory.join(format!(
+            "{path}.{}.{TEMPORARY_EXTENSION}",
+            ulid::Ulid::new()
+        ));
+        let file = tokio::fs::File::create(&temporary).await?;
+
+        Ok(Filling {
+            cache,
+            path: path.to_owned(),
+            temporary,
+            file,
+            written: 0,
+            finished: false,
+        })
+    }
+
+    async fn write(&mut self, chunk: &[u8]) -> Result<(), Error> {
+        self.file.write_all(chunk).await?;
+        self.written += u64::try_from(chunk.len()).map_err(|err| Error::Other(err.into()))?;
+
+        Ok(())
+    }
+
+    async fn finish(mut self, size: u64) -> Result<(), Error> {
+        if self.written != size {
+            return Err(Error::Other(anyhow!(
+                "Expected {size} bytes, but read {}.",
+                self.written
+            )));
+        }
+
+        self.file.flush().await?;
+        self.cache
+            .insert(&self.path, &self.temporary, self.written)
+            .await?;
+        self.finished = true;
+
+        Ok(())
+    }
+
+    // Writes the image as the stream is read.
+    fn tee(stream: ByteStream, filling: Option<Self>, size: u64) -> ByteStream {
+        Box::pin(futures::stream::unfold(
+            (stream, filling),
+            move |(mut stream, mut filling)| async move {
+                match stream.next().await {
+                    Some(Ok(chunk)) => {
+                        if let Some(mut writing) = filling.take() {
+                            match writing.write(&chunk).await {
+                                Ok(()) => filling = Some(writing),
+                                Err(err) => {
+                                    println!("Failed to cache image {}: {err:?}.", writing.path);
+                                }
+                            }
+                        }
+                        Some((Ok(chunk), (stream, filling)))
+                    }
+                    Some(Err(err)) => Some((Err(err), (stream, None))),
+                    None => {
+                        if let Some(filling) = filling {
+                            let path = filling.path.clone();
+                            if let Err(err) = filling.finish(size).await {
+                                println!("Failed to cache image {path}: {err:?}.");
+                            }
+                        }
+                        None
+                    }
+                }
+            },
+        ))
+    }
+}
+
+impl Drop for Filling {
+    fn drop(&mut self) {
+        if self.finished {
+            return;
+        }
+
+        // streams are dropped on the runtime, which must not block on the file system
+        let temporary = std::mem::take(&mut self.temporary);
+        if let Ok(runtime) = tokio::runtime::Handle::try_current() {
+            runtime.spawn(async move {
+                let _ = tokio::fs::remove_file(temporary).await;
+            });
+        }
+    }
+}
+
+#[async_trait]
+impl imagestore::ImageBackend for ImageBackend {
+    async fn get(&self, path: &str, range: Option<Range>) -> Result<Object, Error> {
+        if self.cache.entries().touch(path) {
+            match self.cached.get(&cached_name(path), range).await {
+                Ok(object) => return Ok(object),
+                // removed since it was looked up, fall back to the inner backend
+                Err(Error::NotFound(_)) => self.cache.entries().remove(path),
+                Err(err) => return Err(err),
+            }
+        }
+
+        // only whole images are cached, so ranges are read from the inner backend until the image
+        // is read whole
+        if range.is_some() {
+            return self.inner.get(path, range).await;
+        }
+
+        let object = self.inner.get(path, None).await?;
+
+        if object.size > self.cache.max_size_bytes {
+            return Ok(object);
+        }
+
+        let filling = match Filling::new(self.cache.clone(), path).await {
+            Ok(filling) => Some(filling),
+            Err(err) => {
+                println!("Failed to cache image {path}: {err:?}.");
+                None
+            }
+        };
+
+        Ok(Object {
+            size: object.size,
+            range: None,
+            stream: Filling::tee(object.stream, filling, object.size),
+        })
+    }
+
+    async fn upload(&self, path: &str, file: Vec<u8>) -> Result<(), Error> {
+        self.inner.upload(path, file).await
+    }
+
+    async fn exists(&self, path: &str) -> Result<bool, Error> {
+        if self.cache.entries().by_path.contains_key(path) {
+            return Ok(true);
+        }
+
+        self.inner.exists(path).await
+    }
+
+    async fn delete(&self, path: &str) -> Result<(), Error> {
+        self.cache.entries().remove(path);
+        self.cache.remove_files(vec![path.to_owned()]).await;
+
+        self.inner.delete(path).await
+    }
+
+    // Clients sent to a presigned url download from the inner backend directly, bypassing the cache.
+    fn presigned_url(&self, path: &str) -> Result<Option<String>, Error> {
+        self.inner.presigned_url(path)
+    }
+}
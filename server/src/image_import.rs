@@ -0,0 +1,261 @@
+use std::{
+    net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr},
+    sync::Arc,
+    time::Duration,
+};
+
+use anyhow::Context;
+use reqwest::{
+    Url,
+    dns::{Addrs, Name, Resolve, Resolving},
+    header::CONTENT_TYPE,
+    redirect,
+};
+use thiserror::Error;
+
+use crate::config;
+
+const MAX_REDIRECTS: usize = 5;
+
+#[derive(Error, Debug)]
+pub enum Error {
+    #[error("{0}")]
+    Invalid(String),
+
+    #[error("Image could not be downloaded: {0}")]
+    Fetch(#[source] reqwest::Error),
+
+    #[error(transparent)]
+    Other(#[from] anyhow::Error),
+}
+
+// Downloads images from user supplied urls. Since the server makes the request, every address
+// a url resolves to (including through redirects) must be public, otherwise the import could be
+// used to reach services on the server's private network.
+pub struct ImageImporter {
+    client: reqwest::Client,
+    max_size_bytes: u64,
+    allow_private_addresses: bool,
+}
+
+impl ImageImporter {
+    pub fn new(config: &config::ImageImport) -> Result<ImageImporter, Error> {
+        let allow_private_addresses = config.allow_private_addresses;
+
+        // a proxy would resolve the host itself, getting around the resolver
+        let client = reqwest::ClientBuilder::new()
+            .no_proxy()
+            .timeout(Duration::from_secs(config.timeout_seconds))
+            .dns_resolver(Arc::new(PublicResolver {
+                allow_private_addresses,
+            }))
+            .redirect(redirect::Policy::custom(move |attempt| {
+                if attempt.previous().len() >= MAX_REDIRECTS {
+                    attempt.error("Too many redirects.")
+                } else if let Err(err) = check_url(attempt.url(), allow_private_addresses) {
+                    attempt.error(err)
+                } else {
+                    attempt.follow()
+                }
+            }))
+            .build()
+            .context("Could not create image import client.")?;
+
+        Ok(ImageImporter {
+            client,
+            max_size_bytes: config.max_size_bytes,
+            allow_private_addresses,
+        })
+    }
+
+    pub async fn fetch(&self, url: &str) -> Result<Vec<u8>, Error> {
+        let url = Url::parse(url).map_err(|_| Error::Invalid("Image url is not valid.".into()))?;
+        check_url(&url, self.allow_private_addresses)?;
+
+        let mut response = self
+            .client
+            .get(url)
+            .send()
+            .await
+            .and_then(reqwest::Response::error_for_status)
+            .map_err(Error::Fetch)?;
+
+        let is_image = response
+            .headers()
+            .get(CONTENT_TYPE)
+            .and_then(|content_type| content_type.to_str().ok())
+            .is_some_and(|content_type| content_type.trim().starts_with("image/"));
+        if !is_image {
+            return Err(Error::Invalid("Url does not point to an image.".into()));
+        }
+
+        let too_large = || {
+            Error::Invalid(format!(
+                "Image is larger than the limit of {} bytes.",
+                self.max_size_bytes
+            ))
+        };
+
+        // the length is only a hint, the body is still limited while it is read
+        if response
+            .content_length()
+            .is_some_and(|length| length > self.max_size_bytes)
+        {
+            return Err(too_large());
+        }
+
+        let max_size_bytes = usize::try_from(self.max_size_bytes).unwrap_or(usize::MAX);
+        let mut bytes = vec![];
+        while let Some(chunk) = response.chunk().await.map_err(Error::Fetch)? {
+            if bytes.len() + chunk.len() > max_size_bytes {
+                return Err(too_large());
+            }
+            bytes.extend_from_slice(&chunk);
+        }
+
+        Ok(bytes)
+    }
+}
+
+fn check_url(url: &Url, allow_private_addresses: bool) -> Result<(), Error> {
+    if url.scheme() != "http" && url.scheme() != "https" {
+        return Err(Error::Invalid("Image url must use http or https.".into()));
+    }
+
+    // names are checked when they are resolved, addresses never are
+    let Some(host) = url.host_str() else {
+        return Err(Error::Invalid("Image url has no host.".into()));
+    };
+    let Ok(address) = host
+        .trim_start_matches('[')
+        .trim_end_matches(']')
+        .parse::<IpAddr>()
+    else {
+        return Ok(());
+    };
+
+    if allow_private_addresses || is_public(address) {
+        Ok(())
+    } else {
+        Err(Error::Invalid(
+            "Image url points to a private address.".into(),
+        ))
+    }
+}
+
+// Resolves names like the system resolver, but fails if any resolved address is not public.
+// Failing on any rather than filtering stops a name from mixing public and private addresses.
+struct PublicResolver {
+    allow_private_addresses: bool,
+}
+
+impl Resolve for PublicResolver {
+    fn resolve(&self, name: Name) -> Resolving {
+        let allow_private_addresses = self.allow_private_addresses;
+
+        Box::pin(async move {
+            let addresses: Vec<SocketAddr> =
+                tokio::net::lookup_host((name.as_str(), 0)).await?.collect();
+
+            if !allow_private_addresses && addresses.iter().any(|address| !is_public(address.ip()))
+            {
+                return Err(Error::Invalid(format!(
+                    "{} resolves to a private address.",
+                    name.as_str()
+                ))
+                .into());
+            }
+
+            let addresses: Addrs = Box::new(addresses.into_iter());
+            Ok(addresses)
+        })
+    }
+}
+
+fn is_public(address: IpAddr) -> bool {
+    match address {
+        IpAddr::V4(address) => is_public_v4(address),
+        IpAddr::V6(address) => match address.to_ipv4_mapped() {
+            Some(address) => is_public_v4(address),
+            None => is_public_v6(address),
+        },
+    }
+}
+
+fn is_public_v4(address: Ipv4Addr) -> bool {
+    let [first, second, ..] = address.octets();
+
+    !(address.is_unspecified()
+        || address.is_loopback()
+        || address.is_private()
+        || address.is_link_local()
+        || address.is_broadcast()
+        || address.is_documentation()
+        || address.is_multicast()
+        // 0.0.0.0/8, "this network"
+        || first == 0
+        // 100.64.0.0/10, carrier grade nat
+        || (first == 100 && (second & 0b1100_0000) == 64)
+        // 198.18.0.0/15, benchmarking
+        || (first == 198 && (second & 0b1111_1110) == 18)
+        // 240.0.0.0/4, reserved
+        || first >= 240)
+}
+
+fn is_public_v6(address: Ipv6Addr) -> bool {
+    let [first, ..] = address.segments();
+
+    !(address.is_unspecified()
+        || address.is_loopback()
+        || address.is_multicast()
+        || address.is_unique_local()
+        || address.is_unicast_link_local()
+        // 2001:db8::/32, documentation
+        || address.segments()[..2] == [0x2001, 0x0db8]
+        // 64:ff9b::/96 and 64:ff9b:1::/48, nat64 which can reach ipv4 addresses
+        || first == 0x0064
+        // 2002::/16, 6to4 which can reach ipv4 addresses
+        || first == 0x2002
+        // ::/96, deprecated ipv4-compatible addresses
+        || address.segments()[..6] == [0; 6])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_is_public() {
+        let table = [
+            ("93.184.215.14", true),
+            ("1.1.1.1", true),
+            ("127.0.0.1", false),
+            ("10.1.2.3", false),
+            ("172.16.0.1", false),
+            ("192.168.1.1", false),
+            ("169.254.169.254", false),
+            ("100.64.0.1", false),
+            ("0.0.0.0", false),
+            ("255.255.255.255", false),
+            ("2606:2800:21f:cb07:6820:80da:af6b:8b2c", true),
+            ("::1", false),
+            ("::", false),
+            ("fd00::1", false),
+            ("fe80::1", false),
+            ("::ffff:127.0.0.1", false),
+            ("::ffff:93.184.215.14", true),
+            ("64:ff9b::7f00:1", false),
+            ("::7f00:1", false),
+            ("::a00:1", false),
+            ("2002:7f00:1::", false),
+        ];
+
+        for (address, expected) in table {
+            assert_eq!(
+                expected,
+                is_public(address.parse().unwrap()),
+                "{address} should be public: {expected}"
+            );
+        }
+    }
+}
@@ -1,10 +1,27 @@
+use std::{future::Future, sync::Arc, time::Duration};
+
 use anyhow::{Context, anyhow};
 use base64::Engine;
 use ring::rand::SecureRandom;
 use thiserror::Error;
+use tokio::sync::Semaphore;
+
+use crate::{config, domain};
+
+// how long clients are asked to wait before retrying when the queue is full
+pub const RETRY_AFTER_SECONDS: u64 = 10;
 
 #[derive(Error, Debug)]
 pub enum Error {
+    #[error("Image processing queue is full.")]
+    Busy,
+
+    #[error("Image processing timed out.")]
+    TimedOut,
+
+    #[error("{0}")]
+    Invalid(String),
+
     #[error(transparent)]
     Other(#[from] anyhow::Error),
 }
@@ -12,10 +29,78 @@ pub enum Error {
 pub struct ImageProcessor {
     working_directory: String,
     vips_has_keep_flag: bool,
+    queue: Queue,
+    timeout: Duration,
+    max_pixels: u64,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Format {
+    Jpeg,
+    Png,
+    Heic,
+    WebP,
+    Gif,
+}
+
+// ftyp brands used by HEIF images, including HEIC
+const HEIF_BRANDS: [&[u8]; 8] = [
+    b"heic", b"heix", b"hevc", b"hevx", b"heim", b"heis", b"mif1", b"msf1",
+];
+
+impl Format {
+    // Detects the format of an image from its magic bytes.
+    #[must_use]
+    pub fn sniff(image: &[u8]) -> Option<Format> {
+        if image.starts_with(&[0xFF, 0xD8, 0xFF]) {
+            Some(Format::Jpeg)
+        } else if image.starts_with(&[0x89, b'P', b'N', b'G', 0x0D, 0x0A, 0x1A, 0x0A]) {
+            Some(Format::Png)
+        } else if image.starts_with(b"GIF87a") || image.starts_with(b"GIF89a") {
+            Some(Format::Gif)
+        } else if image.starts_with(b"RIFF") && image.get(8..12) == Some(b"WEBP") {
+            Some(Format::WebP)
+        } else if image.get(4..8) == Some(b"ftyp")
+            && image
+                .get(8..12)
+                .is_some_and(|brand| HEIF_BRANDS.contains(&brand))
+        {
+            Some(Format::Heic)
+        } else {
+            None
+        }
+    }
+}
+
+// Limits how many jobs run at once, and how many may wait for a turn. Jobs beyond that are
+// rejected instead of piling up.
+struct Queue {
+    admitted: Arc<Semaphore>,
+    running: Arc<Semaphore>,
+}
+
+impl Queue {
+    fn new(parallelism: usize, queue_size: usize) -> Self {
+        Queue {
+            admitted: Arc::new(Semaphore::new(parallelism + queue_size)),
+            running: Arc::new(Semaphore::new(parallelism)),
+        }
+    }
+
+    async fn run<T>(&self, job: impl Future<Output = Result<T, Error>>) -> Result<T, Error> {
+        let _admitted = self.admitted.try_acquire().map_err(|_| Error::Busy)?;
+        let _running = self
+            .running
+            .acquire()
+            .await
+            .context("Image processing queue closed.")?;
+
+        job.await
+    }
 }
 
 impl ImageProcessor {
-    pub async fn new() -> Result<ImageProcessor, Error> {
+    pub async fn new(config: &config::ImageProcessing) -> Result<ImageProcessor, Error> {
         let working_dir = "/tmp/mise-images";
         if !tokio::fs::try_exists(working_dir)
             .await
@@ -35,6 +120,9 @@ impl ImageProcessor {
         Ok(ImageProcessor {
             working_directory: working_dir.to_owned(),
             vips_has_keep_flag: has_keep_flag,
+            queue: Queue::new(config.parallelism, config.queue_size),
+            timeout: Duration::from_secs(config.timeout_seconds),
+            max_pixels: config.max_pixels,
         })
     }
 
@@ -60,6 +148,48 @@ impl ImageProcessor {
     }
 
     pub async fn process_image(&self, image: Vec<u8>) -> Result<Vec<u8>, Error> {
+        if Format::sniff(&image).is_none() {
+            return Err(Error::Invalid(
+                "Unsupported image format, expected JPEG, PNG, HEIC, WebP, or GIF.".into(),
+            ));
+        }
+
+        self.queue
+            .run(self.in_working_directory(image, |file_dir| self.convert(file_dir)))
+            .await
+    }
+
+    // Crops a processed image to the given rectangle.
+    pub async fn crop(&self, image: Vec<u8>, crop: &domain::image::Crop) -> Result<Vec<u8>, Error> {
+        self.queue
+            .run(self.in_working_directory(image, |file_dir| self.crop_file(file_dir, *crop)))
+            .await
+    }
+
+    // Crops a processed image to the crop of the framing, then fills the given size with it,
+    // keeping the focal point in view when the aspect ratio has to be cut down.
+    pub async fn frame(
+        &self,
+        image: Vec<u8>,
+        framing: &domain::image::Framing,
+        size: (u32, u32),
+    ) -> Result<Vec<u8>, Error> {
+        self.queue
+            .run(
+                self.in_working_directory(image, |file_dir| {
+                    self.frame_file(file_dir, framing, size)
+                }),
+            )
+            .await
+    }
+
+    // Writes the image to a new working directory as "-original", and runs the job against it
+    // within the timeout. The directory is removed afterwards.
+    async fn in_working_directory<F, Fut>(&self, image: Vec<u8>, job: F) -> Result<Vec<u8>, Error>
+    where
+        F: FnOnce(String) -> Fut,
+        Fut: Future<Output = Result<Vec<u8>, Error>>,
+    {
         let mut bytes: [u8; 32] = [0; 32];
         ring::rand::SystemRandom::new()
             .fill(&mut bytes)
@@ -74,63 +204,288 @@ impl ImageProcessor {
             .await
             .context("Creating working dir for upload")?;
 
+        tokio::fs::write(format!("{file_dir}-original"), image)
+            .await
+            .context("write old")?;
+
+        let result = tokio::time::timeout(self.timeout, job(file_dir.clone()))
+            .await
+            .unwrap_or(Err(Error::TimedOut));
+
+        // remove working files
+        tokio::fs::remove_dir_all(&file_dir)
+            .await
+            .context("cleanup file dir")?;
+
+        result
+    }
+
+    // Runs a single vips command. There is no shell in between, so when the job times out and the
+    // command is dropped, it is vips itself that gets killed.
+    async fn run(program: &str, args: &[&str]) -> Result<std::process::Output, Error> {
+        let output = tokio::process::Command::new(program)
+            .args(args)
+            .kill_on_drop(true)
+            .output()
+            .await
+            .with_context(|| format!("execute {program} {}", args.first().unwrap_or(&"")))?;
+
+        Ok(output)
+    }
+
+    // Reads the width and height of an image from its header.
+    async fn dimensions(file: &str) -> Result<(u64, u64), Error> {
+        let mut dimensions = [0; 2];
+        for (field, dimension) in ["width", "height"].into_iter().zip(&mut dimensions) {
+            let output = Self::run("vipsheader", &["-f", field, file]).await?;
+            if !output.status.success() {
+                return Err(Error::Invalid(
+                    "Image could not be read, it may be corrupt.".into(),
+                ));
+            }
+
+            let stdout = String::from_utf8(output.stdout).context("vipsheader to utf8")?;
+            *dimension = stdout
+                .trim()
+                .parse()
+                .with_context(|| format!("invalid vipsheader output: {stdout}"))?;
+        }
+        let [width, height] = dimensions;
+
+        Ok((width, height))
+    }
+
+    async fn crop_file(
+        &self,
+        file_dir: String,
+        crop: domain::image::Crop,
+    ) -> Result<Vec<u8>, Error> {
+        let file_original = format!("{file_dir}-original");
+        let file_cropped = format!("{file_dir}-cropped.jpg");
+
+        let (width, height) = Self::dimensions(&file_original).await?;
+        let (left, crop_width) = crop_span(width, crop.x(), crop.width());
+        let (top, crop_height) = crop_span(height, crop.y(), crop.height());
+
+        let output = Self::run(
+            "vips",
+            &[
+                "crop",
+                &file_original,
+                &format!("{file_cropped}[Q=75]"),
+                &left.to_string(),
+                &top.to_string(),
+                &crop_width.to_string(),
+                &crop_height.to_string(),
+            ],
+        )
+        .await?;
+        if !output.status.success() {
+            return Err(Error::from(anyhow!(
+                "vips crop error: {}",
+                String::from_utf8(output.stderr).context("stderr not utf8")?,
+            )));
+        }
+
+        let result = tokio::fs::read(file_cropped)
+            .await
+            .context("read cropped jpeg")?;
+
+        Ok(result)
+    }
+
+    async fn frame_file(
+        &self,
+        file_dir: String,
+        framing: &domain::image::Framing,
+        (width, height): (u32, u32),
+    ) -> Result<Vec<u8>, Error> {
+        let file_original = format!("{file_dir}-original");
+        let file_area = format!("{file_dir}-area.v");
+        let file_framed = format!("{file_dir}-framed.jpg");
+
+        let dimensions = Self::dimensions(&file_original).await?;
+        let (left, top, area_width, area_height) = frame_area(dimensions, framing, (width, height));
+
+        let (left, top, area_width, area_height) = (
+            left.to_string(),
+            top.to_string(),
+            area_width.to_string(),
+            area_height.to_string(),
+        );
+        let (width, height) = (width.to_string(), height.to_string());
+        let file_thumbnail = format!("{file_framed}[Q=75]");
+        let steps: [&[&str]; 2] = [
+            &[
+                "crop",
+                &file_original,
+                &file_area,
+                &left,
+                &top,
+                &area_width,
+                &area_height,
+            ],
+            &[
+                "thumbnail",
+                &file_area,
+                &file_thumbnail,
+                &width,
+                "--height",
+                &height,
+                "--size",
+                "down",
+            ],
+        ];
+        for args in steps {
+            let output = Self::run("vips", args).await?;
+            if !output.status.success() {
+                return Err(Error::from(anyhow!(
+                    "vips frame error: {}",
+                    String::from_utf8(output.stderr).context("stderr not utf8")?,
+                )));
+            }
+        }
+
+        let result = tokio::fs::read(file_framed)
+            .await
+            .context("read framed jpeg")?;
+
+        Ok(result)
+    }
+
+    async fn convert(&self, file_dir: String) -> Result<Vec<u8>, Error> {
         let file_original = format!("{file_dir}-original");
         let file_jpg = format!("{file_dir}-jpg.jpg");
         let file_rotated = format!("{file_dir}-rotated.jpg");
         let file_stripped = format!("{file_dir}-stripped.jpg");
 
-        tokio::fs::write(&file_original, image)
-            .await
-            .context("write old")?;
+        // reject images that would decompress to too many pixels before decoding them
+        let (width, height) = Self::dimensions(&file_original).await?;
+        let pixels = width.saturating_mul(height);
+        if pixels > self.max_pixels {
+            return Err(Error::Invalid(format!(
+                "Image is too large, it has {pixels} pixels but at most {} are allowed.",
+                self.max_pixels
+            )));
+        }
 
-        let mut vips_command = std::process::Command::new("sh");
+        let metadata: &[&str] = if self.vips_has_keep_flag {
+            &["--keep", "icc"]
+        } else {
+            &["--strip"]
+        };
         /*
          * 1. Convert from any format to jpeg
          * 2. Encode jpeg rotation, iOS adds rotation as metadata and we want to strip that
          * 3. Save as jpeg again, but without metadata
          */
-        let vips_command = vips_command.args([
-            "-c",
-            &format!(
-                "vips jpegsave {} {} &&
-                     vips autorot {} {} &&
-                     vips jpegsave {} {} --Q 75 {}",
-                &file_original,
-                &file_jpg,
-                &file_jpg,
-                &file_rotated,
-                &file_rotated,
-                &file_stripped,
-                if self.vips_has_keep_flag {
-                    "--keep icc"
-                } else {
-                    "--strip"
-                },
-            ),
-        ]);
-
-        // execute the command
-        let output = vips_command.output().context("execute vips commands")?;
-        if !output.status.success() {
-            return Err(Error::from(anyhow!(
-                "vips command error: {}",
-                String::from_utf8(output.stderr).context("stderr not utf8")?,
-            )));
+        let steps: [&[&str]; 3] = [
+            &["jpegsave", &file_original, &file_jpg],
+            &["autorot", &file_jpg, &file_rotated],
+            &[
+                &["jpegsave", &file_rotated, &file_stripped, "--Q", "75"],
+                metadata,
+            ]
+            .concat(),
+        ];
+        for args in steps {
+            let output = Self::run("vips", args).await?;
+            if !output.status.success() {
+                // the header was readable, so the rest of the upload is corrupt or truncated
+                println!(
+                    "vips command error: {}",
+                    String::from_utf8_lossy(&output.stderr).trim()
+                );
+                return Err(Error::Invalid(
+                    "Image could not be converted, it may be corrupt or truncated.".into(),
+                ));
+            }
         }
 
-        let result = tokio::fs::read(&file_stripped)
+        let result = tokio::fs::read(file_stripped)
             .await
             .context("read final jpeg")?;
 
-        // remove working files
-        tokio::fs::remove_dir_all(&file_dir)
-            .await
-            .context("cleanup file dir")?;
-
         Ok(result)
     }
 }
 
+// Converts a span given as fractions of a side into whole pixels, keeping at least one pixel and
+// staying within the side.
+#[allow(
+    clippy::cast_possible_truncation,
+    clippy::cast_precision_loss,
+    clippy::cast_sign_loss
+)]
+fn crop_span(side: u64, start: f64, length: f64) -> (u64, u64) {
+    let side_f = side as f64;
+    let start = ((start * side_f).round() as u64).min(side.saturating_sub(1));
+    let length = ((length * side_f).round() as u64).max(1).min(side - start);
+
+    (start, length)
+}
+
+// The area of an image to scale to a size: the largest area with the size's aspect ratio that fits
+// in the crop, centered on the focal point as far as the crop allows. Without a crop it is the
+// whole image, and without a focal point the center of the crop.
+#[allow(
+    clippy::cast_possible_truncation,
+    clippy::cast_precision_loss,
+    clippy::cast_sign_loss
+)]
+fn frame_area(
+    (width, height): (u64, u64),
+    framing: &domain::image::Framing,
+    (size_width, size_height): (u32, u32),
+) -> (u64, u64, u64, u64) {
+    let ((left, crop_width), (top, crop_height)) = match framing.crop() {
+        Some(crop) => (
+            crop_span(width, crop.x(), crop.width()),
+            crop_span(height, crop.y(), crop.height()),
+        ),
+        None => ((0, width), (0, height)),
+    };
+
+    let ratio = f64::from(size_width) / f64::from(size_height);
+    let (area_width, area_height) = if crop_width as f64 / crop_height as f64 > ratio {
+        (
+            ((crop_height as f64 * ratio).round() as u64).max(1),
+            crop_height,
+        )
+    } else {
+        (
+            crop_width,
+            ((crop_width as f64 / ratio).round() as u64).max(1),
+        )
+    };
+
+    let (focal_x, focal_y) = framing.focal_point().map_or(
+        (
+            left as f64 + crop_width as f64 / 2.0,
+            top as f64 + crop_height as f64 / 2.0,
+        ),
+        |focal_point| {
+            (
+                focal_point.x() * width as f64,
+                focal_point.y() * height as f64,
+            )
+        },
+    );
+
+    // as close to centered on the focal point as the crop allows
+    let center = |focal: f64, start: u64, side: u64, length: u64| {
+        let centered = (focal - length as f64 / 2.0).round().max(0.0) as u64;
+        centered.clamp(start, start + side - length)
+    };
+
+    (
+        center(focal_x, left, crop_width, area_width),
+        center(focal_y, top, crop_height, area_height),
+        area_width,
+        area_height,
+    )
+}
+
 fn is_at_least_major_minor(installed: (i32, i32), minimum: (i32, i32)) -> bool {
     match installed.0.cmp(&minimum.0) {
         std::cmp::Ordering::Less => false,
@@ -165,4 +520,157 @@ mod tests {
             );
         }
     }
+
+    #[test]
+    fn test_crop_span() {
+        let test_cases = vec![
+            // (side, start, length, expected)
+            (100, 0.0, 1.0, (0, 100)),
+            (100, 0.25, 0.5, (25, 50)),
+            (100, 0.999, 0.001, (99, 1)),
+            (100, 1.0, 0.0, (99, 1)),
+            (3, 0.5, 0.5, (2, 1)),
+        ];
+
+        for (side, start, length, expected) in test_cases {
+            assert_eq!(
+                crop_span(side, start, length),
+                expected,
+                "side: {side}, start: {start}, length: {length}"
+            );
+        }
+    }
+
+    #[test]
+    fn test_frame_area() {
+        let crop =
+            |x, y, width, height| Some(domain::image::Crop::new(x, y, width, height).unwrap());
+        let focal_point = |x, y| Some(domain::image::FocalPoint::new(x, y).unwrap());
+
+        let test_cases = vec![
+            // (dimensions, crop, focal point, size, expected)
+            ((200, 100), None, None, (100, 100), (50, 0, 100, 100)),
+            (
+                (200, 100),
+                None,
+                focal_point(0.0, 0.5),
+                (100, 100),
+                (0, 0, 100, 100),
+            ),
+            (
+                (200, 100),
+                None,
+                focal_point(0.6, 0.5),
+                (100, 100),
+                (70, 0, 100, 100),
+            ),
+            (
+                (200, 100),
+                None,
+                focal_point(1.0, 0.5),
+                (100, 100),
+                (100, 0, 100, 100),
+            ),
+            (
+                (100, 200),
+                None,
+                focal_point(0.5, 0.1),
+                (100, 50),
+                (0, 0, 100, 50),
+            ),
+            ((200, 100), None, None, (400, 200), (0, 0, 200, 100)),
+            (
+                (200, 200),
+                crop(0.5, 0.0, 0.5, 0.25),
+                focal_point(0.9, 0.1),
+                (50, 50),
+                (150, 0, 50, 50),
+            ),
+            (
+                (200, 200),
+                crop(0.5, 0.0, 0.5, 0.25),
+                focal_point(0.5, 0.1),
+                (50, 50),
+                (100, 0, 50, 50),
+            ),
+        ];
+
+        for (dimensions, crop, focal_point, size, expected) in test_cases {
+            let framing = domain::image::Framing::new(crop, focal_point).unwrap();
+            assert_eq!(
+                frame_area(dimensions, &framing, size),
+                expected,
+                "dimensions: {dimensions:?}, framing: {framing:?}, size: {size:?}"
+            );
+        }
+    }
+
+    #[test]
+    fn test_sniff_format() {
+        let test_cases: Vec<(&[u8], Option<Format>)> = vec![
+            // (image, expected)
+            (&[0xFF, 0xD8, 0xFF, 0xE0, 0x00], Some(Format::Jpeg)),
+            (
+                &[0x89, b'P', b'N', b'G', 0x0D, 0x0A, 0x1A, 0x0A, 0x00],
+                Some(Format::Png),
+            ),
+            (b"GIF89a\x01\x00", Some(Format::Gif)),
+            (b"GIF87a\x01\x00", Some(Format::Gif)),
+            (b"RIFF\x00\x00\x00\x00WEBPVP8 ", Some(Format::WebP)),
+            (b"\x00\x00\x00\x18ftypheic\x00", Some(Format::Heic)),
+            (b"\x00\x00\x00\x18ftypmif1\x00", Some(Format::Heic)),
+            (b"\x00\x00\x00\x18ftypisom\x00", None),
+            (b"RIFF\x00\x00\x00\x00WAVEfmt ", None),
+            (b"<svg></svg>", None),
+            (&[0xFF, 0xD8], None),
+            (&[], None),
+        ];
+
+        for (image, expected) in test_cases {
+            assert_eq!(
+                Format::sniff(image),
+                expected,
+                "image: {:?}, expected to be: {:?}",
+                image,
+                expected
+            );
+        }
+    }
+
+    #[tokio::test]
+    async fn test_queue_rejects_jobs_when_full() {
+        let queue = Arc::new(Queue::new(1, 1));
+        let (release, released) = tokio::sync::oneshot::channel::<()>();
+
+        // occupies the only running slot until released
+        let running = tokio::spawn({
+            let queue = queue.clone();
+            async move {
+                queue
+                    .run(async move {
+                        let _ = released.await;
+                        Ok(1)
+                    })
+                    .await
+            }
+        });
+        tokio::task::yield_now().await;
+
+        // waits in the queue
+        let waiting = tokio::spawn({
+            let queue = queue.clone();
+            async move { queue.run(async { Ok(2) }).await }
+        });
+        tokio::task::yield_now().await;
+
+        let rejected = queue.run(async { Ok(3) }).await;
+        assert!(matches!(rejected, Err(Error::Busy)));
+
+        release.send(()).unwrap();
+        assert_eq!(1, running.await.unwrap().unwrap());
+        assert_eq!(2, waiting.await.unwrap().unwrap());
+
+        // room is made once jobs finish
+        assert_eq!(4, queue.run(async { Ok(4) }).await.unwrap());
+    }
 }
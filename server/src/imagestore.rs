@@ -1,6 +1,12 @@
+use std::pin::Pin;
+
+use anyhow::anyhow;
 use async_trait::async_trait;
+use futures::{Stream, TryStreamExt};
 use thiserror::Error;
 
+use crate::{config, file, s3};
+
 #[derive(Error, Debug)]
 pub enum Error {
     #[error("Image not found.")]
@@ -9,6 +15,9 @@ pub enum Error {
     #[error("Image already exists {0}.")]
     DuplicatePath(String),
 
+    #[error("Range not satisfiable for image of {size} bytes.")]
+    RangeNotSatisfiable { size: u64 },
+
     #[error("Configuration error")]
     Config(#[source] anyhow::Error),
 
@@ -16,14 +25,151 @@ pub enum Error {
     Other(#[from] anyhow::Error),
 }
 
+pub type ByteStream = Pin<Box<dyn Stream<Item = Result<bytes::Bytes, Error>> + Send>>;
+
+// A requested range of bytes, as sent in a Range header.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Range {
+    // start and end are inclusive
+    Bounded { start: u64, end: u64 },
+    From { start: u64 },
+    Suffix { length: u64 },
+}
+
+// A range of bytes that exists within an image. start and end are inclusive.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ContentRange {
+    pub start: u64,
+    pub end: u64,
+}
+
+pub struct Object {
+    // size of the whole image, even if only a range was requested
+    pub size: u64,
+    // the range of the image contained in the stream, or None if the stream is the whole image
+    pub range: Option<ContentRange>,
+    pub stream: ByteStream,
+}
+
+impl Range {
+    pub fn resolve(self, size: u64) -> Result<ContentRange, Error> {
+        let range = match self {
+            Range::Bounded { start, end } => ContentRange {
+                start,
+                end: end.min(size.saturating_sub(1)),
+            },
+            Range::From { start } => ContentRange {
+                start,
+                end: size.saturating_sub(1),
+            },
+            Range::Suffix { length } => ContentRange {
+                start: size.saturating_sub(length),
+                end: size.saturating_sub(1),
+            },
+        };
+
+        if size == 0 || range.start >= size || range.start > range.end {
+            return Err(Error::RangeNotSatisfiable { size });
+        }
+
+        Ok(range)
+    }
+
+    #[must_use]
+    pub fn to_header(self) -> String {
+        match self {
+            Range::Bounded { start, end } => format!("bytes={start}-{end}"),
+            Range::From { start } => format!("bytes={start}-"),
+            Range::Suffix { length } => format!("bytes=-{length}"),
+        }
+    }
+}
+
+impl ContentRange {
+    #[must_use]
+    pub fn length(&self) -> u64 {
+        self.end - self.start + 1
+    }
+}
+
+impl Object {
+    // Builds an object from an image held in memory, limited to the range if one is given.
+    pub fn from_bytes(bytes: Vec<u8>, range: Option<Range>) -> Result<Self, Error> {
+        let size = u64::try_from(bytes.len()).map_err(|err| Error::Other(err.into()))?;
+        let mut bytes = bytes::Bytes::from(bytes);
+
+        let range = match range {
+            None => None,
+            Some(range) => {
+                let content_range = range.resolve(size)?;
+                let start =
+                    usize::try_from(content_range.start).map_err(|err| Error::Other(err.into()))?;
+                let end =
+                    usize::try_from(content_range.end).map_err(|err| Error::Other(err.into()))?;
+                bytes = bytes.slice(start..=end);
+                Some(content_range)
+            }
+        };
+
+        Ok(Object {
+            size,
+            range,
+            stream: Box::pin(futures::stream::once(async move { Ok(bytes) })),
+        })
+    }
+
+    // Reads the whole stream, verifying that the expected number of bytes were received.
+    pub async fn into_bytes(self) -> Result<Vec<u8>, Error> {
+        let expected = self.range.map_or(self.size, |range| range.length());
+        let bytes: Vec<u8> = self
+            .stream
+            .try_fold(Vec::new(), |mut bytes, chunk| async move {
+                bytes.extend_from_slice(&chunk);
+                Ok(bytes)
+            })
+            .await?;
+
+        if u64::try_from(bytes.len()).ok() != Some(expected) {
+            return Err(Error::Other(anyhow!(
+                "Expected {expected} bytes, but read {}.",
+                bytes.len()
+            )));
+        }
+
+        Ok(bytes)
+    }
+}
+
 pub struct ImageStore {
     backend: Box<dyn ImageBackend + Send + Sync>,
 }
 
 #[async_trait]
 pub trait ImageBackend {
-    async fn get(&self, path: &str) -> Result<Vec<u8>, Error>;
+    async fn get(&self, path: &str, range: Option<Range>) -> Result<Object, Error>;
     async fn upload(&self, path: &str, file: Vec<u8>) -> Result<(), Error>;
+    // Whether an image is stored at the path, without reading it.
+    async fn exists(&self, path: &str) -> Result<bool, Error>;
+    // Removes the image at the path, succeeding if there is none.
+    async fn delete(&self, path: &str) -> Result<(), Error>;
+
+    // A short-lived url the image can be downloaded from directly, if the backend supports it.
+    fn presigned_url(&self, _path: &str) -> Result<Option<String>, Error> {
+        Ok(None)
+    }
+}
+
+pub async fn backend(
+    config: &config::ImageBackend,
+) -> Result<Box<dyn ImageBackend + Send + Sync>, Error> {
+    Ok(match config {
+        config::ImageBackend::S3(config) => {
+            Box::from(s3::imagebackend::ImageBackend::new(config.try_into()?)?)
+        }
+        config::ImageBackend::File(config) => {
+            Box::from(file::ImageBackend::new(&config.directory).await?)
+        }
+    })
 }
 
 impl ImageStore {
@@ -36,7 +182,19 @@ impl ImageStore {
         self.backend.upload(path, file).await
     }
 
-    pub async fn get(&self, path: &str) -> Result<Vec<u8>, Error> {
-        self.backend.get(path).await
+    pub async fn get(&self, path: &str, range: Option<Range>) -> Result<Object, Error> {
+        self.backend.get(path, range).await
+    }
+
+    pub async fn exists(&self, path: &str) -> Result<bool, Error> {
+        self.backend.exists(path).await
+    }
+
+    pub async fn delete(&self, path: &str) -> Result<(), Error> {
+        self.backend.delete(path).await
+    }
+
+    pub fn presigned_url(&self, path: &str) -> Result<Option<String>, Error> {
+        self.backend.presigned_url(path)
     }
 }
@@ -7,9 +7,14 @@ pub mod datastore;
 pub mod domain;
 pub mod file;
 pub mod http;
+pub mod image_cache;
+pub mod image_import;
 pub mod image_processing;
 pub mod imagestore;
+pub mod local_auth;
 pub mod oidc;
+pub mod proxy_auth;
+pub mod rate_limit;
 pub mod s3;
 pub mod search;
 pub mod session_store;
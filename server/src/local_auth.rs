@@ -0,0 +1,56 @@
+use argon2::{Argon2, PasswordHash, PasswordVerifier};
+
+use crate::config;
+
+// Local users are linked by this issuer and their username. OIDC issuers are urls, so they never
+// collide with it.
+pub const ISSUER: &str = "local";
+
+#[derive(thiserror::Error, Debug)]
+pub enum Error {
+    #[error("Invalid username or password.")]
+    InvalidCredentials,
+}
+
+// Finds the configured user with the given username and password. A password is checked even
+// when the username does not exist, so the time taken does not reveal which usernames do.
+//
+// Hashing is slow on purpose, so call this from a blocking task.
+pub fn verify<'a>(
+    config: &'a config::LocalAuth,
+    username: &str,
+    password: &str,
+) -> Result<&'a config::LocalUser, Error> {
+    let user = config.users.iter().find(|user| user.username == username);
+
+    let verified = user.or(config.users.first()).is_some_and(|checked| {
+        PasswordHash::new(&checked.password_hash).is_ok_and(|hash| {
+            Argon2::default()
+                .verify_password(password.as_bytes(), &hash)
+                .is_ok()
+        })
+    });
+
+    match user {
+        Some(user) if verified => Ok(user),
+        _ => Err(Error::InvalidCredentials),
+    }
+}
+
+// Identifies a user together with their password hash. Sessions keep it in place of a refresh
+// token, so they end once the user is removed or their password is changed.
+#[must_use]
+pub fn fingerprint(user: &config::LocalUser) -> String {
+    sha256::digest(format!("{}\n{}", user.username, user.password_hash))
+}
+
+// Whether a user with the fingerprint is still configured.
+#[must_use]
+pub fn is_current(config: Option<&config::LocalAuth>, fingerprint: &str) -> bool {
+    config.is_some_and(|config| {
+        config
+            .users
+            .iter()
+            .any(|user| self::fingerprint(user) == fingerprint)
+    })
+}
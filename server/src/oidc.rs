@@ -1,20 +1,23 @@
-use std::time::Duration;
+use std::{collections::HashMap, time::Duration};
 
 use anyhow::Context;
 use chrono::Utc;
 use openidconnect::{
-    AuthorizationCode, ClientId, ClientSecret, CsrfToken, EmptyAdditionalClaims,
-    EmptyExtraTokenFields, IdTokenFields, IssuerUrl, Nonce, OAuth2TokenResponse, PkceCodeChallenge,
-    PkceCodeVerifier, RedirectUrl, RefreshToken, Scope, StandardTokenResponse, TokenResponse,
+    AdditionalClaims, AuthorizationCode, ClientId, ClientSecret, CsrfToken, EmptyExtraTokenFields,
+    EndSessionUrl, IdTokenFields, IssuerUrl, LogoutRequest, Nonce, OAuth2TokenResponse,
+    PkceCodeChallenge, PkceCodeVerifier, PostLogoutRedirectUrl, ProviderMetadataWithLogout,
+    RedirectUrl, RefreshToken, Scope, StandardErrorResponse, StandardTokenResponse, TokenResponse,
     TokenType,
     core::{
-        CoreAuthenticationFlow, CoreClient, CoreGenderClaim, CoreJweContentEncryptionAlgorithm,
-        CoreJwsSigningAlgorithm, CoreProviderMetadata,
+        CoreAuthDisplay, CoreAuthPrompt, CoreAuthenticationFlow, CoreErrorResponseType,
+        CoreGenderClaim, CoreJsonWebKey, CoreJweContentEncryptionAlgorithm,
+        CoreJwsSigningAlgorithm, CoreRevocableToken, CoreRevocationErrorResponse,
+        CoreTokenIntrospectionResponse, CoreTokenType,
     },
 };
 use serde::{Deserialize, Serialize};
 
-use crate::config;
+use crate::{config, domain::household::Role};
 
 #[derive(thiserror::Error, Debug)]
 pub enum Error {
@@ -45,6 +48,15 @@ pub enum Error {
     #[error("Origin must not end with slash.")]
     OriginMustNotEndWithSlash,
 
+    #[error("Subject {0} is not allowed to sign in.")]
+    NotAllowed(String),
+
+    #[error("Unknown OIDC provider {0}.")]
+    UnknownProvider(String),
+
+    #[error("Choose an OIDC provider to sign in with.")]
+    ProviderRequired,
+
     #[error("{msg}")]
     InvalidUrl {
         msg: String,
@@ -57,40 +69,87 @@ pub enum Error {
 }
 
 pub struct Config {
+    name: String,
     issuer_url: String,
     client_id: String,
     client_secret: String,
     origin: String,
+    access: config::OidcAccess,
 }
 
-impl TryFrom<&config::Config> for Config {
-    type Error = Error;
-
-    fn try_from(value: &config::Config) -> Result<Self, Self::Error> {
-        if value.origin.ends_with('/') {
-            return Err(Error::OriginMustNotEndWithSlash);
-        }
+// The config of every provider users may sign in with.
+pub fn configs(value: &config::Config) -> Result<Vec<Config>, Error> {
+    if value.origin.ends_with('/') {
+        return Err(Error::OriginMustNotEndWithSlash);
+    }
 
-        Ok(Config {
-            issuer_url: value.oidc.issuer_url.clone(),
-            client_id: value.oidc.client_id.clone(),
-            client_secret: value.oidc.client_secret.clone(),
+    Ok(value
+        .oidc_providers
+        .iter()
+        .map(|provider| Config {
+            name: provider.name.clone(),
+            issuer_url: provider.issuer_url.clone(),
+            client_id: provider.client_id.clone(),
+            client_secret: provider.client_secret.clone(),
             origin: value.origin.clone(),
+            access: provider.access.clone(),
         })
-    }
+        .collect())
+}
+
+// Claims beyond the standard ones, kept so the groups claim can be looked up by its configured
+// name.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct ExtraClaims {
+    #[serde(flatten)]
+    claims: HashMap<String, serde_json::Value>,
 }
 
+impl AdditionalClaims for ExtraClaims {}
+
+type IdTokenResponse = StandardTokenResponse<
+    IdTokenFields<
+        ExtraClaims,
+        EmptyExtraTokenFields,
+        CoreGenderClaim,
+        CoreJweContentEncryptionAlgorithm,
+        CoreJwsSigningAlgorithm,
+    >,
+    CoreTokenType,
+>;
+
+type OpenIdClient = openidconnect::Client<
+    ExtraClaims,
+    CoreAuthDisplay,
+    CoreGenderClaim,
+    CoreJweContentEncryptionAlgorithm,
+    CoreJsonWebKey,
+    CoreAuthPrompt,
+    StandardErrorResponse<CoreErrorResponseType>,
+    IdTokenResponse,
+    CoreTokenIntrospectionResponse,
+    CoreRevocableToken,
+    CoreRevocationErrorResponse,
+    openidconnect::EndpointSet,
+    openidconnect::EndpointNotSet,
+    openidconnect::EndpointNotSet,
+    openidconnect::EndpointNotSet,
+    openidconnect::EndpointMaybeSet,
+    openidconnect::EndpointMaybeSet,
+>;
+
 #[derive(Clone)]
 pub struct Provider {
+    name: String,
+    // as the provider names itself in its metadata and ID tokens
+    issuer: String,
     http_client: reqwest::Client,
-    openid_client: CoreClient<
-        openidconnect::EndpointSet,
-        openidconnect::EndpointNotSet,
-        openidconnect::EndpointNotSet,
-        openidconnect::EndpointNotSet,
-        openidconnect::EndpointMaybeSet,
-        openidconnect::EndpointMaybeSet,
-    >,
+    openid_client: OpenIdClient,
+    client_id: ClientId,
+    // only set when the provider supports RP-initiated logout
+    end_session_endpoint: Option<EndSessionUrl>,
+    post_logout_redirect_url: PostLogoutRedirectUrl,
+    access: config::OidcAccess,
 }
 
 impl Provider {
@@ -106,13 +165,29 @@ impl Provider {
             source: err,
         })?;
 
-        let provider_metadata = CoreProviderMetadata::discover_async(issuer, &client)
+        let provider_metadata = ProviderMetadataWithLogout::discover_async(issuer, &client)
             .await
             .context("Could not find OIDC metadata.")?;
 
-        let openid_client = CoreClient::from_provider_metadata(
+        let issuer = provider_metadata.issuer().to_string();
+
+        let end_session_endpoint = provider_metadata
+            .additional_metadata()
+            .end_session_endpoint
+            .clone();
+
+        let post_logout_redirect_url =
+            PostLogoutRedirectUrl::new(format!("{}/login", config.origin)).map_err(|err| {
+                Error::InvalidUrl {
+                    msg: "Invalid Post Logout Redirect URL".to_string(),
+                    source: err,
+                }
+            })?;
+
+        let client_id = ClientId::new(config.client_id);
+        let openid_client = OpenIdClient::from_provider_metadata(
             provider_metadata,
-            ClientId::new(config.client_id),
+            client_id.clone(),
             Some(ClientSecret::new(config.client_secret)),
         )
         .set_redirect_uri(
@@ -125,16 +200,68 @@ impl Provider {
         );
 
         Ok(Provider {
+            name: config.name,
+            issuer,
             http_client: client,
             openid_client,
+            client_id,
+            end_session_endpoint,
+            post_logout_redirect_url,
+            access: config.access,
         })
     }
+
+    #[must_use]
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    #[must_use]
+    pub fn issuer(&self) -> &str {
+        &self.issuer
+    }
+}
+
+// Every provider users may sign in with, all sharing the same redirect url. The provider a flow
+// was started with is kept in its state.
+pub struct Providers {
+    providers: Vec<Provider>,
+}
+
+impl Providers {
+    pub async fn new(configs: Vec<Config>) -> Result<Self, Error> {
+        let mut providers = vec![];
+        for config in configs {
+            providers.push(Provider::new(config).await?);
+        }
+
+        Ok(Providers { providers })
+    }
+
+    // The provider with the given name. The name may be left out when there is only one.
+    pub fn get(&self, name: Option<&str>) -> Result<&Provider, Error> {
+        match (name, self.providers.as_slice()) {
+            (None, [provider]) => Ok(provider),
+            (None, _) => Err(Error::ProviderRequired),
+            (Some(name), providers) => providers
+                .iter()
+                .find(|provider| provider.name == name)
+                .ok_or_else(|| Error::UnknownProvider(name.to_owned())),
+        }
+    }
+
+    #[must_use]
+    pub fn names(&self) -> Vec<&str> {
+        self.providers.iter().map(Provider::name).collect()
+    }
 }
 
 // authentication flow
 
 #[derive(Serialize, Deserialize)]
 pub struct AuthState {
+    // name of the provider the flow was started with
+    provider: String,
     csrf_token: CsrfToken,
     nonce: Nonce,
     pkce_verifier: PkceCodeVerifier,
@@ -148,10 +275,15 @@ pub struct CallbackParams<'a> {
 
 #[derive(Serialize, Deserialize)]
 pub struct Authenticated {
+    // name of the provider the user signed in with
+    pub provider: String,
+    pub issuer: String,
     pub subject: String,
     pub name: String,
     pub refresh_token: String,
     pub expires_at: chrono::DateTime<Utc>,
+    // the most the user may do in any household, from the groups they are in
+    pub max_role: Option<Role>,
 }
 
 pub fn begin_auth(
@@ -176,11 +308,14 @@ pub fn begin_auth(
         .add_scope(Scope::new("access".into()))
         .add_scope(Scope::new("offline_access".into()))
         .add_scope(Scope::new("profile".into()))
+        .add_scope(Scope::new("email".into()))
+        .add_scopes(provider.access.scopes.iter().cloned().map(Scope::new))
         .url();
 
     Ok((
         auth_url,
         AuthState {
+            provider: provider.name.clone(),
             csrf_token,
             nonce,
             pkce_verifier,
@@ -190,7 +325,7 @@ pub fn begin_auth(
 }
 
 pub async fn complete_auth(
-    provider: &Provider,
+    providers: &Providers,
     state: AuthState,
     params: CallbackParams<'_>,
 ) -> Result<(Authenticated, Option<String>), Error> {
@@ -198,6 +333,8 @@ pub async fn complete_auth(
         return Err(Error::CsrfMismatch);
     }
 
+    let provider = providers.get(Some(&state.provider))?;
+
     let token_response = provider
         .openid_client
         .exchange_code(AuthorizationCode::new(params.code.to_string()))?
@@ -230,7 +367,7 @@ fn to_authenticated<TT: TokenType>(
     nonce: Option<&Nonce>,
     token_response: &StandardTokenResponse<
         IdTokenFields<
-            EmptyAdditionalClaims,
+            ExtraClaims,
             EmptyExtraTokenFields,
             CoreGenderClaim,
             CoreJweContentEncryptionAlgorithm,
@@ -259,6 +396,20 @@ fn to_authenticated<TT: TokenType>(
 
     let subject = claims.subject();
 
+    // emails can only be trusted once the provider has verified them
+    let email = claims
+        .email()
+        .filter(|_| claims.email_verified() == Some(true))
+        .map(|email| email.as_str());
+    let groups = groups(
+        &claims.additional_claims().claims,
+        &provider.access.groups_claim,
+    );
+
+    if !is_allowed(&provider.access, subject, email, &groups) {
+        return Err(Error::NotAllowed(subject.to_string()));
+    }
+
     let default_name = subject.to_string();
     let name = match claims.name() {
         Some(localized_name) => match localized_name.iter().next() {
@@ -269,14 +420,158 @@ fn to_authenticated<TT: TokenType>(
     };
 
     Ok(Authenticated {
+        provider: provider.name.clone(),
+        issuer: claims.issuer().to_string(),
         subject: subject.to_string(),
         name,
         refresh_token: refresh_token.secret().to_owned(),
         expires_at,
+        max_role: max_role(&provider.access, &groups),
     })
 }
 
+// The groups claim may hold a list of groups or a single group.
+fn groups(claims: &HashMap<String, serde_json::Value>, name: &str) -> Vec<String> {
+    match claims.get(name) {
+        Some(serde_json::Value::Array(values)) => values
+            .iter()
+            .filter_map(serde_json::Value::as_str)
+            .map(str::to_owned)
+            .collect(),
+        Some(serde_json::Value::String(value)) => vec![value.clone()],
+        _ => vec![],
+    }
+}
+
+fn is_allowed(
+    access: &config::OidcAccess,
+    subject: &str,
+    email: Option<&str>,
+    groups: &[String],
+) -> bool {
+    let is_restricted = !access.allowed_subjects.is_empty()
+        || !access.allowed_emails.is_empty()
+        || !access.allowed_groups.is_empty();
+    if !is_restricted {
+        return true;
+    }
+
+    access
+        .allowed_subjects
+        .iter()
+        .any(|allowed| allowed == subject)
+        || email.is_some_and(|email| {
+            access
+                .allowed_emails
+                .iter()
+                .any(|allowed| allowed.eq_ignore_ascii_case(email))
+        })
+        || groups.iter().any(|group| {
+            access.allowed_groups.contains(group) || access.group_roles.contains_key(group)
+        })
+}
+
+fn max_role(access: &config::OidcAccess, groups: &[String]) -> Option<Role> {
+    groups
+        .iter()
+        .filter_map(|group| access.group_roles.get(group))
+        .max()
+        .copied()
+        .or(access.default_role)
+}
+
+// The url to send the user to so they are also logged out of the provider, which then redirects
+// back to the login page. None if the provider does not support RP-initiated logout.
+#[must_use]
+pub fn logout_url(provider: &Provider) -> Option<openidconnect::url::Url> {
+    provider
+        .end_session_endpoint
+        .clone()
+        .map(|end_session_endpoint| {
+            LogoutRequest::from(end_session_endpoint)
+                .set_client_id(provider.client_id.clone())
+                .set_post_logout_redirect_uri(provider.post_logout_redirect_url.clone())
+                .http_get_url()
+        })
+}
+
 #[allow(clippy::unnecessary_wraps)]
 fn ignore_nonce_verification(_nonce: Option<&Nonce>) -> Result<(), String> {
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_is_allowed() {
+        let access = config::OidcAccess {
+            allowed_subjects: vec!["thomas".into()],
+            allowed_emails: vec!["barley@example.com".into()],
+            allowed_groups: vec!["family".into()],
+            group_roles: HashMap::from([("kids".into(), Role::Viewer)]),
+            ..config::OidcAccess::default()
+        };
+
+        let table = [
+            ("thomas", None, vec![], true),
+            ("someone", Some("Barley@Example.com"), vec![], true),
+            ("someone", None, vec!["family".to_owned()], true),
+            ("someone", None, vec!["kids".to_owned()], true),
+            (
+                "someone",
+                Some("other@example.com"),
+                vec!["other".to_owned()],
+                false,
+            ),
+        ];
+
+        for (subject, email, groups, expected) in table {
+            assert_eq!(
+                expected,
+                is_allowed(&access, subject, email, &groups),
+                "{subject} should be allowed: {expected}"
+            );
+        }
+
+        assert!(is_allowed(
+            &config::OidcAccess::default(),
+            "anyone",
+            None,
+            &[]
+        ));
+    }
+
+    #[test]
+    fn test_max_role() {
+        let access = config::OidcAccess {
+            group_roles: HashMap::from([
+                ("kids".into(), Role::Viewer),
+                ("parents".into(), Role::Admin),
+            ]),
+            default_role: Some(Role::Editor),
+            ..config::OidcAccess::default()
+        };
+
+        assert_eq!(Some(Role::Viewer), max_role(&access, &["kids".into()]));
+        assert_eq!(
+            Some(Role::Admin),
+            max_role(&access, &["kids".into(), "parents".into()])
+        );
+        assert_eq!(Some(Role::Editor), max_role(&access, &["other".into()]));
+        assert_eq!(None, max_role(&config::OidcAccess::default(), &[]));
+    }
+
+    #[test]
+    fn test_groups() {
+        let claims = HashMap::from([
+            ("groups".to_owned(), serde_json::json!(["a", "b", 1])),
+            ("group".to_owned(), serde_json::json!("c")),
+        ]);
+
+        assert_eq!(vec!["a", "b"], groups(&claims, "groups"));
+        assert_eq!(vec!["c"], groups(&claims, "group"));
+        assert!(groups(&claims, "roles").is_empty());
+    }
+}
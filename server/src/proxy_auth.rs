@@ -0,0 +1,180 @@
+use std::{
+    collections::HashMap,
+    net::IpAddr,
+    sync::{Mutex, PoisonError},
+};
+
+use axum::http::HeaderMap;
+
+use crate::config;
+
+// Users signed in by a proxy are linked by this issuer and their username.
+pub const ISSUER: &str = "proxy";
+
+#[derive(Clone, PartialEq, Eq, Hash)]
+pub struct Identity {
+    pub username: String,
+    pub name: String,
+}
+
+// Whether the address is one of the reverse proxies in trusted_proxies.
+#[must_use]
+pub fn is_trusted(trusted_proxies: &[ipnet::IpNet], address: IpAddr) -> bool {
+    let address = address.to_canonical();
+
+    trusted_proxies
+        .iter()
+        .any(|network| network.contains(&address))
+}
+
+// The address of the client. Behind trusted proxies it is the last address in X-Forwarded-For
+// that is not one of them, as anything before that was set by the client.
+#[must_use]
+pub fn client_address(
+    trusted_proxies: &[ipnet::IpNet],
+    peer: IpAddr,
+    headers: &HeaderMap,
+) -> IpAddr {
+    let mut address = peer.to_canonical();
+    if !is_trusted(trusted_proxies, address) {
+        return address;
+    }
+
+    let forwarded: Vec<&str> = headers
+        .get_all("X-Forwarded-For")
+        .iter()
+        .filter_map(|value| value.to_str().ok())
+        .flat_map(|value| value.split(','))
+        .map(str::trim)
+        .collect();
+
+    for hop in forwarded.into_iter().rev() {
+        let Ok(hop) = hop.parse::<IpAddr>() else {
+            break;
+        };
+
+        address = hop.to_canonical();
+        if !is_trusted(trusted_proxies, address) {
+            break;
+        }
+    }
+
+    address
+}
+
+// The user a trusted proxy signed in. The headers of requests that do not come from a trusted
+// proxy are ignored, as anyone could have set them.
+#[must_use]
+pub fn identity(
+    config: &config::ProxyAuth,
+    trusted_proxies: &[ipnet::IpNet],
+    address: IpAddr,
+    headers: &HeaderMap,
+) -> Option<Identity> {
+    if !is_trusted(trusted_proxies, address) {
+        return None;
+    }
+
+    let header = |name: &str| {
+        headers
+            .get(name)
+            .and_then(|value| value.to_str().ok())
+            .map(str::trim)
+            .filter(|value| !value.is_empty())
+            .map(str::to_owned)
+    };
+
+    let username = header(&config.user_header)?;
+    let name = header(&config.name_header).unwrap_or_else(|| username.clone());
+
+    Some(Identity { username, name })
+}
+
+// Remembers the ids of users signed in by a proxy, so that only their first request, or one after
+// their name changed, writes to the datastore.
+#[derive(Default)]
+pub struct Users {
+    ids: Mutex<HashMap<Identity, String>>,
+}
+
+impl Users {
+    pub fn get(&self, identity: &Identity) -> Option<String> {
+        self.ids
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .get(identity)
+            .cloned()
+    }
+
+    pub fn insert(&self, identity: Identity, id: String) {
+        self.ids
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .insert(identity, id);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn networks(trusted_proxies: &[&str]) -> Vec<ipnet::IpNet> {
+        trusted_proxies
+            .iter()
+            .map(|network| network.parse().unwrap())
+            .collect()
+    }
+
+    #[test]
+    fn test_client_address() {
+        let table = [
+            (&[][..], "127.0.0.1", "1.1.1.1", "127.0.0.1"),
+            (&["10.0.0.0/8"][..], "127.0.0.1", "1.1.1.1", "127.0.0.1"),
+            (&["127.0.0.1/32"][..], "127.0.0.1", "", "127.0.0.1"),
+            (&["127.0.0.1/32"][..], "127.0.0.1", "1.1.1.1", "1.1.1.1"),
+            (
+                &["127.0.0.1/32"][..],
+                "::ffff:127.0.0.1",
+                "1.1.1.1",
+                "1.1.1.1",
+            ),
+            (
+                &["127.0.0.1/32"][..],
+                "127.0.0.1",
+                "2.2.2.2, 1.1.1.1",
+                "1.1.1.1",
+            ),
+            (
+                &["127.0.0.1/32", "10.0.0.0/8"][..],
+                "127.0.0.1",
+                "2.2.2.2, 1.1.1.1, 10.0.0.1",
+                "1.1.1.1",
+            ),
+            (
+                &["127.0.0.1/32", "10.0.0.0/8"][..],
+                "127.0.0.1",
+                "10.0.0.2, 10.0.0.1",
+                "10.0.0.2",
+            ),
+            (
+                &["127.0.0.1/32"][..],
+                "127.0.0.1",
+                "1.1.1.1, unknown",
+                "127.0.0.1",
+            ),
+        ];
+
+        for (trusted_proxies, peer, forwarded, expected) in table {
+            let mut headers = HeaderMap::new();
+            if !forwarded.is_empty() {
+                headers.insert("X-Forwarded-For", forwarded.parse().unwrap());
+            }
+
+            assert_eq!(
+                expected.parse::<IpAddr>().unwrap(),
+                client_address(&networks(trusted_proxies), peer.parse().unwrap(), &headers),
+                "{peer} forwarding {forwarded} with trusted proxies {trusted_proxies:?}"
+            );
+        }
+    }
+}
@@ -0,0 +1,180 @@
+use std::{
+    collections::HashMap,
+    net::IpAddr,
+    sync::{Mutex, PoisonError},
+    time::{Duration, Instant},
+};
+
+use axum::http::Method;
+
+use crate::config;
+
+// Buckets are only forgotten once there are at least this many, pruning is not worth it before.
+const MIN_PRUNE_AT: usize = 1024;
+
+// Routes are limited in groups, so that a client signing in over and over or uploading images in
+// a loop is stopped long before someone reading recipes is.
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug)]
+pub enum Group {
+    Auth,
+    ImageUpload,
+    Write,
+    Read,
+}
+
+impl Group {
+    // The group of a route that is in neither of the other groups.
+    #[must_use]
+    pub fn of(method: &Method) -> Self {
+        if method.is_safe() {
+            Group::Read
+        } else {
+            Group::Write
+        }
+    }
+}
+
+// Requests are limited by the address they come from and, once authenticated, by the user making
+// them, whatever address that is.
+#[derive(Clone, PartialEq, Eq, Hash, Debug)]
+pub enum Key {
+    Address(IpAddr),
+    User(String),
+}
+
+#[derive(Debug)]
+pub struct Limited {
+    // how long until the next request is allowed
+    pub retry_after: Duration,
+}
+
+struct Bucket {
+    tokens: f64,
+    updated_at: Instant,
+}
+
+impl Bucket {
+    fn refill(&mut self, limit: config::RateLimitBucket, now: Instant) {
+        let elapsed = now.saturating_duration_since(self.updated_at);
+
+        self.tokens =
+            (self.tokens + elapsed.as_secs_f64() * per_second(limit)).min(f64::from(limit.burst));
+        self.updated_at = now;
+    }
+}
+
+struct Buckets {
+    buckets: HashMap<(Group, Key), Bucket>,
+    // forget buckets once there are this many, so that every address ever seen is not kept
+    prune_at: usize,
+}
+
+pub struct Limiter {
+    config: config::RateLimit,
+    buckets: Mutex<Buckets>,
+}
+
+impl Limiter {
+    #[must_use]
+    pub fn new(config: config::RateLimit) -> Self {
+        Limiter {
+            config,
+            buckets: Mutex::new(Buckets {
+                buckets: HashMap::new(),
+                prune_at: MIN_PRUNE_AT,
+            }),
+        }
+    }
+
+    // Takes a request from the bucket of the key in the group.
+    pub fn check(&self, group: Group, key: Key) -> Result<(), Limited> {
+        self.check_at(group, key, Instant::now())
+    }
+
+    fn check_at(&self, group: Group, key: Key, now: Instant) -> Result<(), Limited> {
+        let limit = self.limit(group);
+        let mut buckets = self.buckets.lock().unwrap_or_else(PoisonError::into_inner);
+
+        if buckets.buckets.len() >= buckets.prune_at {
+            // a full bucket is the same as no bucket, only clients that are being limited remain
+            buckets.buckets.retain(|(group, _), bucket| {
+                let limit = self.limit(*group);
+                bucket.refill(limit, now);
+                bucket.tokens < f64::from(limit.burst)
+            });
+            buckets.prune_at = MIN_PRUNE_AT.max(buckets.buckets.len() * 2);
+        }
+
+        let bucket = buckets.buckets.entry((group, key)).or_insert(Bucket {
+            tokens: f64::from(limit.burst),
+            updated_at: now,
+        });
+        bucket.refill(limit, now);
+
+        if bucket.tokens >= 1.0 {
+            bucket.tokens -= 1.0;
+            return Ok(());
+        }
+
+        Err(Limited {
+            retry_after: Duration::from_secs_f64((1.0 - bucket.tokens) / per_second(limit)),
+        })
+    }
+
+    fn limit(&self, group: Group) -> config::RateLimitBucket {
+        match group {
+            Group::Auth => self.config.auth,
+            Group::ImageUpload => self.config.image_upload,
+            Group::Write => self.config.write,
+            Group::Read => self.config.read,
+        }
+    }
+}
+
+fn per_second(limit: config::RateLimitBucket) -> f64 {
+    f64::from(limit.per_minute) / 60.0
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn limiter() -> Limiter {
+        let bucket = config::RateLimitBucket {
+            burst: 2,
+            per_minute: 60,
+        };
+
+        Limiter::new(config::RateLimit {
+            auth: bucket,
+            image_upload: bucket,
+            write: bucket,
+            read: bucket,
+        })
+    }
+
+    #[test]
+    fn test_check() {
+        let limiter = limiter();
+        let key = Key::User("user".into());
+        let now = Instant::now();
+
+        assert!(limiter.check_at(Group::Auth, key.clone(), now).is_ok());
+        assert!(limiter.check_at(Group::Auth, key.clone(), now).is_ok());
+
+        let limited = limiter.check_at(Group::Auth, key.clone(), now).unwrap_err();
+        assert_eq!(Duration::from_secs(1), limited.retry_after);
+
+        // other groups and keys have their own buckets
+        assert!(limiter.check_at(Group::Read, key.clone(), now).is_ok());
+        assert!(
+            limiter
+                .check_at(Group::Auth, Key::User("other".into()), now)
+                .is_ok()
+        );
+
+        let later = now + Duration::from_secs(1);
+        assert!(limiter.check_at(Group::Auth, key.clone(), later).is_ok());
+        assert!(limiter.check_at(Group::Auth, key, later).is_err());
+    }
+}
@@ -37,11 +37,13 @@ impl Backend {
     pub async fn search(
         &self,
         query: String,
+        household_id: &domain::household::Id,
         filter: domain::filter::Recipe,
     ) -> Result<Vec<domain::recipe::Id>, Error> {
         let (tx, rx) = oneshot::channel();
         let msg = Message::Search {
             query,
+            household_id: household_id.into(),
             filter,
             respond_to: tx,
         };
@@ -84,9 +86,10 @@ fn spawn_worker(index_path: &str, store: datastore::Pool) -> Result<mpsc::Sender
                         Message::Search {
                             respond_to,
                             query,
+                            household_id,
                             filter,
                         } => {
-                            let _ = respond_to.send(search(&index, &query, filter));
+                            let _ = respond_to.send(search(&index, &query, &household_id, filter));
                         }
                         Message::IndexAll { respond_to } => {
                             let _ = respond_to.send(load_recipes(&index, &store).await);
@@ -102,6 +105,7 @@ fn spawn_worker(index_path: &str, store: datastore::Pool) -> Result<mpsc::Sender
 fn search(
     index: &milli_v1::Index,
     search: &str,
+    household_id: &str,
     filter: domain::filter::Recipe,
 ) -> Result<Vec<domain::recipe::Id>, Error> {
     let txn = index.read_txn().context("search: readtxn")?;
@@ -110,19 +114,24 @@ fn search(
     search_obj.query(search);
     search_obj.limit(20);
 
+    // only recipes of the household are ever found
     let tag_id_strings: Vec<String> = filter.tag_ids.into_iter().map(String::from).collect();
-    if !tag_id_strings.is_empty() {
-        let cond = milli_v1::FilterCondition::And(
+    let cond = milli_v1::FilterCondition::And(
+        std::iter::once(milli_v1::FilterCondition::In {
+            fid: "household_id".into(),
+            els: vec![household_id.into()],
+        })
+        .chain(
             tag_id_strings
                 .iter()
                 .map(|tag_id| milli_v1::FilterCondition::In {
                     fid: "tag_ids".into(),
                     els: vec![tag_id.as_ref()].into_iter().map(Into::into).collect(),
-                })
-                .collect(),
-        );
-        search_obj.filter(cond.into());
-    }
+                }),
+        )
+        .collect(),
+    );
+    search_obj.filter(cond.into());
 
     // get results
     let result = search_obj.execute().context("search: execute")?;
@@ -182,6 +191,9 @@ async fn load_recipes(index: &milli_v1::Index, store: &datastore::Pool) -> Resul
     ]);
     let mut filterable_fields = Vec::new();
     filterable_fields.push(milli_v1::FilterableAttributesRule::Field("tag_ids".into()));
+    filterable_fields.push(milli_v1::FilterableAttributesRule::Field(
+        "household_id".into(),
+    ));
     builder.set_filterable_fields(filterable_fields);
     builder.set_displayed_fields(vec!["id".into()]);
 
@@ -205,6 +217,10 @@ async fn load_recipes(index: &milli_v1::Index, store: &datastore::Pool) -> Resul
             let mut obj = milli_v1::Object::new();
 
             obj.insert("id".into(), serde_json::Value::String(recipe.id.into()));
+            obj.insert(
+                "household_id".into(),
+                serde_json::Value::String(recipe.household_id.into()),
+            );
             obj.insert(
                 "title".into(),
                 serde_json::Value::String(recipe.title.into()),
@@ -270,6 +286,7 @@ pub enum Message {
     },
     Search {
         query: String,
+        household_id: String,
         filter: domain::filter::Recipe,
         respond_to: oneshot::Sender<Result<Vec<domain::recipe::Id>, Error>>,
     },
@@ -1,11 +1,22 @@
-use std::time::Duration;
+use std::{
+    collections::HashMap, io::Write, os::unix::fs::OpenOptionsExt, sync::Arc, time::Duration,
+};
 
-use anyhow::Context;
+use anyhow::{Context, anyhow};
+use base64::Engine;
 use rand::Rng;
+use ring::{
+    aead::{AES_256_GCM, Aad, LessSafeKey, NONCE_LEN, Nonce, UnboundKey},
+    rand::{SecureRandom, SystemRandom},
+};
+use serde::{Deserialize, Serialize};
 use thiserror::Error;
 use tokio::sync::{mpsc, oneshot};
 
-use crate::domain::{Session, SessionKey};
+use crate::{
+    config,
+    domain::{Session, SessionInfo, SessionKey},
+};
 
 // Error
 
@@ -27,12 +38,199 @@ impl From<tokio::sync::oneshot::error::RecvError> for Error {
     }
 }
 
+// Records
+
+// A session as it is persisted. Only a hash of the session key is kept, so the key cannot be
+// recovered from the store, and the refresh token and client details are sealed with an
+// encryption key.
+pub struct Record {
+    pub key_hash: String,
+    pub id: String,
+    pub user_id: String,
+    pub provider: String,
+    pub revalidate_at: chrono::DateTime<chrono::Utc>,
+    pub expires_at: chrono::DateTime<chrono::Utc>,
+    pub created_at: chrono::DateTime<chrono::Utc>,
+    pub last_used_at: chrono::DateTime<chrono::Utc>,
+    // id of the encryption key the sealed fields were sealed with
+    pub encryption_key_id: String,
+    pub sealed: Vec<u8>,
+    pub household_id: Option<String>,
+}
+
+#[derive(Serialize, Deserialize)]
+struct Sealed {
+    refresh_token: String,
+    user_agent: Option<String>,
+    client: Option<String>,
+}
+
+#[must_use]
+pub fn hash_key(key: &SessionKey) -> String {
+    sha256::digest(key.as_str())
+}
+
+// Seals records with the first configured key, and opens records sealed with any of them, so
+// keys can be rotated by adding a new key in front and removing the old one once the sessions
+// sealed with it have been refreshed or have expired.
+pub struct Cipher {
+    current_key_id: String,
+    keys: HashMap<String, LessSafeKey>,
+    rng: SystemRandom,
+}
+
+impl Cipher {
+    pub fn new(keys: &[config::SessionEncryptionKey]) -> Result<Self, Error> {
+        let current_key_id = keys
+            .first()
+            .ok_or(Error::Other(anyhow!(
+                "No session encryption key configured."
+            )))?
+            .id
+            .clone();
+
+        let keys = keys
+            .iter()
+            .map(|key| {
+                let unbound = UnboundKey::new(&AES_256_GCM, &key.key).map_err(|_| {
+                    Error::Other(anyhow!(
+                        "Session encryption key {} must be 32 bytes.",
+                        key.id
+                    ))
+                })?;
+                Ok((key.id.clone(), LessSafeKey::new(unbound)))
+            })
+            .collect::<Result<HashMap<_, _>, Error>>()?;
+
+        Ok(Cipher {
+            current_key_id,
+            keys,
+            rng: SystemRandom::new(),
+        })
+    }
+
+    pub fn seal(&self, session: &Session) -> Result<Record, Error> {
+        let key_hash = hash_key(&SessionKey(session.key.clone()));
+
+        let mut sealed = postcard::to_allocvec(&Sealed {
+            refresh_token: session.refresh_token.clone(),
+            user_agent: session.user_agent.clone(),
+            client: session.client.clone(),
+        })
+        .context("Could not serialize session.")?;
+
+        let mut nonce = [0; NONCE_LEN];
+        self.rng
+            .fill(&mut nonce)
+            .map_err(|_| Error::Other(anyhow!("Could not generate nonce.")))?;
+
+        // the hash is authenticated as well, so sealed fields cannot be moved to another session
+        self.keys[&self.current_key_id]
+            .seal_in_place_append_tag(
+                Nonce::assume_unique_for_key(nonce),
+                Aad::from(key_hash.as_bytes()),
+                &mut sealed,
+            )
+            .map_err(|_| Error::Other(anyhow!("Could not seal session.")))?;
+
+        Ok(Record {
+            key_hash,
+            id: session.id.clone(),
+            user_id: session.user_id.clone(),
+            provider: session.provider.clone(),
+            revalidate_at: session.revalidate_at,
+            expires_at: session.expires_at,
+            created_at: session.created_at,
+            last_used_at: session.last_used_at,
+            encryption_key_id: self.current_key_id.clone(),
+            sealed: [nonce.as_slice(), &sealed].concat(),
+            household_id: session.household_id.clone(),
+        })
+    }
+
+    fn open(&self, record: &Record) -> Result<Sealed, Error> {
+        // sessions sealed with a key that was since removed can no longer be used
+        let key = self.keys.get(&record.encryption_key_id).ok_or_else(|| {
+            Error::NotFound(anyhow!(
+                "session sealed with unknown key {}",
+                record.encryption_key_id
+            ))
+        })?;
+
+        if record.sealed.len() < NONCE_LEN {
+            return Err(Error::Other(anyhow!("Sealed session is too short.")));
+        }
+        let (nonce, sealed) = record.sealed.split_at(NONCE_LEN);
+        let nonce = Nonce::try_assume_unique_for_key(nonce)
+            .map_err(|_| Error::Other(anyhow!("Invalid session nonce.")))?;
+
+        let mut sealed = sealed.to_vec();
+        let opened = key
+            .open_in_place(nonce, Aad::from(record.key_hash.as_bytes()), &mut sealed)
+            .map_err(|_| Error::Other(anyhow!("Could not open sealed session.")))?;
+
+        Ok(postcard::from_bytes(opened).context("Could not deserialize session.")?)
+    }
+}
+
+// id of the key generated when none is configured
+pub const GENERATED_KEY_ID: &str = "generated";
+
+// The key sessions are sealed with when none is configured. It is generated on first start and
+// written to the file at path, base64 encoded, so sessions survive a restart. To rotate it,
+// configure it with the id "generated" behind a new key.
+pub fn generated_key(path: &str) -> Result<config::SessionEncryptionKey, Error> {
+    let encoded = match std::fs::read_to_string(path) {
+        Ok(encoded) => encoded,
+        Err(err) if err.kind() == std::io::ErrorKind::NotFound => {
+            let mut key = [0; 32];
+            SystemRandom::new()
+                .fill(&mut key)
+                .map_err(|_| Error::Other(anyhow!("Could not generate session encryption key.")))?;
+            let encoded = base64::engine::general_purpose::STANDARD.encode(key);
+
+            let mut file = std::fs::OpenOptions::new()
+                .write(true)
+                .create_new(true)
+                .mode(0o600)
+                .open(path)
+                .context(format!(
+                    "Could not create session encryption key file {path}."
+                ))?;
+            file.write_all(encoded.as_bytes()).context(format!(
+                "Could not write session encryption key file {path}."
+            ))?;
+
+            encoded
+        }
+        Err(err) => {
+            return Err(Error::Other(anyhow!(err).context(format!(
+                "Could not read session encryption key file {path}."
+            ))));
+        }
+    };
+
+    let key = base64::engine::general_purpose::STANDARD
+        .decode(encoded.trim())
+        .ok()
+        .filter(|key| key.len() == 32)
+        .ok_or(Error::Other(anyhow!(
+            "Session encryption key file {path} must hold 32 bytes encoded as base64."
+        )))?;
+
+    Ok(config::SessionEncryptionKey {
+        id: GENERATED_KEY_ID.to_owned(),
+        key,
+    })
+}
+
 const PRUNE_EXPIRED_SESSIONS_DELAY: u64 = 60 * 60;
 const PRUNE_EXPIRED_REFRESH_LOCKS_DELAY: u64 = 60 * 60;
 
 #[derive(Clone)]
 pub struct SessionStore {
     sender: mpsc::Sender<Message>,
+    cipher: Arc<Cipher>,
 }
 
 pub struct RefreshLockStatus {
@@ -44,6 +242,7 @@ impl SessionStore {
     pub fn new(
         sender: mpsc::Sender<Message>,
         background_job_result_sender: mpsc::Sender<BackgroundResultMessage>,
+        cipher: Cipher,
     ) -> Self {
         let prune_sessions_sender = sender.clone();
         let prune_refresh_locks_sender = sender.clone();
@@ -94,24 +293,43 @@ impl SessionStore {
             }
         });
 
-        SessionStore { sender }
+        SessionStore {
+            sender,
+            cipher: Arc::new(cipher),
+        }
     }
 
     pub async fn get(&self, key: SessionKey) -> Result<Session, Error> {
         let (tx, rx) = oneshot::channel();
         let msg = Message::Get {
-            key,
+            key_hash: hash_key(&key),
             respond_to: tx,
         };
 
         let _ = self.sender.send(msg).await;
-        rx.await.context("Session::get")?
+        let record = rx.await.context("Session::get")??;
+        let sealed = self.cipher.open(&record)?;
+
+        Ok(Session {
+            key: key.0,
+            id: record.id,
+            user_id: record.user_id,
+            provider: record.provider,
+            refresh_token: sealed.refresh_token,
+            revalidate_at: record.revalidate_at,
+            expires_at: record.expires_at,
+            created_at: record.created_at,
+            last_used_at: record.last_used_at,
+            user_agent: sealed.user_agent,
+            client: sealed.client,
+            household_id: record.household_id,
+        })
     }
 
     pub async fn set(&self, session: Session) -> Result<(), Error> {
         let (tx, rx) = oneshot::channel();
         let msg = Message::Set {
-            session,
+            record: self.cipher.seal(&session)?,
             respond_to: tx,
         };
 
@@ -120,9 +338,13 @@ impl SessionStore {
     }
 
     pub async fn delete(&self, key: SessionKey) -> Result<(), Error> {
+        self.delete_key_hash(hash_key(&key)).await
+    }
+
+    async fn delete_key_hash(&self, key_hash: String) -> Result<(), Error> {
         let (tx, rx) = oneshot::channel();
         let msg = Message::Delete {
-            key,
+            key_hash,
             respond_to: tx,
         };
 
@@ -130,12 +352,118 @@ impl SessionStore {
         rx.await.context("Session::delete")?
     }
 
+    // Records that the session was just used. It is sealed again with the current key as well, so
+    // sessions that are never refreshed, such as local ones, move off a key being rotated out.
+    pub async fn touch(&self, session: &Session) -> Result<(), Error> {
+        let record = self.cipher.seal(session)?;
+
+        let (tx, rx) = oneshot::channel();
+        let msg = Message::Touch {
+            key_hash: record.key_hash,
+            encryption_key_id: record.encryption_key_id,
+            sealed: record.sealed,
+            respond_to: tx,
+        };
+
+        let _ = self.sender.send(msg).await;
+        rx.await.context("Session::touch")?
+    }
+
+    pub async fn list_for_user(&self, user_id: String) -> Result<Vec<SessionInfo>, Error> {
+        let (tx, rx) = oneshot::channel();
+        let msg = Message::ListForUser {
+            user_id,
+            respond_to: tx,
+        };
+
+        let _ = self.sender.send(msg).await;
+        let records = rx.await.context("Session::list_for_user")??;
+
+        let mut sessions = vec![];
+        for record in records {
+            let sealed = match self.cipher.open(&record) {
+                Ok(sealed) => sealed,
+                Err(err) => {
+                    // sealed with a key that was since removed, or otherwise unreadable, so it
+                    // can never be used again and is removed rather than failing the whole list
+                    println!(
+                        "Removing session {} that cannot be opened: {err:?}.",
+                        record.id
+                    );
+                    if let Err(err) = self.delete_key_hash(record.key_hash).await {
+                        println!("Failed to remove session {}: {err:?}.", record.id);
+                    }
+                    continue;
+                }
+            };
+
+            sessions.push(SessionInfo {
+                id: record.id,
+                created_at: record.created_at,
+                last_used_at: record.last_used_at,
+                user_agent: sealed.user_agent,
+                client: sealed.client,
+            });
+        }
+
+        Ok(sessions)
+    }
+
+    // Switches the household every key of the session acts in.
+    pub async fn set_household_for_user(
+        &self,
+        user_id: String,
+        id: String,
+        household_id: String,
+    ) -> Result<(), Error> {
+        let (tx, rx) = oneshot::channel();
+        let msg = Message::SetHouseholdForUser {
+            user_id,
+            id,
+            household_id,
+            respond_to: tx,
+        };
+
+        let _ = self.sender.send(msg).await;
+        rx.await.context("Session::set_household_for_user")?
+    }
+
+    pub async fn delete_for_user(&self, user_id: String, id: String) -> Result<(), Error> {
+        let (tx, rx) = oneshot::channel();
+        let msg = Message::DeleteForUser {
+            user_id,
+            id,
+            respond_to: tx,
+        };
+
+        let _ = self.sender.send(msg).await;
+        rx.await.context("Session::delete_for_user")?
+    }
+
+    // Deletes every session of the user except the one with keep_id.
+    pub async fn delete_others_for_user(
+        &self,
+        user_id: String,
+        keep_id: String,
+    ) -> Result<(), Error> {
+        let (tx, rx) = oneshot::channel();
+        let msg = Message::DeleteOthersForUser {
+            user_id,
+            keep_id,
+            respond_to: tx,
+        };
+
+        let _ = self.sender.send(msg).await;
+        rx.await.context("Session::delete_others_for_user")?
+    }
+
     pub async fn lock_refresh(&self, key: SessionKey) -> Result<(), Error> {
+        let key_hash = hash_key(&key);
         let mut retries = 0;
         while retries < 9 {
             let (tx, rx) = oneshot::channel();
             let msg = Message::LockRefresh {
-                key: key.clone(),
+                key_hash: key_hash.clone(),
                 max_lock: Duration::from_secs(45),
                 respond_to: tx,
             };
@@ -159,7 +487,7 @@ impl SessionStore {
     pub async fn unlock_refresh(&self, key: SessionKey) -> Result<(), Error> {
         let (tx, rx) = oneshot::channel();
         let msg = Message::UnlockRefresh {
-            key,
+            key_hash: hash_key(&key),
             respond_to: tx,
         };
 
@@ -176,27 +504,53 @@ pub enum BackgroundResultMessage {
 
 pub enum Message {
     Get {
-        key: SessionKey,
-        respond_to: oneshot::Sender<Result<Session, Error>>,
+        key_hash: String,
+        respond_to: oneshot::Sender<Result<Record, Error>>,
     },
     Delete {
-        key: SessionKey,
+        key_hash: String,
         respond_to: oneshot::Sender<Result<(), Error>>,
     },
     Set {
-        session: Session,
+        record: Record,
         respond_to: oneshot::Sender<Result<(), Error>>,
     },
     PruneExpired {
         respond_to: oneshot::Sender<Result<(), Error>>,
     },
+    Touch {
+        key_hash: String,
+        encryption_key_id: String,
+        sealed: Vec<u8>,
+        respond_to: oneshot::Sender<Result<(), Error>>,
+    },
+    ListForUser {
+        user_id: String,
+        respond_to: oneshot::Sender<Result<Vec<Record>, Error>>,
+    },
+    SetHouseholdForUser {
+        user_id: String,
+        id: String,
+        household_id: String,
+        respond_to: oneshot::Sender<Result<(), Error>>,
+    },
+    DeleteForUser {
+        user_id: String,
+        id: String,
+        respond_to: oneshot::Sender<Result<(), Error>>,
+    },
+    DeleteOthersForUser {
+        user_id: String,
+        keep_id: String,
+        respond_to: oneshot::Sender<Result<(), Error>>,
+    },
     LockRefresh {
-        key: SessionKey,
+        key_hash: String,
         max_lock: Duration,
         respond_to: oneshot::Sender<Result<bool, Error>>,
     },
     UnlockRefresh {
-        key: SessionKey,
+        key_hash: String,
         respond_to: oneshot::Sender<Result<(), Error>>,
     },
     PruneExpiredRefresh {
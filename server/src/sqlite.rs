@@ -1,8 +1,12 @@
+mod audit;
+mod household;
 mod image;
 mod pool;
 mod recipe;
 mod session_store;
+mod share;
 mod tag;
+mod token;
 
 pub use pool::DatastoreConfig;
 pub use pool::datastore_handler;
@@ -0,0 +1,88 @@
+use rusqlite::{Connection, params};
+
+use crate::{datastore::Error, domain};
+
+pub fn insert(
+    conn: &Connection,
+    id: &str,
+    recording: &domain::audit::Recording,
+) -> Result<(), Error> {
+    let mut stmt = conn.prepare_cached(
+        "INSERT INTO audit_log (id,occurred_at,user_id,household_id,action,entity,entity_id,detail)
+            VALUES (?1,?2,?3,?4,?5,?6,?7,?8)",
+    )?;
+    stmt.execute(params![
+        id,
+        chrono::Utc::now(),
+        recording.user_id,
+        recording.household_id.as_ref().map(String::from),
+        recording.action.as_str(),
+        recording.action.entity().as_str(),
+        recording.entity_id,
+        recording.detail,
+    ])?;
+
+    Ok(())
+}
+
+// Entries of the household, along with what its members did outside of any household, such as
+// signing in.
+pub fn list(
+    conn: &Connection,
+    household_id: &str,
+    filter: &domain::audit::Filter,
+    limit: u32,
+) -> Result<Vec<domain::audit::Entry>, Error> {
+    let q = "
+        SELECT id, occurred_at, user_id, household_id, action, entity_id, detail
+        FROM audit_log
+        WHERE (
+                household_id = ?1
+                OR (
+                    household_id IS NULL
+                    AND user_id IN (SELECT user_id FROM household_members WHERE household_id = ?1)
+                )
+            )
+            AND (?2 IS NULL OR user_id = ?2)
+            AND (?3 IS NULL OR entity = ?3)
+            AND (?4 IS NULL OR entity_id = ?4)
+            AND (?5 IS NULL OR occurred_at >= ?5)
+            AND (?6 IS NULL OR occurred_at < ?6)
+        ORDER BY occurred_at DESC, id DESC
+        LIMIT ?7";
+
+    let mut stmt = conn.prepare_cached(q)?;
+    let result = stmt.query_and_then(
+        params![
+            household_id,
+            filter.user_id,
+            filter.entity.map(|entity| entity.as_str()),
+            filter.entity_id,
+            filter.from,
+            filter.to,
+            limit,
+        ],
+        |row| {
+            let id: String = row.get("id")?;
+            let household_id: Option<String> = row.get("household_id")?;
+            let action: String = row.get("action")?;
+            Ok(domain::audit::Entry {
+                id: id.as_str().try_into()?,
+                occurred_at: row.get("occurred_at")?,
+                user_id: row.get("user_id")?,
+                household_id: household_id.as_deref().map(TryInto::try_into).transpose()?,
+                action: action.as_str().try_into()?,
+                entity_id: row.get("entity_id")?,
+                detail: row.get("detail")?,
+            })
+        },
+    )?;
+    result.collect()
+}
+
+// Deletes entries older than the retention period, the only way entries are ever removed.
+pub fn prune(conn: &Connection, before: chrono::DateTime<chrono::Utc>) -> Result<usize, Error> {
+    let mut stmt = conn.prepare_cached("DELETE FROM audit_log WHERE occurred_at < ?1")?;
+
+    Ok(stmt.execute(params![before])?)
+}
@@ -0,0 +1,249 @@
+use rusqlite::{Connection, OptionalExtension, Transaction, params};
+
+use crate::{datastore::Error, domain};
+
+pub fn get_active(
+    conn: &Connection,
+    user_id: &str,
+) -> Result<domain::household::Membership, Error> {
+    let q = "
+        SELECT users.active_household_id, household_members.role, users.max_role
+        FROM users
+        LEFT JOIN household_members
+            ON household_members.household_id = users.active_household_id
+            AND household_members.user_id = users.id
+        WHERE users.id = ?1";
+
+    let mut stmt = conn.prepare_cached(q)?;
+    let (id, role, max_role): (Option<String>, Option<String>, Option<String>) = stmt
+        .query_row(params![user_id], |row| {
+            Ok((row.get(0)?, row.get(1)?, row.get(2)?))
+        })?;
+    let (Some(id), Some(role)) = (id, role) else {
+        return Err(Error::NotFound);
+    };
+
+    Ok(domain::household::Membership {
+        household_id: id.as_str().try_into()?,
+        role: capped_role(&role, max_role.as_deref())?,
+    })
+}
+
+pub fn get_role(
+    conn: &Connection,
+    household_id: &str,
+    user_id: &str,
+) -> Result<domain::household::Role, Error> {
+    let q = "
+        SELECT household_members.role, users.max_role
+        FROM household_members
+        JOIN users ON users.id = household_members.user_id
+        WHERE household_members.household_id = ?1 AND household_members.user_id = ?2";
+
+    let mut stmt = conn.prepare_cached(q)?;
+    let (role, max_role): (String, Option<String>) = stmt
+        .query_row(params![household_id, user_id], |row| {
+            Ok((row.get(0)?, row.get(1)?))
+        })?;
+
+    capped_role(&role, max_role.as_deref())
+}
+
+pub fn list(conn: &Connection, user_id: &str) -> Result<Vec<domain::household::Household>, Error> {
+    let q = "
+        SELECT
+            households.id,
+            households.name,
+            household_members.role,
+            households.id IS users.active_household_id,
+            users.max_role
+        FROM household_members
+        JOIN households ON households.id = household_members.household_id
+        JOIN users ON users.id = household_members.user_id
+        WHERE household_members.user_id = ?1
+        ORDER BY households.name ASC, households.id ASC";
+
+    let mut stmt = conn.prepare_cached(q)?;
+    let result = stmt.query_and_then(params![user_id], |row| {
+        let id: String = row.get(0)?;
+        let name: String = row.get(1)?;
+        let role: String = row.get(2)?;
+        let max_role: Option<String> = row.get(4)?;
+        Ok(domain::household::Household {
+            id: id.as_str().try_into()?,
+            name: name.try_into()?,
+            role: capped_role(&role, max_role.as_deref())?,
+            active: row.get(3)?,
+        })
+    })?;
+    result.collect()
+}
+
+pub fn insert(conn: &mut Connection, id: &str, user_id: &str, name: &str) -> Result<(), Error> {
+    let tx = conn.transaction()?;
+    insert_with_member(&tx, id, user_id, name)?;
+    tx.commit()?;
+
+    Ok(())
+}
+
+// Gives the user a household of their own if they are not acting in one, which is the case for
+// users that were just registered.
+pub fn ensure_active(tx: &Transaction, user_id: &str, name: &str) -> Result<(), Error> {
+    let mut stmt = tx.prepare_cached("SELECT active_household_id FROM users WHERE id = ?1")?;
+    let active: Option<String> = stmt.query_row(params![user_id], |row| row.get(0))?;
+    if active.is_some() {
+        return Ok(());
+    }
+
+    let id = String::from(domain::household::Id::new());
+    insert_with_member(tx, &id, user_id, name)?;
+
+    let mut stmt = tx.prepare_cached("UPDATE users SET active_household_id = ?2 WHERE id = ?1")?;
+    stmt.execute(params![user_id, id])?;
+
+    Ok(())
+}
+
+pub fn set_active(conn: &Connection, user_id: &str, household_id: &str) -> Result<(), Error> {
+    let mut stmt = conn.prepare_cached(
+        "UPDATE users SET active_household_id = ?2
+            WHERE id = ?1
+            AND EXISTS (SELECT 1 FROM household_members WHERE household_id = ?2 AND user_id = ?1)",
+    )?;
+    let count = stmt.execute(params![user_id, household_id])?;
+    if count == 0 {
+        return Err(Error::NotFound);
+    }
+
+    Ok(())
+}
+
+pub fn add_member(
+    conn: &mut Connection,
+    household_id: &str,
+    member_id: &str,
+    role: domain::household::Role,
+) -> Result<(), Error> {
+    let tx = conn.transaction_with_behavior(rusqlite::TransactionBehavior::Immediate)?;
+
+    {
+        let mut stmt = tx.prepare_cached("SELECT 1 FROM households WHERE id = ?1")?;
+        stmt.query_row(params![household_id], |_| Ok(()))?;
+
+        let mut stmt = tx.prepare_cached("SELECT 1 FROM users WHERE id = ?1")?;
+        stmt.query_row(params![member_id], |_| Ok(()))?;
+
+        let mut stmt = tx.prepare_cached(
+            "INSERT OR IGNORE INTO household_members (household_id, user_id, role) VALUES (?1,?2,?3)",
+        )?;
+        stmt.execute(params![household_id, member_id, role.as_str()])?;
+    }
+
+    tx.commit()?;
+
+    Ok(())
+}
+
+pub fn set_role(
+    conn: &mut Connection,
+    household_id: &str,
+    member_id: &str,
+    role: domain::household::Role,
+) -> Result<(), Error> {
+    let tx = conn.transaction_with_behavior(rusqlite::TransactionBehavior::Immediate)?;
+
+    {
+        let mut stmt = tx.prepare_cached(
+            "UPDATE household_members SET role = ?3 WHERE household_id = ?1 AND user_id = ?2",
+        )?;
+        if stmt.execute(params![household_id, member_id, role.as_str()])? == 0 {
+            return Err(Error::NotFound);
+        }
+
+        if !has_admin(&tx, household_id)? {
+            return Err(Error::Conflict);
+        }
+    }
+
+    tx.commit()?;
+
+    Ok(())
+}
+
+pub fn remove_member(
+    conn: &mut Connection,
+    household_id: &str,
+    member_id: &str,
+) -> Result<(), Error> {
+    let tx = conn.transaction_with_behavior(rusqlite::TransactionBehavior::Immediate)?;
+
+    {
+        let mut stmt = tx.prepare_cached(
+            "DELETE FROM household_members WHERE household_id = ?1 AND user_id = ?2",
+        )?;
+        if stmt.execute(params![household_id, member_id])? == 0 {
+            return Err(Error::NotFound);
+        }
+
+        if !has_admin(&tx, household_id)? {
+            return Err(Error::Conflict);
+        }
+
+        // the member keeps acting in the household they joined first of the ones left
+        let mut stmt = tx.prepare_cached(
+            "SELECT household_id FROM household_members
+                WHERE user_id = ?1
+                ORDER BY created_at ASC, household_id ASC
+                LIMIT 1",
+        )?;
+        let remaining: Option<String> = stmt
+            .query_row(params![member_id], |row| row.get(0))
+            .optional()?;
+        let Some(remaining) = remaining else {
+            return Err(Error::Conflict);
+        };
+
+        let mut stmt = tx.prepare_cached(
+            "UPDATE users SET active_household_id = ?3 WHERE id = ?1 AND active_household_id = ?2",
+        )?;
+        stmt.execute(params![member_id, household_id, remaining])?;
+    }
+
+    tx.commit()?;
+
+    Ok(())
+}
+
+// The role of a member, limited by the most the user may do in any household.
+fn capped_role(role: &str, max_role: Option<&str>) -> Result<domain::household::Role, Error> {
+    let role = domain::household::Role::try_from(role)?;
+
+    Ok(match max_role {
+        Some(max_role) => role.min(max_role.try_into()?),
+        None => role,
+    })
+}
+
+fn insert_with_member(tx: &Transaction, id: &str, user_id: &str, name: &str) -> Result<(), Error> {
+    let mut stmt = tx.prepare_cached("INSERT INTO households (id, name) VALUES (?1,?2)")?;
+    stmt.execute(params![id, name])?;
+
+    // whoever creates a household administers it
+    let mut stmt = tx.prepare_cached(
+        "INSERT INTO household_members (household_id, user_id, role) VALUES (?1,?2,'admin')",
+    )?;
+    stmt.execute(params![id, user_id])?;
+
+    Ok(())
+}
+
+// A household that still has members must keep at least one admin to manage them.
+fn has_admin(conn: &Connection, household_id: &str) -> Result<bool, Error> {
+    let mut stmt = conn.prepare_cached(
+        "SELECT NOT EXISTS (SELECT 1 FROM household_members WHERE household_id = ?1)
+            OR EXISTS (SELECT 1 FROM household_members WHERE household_id = ?1 AND role = 'admin')",
+    )?;
+
+    Ok(stmt.query_row(params![household_id], |row| row.get(0))?)
+}
@@ -1,17 +1,228 @@
-use rusqlite::{Connection, params};
+use rusqlite::{Connection, OptionalExtension, params};
 
-use crate::datastore::Error;
+use crate::{datastore::Error, domain};
 
-pub fn insert(conn: &Connection, id: &str) -> Result<(), Error> {
-    let mut stmt = conn.prepare_cached("INSERT INTO images (id) VALUES (?1)")?;
-    stmt.insert(params![id])?;
+pub fn insert(
+    conn: &mut Connection,
+    household_id: &str,
+    id: &str,
+    content_hash: &str,
+) -> Result<domain::image::Id, Error> {
+    let tx = conn.transaction_with_behavior(rusqlite::TransactionBehavior::Immediate)?;
+
+    let existing_id = {
+        // the same content may have been stored while this image was being processed
+        let mut stmt = tx.prepare_cached(
+            "SELECT image_id FROM image_contents WHERE household_id = ?1 AND content_hash = ?2",
+        )?;
+        let existing_id: Option<String> = stmt
+            .query_row(params![household_id, content_hash], |row| row.get(0))
+            .optional()?;
+
+        if existing_id.is_none() {
+            let mut stmt =
+                tx.prepare_cached("INSERT INTO images (id, household_id) VALUES (?1,?2)")?;
+            stmt.insert(params![id, household_id])?;
+
+            let mut stmt = tx.prepare_cached(
+                "INSERT INTO image_contents (household_id, content_hash, image_id) VALUES (?1,?2,?3)",
+            )?;
+            stmt.insert(params![household_id, content_hash, id])?;
+        }
+
+        existing_id
+    };
+
+    tx.commit()?;
+
+    Ok(existing_id.as_deref().unwrap_or(id).try_into()?)
+}
+
+// Finds an image of the household, or the avatar of one of its members. Avatars stay in the
+// household they were uploaded to, but are seen wherever their user is a member.
+pub fn get_image(conn: &Connection, household_id: &str, id: &str) -> Result<(), Error> {
+    let mut stmt = conn.prepare_cached(
+        "SELECT id FROM images
+            WHERE id = ?1
+            AND (household_id = ?2 OR id IN (
+                SELECT users.avatar_image_id
+                FROM household_members
+                JOIN users ON users.id = household_members.user_id
+                WHERE household_members.household_id = ?2
+            ))",
+    )?;
+    stmt.query_row(params![id, household_id], |_| Ok(()))?;
 
     Ok(())
 }
 
-pub fn get_image(conn: &Connection, id: &str) -> Result<(), Error> {
-    let mut stmt = conn.prepare_cached("SELECT id FROM images WHERE id = ?1")?;
-    stmt.query_row(params![id], |_| Ok(()))?;
+pub fn list(
+    conn: &Connection,
+    page_size: u64,
+    cursor: Option<domain::page::cursor::Image>,
+) -> Result<domain::page::Image, Error> {
+    let query = format!(
+        "SELECT id, household_id FROM images WHERE id > ?1 ORDER BY id ASC LIMIT {page_size}"
+    );
+
+    let mut stmt = conn.prepare_cached(&query)?;
+    let result = stmt.query_and_then(params![cursor.map_or(String::new(), |c| c.id)], |row| {
+        Ok(domain::image::Stored {
+            id: row.get::<_, String>("id")?.as_str().try_into()?,
+            household_id: row.get::<_, String>("household_id")?.as_str().try_into()?,
+        })
+    })?;
+    let images = result.collect::<Result<Vec<domain::image::Stored>, Error>>()?;
+
+    let last =
+        if images.len() == usize::try_from(page_size).map_err(|err| Error::Unknown(err.into()))? {
+            images.last()
+        } else {
+            None
+        };
+
+    Ok(domain::page::Image {
+        next: last.map(|last| domain::page::cursor::Image {
+            id: last.id.to_string(),
+        }),
+        items: images,
+    })
+}
+
+pub fn count(conn: &Connection) -> Result<u64, Error> {
+    let mut stmt = conn.prepare_cached("SELECT COUNT(*) FROM images")?;
+
+    Ok(stmt.query_row([], |row| row.get(0))?)
+}
+
+pub fn get_by_content_hash(
+    conn: &Connection,
+    household_id: &str,
+    content_hash: &str,
+) -> Result<domain::image::Id, Error> {
+    let mut stmt = conn.prepare_cached(
+        "SELECT image_id FROM image_contents WHERE household_id = ?1 AND content_hash = ?2",
+    )?;
+    let id: String = stmt.query_row(params![household_id, content_hash], |row| row.get(0))?;
+
+    Ok(id.as_str().try_into()?)
+}
+
+// Fails unless the recipe is in the household and uses the image.
+fn get_usage(
+    conn: &Connection,
+    household_id: &str,
+    recipe_id: &str,
+    id: &str,
+) -> Result<(), Error> {
+    let mut stmt = conn.prepare_cached(
+        "SELECT 1 FROM recipe_images
+            JOIN recipes ON recipes.id = recipe_images.recipe_id
+            WHERE recipe_images.recipe_id = ?1 AND recipe_images.image_id = ?2
+                AND recipes.household_id = ?3",
+    )?;
+    stmt.query_row(params![recipe_id, id, household_id], |_| Ok(()))?;
 
     Ok(())
 }
+
+// Where a recipe uses an image without a stored framing, it has neither a crop nor a focal point.
+pub fn get_framing(
+    conn: &Connection,
+    household_id: &str,
+    recipe_id: &str,
+    id: &str,
+) -> Result<domain::image::Framing, Error> {
+    get_usage(conn, household_id, recipe_id, id)?;
+
+    let mut stmt = conn.prepare_cached(
+        "SELECT crop_x, crop_y, crop_width, crop_height, focal_x, focal_y
+            FROM recipe_image_framings WHERE recipe_id = ?1 AND image_id = ?2",
+    )?;
+    let framing = stmt
+        .query_row(params![recipe_id, id], read_framing)
+        .optional()?;
+
+    match framing {
+        Some(framing) => to_framing(framing),
+        None => Ok(domain::image::Framing::default()),
+    }
+}
+
+pub fn set_framing(
+    conn: &Connection,
+    household_id: &str,
+    recipe_id: &str,
+    id: &str,
+    framing: &domain::image::Framing,
+) -> Result<(), Error> {
+    get_usage(conn, household_id, recipe_id, id)?;
+
+    let crop = framing.crop();
+    let focal_point = framing.focal_point();
+
+    let mut stmt = conn.prepare_cached(
+        "INSERT INTO recipe_image_framings
+            (recipe_id, image_id, crop_x, crop_y, crop_width, crop_height, focal_x, focal_y)
+            VALUES (?1,?2,?3,?4,?5,?6,?7,?8)
+            ON CONFLICT (recipe_id, image_id) DO UPDATE SET
+                crop_x = excluded.crop_x,
+                crop_y = excluded.crop_y,
+                crop_width = excluded.crop_width,
+                crop_height = excluded.crop_height,
+                focal_x = excluded.focal_x,
+                focal_y = excluded.focal_y",
+    )?;
+    stmt.execute(params![
+        recipe_id,
+        id,
+        crop.map(domain::image::Crop::x),
+        crop.map(domain::image::Crop::y),
+        crop.map(domain::image::Crop::width),
+        crop.map(domain::image::Crop::height),
+        focal_point.map(domain::image::FocalPoint::x),
+        focal_point.map(domain::image::FocalPoint::y),
+    ])?;
+
+    Ok(())
+}
+
+pub fn list_framings(conn: &Connection, id: &str) -> Result<Vec<domain::image::Framing>, Error> {
+    let mut stmt = conn.prepare_cached(
+        "SELECT crop_x, crop_y, crop_width, crop_height, focal_x, focal_y
+            FROM recipe_image_framings WHERE image_id = ?1",
+    )?;
+    let framings = stmt.query_map([id], read_framing)?;
+
+    framings.map(|framing| to_framing(framing?)).collect()
+}
+
+type FramingRow = [Option<f64>; 6];
+
+fn read_framing(row: &rusqlite::Row) -> rusqlite::Result<FramingRow> {
+    Ok([
+        row.get(0)?,
+        row.get(1)?,
+        row.get(2)?,
+        row.get(3)?,
+        row.get(4)?,
+        row.get(5)?,
+    ])
+}
+
+fn to_framing(row: FramingRow) -> Result<domain::image::Framing, Error> {
+    let [crop_x, crop_y, crop_width, crop_height, focal_x, focal_y] = row;
+
+    let crop = match (crop_x, crop_y, crop_width, crop_height) {
+        (Some(x), Some(y), Some(width), Some(height)) => {
+            Some(domain::image::Crop::new(x, y, width, height)?)
+        }
+        _ => None,
+    };
+    let focal_point = match (focal_x, focal_y) {
+        (Some(x), Some(y)) => Some(domain::image::FocalPoint::new(x, y)?),
+        _ => None,
+    };
+
+    Ok(domain::image::Framing::new(crop, focal_point)?)
+}
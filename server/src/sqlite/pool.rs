@@ -5,10 +5,10 @@ use rusqlite::Connection;
 
 use crate::{
     datastore::{Error, Message},
-    domain::{RegisteringUser, User},
+    domain::{RegisteringUser, User, user},
 };
 
-use super::{image, recipe, tag};
+use super::{audit, household, image, recipe, share, tag, token};
 
 impl From<rusqlite::Error> for Error {
     fn from(value: rusqlite::Error) -> Self {
@@ -19,7 +19,7 @@ impl From<rusqlite::Error> for Error {
     }
 }
 
-const MIGRATION: [&str; 6] = [
+const MIGRATION: [&str; 53] = [
     "
 CREATE TABLE users (
     id TEXT PRIMARY KEY,
@@ -69,6 +69,211 @@ CREATE TABLE recipe_tags (
     FOREIGN KEY (tag_id) REFERENCES tags (id) ON DELETE CASCADE,
     FOREIGN KEY (recipe_id) REFERENCES recipes (id) ON DELETE CASCADE
 );",
+    "
+CREATE TABLE image_contents (
+    content_hash TEXT PRIMARY KEY,
+    image_id TEXT NOT NULL UNIQUE,
+    FOREIGN KEY (image_id) REFERENCES images (id) ON DELETE CASCADE
+);",
+    "
+CREATE TABLE recipe_images (
+    recipe_id TEXT NOT NULL,
+    image_id TEXT NOT NULL,
+    PRIMARY KEY (recipe_id, image_id),
+    FOREIGN KEY (image_id) REFERENCES images (id) ON DELETE RESTRICT,
+    FOREIGN KEY (recipe_id) REFERENCES recipes (id) ON DELETE CASCADE
+);",
+    "
+INSERT INTO recipe_images (recipe_id, image_id)
+    SELECT id, image_id FROM recipes WHERE image_id IS NOT NULL;",
+    // recipes with the same photo share an image, so each recipe frames it on its own
+    "
+CREATE TABLE recipe_image_framings (
+    recipe_id TEXT NOT NULL,
+    image_id TEXT NOT NULL,
+    crop_x REAL,
+    crop_y REAL,
+    crop_width REAL,
+    crop_height REAL,
+    focal_x REAL,
+    focal_y REAL,
+    PRIMARY KEY (recipe_id, image_id),
+    FOREIGN KEY (recipe_id, image_id) REFERENCES recipe_images (recipe_id, image_id) ON DELETE CASCADE
+);",
+    // households, existing users and data are moved into a single shared household
+    "
+CREATE TABLE households (
+    id TEXT PRIMARY KEY,
+    name TEXT NOT NULL,
+    created_at TIMESTAMP DEFAULT CURRENT_TIMESTAMP
+);",
+    "
+CREATE TABLE household_members (
+    household_id TEXT NOT NULL,
+    user_id TEXT NOT NULL,
+    created_at TIMESTAMP DEFAULT CURRENT_TIMESTAMP,
+    PRIMARY KEY (household_id, user_id),
+    FOREIGN KEY (household_id) REFERENCES households (id) ON DELETE CASCADE,
+    FOREIGN KEY (user_id) REFERENCES users (id) ON DELETE CASCADE
+);",
+    "
+ALTER TABLE users ADD COLUMN active_household_id TEXT REFERENCES households (id) ON DELETE SET NULL;",
+    "
+INSERT INTO households (id, name)
+    SELECT '00000000000000000000000000', 'Household'
+    WHERE EXISTS (SELECT 1 FROM users)
+        OR EXISTS (SELECT 1 FROM recipes)
+        OR EXISTS (SELECT 1 FROM tags)
+        OR EXISTS (SELECT 1 FROM images);",
+    "
+INSERT INTO household_members (household_id, user_id)
+    SELECT households.id, users.id FROM households, users;",
+    "
+UPDATE users SET active_household_id = (SELECT id FROM households);",
+    "
+ALTER TABLE recipes ADD COLUMN household_id TEXT REFERENCES households (id) ON DELETE RESTRICT;",
+    "
+UPDATE recipes SET household_id = (SELECT id FROM households);",
+    "
+CREATE INDEX recipes_by_household ON recipes (household_id, title, id);",
+    "
+ALTER TABLE images ADD COLUMN household_id TEXT REFERENCES households (id) ON DELETE RESTRICT;",
+    "
+UPDATE images SET household_id = (SELECT id FROM households);",
+    "
+CREATE INDEX images_by_household ON images (household_id, id);",
+    "
+CREATE TABLE household_tags (
+    id TEXT PRIMARY KEY,
+    household_id TEXT NOT NULL,
+    name TEXT NOT NULL,
+    created_by_user_id TEXT NOT NULL,
+    created_at TIMESTAMP DEFAULT CURRENT_TIMESTAMP,
+    UNIQUE (household_id, name),
+    FOREIGN KEY (household_id) REFERENCES households (id) ON DELETE RESTRICT
+);",
+    "
+INSERT INTO household_tags (id, household_id, name, created_by_user_id, created_at)
+    SELECT tags.id, households.id, tags.name, tags.created_by_user_id, tags.created_at
+    FROM tags, households;",
+    "
+DROP TABLE tags;",
+    "
+ALTER TABLE household_tags RENAME TO tags;",
+    "
+CREATE TABLE household_image_contents (
+    household_id TEXT NOT NULL,
+    content_hash TEXT NOT NULL,
+    image_id TEXT NOT NULL UNIQUE,
+    PRIMARY KEY (household_id, content_hash),
+    FOREIGN KEY (image_id) REFERENCES images (id) ON DELETE CASCADE
+);",
+    "
+INSERT INTO household_image_contents (household_id, content_hash, image_id)
+    SELECT households.id, image_contents.content_hash, image_contents.image_id
+    FROM image_contents, households;",
+    "
+DROP TABLE image_contents;",
+    "
+ALTER TABLE household_image_contents RENAME TO image_contents;",
+    // roles, existing members keep being able to do everything
+    "
+ALTER TABLE household_members ADD COLUMN role TEXT NOT NULL DEFAULT 'viewer'
+    CHECK (role IN ('viewer', 'editor', 'admin'));",
+    "
+UPDATE household_members SET role = 'admin';",
+    "
+ALTER TABLE users ADD COLUMN max_role TEXT CHECK (max_role IN ('viewer', 'editor', 'admin'));",
+    "
+CREATE TABLE tokens (
+    id TEXT PRIMARY KEY,
+    user_id TEXT NOT NULL,
+    name TEXT NOT NULL,
+    token_hash TEXT NOT NULL UNIQUE,
+    read_only BOOLEAN NOT NULL,
+    expires_at TIMESTAMP,
+    created_at TIMESTAMP NOT NULL,
+    last_used_at TIMESTAMP,
+    household_id TEXT NOT NULL,
+    FOREIGN KEY (user_id) REFERENCES users (id) ON DELETE CASCADE,
+    FOREIGN KEY (household_id) REFERENCES households (id) ON DELETE CASCADE
+);",
+    "
+CREATE INDEX tokens_by_user ON tokens (user_id, created_at);",
+    "
+CREATE TABLE shares (
+    id TEXT PRIMARY KEY,
+    household_id TEXT NOT NULL,
+    recipe_id TEXT NOT NULL,
+    token_hash TEXT NOT NULL UNIQUE,
+    expires_at TIMESTAMP,
+    created_at TIMESTAMP NOT NULL,
+    FOREIGN KEY (household_id) REFERENCES households (id) ON DELETE CASCADE,
+    FOREIGN KEY (recipe_id) REFERENCES recipes (id) ON DELETE CASCADE
+);",
+    "
+CREATE INDEX shares_by_recipe ON shares (recipe_id, created_at);",
+    // Users registered so far are only known by their subject, they are linked to the issuer of
+    // the default provider on start.
+    "
+ALTER TABLE users ADD COLUMN issuer TEXT;",
+    "
+ALTER TABLE users ADD COLUMN subject TEXT;",
+    "
+UPDATE users SET subject = CASE
+    WHEN oauth_id LIKE 'custom|%' THEN substr(oauth_id, 8)
+    ELSE oauth_id
+END;",
+    // oauth_id is replaced by issuer and subject, so users are rebuilt without it
+    "
+CREATE TABLE identified_users (
+    id TEXT PRIMARY KEY,
+    name TEXT NOT NULL,
+    created_at TIMESTAMP DEFAULT CURRENT_TIMESTAMP,
+    active_household_id TEXT REFERENCES households (id) ON DELETE SET NULL,
+    max_role TEXT CHECK (max_role IN ('viewer', 'editor', 'admin')),
+    issuer TEXT,
+    subject TEXT NOT NULL
+);",
+    "
+INSERT INTO identified_users (id, name, created_at, active_household_id, max_role, issuer, subject)
+    SELECT id, name, created_at, active_household_id, max_role, issuer, subject FROM users;",
+    "
+DROP TABLE users;",
+    "
+ALTER TABLE identified_users RENAME TO users;",
+    "
+CREATE UNIQUE INDEX users_by_identity ON users (issuer, subject);",
+    // the name chosen by the user, the name column keeps the one given by their provider
+    "
+ALTER TABLE users ADD COLUMN display_name TEXT;",
+    "
+ALTER TABLE users ADD COLUMN avatar_image_id TEXT REFERENCES images (id) ON DELETE SET NULL;",
+    "
+ALTER TABLE users ADD COLUMN preferred_units TEXT CHECK (preferred_units IN ('metric', 'imperial'));",
+    "
+ALTER TABLE users ADD COLUMN default_servings INTEGER;",
+    // entries refer to users and households without foreign keys, so they outlive them
+    "
+CREATE TABLE audit_log (
+    id TEXT PRIMARY KEY,
+    occurred_at TIMESTAMP NOT NULL,
+    user_id TEXT NOT NULL,
+    household_id TEXT,
+    action TEXT NOT NULL,
+    entity TEXT NOT NULL,
+    entity_id TEXT NOT NULL,
+    detail TEXT
+);",
+    "
+CREATE INDEX audit_log_by_household ON audit_log (household_id, occurred_at);",
+    "
+CREATE INDEX audit_log_by_user ON audit_log (user_id, occurred_at);",
+    "
+CREATE TRIGGER audit_log_is_append_only BEFORE UPDATE ON audit_log
+BEGIN
+    SELECT RAISE(ABORT, 'the audit log is append-only');
+END;",
 ];
 
 fn prepare_connection(conn: &Connection) -> Result<(), Error> {
@@ -83,6 +288,7 @@ pub struct DatastoreHandler {}
 pub struct DatastoreConfig {
     pub recipe_page_size: u64,
     pub recipe_dump_page_size: u64,
+    pub image_dump_page_size: u64,
 }
 
 pub fn datastore_handler(
@@ -92,6 +298,10 @@ pub fn datastore_handler(
     let mut conn = Connection::open(path)?;
     prepare_connection(&conn)?;
 
+    // Migrations that rebuild a table must not cascade deletes into the tables referring to it,
+    // so foreign keys are only checked once all migrations have run.
+    conn.pragma_update(None, "foreign_keys", "OFF")?;
+
     // run migrations
     let tx = conn.transaction_with_behavior(rusqlite::TransactionBehavior::Immediate)?;
     let user_version: usize = tx.pragma_query_value(None, "user_version", |row| row.get(0))?;
@@ -102,6 +312,16 @@ pub fn datastore_handler(
             for query in &MIGRATION[user_version..] {
                 tx.execute(query, ())?;
             }
+
+            let violations: u64 =
+                tx.query_row("SELECT COUNT(*) FROM pragma_foreign_key_check", [], |row| {
+                    row.get(0)
+                })?;
+            if violations > 0 {
+                return Err(Error::Unknown(anyhow!(
+                    "Migration left {violations} foreign key violations"
+                )));
+            }
             tx.pragma_update(None, "user_version", desired_version)?;
         }
         Ordering::Equal => {}
@@ -128,11 +348,13 @@ pub fn datastore_handler(
 struct ThreadWorker {}
 
 impl ThreadWorker {
+    #[allow(clippy::too_many_lines)]
     fn new(path: &str, config: &DatastoreConfig) -> Result<(Self, mpsc::Sender<Message>), Error> {
         let (sender, receiver) = mpsc::channel();
 
         let recipe_page_size = config.recipe_page_size;
         let recipe_dump_page_size = config.recipe_dump_page_size;
+        let image_dump_page_size = config.image_dump_page_size;
 
         let mut conn = Connection::open(path)?;
         prepare_connection(&conn)?;
@@ -147,13 +369,198 @@ impl ThreadWorker {
                     Message::GetUser { respond_to, id } => {
                         let _ = respond_to.send(get_user(&conn, &id));
                     }
-                    Message::UpsertUserByOauthId { respond_to, user } => {
-                        let _ = respond_to.send(upsert_user_by_oauth_id(&conn, &user));
+                    Message::UpsertUserByIdentity { respond_to, user } => {
+                        let _ = respond_to.send(upsert_user_by_identity(&mut conn, &user));
+                    }
+                    Message::UpdateUser {
+                        id,
+                        user,
+                        respond_to,
+                    } => {
+                        let _ = respond_to.send(update_user(&conn, &id, &user));
+                    }
+                    Message::LinkUnlinkedUsers { issuer, respond_to } => {
+                        let _ = respond_to.send(link_unlinked_users(&conn, &issuer));
+                    }
+                    Message::CountUnlinkedUsers { respond_to } => {
+                        let _ = respond_to.send(count_unlinked_users(&conn));
                     }
-                    Message::GetRecipe { id, respond_to } => {
-                        let _ = respond_to.send(recipe::get(&conn, &id));
+                    Message::GetActiveMembership {
+                        user_id,
+                        respond_to,
+                    } => {
+                        let _ = respond_to.send(household::get_active(&conn, &user_id));
+                    }
+                    Message::GetHouseholdRole {
+                        household_id,
+                        user_id,
+                        respond_to,
+                    } => {
+                        let _ =
+                            respond_to.send(household::get_role(&conn, &household_id, &user_id));
+                    }
+                    Message::ListHouseholds {
+                        user_id,
+                        respond_to,
+                    } => {
+                        let _ = respond_to.send(household::list(&conn, &user_id));
+                    }
+                    Message::CreateHousehold {
+                        id,
+                        user_id,
+                        name,
+                        respond_to,
+                    } => {
+                        let _ = respond_to.send(household::insert(&mut conn, &id, &user_id, &name));
+                    }
+                    Message::SetActiveHousehold {
+                        user_id,
+                        household_id,
+                        respond_to,
+                    } => {
+                        let _ =
+                            respond_to.send(household::set_active(&conn, &user_id, &household_id));
+                    }
+                    Message::AddHouseholdMember {
+                        household_id,
+                        member_id,
+                        role,
+                        respond_to,
+                    } => {
+                        let _ = respond_to.send(household::add_member(
+                            &mut conn,
+                            &household_id,
+                            &member_id,
+                            role,
+                        ));
+                    }
+                    Message::SetHouseholdMemberRole {
+                        household_id,
+                        member_id,
+                        role,
+                        respond_to,
+                    } => {
+                        let _ = respond_to.send(household::set_role(
+                            &mut conn,
+                            &household_id,
+                            &member_id,
+                            role,
+                        ));
+                    }
+                    Message::RemoveHouseholdMember {
+                        household_id,
+                        member_id,
+                        respond_to,
+                    } => {
+                        let _ = respond_to.send(household::remove_member(
+                            &mut conn,
+                            &household_id,
+                            &member_id,
+                        ));
+                    }
+                    Message::CreateToken {
+                        id,
+                        user_id,
+                        token_hash,
+                        token,
+                        respond_to,
+                    } => {
+                        let _ = respond_to.send(token::insert(
+                            &conn,
+                            &id,
+                            &user_id,
+                            &token_hash,
+                            &token,
+                        ));
+                    }
+                    Message::ListTokens {
+                        user_id,
+                        respond_to,
+                    } => {
+                        let _ = respond_to.send(token::list(&conn, &user_id));
+                    }
+                    Message::DeleteToken {
+                        user_id,
+                        id,
+                        respond_to,
+                    } => {
+                        let _ = respond_to.send(token::delete(&conn, &user_id, &id));
+                    }
+                    Message::GetTokenByHash {
+                        token_hash,
+                        respond_to,
+                    } => {
+                        let _ = respond_to.send(token::get_by_hash(&conn, &token_hash));
+                    }
+                    Message::TouchToken { id, respond_to } => {
+                        let _ = respond_to.send(token::touch(&conn, &id));
+                    }
+                    Message::CreateShare {
+                        id,
+                        household_id,
+                        recipe_id,
+                        token_hash,
+                        share,
+                        respond_to,
+                    } => {
+                        let _ = respond_to.send(share::insert(
+                            &conn,
+                            &id,
+                            &household_id,
+                            &recipe_id,
+                            &token_hash,
+                            &share,
+                        ));
+                    }
+                    Message::ListShares {
+                        household_id,
+                        recipe_id,
+                        respond_to,
+                    } => {
+                        let _ = respond_to.send(share::list(&conn, &household_id, &recipe_id));
+                    }
+                    Message::DeleteShare {
+                        household_id,
+                        recipe_id,
+                        id,
+                        respond_to,
+                    } => {
+                        let _ =
+                            respond_to.send(share::delete(&conn, &household_id, &recipe_id, &id));
+                    }
+                    Message::GetShareByHash {
+                        token_hash,
+                        respond_to,
+                    } => {
+                        let _ = respond_to.send(share::get_by_hash(&conn, &token_hash));
+                    }
+                    Message::RecordAudit {
+                        id,
+                        recording,
+                        respond_to,
+                    } => {
+                        let _ = respond_to.send(audit::insert(&conn, &id, &recording));
+                    }
+                    Message::ListAudit {
+                        household_id,
+                        filter,
+                        limit,
+                        respond_to,
+                    } => {
+                        let _ = respond_to.send(audit::list(&conn, &household_id, &filter, limit));
+                    }
+                    Message::PruneAudit { before, respond_to } => {
+                        let _ = respond_to.send(audit::prune(&conn, before));
+                    }
+                    Message::GetRecipe {
+                        household_id,
+                        id,
+                        respond_to,
+                    } => {
+                        let _ = respond_to.send(recipe::get(&conn, &household_id, &id));
                     }
                     Message::ListRecipes {
+                        household_id,
                         filter,
                         cursor,
                         respond_to,
@@ -161,19 +568,28 @@ impl ThreadWorker {
                         let _ = respond_to.send(recipe::list_recipes(
                             &conn,
                             recipe_page_size,
+                            &household_id,
                             &filter,
                             cursor,
                         ));
                     }
                     Message::CreateRecipe {
+                        household_id,
                         id,
                         user_id,
                         recipe,
                         respond_to,
                     } => {
-                        let _ = respond_to.send(recipe::insert(&mut conn, &id, &user_id, recipe));
+                        let _ = respond_to.send(recipe::insert(
+                            &mut conn,
+                            &household_id,
+                            &id,
+                            &user_id,
+                            recipe,
+                        ));
                     }
                     Message::UpdateRecipe {
+                        household_id,
                         id,
                         user_id,
                         recipe,
@@ -182,6 +598,7 @@ impl ThreadWorker {
                     } => {
                         let _ = respond_to.send(recipe::update(
                             &mut conn,
+                            &household_id,
                             &id,
                             &user_id,
                             recipe,
@@ -189,17 +606,28 @@ impl ThreadWorker {
                         ));
                     }
                     Message::GetRevisions {
+                        household_id,
                         recipe_id,
                         respond_to,
                     } => {
-                        let _ = respond_to.send(recipe::get_revisions(&conn, &recipe_id));
+                        let _ = respond_to.send(recipe::get_revisions(
+                            &conn,
+                            &household_id,
+                            &recipe_id,
+                        ));
                     }
                     Message::GetRevision {
+                        household_id,
                         recipe_id,
                         revision,
                         respond_to,
                     } => {
-                        let _ = respond_to.send(recipe::get_revision(&conn, &recipe_id, revision));
+                        let _ = respond_to.send(recipe::get_revision(
+                            &conn,
+                            &household_id,
+                            &recipe_id,
+                            revision,
+                        ));
                     }
                     Message::DumpRecipesForIndex { cursor, respond_to } => {
                         let _ = respond_to.send(recipe::dump_recipes_for_index(
@@ -208,21 +636,87 @@ impl ThreadWorker {
                             cursor,
                         ));
                     }
-                    Message::GetTags { respond_to } => {
-                        let _ = respond_to.send(tag::get_all(&conn));
+                    Message::GetTags {
+                        household_id,
+                        respond_to,
+                    } => {
+                        let _ = respond_to.send(tag::get_all(&conn, &household_id));
                     }
                     Message::CreateTag {
+                        household_id,
                         user_id,
                         name,
                         respond_to,
                     } => {
-                        let _ = respond_to.send(tag::insert(&conn, &user_id, &name));
+                        let _ = respond_to.send(tag::insert(&conn, &household_id, &user_id, &name));
                     }
-                    Message::CreateImage { id, respond_to } => {
-                        let _ = respond_to.send(image::insert(&conn, &id));
+                    Message::CreateImage {
+                        household_id,
+                        id,
+                        content_hash,
+                        respond_to,
+                    } => {
+                        let _ = respond_to.send(image::insert(
+                            &mut conn,
+                            &household_id,
+                            &id,
+                            &content_hash,
+                        ));
+                    }
+                    Message::GetImage {
+                        household_id,
+                        id,
+                        respond_to,
+                    } => {
+                        let _ = respond_to.send(image::get_image(&conn, &household_id, &id));
+                    }
+                    Message::GetImageByContentHash {
+                        household_id,
+                        content_hash,
+                        respond_to,
+                    } => {
+                        let _ = respond_to.send(image::get_by_content_hash(
+                            &conn,
+                            &household_id,
+                            &content_hash,
+                        ));
+                    }
+                    Message::ListImages { cursor, respond_to } => {
+                        let _ = respond_to.send(image::list(&conn, image_dump_page_size, cursor));
+                    }
+                    Message::CountImages { respond_to } => {
+                        let _ = respond_to.send(image::count(&conn));
+                    }
+                    Message::GetImageFraming {
+                        household_id,
+                        recipe_id,
+                        id,
+                        respond_to,
+                    } => {
+                        let _ = respond_to.send(image::get_framing(
+                            &conn,
+                            &household_id,
+                            &recipe_id,
+                            &id,
+                        ));
+                    }
+                    Message::SetImageFraming {
+                        household_id,
+                        recipe_id,
+                        id,
+                        framing,
+                        respond_to,
+                    } => {
+                        let _ = respond_to.send(image::set_framing(
+                            &conn,
+                            &household_id,
+                            &recipe_id,
+                            &id,
+                            &framing,
+                        ));
                     }
-                    Message::GetImage { id, respond_to } => {
-                        let _ = respond_to.send(image::get_image(&conn, &id));
+                    Message::ListImageFramings { id, respond_to } => {
+                        let _ = respond_to.send(image::list_framings(&conn, &id));
                     }
                 }
             }
@@ -232,41 +726,111 @@ impl ThreadWorker {
     }
 }
 
-fn upsert_user_by_oauth_id(
-    conn: &Connection,
+fn upsert_user_by_identity(
+    conn: &mut Connection,
     registering: &RegisteringUser,
 ) -> Result<User, Error> {
-    let q = "INSERT INTO users (id,oauth_id,name) VALUES (?1,?2,?3) ON CONFLICT (oauth_id) DO UPDATE SET name = ?3";
+    let tx = conn.transaction_with_behavior(rusqlite::TransactionBehavior::Immediate)?;
 
-    let mut stmt = conn.prepare_cached(q)?;
-    stmt.execute([
-        &registering.potential_id,
-        &registering.oauth_id,
-        &registering.name,
-    ])?;
+    let user = {
+        let q = "INSERT INTO users (id,issuer,subject,name,max_role)
+            VALUES (?1,?2,?3,?4,?5)
+            ON CONFLICT (issuer, subject) DO UPDATE SET name = ?4, max_role = ?5";
+
+        let mut stmt = tx.prepare_cached(q)?;
+        stmt.execute(rusqlite::params![
+            &registering.potential_id,
+            &registering.issuer,
+            &registering.subject,
+            &registering.name,
+            registering.max_role.map(|role| role.as_str()),
+        ])?;
+
+        // fetch user back from the database so that the id is known
+        let mut stmt =
+            tx.prepare_cached(&format!("{SELECT_USER} WHERE issuer = ?1 AND subject = ?2"))?;
+        let user =
+            to_user(stmt.query_row([&registering.issuer, &registering.subject], read_user)?)?;
+
+        // new users start out in a household named after them
+        household::ensure_active(&tx, &user.id, &user.name)?;
+
+        user
+    };
+
+    tx.commit()?;
 
-    // fetch user back from the database so that the id is known
-    let mut stmt = conn.prepare_cached("SELECT * FROM users WHERE oauth_id = ?1")?;
-    let user = stmt.query_row([&registering.oauth_id], |row| {
-        Ok(User {
-            id: row.get(0)?,
-            oauth_id: row.get(1)?,
-            name: row.get(2)?,
-        })
-    })?;
     Ok(user)
 }
 
+const SELECT_USER: &str = "
+    SELECT id, COALESCE(display_name, name), avatar_image_id, preferred_units, default_servings
+    FROM users";
+
+type UserRow = (String, String, Option<String>, Option<String>, Option<u32>);
+
+fn read_user(row: &rusqlite::Row) -> rusqlite::Result<UserRow> {
+    Ok((
+        row.get(0)?,
+        row.get(1)?,
+        row.get(2)?,
+        row.get(3)?,
+        row.get(4)?,
+    ))
+}
+
+fn to_user(row: UserRow) -> Result<User, Error> {
+    let (id, name, avatar_image_id, units, default_servings) = row;
+
+    Ok(User {
+        id,
+        name,
+        avatar_image_id,
+        preferences: user::Preferences {
+            units: units.as_deref().map(user::Units::try_from).transpose()?,
+            default_servings: default_servings.map(user::Servings::try_from).transpose()?,
+        },
+    })
+}
+
 fn get_user(conn: &Connection, id: &str) -> Result<User, Error> {
-    let q = "SELECT * FROM users WHERE id = ?1";
+    let mut stmt = conn.prepare_cached(&format!("{SELECT_USER} WHERE id = ?1"))?;
+
+    to_user(stmt.query_row([id], read_user)?)
+}
+
+fn update_user(conn: &Connection, id: &str, updating: &user::Updating) -> Result<User, Error> {
+    let q = "
+        UPDATE users
+        SET display_name = ?2, avatar_image_id = ?3, preferred_units = ?4, default_servings = ?5
+        WHERE id = ?1";
 
     let mut stmt = conn.prepare_cached(q)?;
-    let user = stmt.query_row([id], |row| {
-        Ok(User {
-            id: row.get(0)?,
-            oauth_id: row.get(1)?,
-            name: row.get(2)?,
-        })
-    })?;
-    Ok(user)
+    let updated = stmt.execute(rusqlite::params![
+        id,
+        updating.name.clone().map(String::from),
+        updating.avatar_image_id.as_ref().map(String::from),
+        updating.preferences.units.map(|units| units.as_str()),
+        updating.preferences.default_servings.map(u32::from),
+    ])?;
+    if updated == 0 {
+        return Err(Error::NotFound);
+    }
+
+    get_user(conn, id)
+}
+
+// A user with the same subject that was already linked keeps their account, the unlinked one is
+// left as it is.
+fn link_unlinked_users(conn: &Connection, issuer: &str) -> Result<usize, Error> {
+    let mut stmt =
+        conn.prepare_cached("UPDATE OR IGNORE users SET issuer = ?1 WHERE issuer IS NULL")?;
+
+    Ok(stmt.execute([issuer])?)
+}
+
+fn count_unlinked_users(conn: &Connection) -> Result<usize, Error> {
+    let mut stmt = conn.prepare_cached("SELECT COUNT(*) FROM users WHERE issuer IS NULL")?;
+
+    Ok(stmt.query_row([], |row| row.get(0))?)
 }
@@ -1,3 +1,5 @@
+use std::collections::HashSet;
+
 use anyhow::Context;
 use rusqlite::{Connection, params};
 
@@ -6,35 +8,21 @@ use crate::{
     domain::{self, ListedRecipe},
 };
 
-pub fn get(conn: &Connection, id: &str) -> Result<domain::Recipe, Error> {
-    let hashed_document = get_document(conn, id)?;
-    let document = hashed_document.document;
+pub fn get(conn: &Connection, household_id: &str, id: &str) -> Result<domain::Recipe, Error> {
+    let hashed_document = get_document(conn, household_id, id)?;
 
-    Ok(domain::Recipe {
-        id: id.try_into()?,
-        hash: hashed_document.hash,
-        title: document.title.try_into()?,
-        image_id: document.image_id,
-        ingredients: document
-            .ingredients
-            .into_iter()
-            .map(domain::recipe::IngredientBlock::try_from)
-            .collect::<Result<Vec<domain::recipe::IngredientBlock>, domain::ValidationError>>()?,
-        instructions: document
-            .instructions
-            .into_iter()
-            .map(domain::recipe::InstructionBlock::try_from)
-            .collect::<Result<Vec<domain::recipe::InstructionBlock>, domain::ValidationError>>()?,
-        notes: match document.notes {
-            None => None,
-            Some(s) => Some(s.try_into()?),
-        },
-        tags: get_tags_for_recipe(conn, document.tag_ids)?,
-    })
+    to_recipe(
+        conn,
+        household_id,
+        id,
+        hashed_document.hash,
+        hashed_document.document,
+    )
 }
 
 pub fn insert(
     conn: &mut Connection,
+    household_id: &str,
     id: &str,
     user_id: &str,
     recipe: RecipeDocument,
@@ -49,12 +37,15 @@ pub fn insert(
     let tx = conn.transaction()?;
 
     {
+        check_references(&tx, household_id, &recipe)?;
+
         // create recipe
         let mut stmt = tx.prepare_cached(
-            "INSERT INTO recipes (id,title,image_id,document) VALUES (?1,?2,?3,?4)",
+            "INSERT INTO recipes (id,household_id,title,image_id,document) VALUES (?1,?2,?3,?4,?5)",
         )?;
         stmt.insert(params![
             id,
+            household_id,
             recipe.title,
             recipe.image_id.as_ref().map(String::from),
             serialized_document
@@ -65,6 +56,9 @@ pub fn insert(
             tx.prepare_cached("INSERT INTO recipe_revisions (recipe_id, revision, created_by_user_id) VALUES (?1,?2,?3)")?;
         stmt.execute(params![id, 0, user_id])?;
 
+        // reference images
+        add_images_for_recipe(&tx, id, &recipe)?;
+
         // create tags
         update_tags_for_recipe(&tx, id, recipe.tag_ids)?;
     }
@@ -76,6 +70,7 @@ pub fn insert(
 
 pub fn update(
     conn: &mut Connection,
+    household_id: &str,
     id: &str,
     user_id: &str,
     recipe: RecipeDocument,
@@ -84,7 +79,7 @@ pub fn update(
     let tx = conn.transaction_with_behavior(rusqlite::TransactionBehavior::Immediate)?;
 
     {
-        let current_document = get_document(&tx, id)?;
+        let current_document = get_document(&tx, household_id, id)?;
         let current_serialized_document =
             postcard::to_allocvec(&VersionedRecipeDocument::from(current_document.document))
                 .map_err(|err| Error::Unknown(err.into()))?;
@@ -99,6 +94,8 @@ pub fn update(
             return Err(Error::Conflict);
         }
 
+        check_references(&tx, household_id, &recipe)?;
+
         // get current patch revision
         let mut stmt =
             tx.prepare_cached("SELECT COUNT(*) FROM recipe_revisions WHERE recipe_id = ?1")?;
@@ -123,6 +120,9 @@ pub fn update(
         )?;
         stmt.execute(params![id, patch_count, patch, user_id])?;
 
+        // reference images
+        add_images_for_recipe(&tx, id, &recipe)?;
+
         // update tags
         update_tags_for_recipe(&tx, id, recipe.tag_ids)?;
     }
@@ -139,16 +139,19 @@ pub fn dump_recipes_for_index(
 ) -> Result<domain::page::DumpedIndexableRecipe, Error> {
     struct QueryResult {
         id: domain::recipe::Id,
+        household_id: domain::household::Id,
         document: Vec<u8>,
     }
 
-    let query =
-        format!("SELECT id,document FROM recipes WHERE id > ? ORDER BY id ASC LIMIT {page_size}");
+    let query = format!(
+        "SELECT id,household_id,document FROM recipes WHERE id > ? ORDER BY id ASC LIMIT {page_size}"
+    );
 
     let mut stmt = conn.prepare_cached(&query)?;
     let result = stmt.query_and_then(params![cursor.map_or(String::new(), |c| c.id)], |row| {
         Ok(QueryResult {
             id: (row.get::<_, String>("id")?.as_str()).try_into()?,
+            household_id: (row.get::<_, String>("household_id")?.as_str()).try_into()?,
             document: row.get("document")?,
         })
     })?;
@@ -164,7 +167,9 @@ pub fn dump_recipes_for_index(
             let document: RecipeDocument = versioned_document.into();
 
             Ok(RecipeDocument::to_dumped_indexable_recipe(
-                result.id, document,
+                result.id,
+                result.household_id,
+                document,
             )?)
         })
         .collect();
@@ -188,6 +193,7 @@ pub fn dump_recipes_for_index(
 pub fn list_recipes(
     conn: &Connection,
     page_size: u64,
+    household_id: &str,
     filter: &domain::filter::Recipe,
     cursor: Option<domain::page::cursor::Recipe>,
 ) -> Result<domain::page::Recipe, Error> {
@@ -199,6 +205,9 @@ pub fn list_recipes(
     // WHERE
     let mut wheres: Vec<String> = vec![];
 
+    params.push(query::Param::String(household_id.to_owned()));
+    wheres.push("recipes.household_id = ?".into());
+
     if let Some(cursor) = cursor {
         params.push(query::Param::String(cursor.name.clone()));
         params.push(query::Param::String(cursor.name.clone()));
@@ -216,11 +225,7 @@ pub fn list_recipes(
         wheres.push(format!("recipe_tags.tag_id IN ({param_string})"));
     }
 
-    let where_clause = if wheres.is_empty() {
-        ""
-    } else {
-        &format!("WHERE {}", wheres.join(" AND "))
-    };
+    let where_clause = format!("WHERE {}", wheres.join(" AND "));
 
     // HAVING
     let having_clause = if tag_count > 0 {
@@ -276,12 +281,17 @@ pub fn list_recipes(
 
 pub fn get_revisions(
     conn: &Connection,
+    household_id: &str,
     recipe_id: &str,
 ) -> Result<Vec<domain::RecipeRevision>, Error> {
-    let q = "SELECT revision FROM recipe_revisions WHERE recipe_id = ?1 ORDER BY revision DESC";
+    let q = "
+        SELECT revision FROM recipe_revisions
+        JOIN recipes ON recipes.id = recipe_revisions.recipe_id
+        WHERE recipe_revisions.recipe_id = ?1 AND recipes.household_id = ?2
+        ORDER BY revision DESC";
 
     let mut stmt = conn.prepare_cached(q)?;
-    let result = stmt.query_and_then([recipe_id], |row| {
+    let result = stmt.query_and_then([recipe_id, household_id], |row| {
         Ok(domain::RecipeRevision {
             revision: row.get("revision")?,
         })
@@ -291,14 +301,15 @@ pub fn get_revisions(
 
 pub fn get_revision(
     conn: &Connection,
+    household_id: &str,
     recipe_id: &str,
     revision: usize,
 ) -> Result<domain::Recipe, Error> {
     // get current recipe
-    let q = "SELECT document FROM recipes WHERE id = ?1";
+    let q = "SELECT document FROM recipes WHERE id = ?1 AND household_id = ?2";
 
     let mut stmt = conn.prepare_cached(q)?;
-    let current_document: Vec<u8> = stmt.query_row([recipe_id], |row| {
+    let current_document: Vec<u8> = stmt.query_row([recipe_id, household_id], |row| {
         Ok(row.get_ref(0)?.as_bytes()?.to_owned())
     })?;
 
@@ -329,34 +340,73 @@ pub fn get_revision(
     let document: RecipeDocument = versioned_document.into();
     let hash = sha256::digest(&serialized_document);
 
+    to_recipe(conn, household_id, recipe_id, hash, document)
+}
+
+fn to_recipe(
+    conn: &Connection,
+    household_id: &str,
+    id: &str,
+    hash: String,
+    document: RecipeDocument,
+) -> Result<domain::Recipe, Error> {
+    let mut instructions = document
+        .instructions
+        .into_iter()
+        .map(domain::recipe::InstructionBlock::try_from)
+        .collect::<Result<Vec<domain::recipe::InstructionBlock>, domain::ValidationError>>()?;
+    for step_image in document.step_images {
+        if let Some(block) = instructions.get_mut(step_image.block) {
+            block.step_images.push(domain::recipe::StepImage {
+                step: step_image.step,
+                image_id: step_image.image_id,
+            });
+        }
+    }
+
     Ok(domain::Recipe {
-        id: recipe_id.try_into()?,
+        id: id.try_into()?,
         hash,
         title: document.title.try_into()?,
         image_id: document.image_id,
+        images: document
+            .images
+            .into_iter()
+            .map(
+                |image| -> Result<domain::recipe::Image, domain::ValidationError> {
+                    Ok(domain::recipe::Image {
+                        id: image.image_id,
+                        caption: match image.caption {
+                            None => None,
+                            Some(caption) => Some(caption.try_into()?),
+                        },
+                    })
+                },
+            )
+            .collect::<Result<Vec<domain::recipe::Image>, domain::ValidationError>>()?,
         ingredients: document
             .ingredients
             .into_iter()
             .map(domain::recipe::IngredientBlock::try_from)
             .collect::<Result<Vec<domain::recipe::IngredientBlock>, domain::ValidationError>>()?,
-        instructions: document
-            .instructions
-            .into_iter()
-            .map(domain::recipe::InstructionBlock::try_from)
-            .collect::<Result<Vec<domain::recipe::InstructionBlock>, domain::ValidationError>>()?,
+        instructions,
         notes: match document.notes {
             None => None,
             Some(s) => Some(s.try_into()?),
         },
-        tags: get_tags_for_recipe(conn, document.tag_ids)?,
+        tags: get_tags_for_recipe(conn, household_id, document.tag_ids)?,
     })
 }
 
-fn get_document(conn: &Connection, id: &str) -> Result<HashedRecipeDocument, Error> {
-    let q = "SELECT document FROM recipes WHERE id = ?1";
+fn get_document(
+    conn: &Connection,
+    household_id: &str,
+    id: &str,
+) -> Result<HashedRecipeDocument, Error> {
+    let q = "SELECT document FROM recipes WHERE id = ?1 AND household_id = ?2";
 
     let mut stmt = conn.prepare_cached(q)?;
-    let serialized_document: Vec<u8> = stmt.query_row([id], |row| row.get(0))?;
+    let serialized_document: Vec<u8> = stmt.query_row([id, household_id], |row| row.get(0))?;
 
     let versioned_document: VersionedRecipeDocument =
         postcard::from_bytes(&serialized_document).map_err(|err| Error::Unknown(err.into()))?;
@@ -368,18 +418,20 @@ fn get_document(conn: &Connection, id: &str) -> Result<HashedRecipeDocument, Err
 
 fn get_tags_for_recipe(
     conn: &Connection,
+    household_id: &str,
     tag_ids: Vec<domain::tag::Id>,
 ) -> Result<Vec<domain::tag::OnRecipe>, Error> {
     let tag_ids: Vec<String> = tag_ids.into_iter().map(Into::<String>::into).collect();
 
     let query = format!(
-        "SELECT id, name FROM tags WHERE id IN ({}) ORDER BY name ASC",
+        "SELECT id, name FROM tags WHERE household_id = ? AND id IN ({}) ORDER BY name ASC",
         query::param_string(tag_ids.len())
     );
 
     let mut stmt = conn.prepare_cached(&query)?;
 
-    let result = stmt.query_and_then(rusqlite::params_from_iter(tag_ids), |row| {
+    let params = std::iter::once(household_id.to_owned()).chain(tag_ids);
+    let result = stmt.query_and_then(rusqlite::params_from_iter(params), |row| {
         let id: String = row.get("id")?;
         let name: String = row.get("name")?;
         Ok(domain::tag::OnRecipe {
@@ -391,6 +443,55 @@ fn get_tags_for_recipe(
     result.collect()
 }
 
+// A recipe can only refer to the tags and images of its own household.
+fn check_references(
+    conn: &Connection,
+    household_id: &str,
+    recipe: &RecipeDocument,
+) -> Result<(), Error> {
+    let tag_ids: HashSet<String> = recipe.tag_ids.iter().map(String::from).collect();
+    let image_ids: HashSet<String> = recipe.image_ids().into_iter().map(String::from).collect();
+
+    for (table, ids) in [("tags", tag_ids), ("images", image_ids)] {
+        if ids.is_empty() {
+            continue;
+        }
+
+        let query = format!(
+            "SELECT COUNT(*) FROM {table} WHERE household_id = ? AND id IN ({})",
+            query::param_string(ids.len())
+        );
+        let expected = ids.len();
+
+        let mut stmt = conn.prepare_cached(&query)?;
+        let params = std::iter::once(household_id.to_owned()).chain(ids);
+        let count: usize = stmt.query_row(rusqlite::params_from_iter(params), |row| row.get(0))?;
+
+        if count != expected {
+            return Err(Error::MissingReference);
+        }
+    }
+
+    Ok(())
+}
+
+// Images are only ever added, so that every image referenced by any revision of the recipe stays
+// referenced.
+fn add_images_for_recipe(
+    conn: &Connection,
+    recipe_id: &str,
+    recipe: &RecipeDocument,
+) -> Result<(), Error> {
+    let mut stmt = conn.prepare_cached(
+        "INSERT OR IGNORE INTO recipe_images (recipe_id, image_id) VALUES (?1,?2)",
+    )?;
+    for image_id in recipe.image_ids() {
+        stmt.execute(params![recipe_id, String::from(image_id)])?;
+    }
+
+    Ok(())
+}
+
 fn update_tags_for_recipe(
     conn: &Connection,
     recipe_id: &str,
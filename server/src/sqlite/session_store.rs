@@ -1,12 +1,11 @@
 use std::{thread, time::Duration};
 
-use rusqlite::{Connection, ToSql, params};
+use rusqlite::{Connection, Row, params};
 use tokio::sync::mpsc;
 
-use crate::{
-    domain::{Session, SessionKey},
-    session_store::{Error, Message},
-};
+use anyhow::anyhow;
+
+use crate::session_store::{Error, Message, Record};
 
 impl From<rusqlite::Error> for Error {
     fn from(value: rusqlite::Error) -> Self {
@@ -17,7 +16,8 @@ impl From<rusqlite::Error> for Error {
     }
 }
 
-const MIGRATION: &str = "
+const MIGRATION: [&str; 5] = [
+    "
 CREATE TABLE sessions (
     key TEXT PRIMARY KEY,
     user_id TEXT NOT NULL,
@@ -29,7 +29,47 @@ CREATE TABLE refresh_locks (
     key TEXT PRIMARY KEY,
     lock_invalid_at TIMESTAMP NOT NULL
 );
-";
+",
+    "
+ALTER TABLE sessions ADD COLUMN id TEXT NOT NULL DEFAULT '';
+ALTER TABLE sessions ADD COLUMN created_at TIMESTAMP NOT NULL DEFAULT '1970-01-01 00:00:00';
+ALTER TABLE sessions ADD COLUMN last_used_at TIMESTAMP NOT NULL DEFAULT '1970-01-01 00:00:00';
+ALTER TABLE sessions ADD COLUMN user_agent TEXT;
+ALTER TABLE sessions ADD COLUMN client TEXT;
+UPDATE sessions SET
+    id = lower(hex(randomblob(16))),
+    created_at = datetime('now'),
+    last_used_at = datetime('now');
+CREATE INDEX sessions_user_id ON sessions (user_id, id);
+",
+    // Sessions stored in plain text cannot be sealed in sql, so they are dropped and everyone
+    // signs in again.
+    "
+DROP TABLE sessions;
+CREATE TABLE sessions (
+    key_hash TEXT PRIMARY KEY,
+    id TEXT NOT NULL,
+    user_id TEXT NOT NULL,
+    revalidate_at TIMESTAMP NOT NULL,
+    expires_at TIMESTAMP NOT NULL,
+    created_at TIMESTAMP NOT NULL,
+    last_used_at TIMESTAMP NOT NULL,
+    encryption_key_id TEXT NOT NULL,
+    sealed BLOB NOT NULL
+);
+CREATE INDEX sessions_user_id ON sessions (user_id, id);
+DELETE FROM refresh_locks;
+",
+    // Sessions started before there could be several providers were started with the one named
+    // "default".
+    "
+ALTER TABLE sessions ADD COLUMN provider TEXT NOT NULL DEFAULT 'default';
+",
+    // Sessions act in the user's default household until they are switched to another.
+    "
+ALTER TABLE sessions ADD COLUMN household_id TEXT;
+",
+];
 
 fn prepare_connection(conn: &Connection) -> Result<(), Error> {
     conn.pragma_update(None, "journal_mode", "WAL")?;
@@ -43,10 +83,17 @@ pub fn session_store(path: &str) -> Result<mpsc::Sender<Message>, Error> {
 
     // run migration
     let tx = conn.transaction_with_behavior(rusqlite::TransactionBehavior::Immediate)?;
-    let user_version: u32 = tx.pragma_query_value(None, "user_version", |row| row.get(0))?;
-    let desired_version: u32 = 1;
+    let user_version: usize = tx.pragma_query_value(None, "user_version", |row| row.get(0))?;
+    let desired_version = MIGRATION.len();
+    if user_version > desired_version {
+        return Err(Error::Other(anyhow!(
+            "Session database at unknown migration: {user_version}"
+        )));
+    }
     if user_version < desired_version {
-        tx.execute_batch(MIGRATION)?;
+        for migration in &MIGRATION[user_version..] {
+            tx.execute_batch(migration)?;
+        }
         tx.pragma_update(None, "user_version", desired_version)?;
     }
     tx.commit()?;
@@ -57,12 +104,6 @@ pub fn session_store(path: &str) -> Result<mpsc::Sender<Message>, Error> {
     Ok(sender)
 }
 
-impl ToSql for SessionKey {
-    fn to_sql(&self) -> rusqlite::Result<rusqlite::types::ToSqlOutput<'_>> {
-        self.0.to_sql()
-    }
-}
-
 struct ThreadWorker {}
 
 impl ThreadWorker {
@@ -75,31 +116,79 @@ impl ThreadWorker {
         thread::spawn(move || {
             while let Some(msg) = receiver.blocking_recv() {
                 match msg {
-                    Message::Get { respond_to, key } => {
-                        let _ = respond_to.send(get(&conn, &key));
-                    }
-                    Message::Delete { key, respond_to } => {
-                        let _ = respond_to.send(delete(&conn, &key));
+                    Message::Get {
+                        respond_to,
+                        key_hash,
+                    } => {
+                        let _ = respond_to.send(get(&conn, &key_hash));
                     }
-                    Message::Set {
-                        session,
+                    Message::Delete {
+                        key_hash,
                         respond_to,
                     } => {
-                        let _ = respond_to.send(set(&conn, &session));
+                        let _ = respond_to.send(delete(&conn, &key_hash));
+                    }
+                    Message::Set { record, respond_to } => {
+                        let _ = respond_to.send(set(&conn, &record));
                     }
                     Message::PruneExpired { respond_to } => {
                         let _ = respond_to.send(prune_expired_sessions(&conn));
                     }
+                    Message::Touch {
+                        key_hash,
+                        encryption_key_id,
+                        sealed,
+                        respond_to,
+                    } => {
+                        let _ =
+                            respond_to.send(touch(&conn, &key_hash, &encryption_key_id, &sealed));
+                    }
+                    Message::ListForUser {
+                        user_id,
+                        respond_to,
+                    } => {
+                        let _ = respond_to.send(list_for_user(&conn, &user_id));
+                    }
+                    Message::SetHouseholdForUser {
+                        user_id,
+                        id,
+                        household_id,
+                        respond_to,
+                    } => {
+                        let _ = respond_to.send(set_household_for_user(
+                            &conn,
+                            &user_id,
+                            &id,
+                            &household_id,
+                        ));
+                    }
+                    Message::DeleteForUser {
+                        user_id,
+                        id,
+                        respond_to,
+                    } => {
+                        let _ = respond_to.send(delete_for_user(&conn, &user_id, &id));
+                    }
+                    Message::DeleteOthersForUser {
+                        user_id,
+                        keep_id,
+                        respond_to,
+                    } => {
+                        let _ = respond_to.send(delete_others_for_user(&conn, &user_id, &keep_id));
+                    }
                     Message::LockRefresh {
-                        key,
+                        key_hash,
                         max_lock,
                         respond_to,
                     } => {
-                        let res = lock_refresh(&mut conn, &key, &max_lock);
+                        let res = lock_refresh(&mut conn, &key_hash, &max_lock);
                         let _ = respond_to.send(res);
                     }
-                    Message::UnlockRefresh { key, respond_to } => {
-                        let _ = respond_to.send(unlock_refresh(&conn, &key));
+                    Message::UnlockRefresh {
+                        key_hash,
+                        respond_to,
+                    } => {
+                        let _ = respond_to.send(unlock_refresh(&conn, &key_hash));
                     }
                     Message::PruneExpiredRefresh { respond_to } => {
                         let _ = respond_to.send(prune_expired_refresh_locks(&conn));
@@ -112,45 +201,137 @@ impl ThreadWorker {
     }
 }
 
-fn set(conn: &Connection, session: &Session) -> Result<(), Error> {
-    let q = "INSERT INTO sessions (key,user_id,refresh_token,revalidate_at,expires_at)
-            VALUES (?1,?2,?3,?4,?5) ON CONFLICT (key) DO UPDATE
-            SET user_id=?2, refresh_token=?3, revalidate_at=?4, expires_at=?5";
+fn set(conn: &Connection, record: &Record) -> Result<(), Error> {
+    let q = "INSERT INTO sessions
+            (key_hash,id,user_id,revalidate_at,expires_at,created_at,last_used_at,
+                encryption_key_id,sealed,provider,household_id)
+            VALUES (?1,?2,?3,?4,?5,?6,?7,?8,?9,?10,?11) ON CONFLICT (key_hash) DO UPDATE
+            SET id=?2, user_id=?3, revalidate_at=?4, expires_at=?5, created_at=?6,
+                last_used_at=?7, encryption_key_id=?8, sealed=?9, provider=?10, household_id=?11";
+
+    let mut stmt = conn.prepare_cached(q)?;
+    stmt.execute(params![
+        record.key_hash,
+        record.id,
+        record.user_id,
+        record.revalidate_at,
+        record.expires_at,
+        record.created_at,
+        record.last_used_at,
+        record.encryption_key_id,
+        record.sealed,
+        record.provider,
+        record.household_id,
+    ])?;
+
+    Ok(())
+}
+
+fn to_record(row: &Row) -> rusqlite::Result<Record> {
+    Ok(Record {
+        key_hash: row.get("key_hash")?,
+        id: row.get("id")?,
+        user_id: row.get("user_id")?,
+        provider: row.get("provider")?,
+        revalidate_at: row.get("revalidate_at")?,
+        expires_at: row.get("expires_at")?,
+        created_at: row.get("created_at")?,
+        last_used_at: row.get("last_used_at")?,
+        encryption_key_id: row.get("encryption_key_id")?,
+        sealed: row.get("sealed")?,
+        household_id: row.get("household_id")?,
+    })
+}
+
+fn get(conn: &Connection, key_hash: &str) -> Result<Record, Error> {
+    let q = "SELECT * FROM sessions WHERE key_hash = ?1 AND expires_at > datetime('now')";
+
+    let mut stmt = conn.prepare_cached(q)?;
+    let record = stmt.query_row(params![key_hash], to_record)?;
+
+    Ok(record)
+}
+
+fn touch(
+    conn: &Connection,
+    key_hash: &str,
+    encryption_key_id: &str,
+    sealed: &[u8],
+) -> Result<(), Error> {
+    let q = "UPDATE sessions SET last_used_at = ?2, encryption_key_id = ?3, sealed = ?4
+            WHERE key_hash = ?1";
 
     let mut stmt = conn.prepare_cached(q)?;
     stmt.execute(params![
-        session.key,
-        session.user_id,
-        session.refresh_token,
-        session.revalidate_at,
-        session.expires_at
+        key_hash,
+        chrono::Utc::now(),
+        encryption_key_id,
+        sealed
     ])?;
 
     Ok(())
 }
 
-fn get(conn: &Connection, key: &SessionKey) -> Result<Session, Error> {
-    let q = "SELECT * FROM sessions WHERE key = ?1 AND expires_at > datetime('now')";
+fn list_for_user(conn: &Connection, user_id: &str) -> Result<Vec<Record>, Error> {
+    // A refreshed session briefly keeps its previous key as well, both share the same id. The
+    // other columns come from the most recently used of them.
+    let q = "SELECT key_hash, id, user_id, revalidate_at, expires_at, created_at,
+                MAX(last_used_at) AS last_used_at, encryption_key_id, sealed, provider,
+                household_id
+            FROM sessions
+            WHERE user_id = ?1 AND expires_at > datetime('now')
+            GROUP BY id
+            ORDER BY last_used_at DESC";
 
     let mut stmt = conn.prepare_cached(q)?;
-    let session = stmt.query_row(params![key], |row| {
-        Ok(Session {
-            key: row.get("key")?,
-            user_id: row.get("user_id")?,
-            refresh_token: row.get("refresh_token")?,
-            revalidate_at: row.get("revalidate_at")?,
-            expires_at: row.get("expires_at")?,
-        })
-    })?;
+    let records = stmt
+        .query_map(params![user_id], to_record)?
+        .collect::<Result<Vec<_>, _>>()?;
 
-    Ok(session)
+    Ok(records)
+}
+
+fn set_household_for_user(
+    conn: &Connection,
+    user_id: &str,
+    id: &str,
+    household_id: &str,
+) -> Result<(), Error> {
+    let q = "UPDATE sessions SET household_id = ?3 WHERE user_id = ?1 AND id = ?2";
+
+    let mut stmt = conn.prepare_cached(q)?;
+    if stmt.execute(params![user_id, id, household_id])? == 0 {
+        return Err(Error::NotFound(anyhow!("session {id} not found")));
+    }
+
+    Ok(())
+}
+
+fn delete_for_user(conn: &Connection, user_id: &str, id: &str) -> Result<(), Error> {
+    let q = "DELETE FROM sessions WHERE user_id = ?1 AND id = ?2";
+
+    let mut stmt = conn.prepare_cached(q)?;
+    if stmt.execute(params![user_id, id])? == 0 {
+        return Err(Error::NotFound(anyhow!("session {id} not found")));
+    }
+
+    Ok(())
+}
+
+fn delete_others_for_user(conn: &Connection, user_id: &str, keep_id: &str) -> Result<(), Error> {
+    let q = "DELETE FROM sessions WHERE user_id = ?1 AND id != ?2";
+
+    let mut stmt = conn.prepare_cached(q)?;
+    stmt.execute(params![user_id, keep_id])?;
+
+    Ok(())
 }
 
-fn delete(conn: &Connection, key: &SessionKey) -> Result<(), Error> {
-    let q = "DELETE FROM sessions WHERE key = ?1";
+fn delete(conn: &Connection, key_hash: &str) -> Result<(), Error> {
+    let q = "DELETE FROM sessions WHERE key_hash = ?1";
 
     let mut stmt = conn.prepare_cached(q)?;
-    stmt.execute([key])?;
+    stmt.execute([key_hash])?;
 
     Ok(())
 }
@@ -166,7 +347,7 @@ fn prune_expired_sessions(conn: &Connection) -> Result<(), Error> {
 
 fn lock_refresh(
     conn: &mut Connection,
-    key: &SessionKey,
+    key_hash: &str,
     lock_until: &Duration,
 ) -> Result<bool, Error> {
     let mut tx = conn.transaction_with_behavior(rusqlite::TransactionBehavior::Exclusive)?;
@@ -176,7 +357,7 @@ fn lock_refresh(
     let mut stmt = tx.prepare_cached(
         "SELECT key FROM refresh_locks WHERE key = ?1 AND lock_invalid_at > datetime('now')",
     )?;
-    let lock_exists = stmt.exists(params![key])?;
+    let lock_exists = stmt.exists(params![key_hash])?;
 
     if lock_exists {
         Ok(false)
@@ -185,17 +366,17 @@ fn lock_refresh(
         let mut stmt = tx.prepare_cached(
             "INSERT INTO refresh_locks VALUES (?1, datetime('now', '+' || ?2 || ' seconds'))",
         )?;
-        stmt.execute(params![key, lock_until.as_secs()])?;
+        stmt.execute(params![key_hash, lock_until.as_secs()])?;
 
         Ok(true)
     }
 }
 
-fn unlock_refresh(conn: &Connection, key: &SessionKey) -> Result<(), Error> {
+fn unlock_refresh(conn: &Connection, key_hash: &str) -> Result<(), Error> {
     let q = "DELETE FROM refresh_locks WHERE key = ?1";
 
     let mut stmt = conn.prepare_cached(q)?;
-    stmt.execute([key])?;
+    stmt.execute([key_hash])?;
 
     Ok(())
 }
@@ -0,0 +1,94 @@
+use rusqlite::{Connection, params};
+
+use crate::{datastore::Error, domain};
+
+// Fails with NotFound unless the recipe belongs to the household.
+pub fn insert(
+    conn: &Connection,
+    id: &str,
+    household_id: &str,
+    recipe_id: &str,
+    token_hash: &str,
+    share: &domain::share::Creating,
+) -> Result<(), Error> {
+    let mut stmt = conn.prepare_cached(
+        "INSERT INTO shares (id,household_id,recipe_id,token_hash,expires_at,created_at)
+            SELECT ?1,?2,?3,?4,?5,?6
+            WHERE EXISTS (SELECT 1 FROM recipes WHERE id = ?3 AND household_id = ?2)",
+    )?;
+    let count = stmt.execute(params![
+        id,
+        household_id,
+        recipe_id,
+        token_hash,
+        share.expires_at,
+        chrono::Utc::now(),
+    ])?;
+    if count == 0 {
+        return Err(Error::NotFound);
+    }
+
+    Ok(())
+}
+
+pub fn list(
+    conn: &Connection,
+    household_id: &str,
+    recipe_id: &str,
+) -> Result<Vec<domain::share::Share>, Error> {
+    let q = "
+        SELECT id, recipe_id, expires_at, created_at
+        FROM shares
+        WHERE household_id = ?1 AND recipe_id = ?2
+        ORDER BY created_at DESC, id DESC";
+
+    let mut stmt = conn.prepare_cached(q)?;
+    let result = stmt.query_and_then(params![household_id, recipe_id], |row| {
+        let id: String = row.get("id")?;
+        let recipe_id: String = row.get("recipe_id")?;
+        Ok(domain::share::Share {
+            id: id.as_str().try_into()?,
+            recipe_id: recipe_id.as_str().try_into()?,
+            expires_at: row.get("expires_at")?,
+            created_at: row.get("created_at")?,
+        })
+    })?;
+    result.collect()
+}
+
+pub fn delete(
+    conn: &Connection,
+    household_id: &str,
+    recipe_id: &str,
+    id: &str,
+) -> Result<(), Error> {
+    let mut stmt = conn.prepare_cached(
+        "DELETE FROM shares WHERE household_id = ?1 AND recipe_id = ?2 AND id = ?3",
+    )?;
+    if stmt.execute(params![household_id, recipe_id, id])? == 0 {
+        return Err(Error::NotFound);
+    }
+
+    Ok(())
+}
+
+pub fn get_by_hash(conn: &Connection, token_hash: &str) -> Result<domain::share::Stored, Error> {
+    let q = "
+        SELECT id, household_id, recipe_id, expires_at
+        FROM shares
+        WHERE token_hash = ?1";
+
+    let mut stmt = conn.prepare_cached(q)?;
+    let mut result = stmt.query_and_then(params![token_hash], |row| {
+        let id: String = row.get("id")?;
+        let household_id: String = row.get("household_id")?;
+        let recipe_id: String = row.get("recipe_id")?;
+        Ok(domain::share::Stored {
+            id: id.as_str().try_into()?,
+            household_id: household_id.as_str().try_into()?,
+            recipe_id: recipe_id.as_str().try_into()?,
+            expires_at: row.get("expires_at")?,
+        })
+    })?;
+    result.next().ok_or(Error::NotFound)?
+}
@@ -0,0 +1,92 @@
+use rusqlite::{Connection, params};
+
+use crate::{datastore::Error, domain};
+
+pub fn insert(
+    conn: &Connection,
+    id: &str,
+    user_id: &str,
+    token_hash: &str,
+    token: &domain::token::Creating,
+) -> Result<(), Error> {
+    let mut stmt = conn.prepare_cached(
+        "INSERT INTO tokens
+            (id,user_id,name,token_hash,read_only,expires_at,created_at,household_id)
+            VALUES (?1,?2,?3,?4,?5,?6,?7,?8)",
+    )?;
+    stmt.execute(params![
+        id,
+        user_id,
+        String::from(token.name.clone()),
+        token_hash,
+        token.read_only,
+        token.expires_at,
+        chrono::Utc::now(),
+        String::from(&token.household_id),
+    ])?;
+
+    Ok(())
+}
+
+pub fn list(conn: &Connection, user_id: &str) -> Result<Vec<domain::token::Token>, Error> {
+    let q = "
+        SELECT id, name, read_only, expires_at, created_at, last_used_at, household_id
+        FROM tokens
+        WHERE user_id = ?1
+        ORDER BY created_at DESC, id DESC";
+
+    let mut stmt = conn.prepare_cached(q)?;
+    let result = stmt.query_and_then(params![user_id], |row| {
+        let id: String = row.get("id")?;
+        let name: String = row.get("name")?;
+        let household_id: String = row.get("household_id")?;
+        Ok(domain::token::Token {
+            id: id.as_str().try_into()?,
+            name: name.try_into()?,
+            read_only: row.get("read_only")?,
+            expires_at: row.get("expires_at")?,
+            created_at: row.get("created_at")?,
+            last_used_at: row.get("last_used_at")?,
+            household_id: household_id.as_str().try_into()?,
+        })
+    })?;
+    result.collect()
+}
+
+pub fn delete(conn: &Connection, user_id: &str, id: &str) -> Result<(), Error> {
+    let mut stmt = conn.prepare_cached("DELETE FROM tokens WHERE user_id = ?1 AND id = ?2")?;
+    if stmt.execute(params![user_id, id])? == 0 {
+        return Err(Error::NotFound);
+    }
+
+    Ok(())
+}
+
+pub fn get_by_hash(conn: &Connection, token_hash: &str) -> Result<domain::token::Stored, Error> {
+    let q = "
+        SELECT id, user_id, household_id, read_only, expires_at, last_used_at
+        FROM tokens
+        WHERE token_hash = ?1";
+
+    let mut stmt = conn.prepare_cached(q)?;
+    let mut result = stmt.query_and_then(params![token_hash], |row| {
+        let id: String = row.get("id")?;
+        let household_id: String = row.get("household_id")?;
+        Ok(domain::token::Stored {
+            id: id.as_str().try_into()?,
+            user_id: row.get("user_id")?,
+            household_id: household_id.as_str().try_into()?,
+            read_only: row.get("read_only")?,
+            expires_at: row.get("expires_at")?,
+            last_used_at: row.get("last_used_at")?,
+        })
+    })?;
+    result.next().ok_or(Error::NotFound)?
+}
+
+pub fn touch(conn: &Connection, id: &str) -> Result<(), Error> {
+    let mut stmt = conn.prepare_cached("UPDATE tokens SET last_used_at = ?2 WHERE id = ?1")?;
+    stmt.execute(params![id, chrono::Utc::now()])?;
+
+    Ok(())
+}
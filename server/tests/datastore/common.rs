@@ -1,10 +1,46 @@
-use mise::datastore;
+use anyhow::Result;
+use mise::{
+    datastore,
+    domain::{self, RegisteringUser},
+};
 
+pub mod audit;
+pub mod households;
 pub mod images;
 pub mod recipes;
+pub mod shares;
 pub mod tags;
+pub mod tokens;
 pub mod users;
 
+// A registered user and the household they were given.
+pub struct Member {
+    pub id: String,
+    pub household_id: domain::household::Id,
+}
+
+// Registers a user, whose id is also their subject at the issuer.
+pub async fn member(store: &datastore::Pool, id: &str) -> Result<Member> {
+    let user = store
+        .upsert_user_by_identity(RegisteringUser {
+            potential_id: id.into(),
+            issuer: "https://issuer.example.com".into(),
+            subject: id.into(),
+            name: "user".into(),
+            max_role: None,
+        })
+        .await?;
+    let household_id = store
+        .get_active_membership(user.id.clone())
+        .await?
+        .household_id;
+
+    Ok(Member {
+        id: user.id,
+        household_id,
+    })
+}
+
 pub trait CreatesDatastore {
     fn new(&self) -> impl HoldsDatastore;
 }
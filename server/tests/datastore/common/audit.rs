@@ -0,0 +1,186 @@
+use anyhow::Result;
+use mise::{
+    datastore,
+    domain::{self, audit},
+};
+
+use super::{Member, member};
+
+#[macro_export]
+macro_rules! audit_tests {
+    ($cd:expr) => {
+        mod audit {
+            use crate::a_test;
+            use crate::datastore::common::{CreatesDatastore, HoldsDatastore, audit};
+            use anyhow::Result;
+
+            a_test!($cd, audit, can_record_and_filter_entries);
+            a_test!($cd, audit, only_lists_entries_of_household_and_its_members);
+            a_test!($cd, audit, can_prune_old_entries);
+        }
+    };
+}
+
+async fn record(
+    store: &datastore::Pool,
+    user: &Member,
+    household_id: Option<&domain::household::Id>,
+    action: audit::Action,
+    entity_id: &str,
+) -> Result<()> {
+    store
+        .record_audit(
+            &audit::Id::new(),
+            audit::Recording {
+                user_id: user.id.clone(),
+                household_id: household_id.cloned(),
+                action,
+                entity_id: entity_id.into(),
+                detail: None,
+            },
+        )
+        .await?;
+
+    Ok(())
+}
+
+fn actions(entries: &[audit::Entry]) -> Vec<audit::Action> {
+    entries.iter().map(|entry| entry.action).collect()
+}
+
+pub async fn can_record_and_filter_entries(store: datastore::Pool) -> Result<()> {
+    let user = member(&store, "1").await?;
+    let household_id = Some(&user.household_id);
+
+    record(&store, &user, None, audit::Action::SignIn, &user.id).await?;
+    let after_sign_in = chrono::Utc::now();
+    record(&store, &user, household_id, audit::Action::CreateTag, "tag").await?;
+    record(
+        &store,
+        &user,
+        household_id,
+        audit::Action::CreateRecipe,
+        "recipe",
+    )
+    .await?;
+    record(
+        &store,
+        &user,
+        household_id,
+        audit::Action::UpdateRecipe,
+        "recipe",
+    )
+    .await?;
+
+    let entries = store
+        .list_audit(&user.household_id, audit::Filter::default(), 10)
+        .await?;
+    assert_eq!(
+        vec![
+            audit::Action::UpdateRecipe,
+            audit::Action::CreateRecipe,
+            audit::Action::CreateTag,
+            audit::Action::SignIn,
+        ],
+        actions(&entries)
+    );
+    assert_eq!(user.id, entries[0].user_id);
+    assert_eq!(Some(user.household_id.clone()), entries[0].household_id);
+    assert_eq!("recipe", entries[0].entity_id);
+
+    let entries = store
+        .list_audit(&user.household_id, audit::Filter::default(), 2)
+        .await?;
+    assert_eq!(
+        vec![audit::Action::UpdateRecipe, audit::Action::CreateRecipe],
+        actions(&entries)
+    );
+
+    let entries = store
+        .list_audit(
+            &user.household_id,
+            audit::Filter {
+                entity: Some(audit::Entity::Recipe),
+                entity_id: Some("recipe".into()),
+                ..audit::Filter::default()
+            },
+            10,
+        )
+        .await?;
+    assert_eq!(
+        vec![audit::Action::UpdateRecipe, audit::Action::CreateRecipe],
+        actions(&entries)
+    );
+
+    let entries = store
+        .list_audit(
+            &user.household_id,
+            audit::Filter {
+                from: Some(after_sign_in),
+                to: Some(entries[1].occurred_at),
+                ..audit::Filter::default()
+            },
+            10,
+        )
+        .await?;
+    assert_eq!(vec![audit::Action::CreateTag], actions(&entries));
+
+    let entries = store
+        .list_audit(
+            &user.household_id,
+            audit::Filter {
+                user_id: Some("someone else".into()),
+                ..audit::Filter::default()
+            },
+            10,
+        )
+        .await?;
+    assert!(entries.is_empty());
+
+    Ok(())
+}
+
+pub async fn only_lists_entries_of_household_and_its_members(store: datastore::Pool) -> Result<()> {
+    let user = member(&store, "1").await?;
+    let other = member(&store, "2").await?;
+
+    record(&store, &user, None, audit::Action::SignIn, &user.id).await?;
+    record(&store, &other, None, audit::Action::SignIn, &other.id).await?;
+    record(
+        &store,
+        &other,
+        Some(&other.household_id),
+        audit::Action::CreateTag,
+        "tag",
+    )
+    .await?;
+
+    let entries = store
+        .list_audit(&user.household_id, audit::Filter::default(), 10)
+        .await?;
+    assert_eq!(1, entries.len());
+    assert_eq!(user.id, entries[0].user_id);
+
+    Ok(())
+}
+
+pub async fn can_prune_old_entries(store: datastore::Pool) -> Result<()> {
+    let user = member(&store, "1").await?;
+
+    record(&store, &user, None, audit::Action::SignIn, &user.id).await?;
+
+    let pruned = store
+        .prune_audit(chrono::Utc::now() - chrono::TimeDelta::days(1))
+        .await?;
+    assert_eq!(0, pruned);
+
+    let pruned = store.prune_audit(chrono::Utc::now()).await?;
+    assert_eq!(1, pruned);
+
+    let entries = store
+        .list_audit(&user.household_id, audit::Filter::default(), 10)
+        .await?;
+    assert!(entries.is_empty());
+
+    Ok(())
+}
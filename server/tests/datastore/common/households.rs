@@ -0,0 +1,325 @@
+use anyhow::Result;
+use mise::{
+    datastore,
+    domain::{self, RegisteringUser, User, household::Role},
+};
+
+#[macro_export]
+macro_rules! households_tests {
+    ($cd:expr) => {
+        mod households {
+            use crate::a_test;
+            use crate::datastore::common::{CreatesDatastore, HoldsDatastore, households};
+            use anyhow::Result;
+
+            a_test!($cd, households, new_user_is_active_in_own_household);
+            a_test!($cd, households, can_create_and_activate_household);
+            a_test!(
+                $cd,
+                households,
+                cannot_activate_household_without_membership
+            );
+            a_test!($cd, households, can_add_and_remove_member);
+            a_test!($cd, households, cannot_add_unknown_user);
+            a_test!($cd, households, can_change_member_role);
+            a_test!($cd, households, cannot_demote_last_admin);
+            a_test!($cd, households, cannot_remove_last_admin);
+            a_test!($cd, households, cannot_remove_last_household);
+            a_test!($cd, households, max_role_limits_role_in_every_household);
+        }
+    };
+}
+
+async fn register(store: &datastore::Pool, id: &str, name: &str) -> Result<User> {
+    Ok(store
+        .upsert_user_by_identity(RegisteringUser {
+            potential_id: id.into(),
+            issuer: "https://issuer.example.com".into(),
+            subject: id.into(),
+            name: name.into(),
+            max_role: None,
+        })
+        .await?)
+}
+
+pub async fn new_user_is_active_in_own_household(store: datastore::Pool) -> Result<()> {
+    let user = register(&store, "1234", "Barley").await?;
+
+    let active = store
+        .get_active_membership(user.id.clone())
+        .await?
+        .household_id;
+    let households = store.list_households(user.id.clone()).await?;
+
+    assert_eq!(1, households.len());
+    assert_eq!(active, households[0].id);
+    assert_eq!("Barley", String::from(households[0].name.clone()));
+    assert_eq!(Role::Admin, households[0].role);
+    assert!(households[0].active);
+
+    // signing in again keeps the same household
+    let again = register(&store, "1234", "Barley").await?;
+    assert_eq!(
+        active,
+        store.get_active_membership(again.id).await?.household_id
+    );
+
+    Ok(())
+}
+
+pub async fn can_create_and_activate_household(store: datastore::Pool) -> Result<()> {
+    let user = register(&store, "1234", "Barley").await?;
+    let personal = store
+        .get_active_membership(user.id.clone())
+        .await?
+        .household_id;
+
+    let id = domain::household::Id::new();
+    store
+        .create_household(&id, user.id.clone(), "Family".into())
+        .await?;
+
+    // creating a household does not switch to it
+    assert_eq!(
+        personal,
+        store
+            .get_active_membership(user.id.clone())
+            .await?
+            .household_id
+    );
+
+    store.set_active_household(user.id.clone(), &id).await?;
+    assert_eq!(
+        id,
+        store
+            .get_active_membership(user.id.clone())
+            .await?
+            .household_id
+    );
+
+    let households = store.list_households(user.id).await?;
+    assert_eq!(2, households.len());
+    assert_eq!(personal, households[0].id);
+    assert!(!households[0].active);
+    assert_eq!(id, households[1].id);
+    assert!(households[1].active);
+
+    Ok(())
+}
+
+pub async fn cannot_activate_household_without_membership(store: datastore::Pool) -> Result<()> {
+    let owner = register(&store, "1234", "Barley").await?;
+    let other = register(&store, "5678", "Thomas").await?;
+    let household_id = store.get_active_membership(owner.id).await?.household_id;
+
+    let result = store
+        .set_active_household(other.id, &household_id)
+        .await
+        .unwrap_err();
+
+    assert!(
+        matches!(result, datastore::Error::NotFound),
+        "wrong enum: {}",
+        result
+    );
+
+    Ok(())
+}
+
+pub async fn can_add_and_remove_member(store: datastore::Pool) -> Result<()> {
+    let owner = register(&store, "1234", "Barley").await?;
+    let member = register(&store, "5678", "Thomas").await?;
+    let household_id = store
+        .get_active_membership(owner.id.clone())
+        .await?
+        .household_id;
+    let personal = store
+        .get_active_membership(member.id.clone())
+        .await?
+        .household_id;
+
+    store
+        .add_household_member(&household_id, member.id.clone(), Role::Viewer)
+        .await?;
+    store
+        .set_active_household(member.id.clone(), &household_id)
+        .await?;
+    assert_eq!(2, store.list_households(member.id.clone()).await?.len());
+    assert_eq!(
+        Role::Viewer,
+        store.get_active_membership(member.id.clone()).await?.role
+    );
+
+    store
+        .remove_household_member(&household_id, member.id.clone())
+        .await?;
+
+    // the member falls back to the household they have left
+    assert_eq!(
+        personal,
+        store
+            .get_active_membership(member.id.clone())
+            .await?
+            .household_id
+    );
+    assert_eq!(1, store.list_households(member.id).await?.len());
+
+    Ok(())
+}
+
+pub async fn cannot_add_unknown_user(store: datastore::Pool) -> Result<()> {
+    let owner = register(&store, "1234", "Barley").await?;
+    let household_id = store.get_active_membership(owner.id).await?.household_id;
+
+    let result = store
+        .add_household_member(&household_id, "unknown".into(), Role::Viewer)
+        .await
+        .unwrap_err();
+
+    assert!(
+        matches!(result, datastore::Error::NotFound),
+        "wrong enum: {}",
+        result
+    );
+
+    Ok(())
+}
+
+pub async fn can_change_member_role(store: datastore::Pool) -> Result<()> {
+    let owner = register(&store, "1234", "Barley").await?;
+    let member = register(&store, "5678", "Thomas").await?;
+    let household_id = store.get_active_membership(owner.id).await?.household_id;
+
+    store
+        .add_household_member(&household_id, member.id.clone(), Role::Viewer)
+        .await?;
+    store
+        .set_household_member_role(&household_id, member.id.clone(), Role::Editor)
+        .await?;
+
+    assert_eq!(
+        Role::Editor,
+        store.get_household_role(&household_id, member.id).await?
+    );
+
+    Ok(())
+}
+
+pub async fn cannot_demote_last_admin(store: datastore::Pool) -> Result<()> {
+    let owner = register(&store, "1234", "Barley").await?;
+    let household_id = store
+        .get_active_membership(owner.id.clone())
+        .await?
+        .household_id;
+
+    let result = store
+        .set_household_member_role(&household_id, owner.id.clone(), Role::Editor)
+        .await
+        .unwrap_err();
+
+    assert!(
+        matches!(result, datastore::Error::Conflict),
+        "wrong enum: {}",
+        result
+    );
+    assert_eq!(
+        Role::Admin,
+        store.get_household_role(&household_id, owner.id).await?
+    );
+
+    Ok(())
+}
+
+pub async fn cannot_remove_last_admin(store: datastore::Pool) -> Result<()> {
+    let owner = register(&store, "1234", "Barley").await?;
+    let member = register(&store, "5678", "Thomas").await?;
+    let household_id = store
+        .get_active_membership(owner.id.clone())
+        .await?
+        .household_id;
+    let other_household_id = domain::household::Id::new();
+    store
+        .create_household(&other_household_id, owner.id.clone(), "Family".into())
+        .await?;
+
+    store
+        .add_household_member(&household_id, member.id, Role::Editor)
+        .await?;
+
+    let result = store
+        .remove_household_member(&household_id, owner.id)
+        .await
+        .unwrap_err();
+
+    assert!(
+        matches!(result, datastore::Error::Conflict),
+        "wrong enum: {}",
+        result
+    );
+
+    Ok(())
+}
+
+pub async fn cannot_remove_last_household(store: datastore::Pool) -> Result<()> {
+    let user = register(&store, "1234", "Barley").await?;
+    let household_id = store
+        .get_active_membership(user.id.clone())
+        .await?
+        .household_id;
+
+    let result = store
+        .remove_household_member(&household_id, user.id.clone())
+        .await
+        .unwrap_err();
+
+    assert!(
+        matches!(result, datastore::Error::Conflict),
+        "wrong enum: {}",
+        result
+    );
+    assert_eq!(
+        household_id,
+        store.get_active_membership(user.id).await?.household_id
+    );
+
+    Ok(())
+}
+
+pub async fn max_role_limits_role_in_every_household(store: datastore::Pool) -> Result<()> {
+    let registering = RegisteringUser {
+        potential_id: "1234".into(),
+        issuer: "https://issuer.example.com".into(),
+        subject: "1234".into(),
+        name: "Barley".into(),
+        max_role: Some(Role::Viewer),
+    };
+    let user = store.upsert_user_by_identity(registering.clone()).await?;
+
+    // even in the household they administer
+    let membership = store.get_active_membership(user.id.clone()).await?;
+    assert_eq!(Role::Viewer, membership.role);
+    assert_eq!(
+        Role::Viewer,
+        store
+            .get_household_role(&membership.household_id, user.id.clone())
+            .await?
+    );
+    assert_eq!(
+        Role::Viewer,
+        store.list_households(user.id.clone()).await?[0].role
+    );
+
+    // lifted when the user signs in without a limit
+    store
+        .upsert_user_by_identity(RegisteringUser {
+            max_role: None,
+            ..registering
+        })
+        .await?;
+    assert_eq!(
+        Role::Admin,
+        store.get_active_membership(user.id).await?.role
+    );
+
+    Ok(())
+}
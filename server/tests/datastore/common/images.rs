@@ -1,5 +1,10 @@
 use anyhow::Result;
-use mise::{datastore, domain};
+use mise::{
+    datastore::{self, RecipeDocument},
+    domain,
+};
+
+use super::member;
 
 #[macro_export]
 macro_rules! images_tests {
@@ -13,21 +18,72 @@ macro_rules! images_tests {
             a_test!($cd, images, cannot_create_duplicate);
             a_test!($cd, images, can_get_existing_image);
             a_test!($cd, images, returns_failure_if_image_does_not_exist);
+            a_test!($cd, images, can_get_image_by_content_hash);
+            a_test!($cd, images, returns_failure_if_content_hash_does_not_exist);
+            a_test!($cd, images, create_reuses_image_with_same_content);
+            a_test!($cd, images, can_list_images_over_multiple_pages);
+            a_test!($cd, images, can_count_images);
+            a_test!($cd, images, framing_is_empty_by_default);
+            a_test!($cd, images, can_set_and_replace_framing);
+            a_test!($cd, images, framing_is_kept_per_recipe);
+            a_test!($cd, images, cannot_frame_image_recipe_does_not_use);
+            a_test!($cd, images, images_are_isolated_by_household);
         }
     };
 }
+
+async fn household(store: &datastore::Pool) -> Result<domain::household::Id> {
+    Ok(member(store, "user-1").await?.household_id)
+}
+
+// Creates a recipe of the user, with the image as its cover.
+async fn recipe_using(
+    store: &datastore::Pool,
+    user_id: &str,
+    image_id: &domain::image::Id,
+) -> Result<domain::recipe::Id> {
+    let user = member(store, user_id).await?;
+
+    let id = domain::recipe::Id::new();
+    store
+        .create_recipe(
+            &user.household_id,
+            id.clone().into(),
+            user.id,
+            RecipeDocument {
+                title: "Chicken Casserole".into(),
+                image_id: Some(image_id.clone()),
+                ingredients: vec![],
+                instructions: vec![],
+                notes: None,
+                tag_ids: vec![],
+                images: vec![],
+                step_images: vec![],
+            },
+        )
+        .await?;
+
+    Ok(id)
+}
+
 pub async fn can_create(store: datastore::Pool) -> Result<()> {
+    let household_id = household(&store).await?;
+
     let id = domain::image::Id::new();
-    store.create_image(&id).await?;
+    let created_id = store.create_image(&household_id, &id, "hash-1").await?;
+
+    assert_eq!(id, created_id);
 
     Ok(())
 }
 
 pub async fn cannot_create_duplicate(store: datastore::Pool) -> Result<()> {
+    let household_id = household(&store).await?;
+
     let id = domain::image::Id::new();
-    store.create_image(&id).await?;
+    store.create_image(&household_id, &id, "hash-1").await?;
 
-    let result = store.create_image(&id).await;
+    let result = store.create_image(&household_id, &id, "hash-2").await;
     if let Ok(_) = result {
         panic!("result is Ok, expected error.");
     }
@@ -36,21 +92,277 @@ pub async fn cannot_create_duplicate(store: datastore::Pool) -> Result<()> {
 }
 
 pub async fn can_get_existing_image(store: datastore::Pool) -> Result<()> {
+    let household_id = household(&store).await?;
+
     let id = domain::image::Id::new();
-    store.create_image(&id).await?;
+    store.create_image(&household_id, &id, "hash-1").await?;
 
-    let _ = store.get_image(&id).await?;
+    let _ = store.get_image(&household_id, &id).await?;
 
     Ok(())
 }
 
 pub async fn returns_failure_if_image_does_not_exist(store: datastore::Pool) -> Result<()> {
+    let household_id = household(&store).await?;
+
     let id = domain::image::Id::new();
 
-    let result = store.get_image(&id).await;
+    let result = store.get_image(&household_id, &id).await;
     if let Ok(_) = result {
         panic!("result is Ok, expected error.");
     }
 
     Ok(())
 }
+
+pub async fn can_get_image_by_content_hash(store: datastore::Pool) -> Result<()> {
+    let household_id = household(&store).await?;
+
+    let id = domain::image::Id::new();
+    store.create_image(&household_id, &id, "hash-1").await?;
+    store
+        .create_image(&household_id, &domain::image::Id::new(), "hash-2")
+        .await?;
+
+    let result = store
+        .get_image_by_content_hash(&household_id, "hash-1")
+        .await?;
+
+    assert_eq!(id, result);
+
+    Ok(())
+}
+
+pub async fn returns_failure_if_content_hash_does_not_exist(store: datastore::Pool) -> Result<()> {
+    let household_id = household(&store).await?;
+
+    let result = store
+        .get_image_by_content_hash(&household_id, "hash-1")
+        .await
+        .unwrap_err();
+
+    assert!(
+        matches!(result, datastore::Error::NotFound),
+        "wrong enum: {}",
+        result
+    );
+
+    Ok(())
+}
+
+pub async fn create_reuses_image_with_same_content(store: datastore::Pool) -> Result<()> {
+    let household_id = household(&store).await?;
+
+    let id = domain::image::Id::new();
+    store.create_image(&household_id, &id, "hash-1").await?;
+
+    let second_id = domain::image::Id::new();
+    let result = store
+        .create_image(&household_id, &second_id, "hash-1")
+        .await?;
+
+    assert_eq!(id, result);
+
+    // the second id was never persisted
+    let result = store.get_image(&household_id, &second_id).await;
+    if let Ok(_) = result {
+        panic!("result is Ok, expected error.");
+    }
+
+    Ok(())
+}
+
+pub async fn can_list_images_over_multiple_pages(store: datastore::Pool) -> Result<()> {
+    let household_id = household(&store).await?;
+
+    let mut ids: Vec<String> = vec![];
+    for i in 0..3 {
+        let id = domain::image::Id::new();
+        store
+            .create_image(&household_id, &id, &format!("hash-{i}"))
+            .await?;
+        ids.push(id.into());
+    }
+    ids.sort();
+
+    let page = store.list_images(None).await?;
+    let items: Vec<String> = page
+        .items
+        .into_iter()
+        .map(|image| image.id.into())
+        .collect();
+    assert_eq!(ids[0..2], items);
+    assert!(page.next.is_some());
+
+    let page = store.list_images(page.next).await?;
+    let items: Vec<String> = page
+        .items
+        .into_iter()
+        .map(|image| image.id.into())
+        .collect();
+    assert_eq!(ids[2..3], items);
+    assert!(page.next.is_none());
+
+    Ok(())
+}
+
+pub async fn can_count_images(store: datastore::Pool) -> Result<()> {
+    let household_id = household(&store).await?;
+
+    assert_eq!(0, store.count_images().await?);
+
+    store
+        .create_image(&household_id, &domain::image::Id::new(), "hash-1")
+        .await?;
+    store
+        .create_image(&household_id, &domain::image::Id::new(), "hash-2")
+        .await?;
+
+    assert_eq!(2, store.count_images().await?);
+
+    Ok(())
+}
+
+pub async fn framing_is_empty_by_default(store: datastore::Pool) -> Result<()> {
+    let household_id = household(&store).await?;
+
+    let id = domain::image::Id::new();
+    store.create_image(&household_id, &id, "hash-1").await?;
+    let recipe_id = recipe_using(&store, "user-1", &id).await?;
+
+    let framing = store
+        .get_image_framing(&household_id, &recipe_id, &id)
+        .await?;
+
+    assert_eq!(domain::image::Framing::default(), framing);
+
+    Ok(())
+}
+
+pub async fn can_set_and_replace_framing(store: datastore::Pool) -> Result<()> {
+    let household_id = household(&store).await?;
+
+    let id = domain::image::Id::new();
+    store.create_image(&household_id, &id, "hash-1").await?;
+    let recipe_id = recipe_using(&store, "user-1", &id).await?;
+
+    let framing = domain::image::Framing::new(
+        Some(domain::image::Crop::new(0.1, 0.2, 0.5, 0.25)?),
+        Some(domain::image::FocalPoint::new(0.3, 0.4)?),
+    )?;
+    store
+        .set_image_framing(&household_id, &recipe_id, &id, framing.clone())
+        .await?;
+    assert_eq!(
+        framing,
+        store
+            .get_image_framing(&household_id, &recipe_id, &id)
+            .await?
+    );
+
+    let framing =
+        domain::image::Framing::new(None, Some(domain::image::FocalPoint::new(0.9, 0.1)?))?;
+    store
+        .set_image_framing(&household_id, &recipe_id, &id, framing.clone())
+        .await?;
+    assert_eq!(
+        framing,
+        store
+            .get_image_framing(&household_id, &recipe_id, &id)
+            .await?
+    );
+    assert_eq!(vec![framing], store.list_image_framings(&id).await?);
+
+    Ok(())
+}
+
+pub async fn framing_is_kept_per_recipe(store: datastore::Pool) -> Result<()> {
+    let household_id = household(&store).await?;
+
+    let id = domain::image::Id::new();
+    store.create_image(&household_id, &id, "hash-1").await?;
+    let recipe_id = recipe_using(&store, "user-1", &id).await?;
+    let other_recipe_id = recipe_using(&store, "user-1", &id).await?;
+
+    let framing =
+        domain::image::Framing::new(None, Some(domain::image::FocalPoint::new(0.9, 0.1)?))?;
+    store
+        .set_image_framing(&household_id, &recipe_id, &id, framing.clone())
+        .await?;
+
+    assert_eq!(
+        domain::image::Framing::default(),
+        store
+            .get_image_framing(&household_id, &other_recipe_id, &id)
+            .await?
+    );
+    assert_eq!(vec![framing], store.list_image_framings(&id).await?);
+
+    Ok(())
+}
+
+pub async fn cannot_frame_image_recipe_does_not_use(store: datastore::Pool) -> Result<()> {
+    let household_id = household(&store).await?;
+
+    let id = domain::image::Id::new();
+    store.create_image(&household_id, &id, "hash-1").await?;
+    let other_id = domain::image::Id::new();
+    store
+        .create_image(&household_id, &other_id, "hash-2")
+        .await?;
+    let recipe_id = recipe_using(&store, "user-1", &id).await?;
+
+    let result = store
+        .set_image_framing(
+            &household_id,
+            &recipe_id,
+            &other_id,
+            domain::image::Framing::default(),
+        )
+        .await
+        .unwrap_err();
+    assert!(
+        matches!(result, datastore::Error::NotFound),
+        "wrong enum: {}",
+        result
+    );
+
+    Ok(())
+}
+
+pub async fn images_are_isolated_by_household(store: datastore::Pool) -> Result<()> {
+    let household_id = household(&store).await?;
+    let other_household_id = member(&store, "user-2").await?.household_id;
+
+    let id = domain::image::Id::new();
+    store.create_image(&household_id, &id, "hash-1").await?;
+
+    // the same content is a separate image in another household
+    let other_id = domain::image::Id::new();
+    let result = store
+        .create_image(&other_household_id, &other_id, "hash-1")
+        .await?;
+    assert_eq!(other_id, result);
+
+    let result = store.get_image(&other_household_id, &id).await.unwrap_err();
+    assert!(
+        matches!(result, datastore::Error::NotFound),
+        "wrong enum: {}",
+        result
+    );
+
+    let recipe_id = recipe_using(&store, "user-1", &id).await?;
+    let framing =
+        domain::image::Framing::new(None, Some(domain::image::FocalPoint::new(0.9, 0.1)?))?;
+    let result = store
+        .set_image_framing(&other_household_id, &recipe_id, &id, framing)
+        .await
+        .unwrap_err();
+    assert!(
+        matches!(result, datastore::Error::NotFound),
+        "wrong enum: {}",
+        result
+    );
+
+    Ok(())
+}
@@ -1,9 +1,11 @@
 use anyhow::Result;
 use mise::{
     datastore::{self, RecipeDocument},
-    domain::{self, RegisteringUser, User},
+    domain,
 };
 
+use super::{Member, member};
+
 #[macro_export]
 macro_rules! recipes_tests {
     ($cd:expr) => {
@@ -15,6 +17,10 @@ macro_rules! recipes_tests {
             a_test!($cd, recipes, can_create_and_get);
             a_test!($cd, recipes, create_creates_initial_revision);
             a_test!($cd, recipes, cannot_create_duplicate);
+            a_test!($cd, recipes, can_create_with_gallery_and_step_images);
+            a_test!($cd, recipes, cannot_create_with_unknown_image);
+            a_test!($cd, recipes, cannot_create_with_tag_of_other_household);
+            a_test!($cd, recipes, recipes_are_isolated_by_household);
 
             a_test!($cd, recipes, cannot_get_non_existent_recipe);
 
@@ -35,31 +41,31 @@ macro_rules! recipes_tests {
             a_test!($cd, recipes, cannot_get_non_existent_revision);
             a_test!($cd, recipes, cannot_get_revision_for_non_existent_recipe);
             a_test!($cd, recipes, stores_revision_history);
+            a_test!($cd, recipes, stores_images_in_revision_history);
 
             a_test!($cd, recipes, cannot_get_revisions_for_non_existent_recipe);
         }
     };
 }
 
-async fn user(store: &datastore::Pool) -> Result<User> {
-    Ok(store
-        .upsert_user_by_oauth_id(RegisteringUser {
-            potential_id: "user-id".into(),
-            oauth_id: "custom|user-1".into(),
-            name: "user".into(),
-        })
-        .await?)
+async fn user(store: &datastore::Pool) -> Result<Member> {
+    member(store, "user-id").await
 }
 
-async fn tag(store: &datastore::Pool, user_id: &str, name: &str) -> Result<domain::tag::Id> {
+async fn tag(store: &datastore::Pool, user: &Member, name: &str) -> Result<domain::tag::Id> {
     Ok(store
-        .create_tag(user_id.to_owned(), name.to_owned())
+        .create_tag(&user.household_id, user.id.clone(), name.to_owned())
         .await?)
 }
 
-async fn image(store: &datastore::Pool) -> Result<domain::image::Id> {
+async fn image(
+    store: &datastore::Pool,
+    household_id: &domain::household::Id,
+) -> Result<domain::image::Id> {
     let id = domain::image::Id::new();
-    store.create_image(&id).await?;
+    store
+        .create_image(household_id, &id, &String::from(&id))
+        .await?;
     Ok(id)
 }
 
@@ -68,6 +74,8 @@ struct ComparableRecipe {
     id: domain::recipe::Id,
     title: String,
     image_id: Option<domain::image::Id>,
+    images: Vec<(domain::image::Id, Option<String>)>,
+    step_images: Vec<(usize, usize, domain::image::Id)>,
     ingredients: Vec<(Option<String>, Vec<String>)>,
     instructions: Vec<(Option<String>, Vec<String>)>,
     notes: Option<String>,
@@ -80,6 +88,21 @@ impl From<domain::Recipe> for ComparableRecipe {
             id: value.id,
             title: value.title.into(),
             image_id: value.image_id,
+            images: value
+                .images
+                .into_iter()
+                .map(|i| (i.id, i.caption.map(Into::into)))
+                .collect(),
+            step_images: value
+                .instructions
+                .iter()
+                .enumerate()
+                .flat_map(|(block, i)| {
+                    i.step_images
+                        .iter()
+                        .map(move |image| (block, image.step, image.image_id.clone()))
+                })
+                .collect(),
             ingredients: value
                 .ingredients
                 .into_iter()
@@ -153,10 +176,10 @@ impl From<domain::ListedRecipe> for ComparableListedRecipe {
 
 pub async fn can_create_and_get(store: datastore::Pool) -> Result<()> {
     let user = user(&store).await?;
-    let tag_main_id = tag(&store, &user.id, "Main Dish").await?;
-    let tag_yummy_id = tag(&store, &user.id, "Yummy").await?;
+    let tag_main_id = tag(&store, &user, "Main Dish").await?;
+    let tag_yummy_id = tag(&store, &user, "Yummy").await?;
 
-    let image_id = image(&store).await?;
+    let image_id = image(&store, &user.household_id).await?;
 
     let recipe = RecipeDocument {
         title: "Chicken Casserole".into(),
@@ -171,14 +194,19 @@ pub async fn can_create_and_get(store: datastore::Pool) -> Result<()> {
         }],
         notes: Some("Don't burn it!".into()),
         tag_ids: vec![tag_main_id, tag_yummy_id],
+        images: vec![],
+        step_images: vec![],
     };
 
     let id = domain::recipe::Id::new();
     store
-        .create_recipe(id.clone().into(), user.id, recipe)
+        .create_recipe(&user.household_id, id.clone().into(), user.id, recipe)
         .await?;
 
-    let result: ComparableRecipe = store.get_recipe(id.clone().into()).await?.into();
+    let result: ComparableRecipe = store
+        .get_recipe(&user.household_id, id.clone().into())
+        .await?
+        .into();
 
     assert_eq!(id, result.id);
     assert_eq!("Chicken Casserole", result.title);
@@ -193,7 +221,7 @@ pub async fn can_create_and_get(store: datastore::Pool) -> Result<()> {
 
 pub async fn create_creates_initial_revision(store: datastore::Pool) -> Result<()> {
     let user = user(&store).await?;
-    let tag_id = tag(&store, &user.id, "Main Dish").await?;
+    let tag_id = tag(&store, &user, "Main Dish").await?;
 
     let recipe = RecipeDocument {
         title: "Chicken Casserole".into(),
@@ -208,21 +236,25 @@ pub async fn create_creates_initial_revision(store: datastore::Pool) -> Result<(
         }],
         notes: None,
         tag_ids: vec![tag_id],
+        images: vec![],
+        step_images: vec![],
     };
 
     let id = domain::recipe::Id::new();
     store
-        .create_recipe(id.clone().into(), user.id, recipe)
+        .create_recipe(&user.household_id, id.clone().into(), user.id, recipe)
         .await?;
 
     // get revisions - there should be one revision: revision 0
-    let revisions = store.get_recipe_revisions(id.clone().into()).await?;
+    let revisions = store
+        .get_recipe_revisions(&user.household_id, id.clone().into())
+        .await?;
     assert_eq!(1, revisions.len());
     assert_eq!(0, revisions[0].revision);
 
     // try to get revision 0
     let result: ComparableRecipe = store
-        .get_recipe_revision(id.clone().into(), 0)
+        .get_recipe_revision(&user.household_id, id.clone().into(), 0)
         .await?
         .into();
 
@@ -237,6 +269,186 @@ pub async fn create_creates_initial_revision(store: datastore::Pool) -> Result<(
     Ok(())
 }
 
+pub async fn can_create_with_gallery_and_step_images(store: datastore::Pool) -> Result<()> {
+    let user = user(&store).await?;
+    let image_id_1 = image(&store, &user.household_id).await?;
+    let image_id_2 = image(&store, &user.household_id).await?;
+    let image_id_3 = image(&store, &user.household_id).await?;
+
+    let recipe = RecipeDocument {
+        title: "Bread".into(),
+        image_id: None,
+        ingredients: vec![],
+        instructions: vec![
+            domain::recipe::StringifiedBlock {
+                title: None,
+                items: vec!["Mix".to_owned()],
+            },
+            domain::recipe::StringifiedBlock {
+                title: Some("Shaping".to_owned()),
+                items: vec!["Fold".to_owned(), "Shape".to_owned()],
+            },
+        ],
+        notes: None,
+        tag_ids: vec![],
+        images: vec![
+            datastore::DocumentImage {
+                image_id: image_id_2.clone(),
+                caption: Some("The crumb".into()),
+            },
+            datastore::DocumentImage {
+                image_id: image_id_1.clone(),
+                caption: None,
+            },
+        ],
+        step_images: vec![datastore::DocumentStepImage {
+            block: 1,
+            step: 1,
+            image_id: image_id_3.clone(),
+        }],
+    };
+
+    let id = domain::recipe::Id::new();
+    store
+        .create_recipe(&user.household_id, id.clone().into(), user.id, recipe)
+        .await?;
+
+    let result: ComparableRecipe = store
+        .get_recipe(&user.household_id, id.clone().into())
+        .await?
+        .into();
+
+    assert_eq!(
+        vec![
+            (image_id_2, Some("The crumb".to_owned())),
+            (image_id_1, None)
+        ],
+        result.images
+    );
+    assert_eq!(vec![(1, 1, image_id_3)], result.step_images);
+
+    Ok(())
+}
+
+pub async fn cannot_create_with_unknown_image(store: datastore::Pool) -> Result<()> {
+    let user = user(&store).await?;
+
+    let recipe = RecipeDocument {
+        title: "Bread".into(),
+        image_id: None,
+        ingredients: vec![],
+        instructions: vec![domain::recipe::StringifiedBlock {
+            title: None,
+            items: vec!["Mix".to_owned()],
+        }],
+        notes: None,
+        tag_ids: vec![],
+        images: vec![],
+        step_images: vec![datastore::DocumentStepImage {
+            block: 0,
+            step: 0,
+            image_id: domain::image::Id::new(),
+        }],
+    };
+
+    let result = store
+        .create_recipe(
+            &user.household_id,
+            domain::recipe::Id::new().into(),
+            user.id,
+            recipe,
+        )
+        .await;
+    if let Ok(_) = result {
+        panic!("result is Ok, expected error.");
+    }
+
+    Ok(())
+}
+
+pub async fn cannot_create_with_tag_of_other_household(store: datastore::Pool) -> Result<()> {
+    let user = user(&store).await?;
+    let other = member(&store, "other-user-id").await?;
+    let tag_id = tag(&store, &other, "Main Dish").await?;
+
+    let result = store
+        .create_recipe(
+            &user.household_id,
+            domain::recipe::Id::new().into(),
+            user.id,
+            RecipeDocument {
+                title: "Bread".into(),
+                image_id: None,
+                ingredients: vec![],
+                instructions: vec![],
+                notes: None,
+                tag_ids: vec![tag_id],
+                images: vec![],
+                step_images: vec![],
+            },
+        )
+        .await
+        .unwrap_err();
+
+    assert!(
+        matches!(result, datastore::Error::MissingReference),
+        "wrong enum: {}",
+        result
+    );
+
+    Ok(())
+}
+
+pub async fn recipes_are_isolated_by_household(store: datastore::Pool) -> Result<()> {
+    let user = user(&store).await?;
+    let other = member(&store, "other-user-id").await?;
+
+    let id = domain::recipe::Id::new();
+    store
+        .create_recipe(
+            &user.household_id,
+            id.clone().into(),
+            user.id,
+            RecipeDocument {
+                title: "Bread".into(),
+                image_id: None,
+                ingredients: vec![],
+                instructions: vec![],
+                notes: None,
+                tag_ids: vec![],
+                images: vec![],
+                step_images: vec![],
+            },
+        )
+        .await?;
+
+    let result = store
+        .get_recipe(&other.household_id, id.clone().into())
+        .await
+        .unwrap_err();
+    assert!(
+        matches!(result, datastore::Error::NotFound),
+        "wrong enum: {}",
+        result
+    );
+
+    let page = store
+        .list_recipes(
+            &other.household_id,
+            domain::filter::Recipe { tag_ids: vec![] },
+            None,
+        )
+        .await?;
+    assert!(page.items.is_empty());
+
+    let result = store
+        .get_recipe_revisions(&other.household_id, id.into())
+        .await?;
+    assert_eq!(0, result.len());
+
+    Ok(())
+}
+
 pub async fn cannot_create_duplicate(store: datastore::Pool) -> Result<()> {
     let user = user(&store).await?;
     let recipe = RecipeDocument {
@@ -252,15 +464,22 @@ pub async fn cannot_create_duplicate(store: datastore::Pool) -> Result<()> {
         }],
         notes: None,
         tag_ids: vec![],
+        images: vec![],
+        step_images: vec![],
     };
 
     let id = domain::recipe::Id::new();
     store
-        .create_recipe(id.clone().into(), user.id.clone(), recipe.clone())
+        .create_recipe(
+            &user.household_id,
+            id.clone().into(),
+            user.id.clone(),
+            recipe.clone(),
+        )
         .await?;
 
     let result = store
-        .create_recipe(id.clone().into(), user.id, recipe)
+        .create_recipe(&user.household_id, id.clone().into(), user.id, recipe)
         .await;
 
     assert_eq!(true, result.is_err());
@@ -271,7 +490,11 @@ pub async fn cannot_create_duplicate(store: datastore::Pool) -> Result<()> {
 // get_recipe
 
 pub async fn cannot_get_non_existent_recipe(store: datastore::Pool) -> Result<()> {
-    let r = store.get_recipe("a-random-id".into()).await;
+    let user = user(&store).await?;
+
+    let r = store
+        .get_recipe(&user.household_id, "a-random-id".into())
+        .await;
 
     if let Err(datastore::Error::NotFound) = r {
     } else {
@@ -285,12 +508,12 @@ pub async fn cannot_get_non_existent_recipe(store: datastore::Pool) -> Result<()
 
 pub async fn can_update_recipe(store: datastore::Pool) -> Result<()> {
     let user = user(&store).await?;
-    let tag_main_id = tag(&store, &user.id, "Main Dish").await?;
-    let tag_soup_id = tag(&store, &user.id, "Soup").await?;
+    let tag_main_id = tag(&store, &user, "Main Dish").await?;
+    let tag_soup_id = tag(&store, &user, "Soup").await?;
 
     let recipe = RecipeDocument {
         title: "Chicken Casserole".into(),
-        image_id: Some(image(&store).await?),
+        image_id: Some(image(&store, &user.household_id).await?),
         ingredients: vec![domain::recipe::StringifiedBlock {
             title: None,
             items: vec!["Chicken".to_owned()],
@@ -301,20 +524,31 @@ pub async fn can_update_recipe(store: datastore::Pool) -> Result<()> {
         }],
         notes: Some("Don't burn it!".into()),
         tag_ids: vec![tag_main_id],
+        images: vec![],
+        step_images: vec![],
     };
 
     let id = domain::recipe::Id::new();
     store
-        .create_recipe(id.clone().into(), user.id.clone(), recipe)
+        .create_recipe(
+            &user.household_id,
+            id.clone().into(),
+            user.id.clone(),
+            recipe,
+        )
         .await?;
 
     // get current hash
-    let current_hash = store.get_recipe(id.clone().into()).await?.hash;
+    let current_hash = store
+        .get_recipe(&user.household_id, id.clone().into())
+        .await?
+        .hash;
 
     // update document
-    let new_image_id = image(&store).await?;
+    let new_image_id = image(&store, &user.household_id).await?;
     store
         .update_recipe(
+            &user.household_id,
             id.clone().into(),
             user.id,
             RecipeDocument {
@@ -330,12 +564,17 @@ pub async fn can_update_recipe(store: datastore::Pool) -> Result<()> {
                 }],
                 notes: None,
                 tag_ids: vec![tag_soup_id],
+                images: vec![],
+                step_images: vec![],
             },
             current_hash,
         )
         .await?;
 
-    let result: ComparableRecipe = store.get_recipe(id.clone().into()).await?.into();
+    let result: ComparableRecipe = store
+        .get_recipe(&user.household_id, id.clone().into())
+        .await?
+        .into();
 
     assert_eq!(id, result.id);
     assert_eq!("Bean Soup", result.title);
@@ -363,15 +602,18 @@ pub async fn cannot_update_with_bad_hash(store: datastore::Pool) -> Result<()> {
         }],
         notes: Some("Don't burn it!".into()),
         tag_ids: vec![],
+        images: vec![],
+        step_images: vec![],
     };
 
     store
-        .create_recipe("1234".into(), user.id.clone(), recipe)
+        .create_recipe(&user.household_id, "1234".into(), user.id.clone(), recipe)
         .await?;
 
     // update document
     let result = store
         .update_recipe(
+            &user.household_id,
             "1234".into(),
             user.id.clone(),
             RecipeDocument {
@@ -387,6 +629,8 @@ pub async fn cannot_update_with_bad_hash(store: datastore::Pool) -> Result<()> {
                 }],
                 notes: None,
                 tag_ids: vec![],
+                images: vec![],
+                step_images: vec![],
             },
             "this is the wrong hash".into(),
         )
@@ -415,19 +659,30 @@ pub async fn update_creates_new_revision(store: datastore::Pool) -> Result<()> {
         }],
         notes: Some("Don't burn it!".into()),
         tag_ids: vec![],
+        images: vec![],
+        step_images: vec![],
     };
 
     let id = domain::recipe::Id::new();
     store
-        .create_recipe(id.clone().into(), user.id.clone(), recipe)
+        .create_recipe(
+            &user.household_id,
+            id.clone().into(),
+            user.id.clone(),
+            recipe,
+        )
         .await?;
 
     // get current hash
-    let current_hash = store.get_recipe(id.clone().into()).await?.hash;
+    let current_hash = store
+        .get_recipe(&user.household_id, id.clone().into())
+        .await?
+        .hash;
 
     // update document
     store
         .update_recipe(
+            &user.household_id,
             id.clone().into(),
             user.id.clone(),
             RecipeDocument {
@@ -443,20 +698,24 @@ pub async fn update_creates_new_revision(store: datastore::Pool) -> Result<()> {
                 }],
                 notes: None,
                 tag_ids: vec![],
+                images: vec![],
+                step_images: vec![],
             },
             current_hash,
         )
         .await?;
 
     // get revisions - there should be two revision
-    let revisions = store.get_recipe_revisions(id.clone().into()).await?;
+    let revisions = store
+        .get_recipe_revisions(&user.household_id, id.clone().into())
+        .await?;
     assert_eq!(2, revisions.len());
     assert_eq!(1, revisions[0].revision);
     assert_eq!(0, revisions[1].revision);
 
     // try to get revision 1
     let result: ComparableRecipe = store
-        .get_recipe_revision(id.clone().into(), 1)
+        .get_recipe_revision(&user.household_id, id.clone().into(), 1)
         .await?
         .into();
 
@@ -476,6 +735,7 @@ pub async fn handles_updating_unknown_recipe(store: datastore::Pool) -> Result<(
     let id = domain::recipe::Id::new();
     let result = store
         .update_recipe(
+            &user.household_id,
             id.into(),
             user.id,
             RecipeDocument {
@@ -491,6 +751,8 @@ pub async fn handles_updating_unknown_recipe(store: datastore::Pool) -> Result<(
                 }],
                 notes: None,
                 tag_ids: vec![],
+                images: vec![],
+                step_images: vec![],
             },
             "".into(),
         )
@@ -510,9 +772,10 @@ pub async fn can_list_recipes_over_multiple_pages(store: datastore::Pool) -> Res
     let user = user(&store).await?;
 
     let recipe_id_1 = domain::recipe::Id::new();
-    let image_id_1 = image(&store).await?;
+    let image_id_1 = image(&store, &user.household_id).await?;
     store
         .create_recipe(
+            &user.household_id,
             recipe_id_1.clone().into(),
             user.id.clone(),
             RecipeDocument {
@@ -522,14 +785,17 @@ pub async fn can_list_recipes_over_multiple_pages(store: datastore::Pool) -> Res
                 instructions: vec![],
                 notes: None,
                 tag_ids: vec![],
+                images: vec![],
+                step_images: vec![],
             },
         )
         .await?;
 
     let recipe_id_2 = domain::recipe::Id::new();
-    let image_id_2 = image(&store).await?;
+    let image_id_2 = image(&store, &user.household_id).await?;
     store
         .create_recipe(
+            &user.household_id,
             recipe_id_2.clone().into(),
             user.id.clone(),
             RecipeDocument {
@@ -539,14 +805,17 @@ pub async fn can_list_recipes_over_multiple_pages(store: datastore::Pool) -> Res
                 instructions: vec![],
                 notes: None,
                 tag_ids: vec![],
+                images: vec![],
+                step_images: vec![],
             },
         )
         .await?;
 
     let recipe_id_3 = domain::recipe::Id::new();
-    let image_id_3 = image(&store).await?;
+    let image_id_3 = image(&store, &user.household_id).await?;
     store
         .create_recipe(
+            &user.household_id,
             recipe_id_3.clone().into(),
             user.id.clone(),
             RecipeDocument {
@@ -556,6 +825,8 @@ pub async fn can_list_recipes_over_multiple_pages(store: datastore::Pool) -> Res
                 instructions: vec![],
                 notes: None,
                 tag_ids: vec![],
+                images: vec![],
+                step_images: vec![],
             },
         )
         .await?;
@@ -563,7 +834,11 @@ pub async fn can_list_recipes_over_multiple_pages(store: datastore::Pool) -> Res
     // fetch recipes
 
     let first_page = store
-        .list_recipes(domain::filter::Recipe { tag_ids: vec![] }, None)
+        .list_recipes(
+            &user.household_id,
+            domain::filter::Recipe { tag_ids: vec![] },
+            None,
+        )
         .await?;
 
     assert_eq!(2, first_page.items.len());
@@ -578,7 +853,11 @@ pub async fn can_list_recipes_over_multiple_pages(store: datastore::Pool) -> Res
     assert_eq!(Some(image_id_2), recipe.image_id);
 
     let second_page = store
-        .list_recipes(domain::filter::Recipe { tag_ids: vec![] }, first_page.next)
+        .list_recipes(
+            &user.household_id,
+            domain::filter::Recipe { tag_ids: vec![] },
+            first_page.next,
+        )
         .await?;
 
     assert!(second_page.next.is_none());
@@ -594,14 +873,15 @@ pub async fn can_list_recipes_over_multiple_pages(store: datastore::Pool) -> Res
 pub async fn can_list_with_tag_filter(store: datastore::Pool) -> Result<()> {
     let user = user(&store).await?;
 
-    let tag1 = tag(&store, &user.id, "Tag1").await?;
-    let tag2 = tag(&store, &user.id, "Tag2").await?;
-    let tag3 = tag(&store, &user.id, "Tag3").await?;
-    let tag4 = tag(&store, &user.id, "Tag4").await?;
+    let tag1 = tag(&store, &user, "Tag1").await?;
+    let tag2 = tag(&store, &user, "Tag2").await?;
+    let tag3 = tag(&store, &user, "Tag3").await?;
+    let tag4 = tag(&store, &user, "Tag4").await?;
 
     let recipe_id_1 = domain::recipe::Id::new();
     store
         .create_recipe(
+            &user.household_id,
             recipe_id_1.clone().into(),
             user.id.clone(),
             RecipeDocument {
@@ -611,6 +891,8 @@ pub async fn can_list_with_tag_filter(store: datastore::Pool) -> Result<()> {
                 instructions: vec![],
                 notes: None,
                 tag_ids: vec![tag1.clone(), tag2.clone()],
+                images: vec![],
+                step_images: vec![],
             },
         )
         .await?;
@@ -618,6 +900,7 @@ pub async fn can_list_with_tag_filter(store: datastore::Pool) -> Result<()> {
     let recipe_id_2 = domain::recipe::Id::new();
     store
         .create_recipe(
+            &user.household_id,
             recipe_id_2.clone().into(),
             user.id.clone(),
             RecipeDocument {
@@ -627,6 +910,8 @@ pub async fn can_list_with_tag_filter(store: datastore::Pool) -> Result<()> {
                 instructions: vec![],
                 notes: None,
                 tag_ids: vec![tag3],
+                images: vec![],
+                step_images: vec![],
             },
         )
         .await?;
@@ -634,6 +919,7 @@ pub async fn can_list_with_tag_filter(store: datastore::Pool) -> Result<()> {
     let recipe_id_3 = domain::recipe::Id::new();
     store
         .create_recipe(
+            &user.household_id,
             recipe_id_3.clone().into(),
             user.id.clone(),
             RecipeDocument {
@@ -643,6 +929,8 @@ pub async fn can_list_with_tag_filter(store: datastore::Pool) -> Result<()> {
                 instructions: vec![],
                 notes: None,
                 tag_ids: vec![tag2.clone(), tag4.clone()],
+                images: vec![],
+                step_images: vec![],
             },
         )
         .await?;
@@ -653,7 +941,9 @@ pub async fn can_list_with_tag_filter(store: datastore::Pool) -> Result<()> {
         tag_ids: vec![tag1, tag2],
     };
 
-    let result = store.list_recipes(filter.clone(), None).await?;
+    let result = store
+        .list_recipes(&user.household_id, filter.clone(), None)
+        .await?;
 
     assert_eq!(1, result.items.len());
     assert!(result.next.is_none());
@@ -669,12 +959,13 @@ pub async fn can_list_with_tag_filter(store: datastore::Pool) -> Result<()> {
 pub async fn can_list_recipe_if_it_has_tag_with_no_filter(store: datastore::Pool) -> Result<()> {
     let user = user(&store).await?;
 
-    let tag1 = tag(&store, &user.id, "Tag1").await?;
-    let tag2 = tag(&store, &user.id, "Tag2").await?;
+    let tag1 = tag(&store, &user, "Tag1").await?;
+    let tag2 = tag(&store, &user, "Tag2").await?;
 
     let recipe_id_1 = domain::recipe::Id::new();
     store
         .create_recipe(
+            &user.household_id,
             recipe_id_1.clone().into(),
             user.id.clone(),
             RecipeDocument {
@@ -684,6 +975,8 @@ pub async fn can_list_recipe_if_it_has_tag_with_no_filter(store: datastore::Pool
                 instructions: vec![],
                 notes: None,
                 tag_ids: vec![tag1.clone(), tag2.clone()],
+                images: vec![],
+                step_images: vec![],
             },
         )
         .await?;
@@ -692,7 +985,9 @@ pub async fn can_list_recipe_if_it_has_tag_with_no_filter(store: datastore::Pool
 
     let filter = domain::filter::Recipe { tag_ids: vec![] };
 
-    let result = store.list_recipes(filter.clone(), None).await?;
+    let result = store
+        .list_recipes(&user.household_id, filter.clone(), None)
+        .await?;
 
     assert_eq!(1, result.items.len());
     assert!(result.next.is_none());
@@ -707,14 +1002,15 @@ pub async fn filters_properly_if_has_multiple_tags_matching_count_but_wrong_tags
 ) -> Result<()> {
     let user = user(&store).await?;
 
-    let tag1 = tag(&store, &user.id, "Tag1").await?;
-    let tag2 = tag(&store, &user.id, "Tag2").await?;
-    let tag3 = tag(&store, &user.id, "Tag3").await?;
-    let tag4 = tag(&store, &user.id, "Tag4").await?;
+    let tag1 = tag(&store, &user, "Tag1").await?;
+    let tag2 = tag(&store, &user, "Tag2").await?;
+    let tag3 = tag(&store, &user, "Tag3").await?;
+    let tag4 = tag(&store, &user, "Tag4").await?;
 
     let recipe_id_1 = domain::recipe::Id::new();
     store
         .create_recipe(
+            &user.household_id,
             recipe_id_1.clone().into(),
             user.id.clone(),
             RecipeDocument {
@@ -724,6 +1020,8 @@ pub async fn filters_properly_if_has_multiple_tags_matching_count_but_wrong_tags
                 instructions: vec![],
                 notes: None,
                 tag_ids: vec![tag1.clone(), tag2.clone()],
+                images: vec![],
+                step_images: vec![],
             },
         )
         .await?;
@@ -734,7 +1032,9 @@ pub async fn filters_properly_if_has_multiple_tags_matching_count_but_wrong_tags
         tag_ids: vec![tag3, tag4],
     };
 
-    let result = store.list_recipes(filter.clone(), None).await?;
+    let result = store
+        .list_recipes(&user.household_id, filter.clone(), None)
+        .await?;
 
     assert_eq!(0, result.items.len());
     assert!(result.next.is_none());
@@ -754,15 +1054,19 @@ pub async fn cannot_get_non_existent_revision(store: datastore::Pool) -> Result<
         instructions: vec![],
         notes: Some("Don't burn it!".into()),
         tag_ids: vec![],
+        images: vec![],
+        step_images: vec![],
     };
 
     let id = domain::recipe::Id::new();
     store
-        .create_recipe(id.clone().into(), user.id, recipe)
+        .create_recipe(&user.household_id, id.clone().into(), user.id, recipe)
         .await?;
 
     // try to get a non existent revision
-    let result = store.get_recipe_revision(id.into(), 99).await;
+    let result = store
+        .get_recipe_revision(&user.household_id, id.into(), 99)
+        .await;
 
     if let Err(datastore::Error::NotFound) = result {
     } else {
@@ -776,7 +1080,11 @@ pub async fn cannot_get_non_existent_revision(store: datastore::Pool) -> Result<
 }
 
 pub async fn cannot_get_revision_for_non_existent_recipe(store: datastore::Pool) -> Result<()> {
-    let result = store.get_recipe_revision("bad-id".into(), 0).await;
+    let user = user(&store).await?;
+
+    let result = store
+        .get_recipe_revision(&user.household_id, "bad-id".into(), 0)
+        .await;
 
     if let Err(datastore::Error::NotFound) = result {
     } else {
@@ -793,9 +1101,9 @@ pub async fn stores_revision_history(store: datastore::Pool) -> Result<()> {
     let user = user(&store).await?;
     let id = domain::recipe::Id::new();
 
-    let image_id_1 = image(&store).await?;
-    let image_id_2 = image(&store).await?;
-    let image_id_3 = image(&store).await?;
+    let image_id_1 = image(&store, &user.household_id).await?;
+    let image_id_2 = image(&store, &user.household_id).await?;
+    let image_id_3 = image(&store, &user.household_id).await?;
 
     // create recipe
     let recipe = RecipeDocument {
@@ -810,10 +1118,17 @@ pub async fn stores_revision_history(store: datastore::Pool) -> Result<()> {
             items: vec!["three".to_owned()],
         }],
         notes: Some("four".into()),
-        tag_ids: vec![tag(&store, &user.id, "Tag1").await?],
+        tag_ids: vec![tag(&store, &user, "Tag1").await?],
+        images: vec![],
+        step_images: vec![],
     };
     store
-        .create_recipe(id.clone().into(), user.id.clone(), recipe)
+        .create_recipe(
+            &user.household_id,
+            id.clone().into(),
+            user.id.clone(),
+            recipe,
+        )
         .await?;
 
     // update recipe
@@ -829,11 +1144,22 @@ pub async fn stores_revision_history(store: datastore::Pool) -> Result<()> {
             items: vec!["seven".to_owned()],
         }],
         notes: Some("eight".into()),
-        tag_ids: vec![tag(&store, &user.id, "Tag2").await?],
+        tag_ids: vec![tag(&store, &user, "Tag2").await?],
+        images: vec![],
+        step_images: vec![],
     };
-    let hash = store.get_recipe(id.clone().into()).await?.hash;
+    let hash = store
+        .get_recipe(&user.household_id, id.clone().into())
+        .await?
+        .hash;
     store
-        .update_recipe(id.clone().into(), user.id.clone(), recipe, hash)
+        .update_recipe(
+            &user.household_id,
+            id.clone().into(),
+            user.id.clone(),
+            recipe,
+            hash,
+        )
         .await?;
 
     // update recipe again
@@ -849,15 +1175,28 @@ pub async fn stores_revision_history(store: datastore::Pool) -> Result<()> {
             items: vec!["eleven".to_owned()],
         }],
         notes: Some("twelve".into()),
-        tag_ids: vec![tag(&store, &user.id, "Tag3").await?],
+        tag_ids: vec![tag(&store, &user, "Tag3").await?],
+        images: vec![],
+        step_images: vec![],
     };
-    let hash = store.get_recipe(id.clone().into()).await?.hash;
+    let hash = store
+        .get_recipe(&user.household_id, id.clone().into())
+        .await?
+        .hash;
     store
-        .update_recipe(id.clone().into(), user.id.clone(), recipe, hash)
+        .update_recipe(
+            &user.household_id,
+            id.clone().into(),
+            user.id.clone(),
+            recipe,
+            hash,
+        )
         .await?;
 
     // now validate revision history - there should be three revisions
-    let revisions = store.get_recipe_revisions(id.clone().into()).await?;
+    let revisions = store
+        .get_recipe_revisions(&user.household_id, id.clone().into())
+        .await?;
     assert_eq!(3, revisions.len());
     assert_eq!(2, revisions[0].revision);
     assert_eq!(1, revisions[1].revision);
@@ -865,7 +1204,7 @@ pub async fn stores_revision_history(store: datastore::Pool) -> Result<()> {
 
     // check revision 0
     let result: ComparableRecipe = store
-        .get_recipe_revision(id.clone().into(), 0)
+        .get_recipe_revision(&user.household_id, id.clone().into(), 0)
         .await?
         .into();
     assert_eq!("one", result.title);
@@ -877,7 +1216,7 @@ pub async fn stores_revision_history(store: datastore::Pool) -> Result<()> {
 
     // check revision 1
     let result: ComparableRecipe = store
-        .get_recipe_revision(id.clone().into(), 1)
+        .get_recipe_revision(&user.household_id, id.clone().into(), 1)
         .await?
         .into();
     assert_eq!("five", result.title);
@@ -889,7 +1228,7 @@ pub async fn stores_revision_history(store: datastore::Pool) -> Result<()> {
 
     // check revision 2
     let result: ComparableRecipe = store
-        .get_recipe_revision(id.clone().into(), 2)
+        .get_recipe_revision(&user.household_id, id.clone().into(), 2)
         .await?
         .into();
     assert_eq!("nine", result.title);
@@ -905,8 +1244,83 @@ pub async fn stores_revision_history(store: datastore::Pool) -> Result<()> {
 // get_revisions
 
 pub async fn cannot_get_revisions_for_non_existent_recipe(store: datastore::Pool) -> Result<()> {
-    let result = store.get_recipe_revisions("a-random-id".into()).await?;
+    let user = user(&store).await?;
+
+    let result = store
+        .get_recipe_revisions(&user.household_id, "a-random-id".into())
+        .await?;
     assert_eq!(0, result.len());
 
     Ok(())
 }
+
+pub async fn stores_images_in_revision_history(store: datastore::Pool) -> Result<()> {
+    let user = user(&store).await?;
+    let id = domain::recipe::Id::new();
+
+    let image_id_1 = image(&store, &user.household_id).await?;
+    let image_id_2 = image(&store, &user.household_id).await?;
+
+    let recipe = RecipeDocument {
+        title: "Bread".into(),
+        image_id: None,
+        ingredients: vec![],
+        instructions: vec![domain::recipe::StringifiedBlock {
+            title: None,
+            items: vec!["Mix".to_owned()],
+        }],
+        notes: None,
+        tag_ids: vec![],
+        images: vec![datastore::DocumentImage {
+            image_id: image_id_1.clone(),
+            caption: Some("one".into()),
+        }],
+        step_images: vec![],
+    };
+    store
+        .create_recipe(
+            &user.household_id,
+            id.clone().into(),
+            user.id.clone(),
+            recipe.clone(),
+        )
+        .await?;
+
+    let hash = store
+        .get_recipe(&user.household_id, id.clone().into())
+        .await?
+        .hash;
+    store
+        .update_recipe(
+            &user.household_id,
+            id.clone().into(),
+            user.id.clone(),
+            RecipeDocument {
+                images: vec![],
+                step_images: vec![datastore::DocumentStepImage {
+                    block: 0,
+                    step: 0,
+                    image_id: image_id_2.clone(),
+                }],
+                ..recipe
+            },
+            hash,
+        )
+        .await?;
+
+    let result: ComparableRecipe = store
+        .get_recipe(&user.household_id, id.clone().into())
+        .await?
+        .into();
+    assert!(result.images.is_empty());
+    assert_eq!(vec![(0, 0, image_id_2)], result.step_images);
+
+    let result: ComparableRecipe = store
+        .get_recipe_revision(&user.household_id, id.clone().into(), 0)
+        .await?
+        .into();
+    assert_eq!(vec![(image_id_1, Some("one".to_owned()))], result.images);
+    assert!(result.step_images.is_empty());
+
+    Ok(())
+}
@@ -0,0 +1,166 @@
+use anyhow::Result;
+use mise::{
+    datastore::{self, RecipeDocument},
+    domain,
+};
+
+use super::{Member, member};
+
+#[macro_export]
+macro_rules! shares_tests {
+    ($cd:expr) => {
+        mod shares {
+            use crate::a_test;
+            use crate::datastore::common::{CreatesDatastore, HoldsDatastore, shares};
+            use anyhow::Result;
+
+            a_test!($cd, shares, can_create_and_find_by_hash);
+            a_test!($cd, shares, cannot_share_recipe_of_other_household);
+            a_test!($cd, shares, can_list_and_delete_shares_of_recipe);
+        }
+    };
+}
+
+async fn recipe(store: &datastore::Pool, user: &Member) -> Result<domain::recipe::Id> {
+    let id = domain::recipe::Id::new();
+    store
+        .create_recipe(
+            &user.household_id,
+            id.clone().into(),
+            user.id.clone(),
+            RecipeDocument {
+                title: "Chicken Casserole".into(),
+                image_id: None,
+                ingredients: vec![domain::recipe::StringifiedBlock {
+                    title: None,
+                    items: vec!["Chicken".to_owned()],
+                }],
+                instructions: vec![domain::recipe::StringifiedBlock {
+                    title: None,
+                    items: vec!["Cook chicken".to_owned()],
+                }],
+                notes: None,
+                tag_ids: vec![],
+                images: vec![],
+                step_images: vec![],
+            },
+        )
+        .await?;
+
+    Ok(id)
+}
+
+pub async fn can_create_and_find_by_hash(store: datastore::Pool) -> Result<()> {
+    let user = member(&store, "1234").await?;
+    let recipe_id = recipe(&store, &user).await?;
+    let id = domain::share::Id::new();
+    let expires_at = chrono::Utc::now() + chrono::Duration::days(7);
+
+    store
+        .create_share(
+            &id,
+            &user.household_id,
+            &recipe_id,
+            "hash".into(),
+            domain::share::Creating {
+                expires_at: Some(expires_at),
+            },
+        )
+        .await?;
+
+    let stored = store.get_share_by_hash("hash".into()).await?;
+    assert_eq!(id, stored.id);
+    assert_eq!(user.household_id, stored.household_id);
+    assert_eq!(recipe_id, stored.recipe_id);
+    assert_eq!(Some(expires_at), stored.expires_at);
+
+    let result = store.get_share_by_hash("other".into()).await.unwrap_err();
+    assert!(
+        matches!(result, datastore::Error::NotFound),
+        "wrong enum: {}",
+        result
+    );
+
+    Ok(())
+}
+
+pub async fn cannot_share_recipe_of_other_household(store: datastore::Pool) -> Result<()> {
+    let owner = member(&store, "1234").await?;
+    let other = member(&store, "5678").await?;
+    let recipe_id = recipe(&store, &owner).await?;
+
+    let result = store
+        .create_share(
+            &domain::share::Id::new(),
+            &other.household_id,
+            &recipe_id,
+            "hash".into(),
+            domain::share::Creating { expires_at: None },
+        )
+        .await
+        .unwrap_err();
+
+    assert!(
+        matches!(result, datastore::Error::NotFound),
+        "wrong enum: {}",
+        result
+    );
+
+    Ok(())
+}
+
+pub async fn can_list_and_delete_shares_of_recipe(store: datastore::Pool) -> Result<()> {
+    let user = member(&store, "1234").await?;
+    let other = member(&store, "5678").await?;
+    let recipe_id = recipe(&store, &user).await?;
+    let other_recipe_id = recipe(&store, &user).await?;
+
+    let id = domain::share::Id::new();
+    store
+        .create_share(
+            &id,
+            &user.household_id,
+            &recipe_id,
+            "first".into(),
+            domain::share::Creating { expires_at: None },
+        )
+        .await?;
+    store
+        .create_share(
+            &domain::share::Id::new(),
+            &user.household_id,
+            &other_recipe_id,
+            "second".into(),
+            domain::share::Creating { expires_at: None },
+        )
+        .await?;
+
+    let shares = store.list_shares(&user.household_id, &recipe_id).await?;
+    assert_eq!(1, shares.len());
+    assert_eq!(id, shares[0].id);
+    assert!(
+        store
+            .list_shares(&other.household_id, &recipe_id)
+            .await?
+            .is_empty()
+    );
+
+    // only the household of the recipe can revoke its shares
+    let result = store
+        .delete_share(&other.household_id, &recipe_id, &id)
+        .await
+        .unwrap_err();
+    assert!(
+        matches!(result, datastore::Error::NotFound),
+        "wrong enum: {}",
+        result
+    );
+
+    store
+        .delete_share(&user.household_id, &recipe_id, &id)
+        .await?;
+    assert!(store.get_share_by_hash("first".into()).await.is_err());
+    assert!(store.get_share_by_hash("second".into()).await.is_ok());
+
+    Ok(())
+}
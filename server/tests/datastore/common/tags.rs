@@ -15,35 +15,50 @@ macro_rules! tags_tests {
             a_test!($cd, tags, can_create);
             a_test!($cd, tags, cannot_create_duplicate_name);
             a_test!($cd, tags, can_get_all_tags);
+            a_test!($cd, tags, tags_are_isolated_by_household);
         }
     };
 }
 
 async fn user(store: &datastore::Pool) -> Result<User> {
     Ok(store
-        .upsert_user_by_oauth_id(RegisteringUser {
+        .upsert_user_by_identity(RegisteringUser {
             potential_id: "user-id".into(),
-            oauth_id: "custom|user-1".into(),
+            issuer: "https://issuer.example.com".into(),
+            subject: "user-1".into(),
             name: "user".into(),
+            max_role: None,
         })
         .await?)
 }
 
 pub async fn can_create(store: datastore::Pool) -> Result<()> {
     let user = user(&store).await?;
+    let household_id = store
+        .get_active_membership(user.id.clone())
+        .await?
+        .household_id;
 
-    store.create_tag(user.id, "Main Dish".into()).await?;
+    store
+        .create_tag(&household_id, user.id, "Main Dish".into())
+        .await?;
 
     Ok(())
 }
 
 pub async fn cannot_create_duplicate_name(store: datastore::Pool) -> Result<()> {
     let user = user(&store).await?;
+    let household_id = store
+        .get_active_membership(user.id.clone())
+        .await?
+        .household_id;
 
     store
-        .create_tag(user.id.clone(), "Main Dish".into())
+        .create_tag(&household_id, user.id.clone(), "Main Dish".into())
         .await?;
-    let result = store.create_tag(user.id.clone(), "Main Dish".into()).await;
+    let result = store
+        .create_tag(&household_id, user.id.clone(), "Main Dish".into())
+        .await;
     if let Ok(_) = result {
         panic!("result is Ok, expected error.");
     }
@@ -53,15 +68,19 @@ pub async fn cannot_create_duplicate_name(store: datastore::Pool) -> Result<()>
 
 pub async fn can_get_all_tags(store: datastore::Pool) -> Result<()> {
     let user = user(&store).await?;
+    let household_id = store
+        .get_active_membership(user.id.clone())
+        .await?
+        .household_id;
 
     let main_id = store
-        .create_tag(user.id.clone(), "Main Dish".into())
+        .create_tag(&household_id, user.id.clone(), "Main Dish".into())
         .await?;
     let side_id = store
-        .create_tag(user.id.clone(), "Side Dish".into())
+        .create_tag(&household_id, user.id.clone(), "Side Dish".into())
         .await?;
 
-    let result = store.get_tags().await?;
+    let result = store.get_tags(&household_id).await?;
 
     assert_eq!(2, result.len());
 
@@ -73,3 +92,39 @@ pub async fn can_get_all_tags(store: datastore::Pool) -> Result<()> {
 
     Ok(())
 }
+
+pub async fn tags_are_isolated_by_household(store: datastore::Pool) -> Result<()> {
+    let user = user(&store).await?;
+    let household_id = store
+        .get_active_membership(user.id.clone())
+        .await?
+        .household_id;
+    let other = store
+        .upsert_user_by_identity(RegisteringUser {
+            potential_id: "other-user-id".into(),
+            issuer: "https://issuer.example.com".into(),
+            subject: "user-2".into(),
+            name: "other".into(),
+            max_role: None,
+        })
+        .await?;
+    let other_household_id = store
+        .get_active_membership(other.id.clone())
+        .await?
+        .household_id;
+
+    store
+        .create_tag(&household_id, user.id.clone(), "Main Dish".into())
+        .await?;
+    // the same name can be used in another household
+    let other_id = store
+        .create_tag(&other_household_id, other.id, "Main Dish".into())
+        .await?;
+
+    let result = store.get_tags(&other_household_id).await?;
+
+    assert_eq!(1, result.len());
+    assert_eq!(other_id, result[0].id);
+
+    Ok(())
+}
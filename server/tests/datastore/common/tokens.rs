@@ -0,0 +1,158 @@
+use anyhow::Result;
+use mise::{datastore, domain};
+
+use super::{Member, member};
+
+#[macro_export]
+macro_rules! tokens_tests {
+    ($cd:expr) => {
+        mod tokens {
+            use crate::a_test;
+            use crate::datastore::common::{CreatesDatastore, HoldsDatastore, tokens};
+            use anyhow::Result;
+
+            a_test!($cd, tokens, can_create_and_find_by_hash);
+            a_test!($cd, tokens, can_list_own_tokens);
+            a_test!($cd, tokens, can_only_delete_own_tokens);
+            a_test!($cd, tokens, can_record_last_use);
+        }
+    };
+}
+
+fn creating(user: &Member, name: &str) -> Result<domain::token::Creating> {
+    Ok(domain::token::Creating {
+        name: name.to_owned().try_into()?,
+        read_only: false,
+        expires_at: None,
+        household_id: user.household_id.clone(),
+    })
+}
+
+pub async fn can_create_and_find_by_hash(store: datastore::Pool) -> Result<()> {
+    let user = member(&store, "1234").await?;
+    let id = domain::token::Id::new();
+    let expires_at = chrono::Utc::now() + chrono::Duration::days(30);
+    store
+        .create_token(
+            &id,
+            user.id.clone(),
+            "hash".into(),
+            domain::token::Creating {
+                name: "Home Assistant".to_owned().try_into()?,
+                read_only: true,
+                expires_at: Some(expires_at),
+                household_id: user.household_id.clone(),
+            },
+        )
+        .await?;
+
+    let stored = store.get_token_by_hash("hash".into()).await?;
+    assert_eq!(id, stored.id);
+    assert_eq!(user.id, stored.user_id);
+    assert!(stored.read_only);
+    assert_eq!(Some(expires_at), stored.expires_at);
+    assert_eq!(user.household_id, stored.household_id);
+    assert_eq!(None, stored.last_used_at);
+
+    let result = store.get_token_by_hash("other".into()).await.unwrap_err();
+    assert!(
+        matches!(result, datastore::Error::NotFound),
+        "wrong enum: {}",
+        result
+    );
+
+    Ok(())
+}
+
+pub async fn can_list_own_tokens(store: datastore::Pool) -> Result<()> {
+    let user = member(&store, "1234").await?;
+    let other = member(&store, "5678").await?;
+
+    let first = domain::token::Id::new();
+    store
+        .create_token(
+            &first,
+            user.id.clone(),
+            "first".into(),
+            creating(&user, "First")?,
+        )
+        .await?;
+    let second = domain::token::Id::new();
+    store
+        .create_token(
+            &second,
+            user.id.clone(),
+            "second".into(),
+            creating(&user, "Second")?,
+        )
+        .await?;
+    store
+        .create_token(
+            &domain::token::Id::new(),
+            other.id.clone(),
+            "third".into(),
+            creating(&other, "Third")?,
+        )
+        .await?;
+
+    let tokens = store.list_tokens(user.id).await?;
+    assert_eq!(2, tokens.len());
+    assert!(tokens.iter().any(|token| token.id == first));
+    assert!(tokens.iter().any(|token| token.id == second));
+
+    Ok(())
+}
+
+pub async fn can_only_delete_own_tokens(store: datastore::Pool) -> Result<()> {
+    let user = member(&store, "1234").await?;
+    let other = member(&store, "5678").await?;
+
+    let id = domain::token::Id::new();
+    store
+        .create_token(
+            &id,
+            user.id.clone(),
+            "hash".into(),
+            creating(&user, "Scripts")?,
+        )
+        .await?;
+
+    let result = store.delete_token(other.id, &id).await.unwrap_err();
+    assert!(
+        matches!(result, datastore::Error::NotFound),
+        "wrong enum: {}",
+        result
+    );
+
+    store.delete_token(user.id.clone(), &id).await?;
+    assert!(store.list_tokens(user.id).await?.is_empty());
+    assert!(store.get_token_by_hash("hash".into()).await.is_err());
+
+    Ok(())
+}
+
+pub async fn can_record_last_use(store: datastore::Pool) -> Result<()> {
+    let user = member(&store, "1234").await?;
+    let id = domain::token::Id::new();
+    store
+        .create_token(
+            &id,
+            user.id.clone(),
+            "hash".into(),
+            creating(&user, "Scripts")?,
+        )
+        .await?;
+
+    store.touch_token(&id).await?;
+
+    assert!(
+        store
+            .get_token_by_hash("hash".into())
+            .await?
+            .last_used_at
+            .is_some()
+    );
+    assert!(store.list_tokens(user.id).await?[0].last_used_at.is_some());
+
+    Ok(())
+}
@@ -1,5 +1,8 @@
 use anyhow::Result;
-use mise::{datastore, domain::RegisteringUser};
+use mise::{
+    datastore,
+    domain::{RegisteringUser, user},
+};
 
 #[macro_export]
 macro_rules! users_tests {
@@ -11,25 +14,30 @@ macro_rules! users_tests {
 
             a_test!($cd, users, can_upsert_new_user);
             a_test!($cd, users, updates_existing_user_if_setup);
+            a_test!($cd, users, same_subject_of_other_issuer_is_other_user);
             a_test!($cd, users, cannot_get_non_existent_user);
             a_test!($cd, users, can_get_a_registered_user);
+            a_test!($cd, users, chosen_name_is_kept_when_signing_in_again);
+            a_test!($cd, users, cannot_update_non_existent_user);
+            a_test!($cd, users, registered_users_are_linked);
         }
     };
 }
 
-// upsert_user_by_oauth_id
+// upsert_user_by_identity
 
 pub async fn can_upsert_new_user(store: datastore::Pool) -> Result<()> {
     let registering = RegisteringUser {
         potential_id: "1234".into(),
-        oauth_id: "11".into(),
+        issuer: "https://issuer.example.com".into(),
+        subject: "11".into(),
         name: "Barley".into(),
+        max_role: None,
     };
 
-    let user = store.upsert_user_by_oauth_id(registering).await?;
+    let user = store.upsert_user_by_identity(registering).await?;
 
     assert_eq!("1234", user.id);
-    assert_eq!("11", user.oauth_id);
     assert_eq!("Barley", user.name);
 
     Ok(())
@@ -38,26 +46,55 @@ pub async fn can_upsert_new_user(store: datastore::Pool) -> Result<()> {
 pub async fn updates_existing_user_if_setup(store: datastore::Pool) -> Result<()> {
     let registering = RegisteringUser {
         potential_id: "1234".into(),
-        oauth_id: "11".into(),
+        issuer: "https://issuer.example.com".into(),
+        subject: "11".into(),
         name: "Barley".into(),
+        max_role: None,
     };
 
-    store.upsert_user_by_oauth_id(registering).await?;
+    store.upsert_user_by_identity(registering).await?;
 
     let registering_again = RegisteringUser {
         potential_id: "12345678".into(),
-        oauth_id: "11".into(),
+        issuer: "https://issuer.example.com".into(),
+        subject: "11".into(),
         name: "Barley Bob".into(),
+        max_role: None,
     };
-    let user = store.upsert_user_by_oauth_id(registering_again).await?;
+    let user = store.upsert_user_by_identity(registering_again).await?;
 
     assert_eq!("1234", user.id);
-    assert_eq!("11", user.oauth_id);
     assert_eq!("Barley Bob", user.name);
 
     Ok(())
 }
 
+pub async fn same_subject_of_other_issuer_is_other_user(store: datastore::Pool) -> Result<()> {
+    let registering = RegisteringUser {
+        potential_id: "1234".into(),
+        issuer: "https://issuer.example.com".into(),
+        subject: "11".into(),
+        name: "Barley".into(),
+        max_role: None,
+    };
+
+    store.upsert_user_by_identity(registering.clone()).await?;
+
+    let user = store
+        .upsert_user_by_identity(RegisteringUser {
+            potential_id: "5678".into(),
+            issuer: "https://other.example.com".into(),
+            name: "Thomas".into(),
+            ..registering
+        })
+        .await?;
+
+    assert_eq!("5678", user.id);
+    assert_eq!("Barley", store.get_user("1234".into()).await?.name);
+
+    Ok(())
+}
+
 // get_user
 
 pub async fn cannot_get_non_existent_user(store: datastore::Pool) -> Result<()> {
@@ -75,17 +112,123 @@ pub async fn cannot_get_non_existent_user(store: datastore::Pool) -> Result<()>
 pub async fn can_get_a_registered_user(store: datastore::Pool) -> Result<()> {
     let registering = RegisteringUser {
         potential_id: "1234".into(),
-        oauth_id: "11".into(),
+        issuer: "https://issuer.example.com".into(),
+        subject: "11".into(),
         name: "Barley".into(),
+        max_role: None,
     };
 
-    store.upsert_user_by_oauth_id(registering).await?;
+    store.upsert_user_by_identity(registering).await?;
 
     let user = store.get_user("1234".into()).await?;
 
     assert_eq!("1234", user.id);
-    assert_eq!("11", user.oauth_id);
     assert_eq!("Barley", user.name);
 
     Ok(())
 }
+
+// update_user
+
+pub async fn chosen_name_is_kept_when_signing_in_again(store: datastore::Pool) -> Result<()> {
+    let registering = RegisteringUser {
+        potential_id: "1234".into(),
+        issuer: "https://issuer.example.com".into(),
+        subject: "11".into(),
+        name: "Barley".into(),
+        max_role: None,
+    };
+
+    store.upsert_user_by_identity(registering.clone()).await?;
+
+    let preferences = user::Preferences {
+        units: Some(user::Units::Imperial),
+        default_servings: Some(4.try_into()?),
+    };
+    let user = store
+        .update_user(
+            "1234".into(),
+            user::Updating {
+                name: Some("Barley Bob".to_string().try_into()?),
+                avatar_image_id: None,
+                preferences: preferences.clone(),
+            },
+        )
+        .await?;
+
+    assert_eq!("Barley Bob", user.name);
+    assert_eq!(preferences, user.preferences);
+
+    let user = store
+        .upsert_user_by_identity(RegisteringUser {
+            name: "Barley Jr".into(),
+            ..registering
+        })
+        .await?;
+
+    assert_eq!("Barley Bob", user.name);
+    assert_eq!(preferences, user.preferences);
+
+    // without a chosen name, the latest name of the provider is used
+    let user = store
+        .update_user(
+            "1234".into(),
+            user::Updating {
+                name: None,
+                avatar_image_id: None,
+                preferences: user::Preferences::default(),
+            },
+        )
+        .await?;
+
+    assert_eq!("Barley Jr", user.name);
+    assert_eq!(user::Preferences::default(), user.preferences);
+
+    Ok(())
+}
+
+pub async fn cannot_update_non_existent_user(store: datastore::Pool) -> Result<()> {
+    let result = store
+        .update_user(
+            "random_id".into(),
+            user::Updating {
+                name: None,
+                avatar_image_id: None,
+                preferences: user::Preferences::default(),
+            },
+        )
+        .await
+        .unwrap_err();
+
+    assert!(
+        matches!(result, datastore::Error::NotFound),
+        "wrong enum: {}",
+        result
+    );
+
+    Ok(())
+}
+
+// count_unlinked_users
+
+pub async fn registered_users_are_linked(store: datastore::Pool) -> Result<()> {
+    store
+        .upsert_user_by_identity(RegisteringUser {
+            potential_id: "1234".into(),
+            issuer: "https://issuer.example.com".into(),
+            subject: "11".into(),
+            name: "Barley".into(),
+            max_role: None,
+        })
+        .await?;
+
+    assert_eq!(0, store.count_unlinked_users().await?);
+    assert_eq!(
+        0,
+        store
+            .link_unlinked_users("https://other.example.com".into())
+            .await?
+    );
+
+    Ok(())
+}
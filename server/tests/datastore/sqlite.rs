@@ -1,7 +1,10 @@
 use mise::{datastore, sqlite};
 use rand::Rng;
 
-use crate::{images_tests, recipes_tests, tags_tests, users_tests};
+use crate::{
+    audit_tests, households_tests, images_tests, recipes_tests, shares_tests, tags_tests,
+    tokens_tests, users_tests,
+};
 
 use super::common::{CreatesDatastore, HoldsDatastore};
 
@@ -39,6 +42,7 @@ impl CreatesDatastore for SqliteCreator {
             &sqlite::DatastoreConfig {
                 recipe_page_size: 2,
                 recipe_dump_page_size: 2,
+                image_dump_page_size: 2,
             },
         )
         .unwrap();
@@ -49,7 +53,11 @@ impl CreatesDatastore for SqliteCreator {
     }
 }
 
+audit_tests!(crate::datastore::sqlite::SqliteCreator {});
+households_tests!(crate::datastore::sqlite::SqliteCreator {});
 images_tests!(crate::datastore::sqlite::SqliteCreator {});
 recipes_tests!(crate::datastore::sqlite::SqliteCreator {});
+shares_tests!(crate::datastore::sqlite::SqliteCreator {});
 tags_tests!(crate::datastore::sqlite::SqliteCreator {});
+tokens_tests!(crate::datastore::sqlite::SqliteCreator {});
 users_tests!(crate::datastore::sqlite::SqliteCreator {});
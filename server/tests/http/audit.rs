@@ -0,0 +1,47 @@
+use super::{responses, setup};
+use anyhow::Result;
+use reqwest::StatusCode;
+
+#[tokio::test]
+async fn admins_can_list_what_happened() -> Result<()> {
+    let harness = setup::with_auth().await?;
+    let tag_id = harness.create_tag("Main Dish").await?;
+
+    let response = harness.get("/api/v1/audit").send().await?;
+    assert_eq!(StatusCode::OK, response.status());
+    let entries = response.json::<responses::ListAudit>().await?.data;
+
+    let actions: Vec<&str> = entries.iter().map(|entry| entry.action.as_str()).collect();
+    assert_eq!(vec!["create_tag", "sign_in"], actions);
+    assert_eq!("tag", entries[0].entity);
+    assert_eq!(tag_id, entries[0].entity_id);
+    assert_eq!(Some("default".to_owned()), entries[1].detail);
+
+    let response = harness
+        .get("/api/v1/audit?entity=user&limit=1")
+        .send()
+        .await?;
+    assert_eq!(StatusCode::OK, response.status());
+    let entries = response.json::<responses::ListAudit>().await?.data;
+    assert_eq!(1, entries.len());
+    assert_eq!("sign_in", entries[0].action);
+
+    let response = harness.get("/api/v1/audit?limit=0").send().await?;
+    assert_eq!(StatusCode::UNPROCESSABLE_ENTITY, response.status());
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn only_admins_can_list_what_happened() -> Result<()> {
+    let mut harness = setup::with_config(|config| {
+        config.oidc_providers[0].access.default_role = Some(mise::domain::household::Role::Editor);
+    })
+    .await?;
+    harness.authenticate("thomas").await?;
+
+    let response = harness.get("/api/v1/audit").send().await?;
+    assert_eq!(StatusCode::FORBIDDEN, response.status());
+
+    Ok(())
+}
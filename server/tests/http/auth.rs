@@ -1,4 +1,4 @@
-use super::setup;
+use super::{requests, responses, setup};
 use anyhow::Result;
 use reqwest::StatusCode;
 
@@ -14,3 +14,383 @@ async fn can_get_me() -> Result<()> {
 
     Ok(())
 }
+
+#[tokio::test]
+async fn can_logout() -> Result<()> {
+    let mut harness = setup::harness().await?;
+
+    harness.authenticate("thomas").await?;
+
+    // signing out changes state, so it can't be triggered by following a link
+    let response = harness.get("/auth/logout").send().await?;
+    assert_eq!(StatusCode::METHOD_NOT_ALLOWED, response.status());
+
+    // redirected through the provider's end session endpoint back to the login page
+    let response = harness.post("/auth/logout").send().await?;
+    assert_eq!("/login", response.url().path());
+
+    let response = harness.get("/api/v1/auth/me").send().await?;
+    assert_eq!(StatusCode::UNAUTHORIZED, response.status());
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn can_list_and_revoke_sessions() -> Result<()> {
+    let mut harness = setup::harness().await?;
+
+    // sign in twice, the harness uses the latest session
+    harness.authenticate("thomas").await?;
+    harness.authenticate("thomas").await?;
+
+    let response = harness.get("/api/v1/auth/sessions").send().await?;
+    assert_eq!(StatusCode::OK, response.status());
+    let sessions = response.json::<responses::ListSessions>().await?.data;
+    assert_eq!(2, sessions.len());
+    assert_eq!(1, sessions.iter().filter(|session| session.current).count());
+    assert_eq!(Some("127.0.0.0/24"), sessions[0].client.as_deref());
+
+    // revoke all other sessions
+    let response = harness.delete("/api/v1/auth/sessions").send().await?;
+    assert_eq!(StatusCode::NO_CONTENT, response.status());
+
+    let response = harness.get("/api/v1/auth/sessions").send().await?;
+    let sessions = response.json::<responses::ListSessions>().await?.data;
+    assert_eq!(1, sessions.len());
+    assert!(sessions[0].current);
+
+    // revoke the current session
+    let response = harness
+        .delete(&format!("/api/v1/auth/sessions/{}", sessions[0].id))
+        .send()
+        .await?;
+    assert_eq!(StatusCode::NO_CONTENT, response.status());
+
+    let response = harness.get("/api/v1/auth/me").send().await?;
+    assert_eq!(StatusCode::UNAUTHORIZED, response.status());
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn cannot_revoke_unknown_session() -> Result<()> {
+    let harness = setup::with_auth().await?;
+
+    let response = harness
+        .delete("/api/v1/auth/sessions/not-a-session")
+        .send()
+        .await?;
+    assert_eq!(StatusCode::NOT_FOUND, response.status());
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn only_allowed_subjects_can_sign_in() -> Result<()> {
+    let mut harness = setup::with_config(|config| {
+        config.oidc_providers[0].access.allowed_subjects = vec!["thomas".into()];
+    })
+    .await?;
+
+    harness.authenticate("thomas").await?;
+    let response = harness.get("/api/v1/auth/me").send().await?;
+    assert_eq!(StatusCode::OK, response.status());
+
+    let response = harness.try_authenticate("barley").await?;
+    assert_eq!(StatusCode::FORBIDDEN, response.status());
+
+    let response = harness.get("/api/v1/auth/me").send().await?;
+    assert_eq!(StatusCode::UNAUTHORIZED, response.status());
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn default_role_limits_what_users_can_do() -> Result<()> {
+    let mut harness = setup::with_config(|config| {
+        config.oidc_providers[0].access.default_role = Some(mise::domain::household::Role::Viewer);
+    })
+    .await?;
+    harness.authenticate("thomas").await?;
+
+    let response = harness.get("/api/v1/tags").send().await?;
+    assert_eq!(StatusCode::OK, response.status());
+
+    let response = harness
+        .post("/api/v1/tags")
+        .json(&requests::CreateTag {
+            name: "Main Dish".into(),
+        })
+        .send()
+        .await?;
+    assert_eq!(StatusCode::FORBIDDEN, response.status());
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn can_sign_in_with_one_of_several_providers() -> Result<()> {
+    let mut harness = setup::with_config(|config| {
+        let mut other = config.oidc_providers[0].clone();
+        other.name = "household".into();
+        config.oidc_providers.push(other);
+    })
+    .await?;
+
+    let response = harness.get("/auth/providers").send().await?;
+    assert_eq!(StatusCode::OK, response.status());
+    assert_eq!(
+        vec!["default", "household"],
+        response.json::<responses::ListProviders>().await?.data
+    );
+
+    // a provider must be chosen once there are several
+    let response = harness.get("/auth/init").send().await?;
+    assert_eq!(StatusCode::UNPROCESSABLE_ENTITY, response.status());
+
+    let response = harness.get("/auth/init?provider=unknown").send().await?;
+    assert_eq!(StatusCode::NOT_FOUND, response.status());
+
+    harness.authenticate_with("household", "thomas").await?;
+    let response = harness.get("/api/v1/auth/me").send().await?;
+    assert_eq!(StatusCode::OK, response.status());
+    let me = response.json::<responses::GetMe>().await?.data.id;
+
+    // both providers have the same issuer, so the subject is the same user
+    harness.authenticate_with("default", "thomas").await?;
+    let response = harness.get("/api/v1/auth/me").send().await?;
+    assert_eq!(me, response.json::<responses::GetMe>().await?.data.id);
+
+    Ok(())
+}
+
+// Thomas signs in locally with the password hunter2.
+fn local_auth() -> mise::config::LocalAuth {
+    local_auth_with_password("hunter2")
+}
+
+fn local_auth_with_password(password: &str) -> mise::config::LocalAuth {
+    // cheap parameters, the hash is verified with the parameters it was made with
+    let argon2 = argon2::Argon2::new(
+        argon2::Algorithm::Argon2id,
+        argon2::Version::V0x13,
+        argon2::Params::new(1024, 1, 1, None).unwrap(),
+    );
+    let salt = argon2::password_hash::SaltString::from_b64("c29tZXNhbHRzb21lc2FsdA").unwrap();
+    let password_hash = argon2::PasswordHasher::hash_password(&argon2, password.as_bytes(), &salt)
+        .unwrap()
+        .to_string();
+
+    mise::config::LocalAuth {
+        users: vec![mise::config::LocalUser {
+            username: "thomas".into(),
+            name: "Thomas".into(),
+            password_hash,
+        }],
+    }
+}
+
+#[tokio::test]
+async fn can_sign_in_with_local_password() -> Result<()> {
+    let mut harness = setup::with_config(|config| config.local_auth = Some(local_auth())).await?;
+
+    let response = harness.get("/auth/providers").send().await?;
+    assert_eq!(
+        vec!["default", "local"],
+        response.json::<responses::ListProviders>().await?.data
+    );
+
+    let response = harness.sign_in_locally("thomas", "hunter3").await?;
+    assert_eq!(StatusCode::UNAUTHORIZED, response.status());
+
+    let response = harness.sign_in_locally("ghost", "hunter2").await?;
+    assert_eq!(StatusCode::UNAUTHORIZED, response.status());
+
+    let response = harness.sign_in_locally("thomas", "hunter2").await?;
+    assert_eq!(StatusCode::NO_CONTENT, response.status());
+
+    let response = harness.get("/api/v1/auth/me").send().await?;
+    assert_eq!(StatusCode::OK, response.status());
+
+    // a local user is not the user with the same subject at a provider
+    let me = response.json::<responses::GetMe>().await?.data.id;
+    harness.authenticate("thomas").await?;
+    let response = harness.get("/api/v1/auth/me").send().await?;
+    assert_ne!(me, response.json::<responses::GetMe>().await?.data.id);
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn local_sessions_end_when_the_user_is_removed_or_changes_password() -> Result<()> {
+    let mut harness = setup::with_config(|config| config.local_auth = Some(local_auth())).await?;
+
+    let response = harness.sign_in_locally("thomas", "hunter2").await?;
+    assert_eq!(StatusCode::NO_CONTENT, response.status());
+
+    // unchanged configuration keeps the session
+    harness.restart(|_| {}).await?;
+    let response = harness.get("/api/v1/auth/me").send().await?;
+    assert_eq!(StatusCode::OK, response.status());
+
+    harness
+        .restart(|config| config.local_auth = Some(local_auth_with_password("hunter3")))
+        .await?;
+    let response = harness.get("/api/v1/auth/me").send().await?;
+    assert_eq!(StatusCode::UNAUTHORIZED, response.status());
+
+    harness
+        .restart(|config| config.local_auth = Some(local_auth()))
+        .await?;
+    let response = harness.sign_in_locally("thomas", "hunter2").await?;
+    assert_eq!(StatusCode::NO_CONTENT, response.status());
+
+    // thomas is removed, another user remains
+    harness
+        .restart(|config| config.local_auth.as_mut().unwrap().users[0].username = "tom".into())
+        .await?;
+    let response = harness.get("/api/v1/auth/me").send().await?;
+    assert_eq!(StatusCode::UNAUTHORIZED, response.status());
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn local_sign_in_is_disabled_by_default() -> Result<()> {
+    let mut harness = setup::harness().await?;
+
+    let response = harness.sign_in_locally("thomas", "hunter2").await?;
+    assert_eq!(StatusCode::NOT_FOUND, response.status());
+
+    Ok(())
+}
+
+fn trusting_proxies(config: &mut mise::config::Config, trusted_proxies: &[&str]) {
+    config.trusted_proxies = trusted_proxies
+        .iter()
+        .map(|network| network.parse().unwrap())
+        .collect();
+    config.proxy_auth = Some(mise::config::ProxyAuth {
+        user_header: "Remote-User".into(),
+        name_header: "Remote-Name".into(),
+    });
+}
+
+#[tokio::test]
+async fn trusts_user_header_of_trusted_proxies() -> Result<()> {
+    let harness = setup::with_config(|config| trusting_proxies(config, &["127.0.0.0/8"])).await?;
+
+    let response = harness
+        .get("/api/v1/auth/me")
+        .header("Remote-User", "thomas")
+        .header("Remote-Name", "Thomas")
+        .send()
+        .await?;
+    assert_eq!(StatusCode::OK, response.status());
+    let me = response.json::<responses::GetMe>().await?.data.id;
+
+    let response = harness
+        .get("/api/v1/auth/me")
+        .header("Remote-User", "thomas")
+        .send()
+        .await?;
+    assert_eq!(me, response.json::<responses::GetMe>().await?.data.id);
+
+    let response = harness
+        .get("/api/v1/auth/me")
+        .header("Remote-User", "dave")
+        .send()
+        .await?;
+    assert_ne!(me, response.json::<responses::GetMe>().await?.data.id);
+
+    let response = harness.get("/api/v1/auth/me").send().await?;
+    assert_eq!(StatusCode::UNAUTHORIZED, response.status());
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn only_accepts_changes_by_proxy_users_from_own_origin() -> Result<()> {
+    let harness = setup::with_config(|config| trusting_proxies(config, &["127.0.0.0/8"])).await?;
+
+    let create_tag = |origin: Option<&str>| {
+        let request = harness
+            .post("/api/v1/tags")
+            .header("Remote-User", "thomas")
+            .json(&requests::CreateTag {
+                name: "Main Dish".into(),
+            });
+
+        match origin {
+            Some(origin) => request.header("Origin", origin),
+            None => request,
+        }
+    };
+
+    let response = create_tag(None).send().await?;
+    assert_eq!(StatusCode::FORBIDDEN, response.status());
+
+    let response = create_tag(Some("https://evil.example.com")).send().await?;
+    assert_eq!(StatusCode::FORBIDDEN, response.status());
+
+    let response = create_tag(Some(harness.origin())).send().await?;
+    assert_eq!(StatusCode::OK, response.status());
+
+    // reads do not change anything, so they are allowed from anywhere
+    let response = harness
+        .get("/api/v1/tags")
+        .header("Remote-User", "thomas")
+        .header("Origin", "https://evil.example.com")
+        .send()
+        .await?;
+    assert_eq!(StatusCode::OK, response.status());
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn ignores_user_header_of_untrusted_addresses() -> Result<()> {
+    let harness = setup::with_config(|config| trusting_proxies(config, &["10.0.0.0/8"])).await?;
+
+    let response = harness
+        .get("/api/v1/auth/me")
+        .header("Remote-User", "thomas")
+        .send()
+        .await?;
+    assert_eq!(StatusCode::UNAUTHORIZED, response.status());
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn records_forwarded_address_of_sessions_behind_trusted_proxies() -> Result<()> {
+    let mut harness = setup::with_config(|config| {
+        config.local_auth = Some(local_auth());
+        config.trusted_proxies = vec!["127.0.0.0/8".parse().unwrap()];
+    })
+    .await?;
+
+    let response = harness
+        .post("/auth/local")
+        .header("X-Forwarded-For", "203.0.113.7")
+        .json(&requests::LocalSignIn {
+            username: "thomas".into(),
+            password: "hunter2".into(),
+        })
+        .send()
+        .await?;
+    assert_eq!(StatusCode::NO_CONTENT, response.status());
+    harness.resume(
+        response
+            .cookies()
+            .find(|cookie| cookie.name() == "id")
+            .map(|cookie| cookie.value().to_string()),
+    );
+
+    let response = harness.get("/api/v1/auth/sessions").send().await?;
+    assert_eq!(StatusCode::OK, response.status());
+    let sessions = response.json::<responses::ListSessions>().await?.data;
+    assert_eq!(Some("203.0.113.0/24"), sessions[0].client.as_deref());
+
+    Ok(())
+}
@@ -0,0 +1,521 @@
+use super::{
+    requests, responses,
+    setup::{self, Harness},
+};
+use anyhow::Result;
+use reqwest::StatusCode;
+
+async fn user_id(harness: &Harness) -> Result<String> {
+    let response = harness.get("/api/v1/auth/me").send().await?;
+    assert_eq!(StatusCode::OK, response.status());
+
+    Ok(response.json::<responses::GetMe>().await?.data.id)
+}
+
+async fn households(harness: &Harness) -> Result<Vec<responses::Household>> {
+    let response = harness.get("/api/v1/households").send().await?;
+    assert_eq!(StatusCode::OK, response.status());
+
+    Ok(response.json::<responses::ListHouseholds>().await?.data)
+}
+
+async fn create_recipe(harness: &Harness, tag_id: String, image_id: String) -> Result<String> {
+    let response = harness
+        .post("/api/v1/recipes")
+        .json(&requests::CreateRecipe {
+            title: "Chicken Parm".into(),
+            image_id: Some(image_id),
+            images: vec![],
+            ingredients: requests::IngredientBlock::new(&[(None, &["One chicken"])]),
+            instructions: requests::InstructionBlock::new(&[(None, &["Broil the chicken"])]),
+            notes: None,
+            tag_ids: vec![tag_id],
+        })
+        .send()
+        .await?;
+    assert_eq!(StatusCode::OK, response.status());
+
+    Ok(response.json::<responses::CreateRecipe>().await?.data)
+}
+
+#[tokio::test]
+async fn new_users_get_their_own_household() -> Result<()> {
+    let mut harness = setup::harness().await?;
+    harness.authenticate("thomas").await?;
+
+    let result = households(&harness).await?;
+
+    assert_eq!(1, result.len());
+    assert_eq!("thomas", result[0].name);
+    assert_eq!("admin", result[0].role);
+    assert!(result[0].active);
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn cannot_see_recipes_tags_or_images_of_other_households() -> Result<()> {
+    let mut harness = setup::harness().await?;
+    harness.authenticate("thomas").await?;
+
+    let image_id = harness.create_image().await?;
+    let tag_id = harness.create_tag("Main Dish").await?;
+    let recipe_id = create_recipe(&harness, tag_id.clone(), image_id.clone()).await?;
+
+    harness.authenticate("barley").await?;
+
+    let response = harness.get("/api/v1/recipes").send().await?;
+    assert_eq!(StatusCode::OK, response.status());
+    assert!(
+        response
+            .json::<responses::ListRecipes>()
+            .await?
+            .data
+            .is_empty()
+    );
+
+    let response = harness
+        .get(&format!("/api/v1/recipes/{recipe_id}"))
+        .send()
+        .await?;
+    assert_eq!(StatusCode::NOT_FOUND, response.status());
+
+    let response = harness.get("/api/v1/tags").send().await?;
+    assert!(response.json::<responses::GetTags>().await?.data.is_empty());
+
+    let response = harness
+        .get(&format!("/api/v1/images/{image_id}"))
+        .send()
+        .await?;
+    assert_eq!(StatusCode::NOT_FOUND, response.status());
+
+    // nor use them in a recipe of their own
+    let response = harness
+        .post("/api/v1/recipes")
+        .json(&requests::CreateRecipe {
+            title: "Chicken Parm".into(),
+            image_id: None,
+            images: vec![],
+            ingredients: requests::IngredientBlock::new(&[(None, &["One chicken"])]),
+            instructions: requests::InstructionBlock::new(&[(None, &["Broil the chicken"])]),
+            notes: None,
+            tag_ids: vec![tag_id],
+        })
+        .send()
+        .await?;
+    assert_eq!(StatusCode::UNPROCESSABLE_ENTITY, response.status());
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn can_share_a_household() -> Result<()> {
+    let mut harness = setup::harness().await?;
+    harness.authenticate("barley").await?;
+    let barley_id = user_id(&harness).await?;
+
+    harness.authenticate("thomas").await?;
+    let response = harness
+        .post("/api/v1/households")
+        .json(&requests::CreateHousehold {
+            name: "Family".into(),
+        })
+        .send()
+        .await?;
+    assert_eq!(StatusCode::OK, response.status());
+    let household_id = response.json::<responses::CreateHousehold>().await?.data;
+
+    let response = harness
+        .put(&format!("/api/v1/households/{household_id}/active"))
+        .send()
+        .await?;
+    assert_eq!(StatusCode::NO_CONTENT, response.status());
+
+    let tag_id = harness.create_tag("Main Dish").await?;
+    let image_id = harness.create_image().await?;
+    let recipe_id = create_recipe(&harness, tag_id, image_id).await?;
+
+    let response = harness
+        .post(&format!("/api/v1/households/{household_id}/members"))
+        .json(&requests::AddHouseholdMember {
+            user_id: barley_id.clone(),
+            role: None,
+        })
+        .send()
+        .await?;
+    assert_eq!(StatusCode::NO_CONTENT, response.status());
+
+    // the new member sees the recipe once they switch to the household
+    harness.authenticate("barley").await?;
+    let response = harness
+        .get(&format!("/api/v1/recipes/{recipe_id}"))
+        .send()
+        .await?;
+    assert_eq!(StatusCode::NOT_FOUND, response.status());
+
+    let response = harness
+        .put(&format!("/api/v1/households/{household_id}/active"))
+        .send()
+        .await?;
+    assert_eq!(StatusCode::NO_CONTENT, response.status());
+
+    let response = harness
+        .get(&format!("/api/v1/recipes/{recipe_id}"))
+        .send()
+        .await?;
+    assert_eq!(StatusCode::OK, response.status());
+
+    // leaving the household switches back to the remaining one
+    let response = harness
+        .delete(&format!(
+            "/api/v1/households/{household_id}/members/{barley_id}"
+        ))
+        .send()
+        .await?;
+    assert_eq!(StatusCode::NO_CONTENT, response.status());
+
+    let result = households(&harness).await?;
+    assert_eq!(1, result.len());
+    assert_eq!("barley", result[0].name);
+    assert!(result[0].active);
+
+    let response = harness
+        .get(&format!("/api/v1/recipes/{recipe_id}"))
+        .send()
+        .await?;
+    assert_eq!(StatusCode::NOT_FOUND, response.status());
+
+    // the last household cannot be left
+    let household_id = &result[0].id;
+    let response = harness
+        .delete(&format!(
+            "/api/v1/households/{household_id}/members/{barley_id}"
+        ))
+        .send()
+        .await?;
+    assert_eq!(StatusCode::UNPROCESSABLE_ENTITY, response.status());
+
+    Ok(())
+}
+
+async fn create_household(harness: &Harness, name: &str) -> Result<String> {
+    let response = harness
+        .post("/api/v1/households")
+        .json(&requests::CreateHousehold { name: name.into() })
+        .send()
+        .await?;
+    assert_eq!(StatusCode::OK, response.status());
+
+    Ok(response.json::<responses::CreateHousehold>().await?.data)
+}
+
+async fn active_household(harness: &Harness) -> Result<String> {
+    let result = households(harness).await?;
+    let active = result
+        .into_iter()
+        .find(|household| household.active)
+        .expect("no active household");
+
+    Ok(active.name)
+}
+
+#[tokio::test]
+async fn sessions_switch_households_independently() -> Result<()> {
+    let mut harness = setup::harness().await?;
+    harness.authenticate("thomas").await?;
+    let first = harness.session_id();
+    let household_id = create_household(&harness, "Family").await?;
+
+    harness.authenticate("thomas").await?;
+    let response = harness
+        .put(&format!("/api/v1/households/{household_id}/active"))
+        .send()
+        .await?;
+    assert_eq!(StatusCode::NO_CONTENT, response.status());
+    assert_eq!("Family", active_household(&harness).await?);
+
+    // the other session stays where it was
+    let second = harness.session_id();
+    harness.resume(first);
+    assert_eq!("thomas", active_household(&harness).await?);
+
+    // sessions started later start in the household switched to last
+    harness.authenticate("thomas").await?;
+    assert_eq!("Family", active_household(&harness).await?);
+
+    harness.resume(second);
+    assert_eq!("Family", active_household(&harness).await?);
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn tokens_keep_the_household_they_were_created_in() -> Result<()> {
+    let mut harness = setup::harness().await?;
+    harness.authenticate("thomas").await?;
+    let household_id = create_household(&harness, "Family").await?;
+
+    let response = harness
+        .post("/api/v1/auth/tokens")
+        .json(&requests::CreateToken {
+            name: "Home Assistant".into(),
+            read_only: None,
+            expires_at: None,
+        })
+        .send()
+        .await?;
+    assert_eq!(StatusCode::OK, response.status());
+    let token = response.json::<responses::CreateToken>().await?.data.token;
+
+    let response = harness
+        .put(&format!("/api/v1/households/{household_id}/active"))
+        .send()
+        .await?;
+    assert_eq!(StatusCode::NO_CONTENT, response.status());
+
+    harness.sign_out();
+    let response = harness
+        .get("/api/v1/households")
+        .bearer_auth(&token)
+        .send()
+        .await?;
+    assert_eq!(StatusCode::OK, response.status());
+    let result = response.json::<responses::ListHouseholds>().await?.data;
+    assert!(
+        result
+            .iter()
+            .any(|household| household.active && household.name == "thomas")
+    );
+
+    // a token cannot switch households
+    let response = harness
+        .put(&format!("/api/v1/households/{household_id}/active"))
+        .bearer_auth(&token)
+        .send()
+        .await?;
+    assert_eq!(StatusCode::UNPROCESSABLE_ENTITY, response.status());
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn cannot_join_household_without_being_a_member() -> Result<()> {
+    let mut harness = setup::harness().await?;
+    harness.authenticate("thomas").await?;
+    let household_id = households(&harness).await?.remove(0).id;
+
+    harness.authenticate("barley").await?;
+    let barley_id = user_id(&harness).await?;
+
+    let response = harness
+        .put(&format!("/api/v1/households/{household_id}/active"))
+        .send()
+        .await?;
+    assert_eq!(StatusCode::NOT_FOUND, response.status());
+
+    let response = harness
+        .post(&format!("/api/v1/households/{household_id}/members"))
+        .json(&requests::AddHouseholdMember {
+            user_id: barley_id,
+            role: None,
+        })
+        .send()
+        .await?;
+    assert_eq!(StatusCode::NOT_FOUND, response.status());
+
+    Ok(())
+}
+
+// Adds barley to the household of thomas with the given role, and leaves barley acting in it.
+async fn join_as(harness: &mut Harness, role: &str) -> Result<String> {
+    harness.authenticate("barley").await?;
+    let barley_id = user_id(harness).await?;
+
+    harness.authenticate("thomas").await?;
+    let household_id = households(harness).await?.remove(0).id;
+    let response = harness
+        .post(&format!("/api/v1/households/{household_id}/members"))
+        .json(&requests::AddHouseholdMember {
+            user_id: barley_id.clone(),
+            role: Some(role.into()),
+        })
+        .send()
+        .await?;
+    assert_eq!(StatusCode::NO_CONTENT, response.status());
+
+    harness.authenticate("barley").await?;
+    let response = harness
+        .put(&format!("/api/v1/households/{household_id}/active"))
+        .send()
+        .await?;
+    assert_eq!(StatusCode::NO_CONTENT, response.status());
+
+    Ok(household_id)
+}
+
+#[tokio::test]
+async fn avatars_can_be_seen_in_every_household_of_their_user() -> Result<()> {
+    let mut harness = setup::harness().await?;
+    harness.authenticate("thomas").await?;
+
+    // barley's avatar is uploaded to their own household
+    harness.authenticate("barley").await?;
+    let avatar_id = harness.create_image().await?;
+    let response = harness
+        .put("/api/v1/auth/me")
+        .json(&requests::UpdateMe {
+            name: None,
+            avatar_image_id: Some(avatar_id.clone()),
+            preferences: requests::Preferences {
+                units: None,
+                default_servings: None,
+            },
+        })
+        .send()
+        .await?;
+    assert_eq!(StatusCode::OK, response.status());
+
+    // still seen by barley after switching to the household of thomas
+    join_as(&mut harness, "viewer").await?;
+    let response = harness
+        .get(&format!("/api/v1/images/{avatar_id}"))
+        .send()
+        .await?;
+    assert_eq!(StatusCode::OK, response.status());
+
+    // and by the other members of it
+    harness.authenticate("thomas").await?;
+    let response = harness
+        .get(&format!("/api/v1/images/{avatar_id}"))
+        .send()
+        .await?;
+    assert_eq!(StatusCode::OK, response.status());
+
+    // but not by anyone else
+    harness.authenticate("pepper").await?;
+    let response = harness
+        .get(&format!("/api/v1/images/{avatar_id}"))
+        .send()
+        .await?;
+    assert_eq!(StatusCode::NOT_FOUND, response.status());
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn viewers_can_browse_but_not_edit() -> Result<()> {
+    let mut harness = setup::harness().await?;
+    harness.authenticate("thomas").await?;
+    let tag_id = harness.create_tag("Main Dish").await?;
+    let image_id = harness.create_image().await?;
+    let recipe_id = create_recipe(&harness, tag_id.clone(), image_id.clone()).await?;
+
+    join_as(&mut harness, "viewer").await?;
+
+    let response = harness
+        .get(&format!("/api/v1/recipes/{recipe_id}"))
+        .send()
+        .await?;
+    assert_eq!(StatusCode::OK, response.status());
+
+    let response = harness.get("/api/v1/tags").send().await?;
+    assert_eq!(1, response.json::<responses::GetTags>().await?.data.len());
+
+    let response = harness
+        .post("/api/v1/tags")
+        .json(&requests::CreateTag {
+            name: "Dessert".into(),
+        })
+        .send()
+        .await?;
+    assert_eq!(StatusCode::FORBIDDEN, response.status());
+
+    let response = harness
+        .post("/api/v1/recipes")
+        .json(&requests::CreateRecipe {
+            title: "Chicken Parm".into(),
+            image_id: Some(image_id),
+            images: vec![],
+            ingredients: requests::IngredientBlock::new(&[(None, &["One chicken"])]),
+            instructions: requests::InstructionBlock::new(&[(None, &["Broil the chicken"])]),
+            notes: None,
+            tag_ids: vec![tag_id],
+        })
+        .send()
+        .await?;
+    assert_eq!(StatusCode::FORBIDDEN, response.status());
+
+    let response = harness.upload_image(vec![]).await?;
+    assert_eq!(StatusCode::FORBIDDEN, response.status());
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn editors_can_edit_once_promoted() -> Result<()> {
+    let mut harness = setup::harness().await?;
+    harness.authenticate("thomas").await?;
+    let household_id = join_as(&mut harness, "viewer").await?;
+    let barley_id = user_id(&harness).await?;
+
+    harness.authenticate("thomas").await?;
+    let response = harness
+        .put(&format!(
+            "/api/v1/households/{household_id}/members/{barley_id}"
+        ))
+        .json(&requests::SetHouseholdMemberRole {
+            role: "editor".into(),
+        })
+        .send()
+        .await?;
+    assert_eq!(StatusCode::NO_CONTENT, response.status());
+
+    harness.authenticate("barley").await?;
+    let tag_id = harness.create_tag("Main Dish").await?;
+    let image_id = harness.create_image().await?;
+    create_recipe(&harness, tag_id, image_id).await?;
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn only_admins_can_manage_members() -> Result<()> {
+    let mut harness = setup::harness().await?;
+    harness.authenticate("thomas").await?;
+    let thomas_id = user_id(&harness).await?;
+    let household_id = join_as(&mut harness, "editor").await?;
+
+    let response = harness
+        .put(&format!(
+            "/api/v1/households/{household_id}/members/{thomas_id}"
+        ))
+        .json(&requests::SetHouseholdMemberRole {
+            role: "viewer".into(),
+        })
+        .send()
+        .await?;
+    assert_eq!(StatusCode::FORBIDDEN, response.status());
+
+    let response = harness
+        .delete(&format!(
+            "/api/v1/households/{household_id}/members/{thomas_id}"
+        ))
+        .send()
+        .await?;
+    assert_eq!(StatusCode::FORBIDDEN, response.status());
+
+    // the last admin cannot step down
+    harness.authenticate("thomas").await?;
+    let response = harness
+        .put(&format!(
+            "/api/v1/households/{household_id}/members/{thomas_id}"
+        ))
+        .json(&requests::SetHouseholdMemberRole {
+            role: "viewer".into(),
+        })
+        .send()
+        .await?;
+    assert_eq!(StatusCode::UNPROCESSABLE_ENTITY, response.status());
+
+    Ok(())
+}
@@ -1,8 +1,31 @@
-use super::setup::{self};
+use super::{
+    requests, responses,
+    setup::{self, Harness},
+};
 use anyhow::Result;
 use axum::http::HeaderValue;
+use base64::Engine;
 use reqwest::StatusCode;
 
+async fn create_recipe(harness: &Harness, image_id: &str) -> Result<String> {
+    let response = harness
+        .post("/api/v1/recipes")
+        .json(&requests::CreateRecipe {
+            title: "Chicken Parm".into(),
+            image_id: Some(image_id.to_owned()),
+            images: vec![],
+            ingredients: requests::IngredientBlock::new(&[(None, &["One chicken"])]),
+            instructions: requests::InstructionBlock::new(&[(None, &["Broil the chicken"])]),
+            notes: None,
+            tag_ids: vec![],
+        })
+        .send()
+        .await?;
+    assert_eq!(StatusCode::OK, response.status());
+
+    Ok(response.json::<responses::CreateRecipe>().await?.data)
+}
+
 #[tokio::test]
 async fn can_create_and_get_image() -> Result<()> {
     let harness = setup::with_auth().await?;
@@ -51,3 +74,404 @@ async fn supplies_etag_for_cache() -> Result<()> {
 
     Ok(())
 }
+
+#[tokio::test]
+async fn redirects_to_presigned_url() -> Result<()> {
+    let harness = setup::with_presigned_urls().await?;
+
+    let id = harness.create_image().await?;
+
+    let client = reqwest::Client::builder()
+        .redirect(reqwest::redirect::Policy::none())
+        .build()?;
+    let response = client
+        .get(format!("{}/api/v1/images/{id}", harness.origin()))
+        .header("cookie", format!("id={}", harness.session_id().unwrap()))
+        .send()
+        .await?;
+    assert_eq!(StatusCode::TEMPORARY_REDIRECT, response.status());
+    let location = response.headers()["Location"].to_str()?;
+    assert!(location.starts_with("https://images.example.com/"));
+    assert!(location.contains(&id));
+    assert_eq!(
+        Some(&HeaderValue::from_static("private, no-store")),
+        response.headers().get("Cache-Control")
+    );
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn reuses_image_with_same_content() -> Result<()> {
+    let harness = setup::with_auth().await?;
+
+    let id = harness.create_image().await?;
+    let second_id = harness.create_image().await?;
+
+    assert_eq!(id, second_id);
+
+    let response = harness
+        .get(&format!("/api/v1/images/{second_id}"))
+        .send()
+        .await?;
+    assert_eq!(StatusCode::OK, response.status());
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn can_get_image_range() -> Result<()> {
+    let harness = setup::with_auth().await?;
+
+    let id = harness.create_image().await?;
+
+    let response = harness.get(&format!("/api/v1/images/{id}")).send().await?;
+    assert_eq!(StatusCode::OK, response.status());
+    assert_eq!(
+        Some(&HeaderValue::from_static("bytes")),
+        response.headers().get("Accept-Ranges")
+    );
+    let full_image = response.bytes().await?;
+
+    let response = harness
+        .get(&format!("/api/v1/images/{id}"))
+        .header("Range", "bytes=2-5")
+        .send()
+        .await?;
+    assert_eq!(StatusCode::PARTIAL_CONTENT, response.status());
+    assert_eq!(
+        Some(&HeaderValue::from_str(&format!("bytes 2-5/{}", full_image.len())).unwrap()),
+        response.headers().get("Content-Range")
+    );
+    assert_eq!(response.content_length(), Some(4));
+    assert_eq!(full_image[2..6], response.bytes().await?);
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn rejects_unsatisfiable_range() -> Result<()> {
+    let harness = setup::with_auth().await?;
+
+    let id = harness.create_image().await?;
+
+    let response = harness
+        .get(&format!("/api/v1/images/{id}"))
+        .header("Range", "bytes=100000000-")
+        .send()
+        .await?;
+    assert_eq!(StatusCode::RANGE_NOT_SATISFIABLE, response.status());
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn rejects_unsupported_image_format() -> Result<()> {
+    let harness = setup::with_auth().await?;
+
+    let response = harness
+        .upload_image(b"<svg xmlns=\"http://www.w3.org/2000/svg\"></svg>".to_vec())
+        .await?;
+    assert_eq!(StatusCode::UNPROCESSABLE_ENTITY, response.status());
+    assert!(response.text().await?.contains("Unsupported image format"));
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn rejects_corrupt_image() -> Result<()> {
+    let harness = setup::with_auth().await?;
+
+    // a jpeg header followed by garbage
+    let response = harness
+        .upload_image(vec![0xFF, 0xD8, 0xFF, 0xE0, 0x01, 0x02, 0x03])
+        .await?;
+    assert_eq!(StatusCode::UNPROCESSABLE_ENTITY, response.status());
+    assert!(response.text().await?.contains("could not be read"));
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn rejects_truncated_image() -> Result<()> {
+    let harness = setup::with_auth().await?;
+
+    let image = base64::engine::general_purpose::STANDARD.decode(setup::JPEG)?;
+    let response = harness
+        .upload_image(image[..image.len() / 2].to_vec())
+        .await?;
+    assert_eq!(StatusCode::UNPROCESSABLE_ENTITY, response.status());
+    assert!(response.text().await?.contains("corrupt or truncated"));
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn asks_to_retry_when_processing_times_out() -> Result<()> {
+    // times out as soon as vips is started
+    let mut harness =
+        setup::with_config(|config| config.image_processing.timeout_seconds = 0).await?;
+    harness.authenticate("user").await?;
+
+    let image = base64::engine::general_purpose::STANDARD.decode(setup::JPEG)?;
+    let response = harness.upload_image(image).await?;
+    assert_eq!(StatusCode::SERVICE_UNAVAILABLE, response.status());
+    let retry_after: u64 = response.headers()["Retry-After"].to_str()?.parse()?;
+    assert!(retry_after > 0);
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn cannot_import_image_from_private_address() -> Result<()> {
+    let harness = setup::with_auth().await?;
+
+    for url in [
+        // resolved by name
+        "http://localhost/image.jpg",
+        "http://127.0.0.1/image.jpg",
+        "http://[::1]/image.jpg",
+        "http://169.254.169.254/latest/meta-data/",
+    ] {
+        let response = harness
+            .post("/api/v1/images/import")
+            .json(&requests::ImportImage { url: url.into() })
+            .send()
+            .await?;
+        assert_eq!(
+            StatusCode::UNPROCESSABLE_ENTITY,
+            response.status(),
+            "importing from {url}"
+        );
+    }
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn cannot_import_image_with_unsupported_scheme() -> Result<()> {
+    let harness = setup::with_auth().await?;
+
+    let response = harness
+        .post("/api/v1/images/import")
+        .json(&requests::ImportImage {
+            url: "file:///etc/passwd".into(),
+        })
+        .send()
+        .await?;
+    assert_eq!(StatusCode::UNPROCESSABLE_ENTITY, response.status());
+    assert!(response.text().await?.contains("http or https"));
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn framing_is_empty_by_default() -> Result<()> {
+    let harness = setup::with_auth().await?;
+
+    let id = harness.create_image().await?;
+    let recipe_id = create_recipe(&harness, &id).await?;
+
+    let response = harness
+        .get(&format!("/api/v1/recipes/{recipe_id}/images/{id}/framing"))
+        .send()
+        .await?;
+    assert_eq!(StatusCode::OK, response.status());
+    let framing = response.json::<responses::GetImageFraming>().await?.data;
+    assert!(framing.crop.is_none());
+    assert!(framing.focal_point.is_none());
+    assert!(framing.cropped_image.is_none());
+    assert!(framing.framed_image.is_none());
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn can_crop_image_and_set_focal_point() -> Result<()> {
+    let harness = setup::with_auth().await?;
+
+    let id = harness.create_image().await?;
+    let recipe_id = create_recipe(&harness, &id).await?;
+
+    let response = harness
+        .put(&format!("/api/v1/recipes/{recipe_id}/images/{id}/framing"))
+        .json(&requests::UpdateImageFraming {
+            crop: Some(requests::Crop {
+                x: 0.0,
+                y: 0.0,
+                width: 1.0,
+                height: 0.5,
+            }),
+            focal_point: Some(requests::FocalPoint { x: 0.5, y: 0.25 }),
+        })
+        .send()
+        .await?;
+    assert_eq!(StatusCode::OK, response.status());
+
+    let response = harness
+        .get(&format!("/api/v1/recipes/{recipe_id}/images/{id}/framing"))
+        .send()
+        .await?;
+    let framing = response.json::<responses::GetImageFraming>().await?.data;
+    assert_eq!(
+        Some(responses::Crop {
+            x: 0.0,
+            y: 0.0,
+            width: 1.0,
+            height: 0.5,
+        }),
+        framing.crop
+    );
+    assert_eq!(
+        Some(responses::FocalPoint { x: 0.5, y: 0.25 }),
+        framing.focal_point
+    );
+
+    let cropped = framing.cropped_image.unwrap();
+    let framed = framing.framed_image.unwrap();
+    for path in [
+        format!("cropped/{cropped}"),
+        format!("cover/{framed}"),
+        format!("tile/{framed}"),
+    ] {
+        let response = harness
+            .get(&format!("/api/v1/images/{id}/{path}"))
+            .send()
+            .await?;
+        assert_eq!(StatusCode::OK, response.status(), "{path}");
+        assert_eq!(
+            Some(&HeaderValue::from_static("image/jpeg")),
+            response.headers().get("Content-Type")
+        );
+        assert!(response.bytes().await?.len() > 1);
+    }
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn rejects_focal_point_outside_of_crop() -> Result<()> {
+    let harness = setup::with_auth().await?;
+
+    let id = harness.create_image().await?;
+    let recipe_id = create_recipe(&harness, &id).await?;
+
+    let response = harness
+        .put(&format!("/api/v1/recipes/{recipe_id}/images/{id}/framing"))
+        .json(&requests::UpdateImageFraming {
+            crop: Some(requests::Crop {
+                x: 0.0,
+                y: 0.0,
+                width: 0.5,
+                height: 0.5,
+            }),
+            focal_point: Some(requests::FocalPoint { x: 0.75, y: 0.75 }),
+        })
+        .send()
+        .await?;
+    assert_eq!(StatusCode::UNPROCESSABLE_ENTITY, response.status());
+
+    let response = harness
+        .put(&format!("/api/v1/recipes/{recipe_id}/images/{id}/framing"))
+        .json(&requests::UpdateImageFraming {
+            crop: Some(requests::Crop {
+                x: 0.5,
+                y: 0.0,
+                width: 0.75,
+                height: 0.5,
+            }),
+            focal_point: None,
+        })
+        .send()
+        .await?;
+    assert_eq!(StatusCode::UNPROCESSABLE_ENTITY, response.status());
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn cannot_get_unknown_cropped_image() -> Result<()> {
+    let harness = setup::with_auth().await?;
+
+    let id = harness.create_image().await?;
+
+    let response = harness
+        .get(&format!("/api/v1/images/{id}/cropped/0123456789abcdef"))
+        .send()
+        .await?;
+    assert_eq!(StatusCode::NOT_FOUND, response.status());
+
+    let response = harness
+        .get(&format!("/api/v1/images/{id}/cropped/..%2F..%2Fetc"))
+        .send()
+        .await?;
+    assert_eq!(StatusCode::NOT_FOUND, response.status());
+
+    let response = harness
+        .get(&format!("/api/v1/images/{id}/original/0123456789abcdef"))
+        .send()
+        .await?;
+    assert_eq!(StatusCode::NOT_FOUND, response.status());
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn recipes_using_the_same_image_frame_it_separately() -> Result<()> {
+    let harness = setup::with_auth().await?;
+
+    let id = harness.create_image().await?;
+    let recipe_id = create_recipe(&harness, &id).await?;
+    let other_recipe_id = create_recipe(&harness, &id).await?;
+
+    let response = harness
+        .put(&format!("/api/v1/recipes/{recipe_id}/images/{id}/framing"))
+        .json(&requests::UpdateImageFraming {
+            crop: None,
+            focal_point: Some(requests::FocalPoint { x: 0.5, y: 0.25 }),
+        })
+        .send()
+        .await?;
+    assert_eq!(StatusCode::OK, response.status());
+
+    let response = harness
+        .get(&format!(
+            "/api/v1/recipes/{other_recipe_id}/images/{id}/framing"
+        ))
+        .send()
+        .await?;
+    assert_eq!(StatusCode::OK, response.status());
+    let framing = response.json::<responses::GetImageFraming>().await?.data;
+    assert!(framing.focal_point.is_none());
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn cannot_frame_image_a_recipe_does_not_use() -> Result<()> {
+    let harness = setup::with_auth().await?;
+
+    let id = harness.create_image().await?;
+    let recipe_id = create_recipe(&harness, &id).await?;
+
+    let other_id = "01J00000000000000000000000";
+    let response = harness
+        .put(&format!(
+            "/api/v1/recipes/{recipe_id}/images/{other_id}/framing"
+        ))
+        .json(&requests::UpdateImageFraming {
+            crop: None,
+            focal_point: Some(requests::FocalPoint { x: 0.5, y: 0.25 }),
+        })
+        .send()
+        .await?;
+    assert_eq!(StatusCode::NOT_FOUND, response.status());
+
+    let response = harness
+        .get(&format!("/api/v1/recipes/{other_id}/images/{id}/framing"))
+        .send()
+        .await?;
+    assert_eq!(StatusCode::NOT_FOUND, response.status());
+
+    Ok(())
+}
@@ -0,0 +1,143 @@
+use super::setup;
+use anyhow::Result;
+use mise::config::{RateLimit, RateLimitBucket};
+use reqwest::StatusCode;
+
+const GENEROUS: RateLimitBucket = RateLimitBucket {
+    burst: 100,
+    per_minute: 60,
+};
+
+const TWICE: RateLimitBucket = RateLimitBucket {
+    burst: 2,
+    per_minute: 1,
+};
+
+const RATE_LIMIT: RateLimit = RateLimit {
+    auth: GENEROUS,
+    image_upload: GENEROUS,
+    write: GENEROUS,
+    read: GENEROUS,
+};
+
+fn trusting(config: &mut mise::config::Config, trusted_proxies: &[&str]) {
+    config.trusted_proxies = trusted_proxies
+        .iter()
+        .map(|network| network.parse().unwrap())
+        .collect();
+}
+
+#[tokio::test]
+async fn limits_sign_in_by_address() -> Result<()> {
+    let harness = setup::with_config(|config| {
+        config.rate_limit = Some(RateLimit {
+            auth: TWICE,
+            ..RATE_LIMIT
+        });
+    })
+    .await?;
+
+    for _ in 0..2 {
+        let response = harness.get("/auth/providers").send().await?;
+        assert_eq!(StatusCode::OK, response.status());
+    }
+
+    let response = harness.get("/auth/providers").send().await?;
+    assert_eq!(StatusCode::TOO_MANY_REQUESTS, response.status());
+    let retry_after: u64 = response.headers()["Retry-After"].to_str()?.parse()?;
+    assert!(retry_after > 0 && retry_after <= 60);
+
+    // other groups are not limited
+    let response = harness.get("/api/v1/shared/unknown").send().await?;
+    assert_eq!(StatusCode::NOT_FOUND, response.status());
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn limits_users_whatever_their_address() -> Result<()> {
+    let mut harness = setup::with_config(|config| {
+        trusting(config, &["127.0.0.0/8"]);
+        config.rate_limit = Some(RateLimit {
+            write: TWICE,
+            ..RATE_LIMIT
+        });
+    })
+    .await?;
+    harness.authenticate("user").await?;
+
+    for (i, address) in ["1.1.1.1", "2.2.2.2", "3.3.3.3"].iter().enumerate() {
+        let response = harness
+            .post("/api/v1/tags")
+            .header("X-Forwarded-For", *address)
+            .json(&super::requests::CreateTag {
+                name: format!("Tag {i}"),
+            })
+            .send()
+            .await?;
+
+        let expected = if i < 2 {
+            StatusCode::OK
+        } else {
+            StatusCode::TOO_MANY_REQUESTS
+        };
+        assert_eq!(expected, response.status());
+    }
+
+    // reads are limited separately
+    let response = harness.get("/api/v1/tags").send().await?;
+    assert_eq!(StatusCode::OK, response.status());
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn ignores_forwarded_for_of_untrusted_addresses() -> Result<()> {
+    let harness = setup::with_config(|config| {
+        trusting(config, &["10.0.0.0/8"]);
+        config.rate_limit = Some(RateLimit {
+            auth: TWICE,
+            ..RATE_LIMIT
+        });
+    })
+    .await?;
+
+    for (i, address) in ["1.1.1.1", "2.2.2.2", "3.3.3.3"].iter().enumerate() {
+        let response = harness
+            .get("/auth/providers")
+            .header("X-Forwarded-For", *address)
+            .send()
+            .await?;
+
+        let expected = if i < 2 {
+            StatusCode::OK
+        } else {
+            StatusCode::TOO_MANY_REQUESTS
+        };
+        assert_eq!(expected, response.status());
+    }
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn limits_image_uploads_on_top_of_writes() -> Result<()> {
+    let mut harness = setup::with_config(|config| {
+        config.rate_limit = Some(RateLimit {
+            image_upload: TWICE,
+            ..RATE_LIMIT
+        });
+    })
+    .await?;
+    harness.authenticate("user").await?;
+
+    harness.create_image().await?;
+    harness.create_image().await?;
+
+    let response = harness.upload_image(vec![]).await?;
+    assert_eq!(StatusCode::TOO_MANY_REQUESTS, response.status());
+
+    harness.create_tag("Main Dish").await?;
+
+    Ok(())
+}
@@ -31,6 +31,7 @@ async fn can_create_and_get_recipe() -> Result<()> {
         .json(&requests::CreateRecipe {
             title: "Chicken Parm".into(),
             image_id: Some(image_id.clone()),
+            images: vec![],
             ingredients: requests::IngredientBlock::new(&[(
                 None,
                 &["One chicken", "Parmesan cheese"],
@@ -70,7 +71,8 @@ async fn can_create_and_get_recipe() -> Result<()> {
             rich_instructions: vec![
                 "<p>Broil the chicken</p>\n".into(),
                 "<p>Add the parmesan</p>\n".into()
-            ]
+            ],
+            step_images: vec![],
         }],
         result.instruction_blocks
     );
@@ -87,6 +89,84 @@ async fn can_create_and_get_recipe() -> Result<()> {
     Ok(())
 }
 
+#[tokio::test]
+async fn can_create_recipe_with_gallery_and_step_images() -> Result<()> {
+    let harness = setup::with_auth().await?;
+
+    let image_id = harness.create_image().await?;
+
+    let mut instructions =
+        requests::InstructionBlock::new(&[(Some("Shaping"), &["Fold the dough", "Shape"])]);
+    instructions[0].step_images = vec![requests::StepImage {
+        step: 1,
+        image_id: image_id.clone(),
+    }];
+
+    let response = harness
+        .post("/api/v1/recipes")
+        .json(&requests::CreateRecipe {
+            title: "Bread".into(),
+            image_id: None,
+            images: vec![requests::RecipeImage {
+                image_id: image_id.clone(),
+                caption: Some("The crumb".into()),
+            }],
+            ingredients: requests::IngredientBlock::new(&[(None, &["Flour", "Water"])]),
+            instructions,
+            notes: None,
+            tag_ids: vec![],
+        })
+        .send()
+        .await?;
+    assert_eq!(StatusCode::OK, response.status());
+    let id = response.json::<responses::CreateRecipe>().await?.data;
+
+    let response = harness.get(&format!("/api/v1/recipes/{id}")).send().await?;
+    assert_eq!(StatusCode::OK, response.status());
+    let result = response.json::<responses::GetRecipe>().await?.data;
+
+    assert_eq!(
+        vec![responses::RecipeImage {
+            image_id: image_id.clone(),
+            caption: Some("The crumb".into()),
+        }],
+        result.images
+    );
+    assert_eq!(
+        vec![responses::StepImage { step: 1, image_id }],
+        result.instruction_blocks[0].step_images
+    );
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn cannot_attach_image_to_missing_step() -> Result<()> {
+    let harness = setup::with_auth().await?;
+
+    let image_id = harness.create_image().await?;
+
+    let mut instructions = requests::InstructionBlock::new(&[(None, &["Fold the dough"])]);
+    instructions[0].step_images = vec![requests::StepImage { step: 1, image_id }];
+
+    let response = harness
+        .post("/api/v1/recipes")
+        .json(&requests::CreateRecipe {
+            title: "Bread".into(),
+            image_id: None,
+            images: vec![],
+            ingredients: requests::IngredientBlock::new(&[(None, &["Flour"])]),
+            instructions,
+            notes: None,
+            tag_ids: vec![],
+        })
+        .send()
+        .await?;
+    assert_eq!(StatusCode::UNPROCESSABLE_ENTITY, response.status());
+
+    Ok(())
+}
+
 #[tokio::test]
 async fn can_create_and_update_recipe() -> Result<()> {
     let harness = setup::with_auth().await?;
@@ -100,6 +180,7 @@ async fn can_create_and_update_recipe() -> Result<()> {
         .json(&requests::CreateRecipe {
             title: "Chicken Parm".into(),
             image_id: Some(image_id_1.clone()),
+            images: vec![],
             ingredients: requests::IngredientBlock::new(&[(
                 None,
                 &["One chicken", "Parmesan cheese"],
@@ -131,6 +212,7 @@ async fn can_create_and_update_recipe() -> Result<()> {
             previous_hash: hash,
             title: "One-Step Salad".into(),
             image_id: Some(image_id_2.clone()),
+            images: vec![],
             ingredients: requests::IngredientBlock::new(&[(None, &["salad"])]),
             instructions: requests::InstructionBlock::new(&[(None, &["serve"])]),
             notes: None,
@@ -159,7 +241,8 @@ async fn can_create_and_update_recipe() -> Result<()> {
         vec![responses::InstructionBlock {
             title: None,
             instructions: vec!["serve".into()],
-            rich_instructions: vec!["<p>serve</p>\n".into()]
+            rich_instructions: vec!["<p>serve</p>\n".into()],
+            step_images: vec![],
         }],
         result.instruction_blocks
     );
@@ -188,6 +271,7 @@ async fn can_list_recipes() -> Result<()> {
         .json(&requests::CreateRecipe {
             title: "Chicken Alpha".into(),
             image_id: Some(image_id_1.clone()),
+            images: vec![],
             ingredients: requests::IngredientBlock::new(&[]),
             instructions: requests::InstructionBlock::new(&[]),
             notes: None,
@@ -203,6 +287,7 @@ async fn can_list_recipes() -> Result<()> {
         .json(&requests::CreateRecipe {
             title: "Chicken Gamma".into(),
             image_id: None,
+            images: vec![],
             ingredients: requests::IngredientBlock::new(&[]),
             instructions: requests::InstructionBlock::new(&[]),
             notes: None,
@@ -218,6 +303,7 @@ async fn can_list_recipes() -> Result<()> {
         .json(&requests::CreateRecipe {
             title: "Chicken Beta".into(),
             image_id: Some(image_id_3.clone()),
+            images: vec![],
             ingredients: requests::IngredientBlock::new(&[]),
             instructions: requests::InstructionBlock::new(&[]),
             notes: None,
@@ -285,6 +371,7 @@ async fn can_list_recipes_with_tag_filter() -> Result<()> {
         .json(&requests::CreateRecipe {
             title: "Chicken Alpha".into(),
             image_id: None,
+            images: vec![],
             ingredients: requests::IngredientBlock::new(&[]),
             instructions: requests::InstructionBlock::new(&[]),
             notes: None,
@@ -300,6 +387,7 @@ async fn can_list_recipes_with_tag_filter() -> Result<()> {
         .json(&requests::CreateRecipe {
             title: "Chicken Gamma Alpha".into(),
             image_id: None,
+            images: vec![],
             ingredients: requests::IngredientBlock::new(&[]),
             instructions: requests::InstructionBlock::new(&[]),
             notes: None,
@@ -315,6 +403,7 @@ async fn can_list_recipes_with_tag_filter() -> Result<()> {
         .json(&requests::CreateRecipe {
             title: "Chicken Beta".into(),
             image_id: None,
+            images: vec![],
             ingredients: requests::IngredientBlock::new(&[]),
             instructions: requests::InstructionBlock::new(&[]),
             notes: None,
@@ -377,6 +466,7 @@ async fn can_apply_search_and_multi_tag_filter_properly() -> Result<()> {
         .json(&requests::CreateRecipe {
             title: "Chicken Alpha".into(),
             image_id: None,
+            images: vec![],
             ingredients: requests::IngredientBlock::new(&[]),
             instructions: requests::InstructionBlock::new(&[]),
             notes: None,
@@ -392,6 +482,7 @@ async fn can_apply_search_and_multi_tag_filter_properly() -> Result<()> {
         .json(&requests::CreateRecipe {
             title: "Butternut Squash".into(),
             image_id: None,
+            images: vec![],
             ingredients: requests::IngredientBlock::new(&[]),
             instructions: requests::InstructionBlock::new(&[]),
             notes: None,
@@ -407,6 +498,7 @@ async fn can_apply_search_and_multi_tag_filter_properly() -> Result<()> {
         .json(&requests::CreateRecipe {
             title: "Chicken Gamma Alpha".into(),
             image_id: None,
+            images: vec![],
             ingredients: requests::IngredientBlock::new(&[]),
             instructions: requests::InstructionBlock::new(&[]),
             notes: None,
@@ -422,6 +514,7 @@ async fn can_apply_search_and_multi_tag_filter_properly() -> Result<()> {
         .json(&requests::CreateRecipe {
             title: "Chicken Beta Alpha".into(),
             image_id: None,
+            images: vec![],
             ingredients: requests::IngredientBlock::new(&[]),
             instructions: requests::InstructionBlock::new(&[]),
             notes: None,
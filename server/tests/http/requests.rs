@@ -4,6 +4,7 @@ use serde::Serialize;
 pub struct CreateRecipe {
     pub title: String,
     pub image_id: Option<String>,
+    pub images: Vec<RecipeImage>,
     pub ingredients: Vec<IngredientBlock>,
     pub instructions: Vec<InstructionBlock>,
     pub notes: Option<String>,
@@ -15,6 +16,7 @@ pub struct UpdateRecipe {
     pub previous_hash: String,
     pub title: String,
     pub image_id: Option<String>,
+    pub images: Vec<RecipeImage>,
     pub ingredients: Vec<IngredientBlock>,
     pub instructions: Vec<InstructionBlock>,
     pub notes: Option<String>,
@@ -26,6 +28,47 @@ pub struct CreateTag {
     pub name: String,
 }
 
+#[derive(Serialize)]
+pub struct CreateHousehold {
+    pub name: String,
+}
+
+#[derive(Serialize)]
+pub struct AddHouseholdMember {
+    pub user_id: String,
+    pub role: Option<String>,
+}
+
+#[derive(Serialize)]
+pub struct SetHouseholdMemberRole {
+    pub role: String,
+}
+
+#[derive(Serialize)]
+pub struct ImportImage {
+    pub url: String,
+}
+
+#[derive(Serialize)]
+pub struct UpdateImageFraming {
+    pub crop: Option<Crop>,
+    pub focal_point: Option<FocalPoint>,
+}
+
+#[derive(Serialize)]
+pub struct Crop {
+    pub x: f64,
+    pub y: f64,
+    pub width: f64,
+    pub height: f64,
+}
+
+#[derive(Serialize)]
+pub struct FocalPoint {
+    pub x: f64,
+    pub y: f64,
+}
+
 #[derive(Serialize)]
 pub struct IngredientBlock {
     pub title: Option<String>,
@@ -47,6 +90,7 @@ impl IngredientBlock {
 pub struct InstructionBlock {
     pub title: Option<String>,
     pub instructions: Vec<String>,
+    pub step_images: Vec<StepImage>,
 }
 
 impl InstructionBlock {
@@ -55,7 +99,51 @@ impl InstructionBlock {
             .map(|(t, i)| Self {
                 title: t.map(|s| s.to_string()),
                 instructions: i.iter().map(|s| s.to_string()).collect(),
+                step_images: vec![],
             })
             .collect()
     }
 }
+
+#[derive(Serialize)]
+pub struct StepImage {
+    pub step: usize,
+    pub image_id: String,
+}
+
+#[derive(Serialize)]
+pub struct RecipeImage {
+    pub image_id: String,
+    pub caption: Option<String>,
+}
+
+#[derive(Serialize)]
+pub struct CreateToken {
+    pub name: String,
+    pub read_only: Option<bool>,
+    pub expires_at: Option<String>,
+}
+
+#[derive(Serialize)]
+pub struct CreateShare {
+    pub expires_at: Option<String>,
+}
+
+#[derive(Serialize)]
+pub struct LocalSignIn {
+    pub username: String,
+    pub password: String,
+}
+
+#[derive(Serialize)]
+pub struct UpdateMe {
+    pub name: Option<String>,
+    pub avatar_image_id: Option<String>,
+    pub preferences: Preferences,
+}
+
+#[derive(Serialize)]
+pub struct Preferences {
+    pub units: Option<String>,
+    pub default_servings: Option<u32>,
+}
@@ -10,6 +10,17 @@ pub type GetRecipe = Data<Recipe>;
 pub type CreateTag = Data<String>;
 pub type GetTags = Data<Vec<Tag>>;
 pub type CreateImage = Data<String>;
+pub type GetImageFraming = Data<ImageFraming>;
+pub type ListSessions = Data<Vec<Session>>;
+pub type CreateHousehold = Data<String>;
+pub type ListHouseholds = Data<Vec<Household>>;
+pub type ListAudit = Data<Vec<AuditEntry>>;
+pub type GetMe = Data<Me>;
+pub type ListProviders = Data<Vec<String>>;
+pub type ListTokens = Data<Vec<Token>>;
+pub type CreateToken = Data<CreatedToken>;
+pub type ListShares = Data<Vec<Share>>;
+pub type CreateShare = Data<CreatedShare>;
 
 #[derive(Debug, Deserialize, PartialEq, Eq)]
 pub struct ListRecipes {
@@ -19,12 +30,41 @@ pub struct ListRecipes {
 
 // models
 
+#[derive(Debug, Deserialize, PartialEq, Eq)]
+pub struct AuditEntry {
+    pub id: String,
+    pub user_id: String,
+    pub household_id: Option<String>,
+    pub action: String,
+    pub entity: String,
+    pub entity_id: String,
+    pub detail: Option<String>,
+}
+
+#[derive(Debug, Deserialize, PartialEq, Eq)]
+pub struct Me {
+    pub id: String,
+    pub name: String,
+    pub avatar_image_id: Option<String>,
+    pub household_id: String,
+    pub role: String,
+    pub households: Vec<Household>,
+    pub preferences: Preferences,
+}
+
+#[derive(Debug, Deserialize, PartialEq, Eq)]
+pub struct Preferences {
+    pub units: Option<String>,
+    pub default_servings: Option<u32>,
+}
+
 #[derive(Debug, Deserialize, PartialEq, Eq)]
 pub struct Recipe {
     pub id: String,
     pub hash: String,
     pub title: String,
     pub image_id: Option<String>,
+    pub images: Vec<RecipeImage>,
     pub ingredient_blocks: Vec<IngredientBlock>,
     pub instruction_blocks: Vec<InstructionBlock>,
     pub notes: Option<String>,
@@ -43,6 +83,19 @@ pub struct InstructionBlock {
     pub title: Option<String>,
     pub instructions: Vec<String>,
     pub rich_instructions: Vec<String>,
+    pub step_images: Vec<StepImage>,
+}
+
+#[derive(Debug, Deserialize, PartialEq, Eq)]
+pub struct StepImage {
+    pub step: usize,
+    pub image_id: String,
+}
+
+#[derive(Debug, Deserialize, PartialEq, Eq)]
+pub struct RecipeImage {
+    pub image_id: String,
+    pub caption: Option<String>,
 }
 
 #[derive(Debug, Deserialize, PartialEq, Eq)]
@@ -63,3 +116,67 @@ pub struct TagOnRecipe {
     pub id: String,
     pub name: String,
 }
+
+#[derive(Debug, Deserialize, PartialEq)]
+pub struct ImageFraming {
+    pub crop: Option<Crop>,
+    pub focal_point: Option<FocalPoint>,
+    pub cropped_image: Option<String>,
+    pub framed_image: Option<String>,
+}
+
+#[derive(Debug, Deserialize, PartialEq)]
+pub struct Crop {
+    pub x: f64,
+    pub y: f64,
+    pub width: f64,
+    pub height: f64,
+}
+
+#[derive(Debug, Deserialize, PartialEq)]
+pub struct FocalPoint {
+    pub x: f64,
+    pub y: f64,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct Session {
+    pub id: String,
+    pub client: Option<String>,
+    pub current: bool,
+}
+
+#[derive(Debug, Deserialize, PartialEq, Eq)]
+pub struct Household {
+    pub id: String,
+    pub name: String,
+    pub role: String,
+    pub active: bool,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct Token {
+    pub id: String,
+    pub name: String,
+    pub read_only: bool,
+    pub expires_at: Option<String>,
+    pub last_used_at: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct CreatedToken {
+    pub id: String,
+    pub token: String,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct Share {
+    pub id: String,
+    pub expires_at: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct CreatedShare {
+    pub id: String,
+    pub token: String,
+}
@@ -1,5 +1,11 @@
 use base64::Engine;
-use mise::{file, image_processing::ImageProcessor, imagestore::ImageStore, oidc, search::Backend};
+use mise::{
+    file,
+    image_processing::ImageProcessor,
+    imagestore::{self, ImageBackend as _, ImageStore},
+    oidc,
+    search::Backend,
+};
 use rand::Rng;
 use std::{net::TcpListener, sync::Arc, time::Duration};
 
@@ -8,7 +14,7 @@ use reqwest::StatusCode;
 
 use crate::http::{requests, responses};
 
-const JPEG: &str = "/9j/4AAQSkZJRgABAQEASABIAAD/2wBDAAMCAgMCAgMDAwMEAwMEBQgFBQQEBQoHBwYIDAoMDAsKCwsNDhIQDQ4RDgsLEBYQERMUFRUVDA8XGBYUGBIUFRT/wAALCAABAAEBAREA/8QAFAABAAAAAAAAAAAAAAAAAAAACf/EABQQAQAAAAAAAAAAAAAAAAAAAAD/2gAIAQEAAD8AKp//2Q==";
+pub const JPEG: &str = "/9j/4AAQSkZJRgABAQEASABIAAD/2wBDAAMCAgMCAgMDAwMEAwMEBQgFBQQEBQoHBwYIDAoMDAsKCwsNDhIQDQ4RDgsLEBYQERMUFRUVDA8XGBYUGBIUFRT/wAALCAABAAEBAREA/8QAFAABAAAAAAAAAAAAAAAAAAAACf/EABQQAQAAAAAAAAAAAAAAAAAAAAD/2gAIAQEAAD8AKp//2Q==";
 
 pub struct OidcServer {
     process: std::process::Child,
@@ -44,6 +50,107 @@ impl OidcServer {
     }
 }
 
+fn open_port() -> Result<u16> {
+    let listener = TcpListener::bind("127.0.0.1:0")?;
+    let port = listener.local_addr()?.port();
+    std::mem::drop(listener);
+
+    Ok(port)
+}
+
+// Stores images in a directory, but sends clients elsewhere to download them, like a backend that
+// presigns urls does.
+struct Presigning(file::ImageBackend);
+
+#[async_trait::async_trait]
+impl imagestore::ImageBackend for Presigning {
+    async fn get(
+        &self,
+        path: &str,
+        range: Option<imagestore::Range>,
+    ) -> Result<imagestore::Object, imagestore::Error> {
+        self.0.get(path, range).await
+    }
+
+    async fn upload(&self, path: &str, file: Vec<u8>) -> Result<(), imagestore::Error> {
+        self.0.upload(path, file).await
+    }
+
+    async fn exists(&self, path: &str) -> Result<bool, imagestore::Error> {
+        self.0.exists(path).await
+    }
+
+    async fn delete(&self, path: &str) -> Result<(), imagestore::Error> {
+        self.0.delete(path).await
+    }
+
+    fn presigned_url(&self, path: &str) -> Result<Option<String>, imagestore::Error> {
+        Ok(Some(format!(
+            "https://images.example.com/{path}?signature=test"
+        )))
+    }
+}
+
+async fn serve(config: mise::config::Config, images_path: String, presigning: bool) -> Result<()> {
+    let http_port = config.http_port;
+
+    let oidc = oidc::Providers::new(oidc::configs(&config).unwrap())
+        .await
+        .unwrap();
+
+    let sv_db_path = config.sqlite.db_path.clone();
+    let sv_cache_path = config.sqlite.session_db_path.clone();
+    let sv_index_path = config.search_index_directory.clone();
+    tokio::task::spawn(async move {
+        let (_, connections) = mise::sqlite::datastore_handler(
+            &sv_db_path,
+            &mise::sqlite::DatastoreConfig {
+                recipe_page_size: 2,
+                recipe_dump_page_size: 10,
+                image_dump_page_size: 10,
+            },
+        )
+        .expect("could not make datastore");
+        let session_store =
+            mise::sqlite::session_store(&sv_cache_path).expect("could not make session store");
+
+        let (background_result_sender, mut receiver) = tokio::sync::mpsc::channel(8);
+        tokio::task::spawn(async move {
+            let _ = receiver.recv().await;
+        });
+
+        let datastore = mise::datastore::Pool::new(connections);
+        let sb = Backend::new(&sv_index_path, datastore.clone()).unwrap();
+        let backend = file::ImageBackend::new(&images_path)
+            .await
+            .expect("could not make image backend");
+        let image_processor = ImageProcessor::new(&config.image_processing)
+            .await
+            .expect("could not init image processor");
+        let cipher = mise::session_store::Cipher::new(&config.session_encryption_keys)
+            .expect("could not make session cipher");
+
+        let server = mise::http::Server::new(
+            config,
+            datastore,
+            mise::session_store::SessionStore::new(session_store, background_result_sender, cipher),
+            oidc,
+            ImageStore::new(if presigning {
+                Box::from(Presigning(backend))
+            } else {
+                Box::from(backend)
+            }),
+            image_processor,
+            sb,
+        );
+        if let Err(err) = server.start().await {
+            println!("Failed to start http server: {:?}", err);
+        }
+    });
+
+    wait_for(format!("http://localhost:{http_port}/health-check")).await
+}
+
 async fn wait_for(url: String) -> Result<()> {
     let increment = 2;
     let mut total = 0;
@@ -73,6 +180,9 @@ pub struct Harness {
     session_db_path: String,
     images_path: String,
     index_path: String,
+    // what the server was started with, so it can be restarted with changes
+    config: mise::config::Config,
+    presigning: bool,
     client: reqwest::Client,
     base_url: String,
     session_id: Option<String>,
@@ -85,16 +195,18 @@ impl Drop for Harness {
         let _ = std::fs::remove_file(&self.session_db_path);
         let _ = std::fs::remove_dir_all(&self.images_path);
         let _ = std::fs::remove_dir_all(&self.index_path);
+        let _ = std::fs::remove_dir_all(&self.config.search_index_directory);
     }
 }
 
 impl Harness {
-    async fn new() -> Result<Self> {
+    async fn new(
+        configure: impl FnOnce(&mut mise::config::Config),
+        presigning: bool,
+    ) -> Result<Self> {
         let oidc_server = OidcServer::new().await?;
 
-        let listener = TcpListener::bind("127.0.0.1:0")?;
-        let http_port = listener.local_addr()?.port();
-        std::mem::drop(listener);
+        let http_port = open_port()?;
 
         let random_prefix: String = rand::rng()
             .sample_iter(&rand::distr::Alphanumeric)
@@ -108,75 +220,57 @@ impl Harness {
 
         std::fs::create_dir(&images_path)?;
 
-        let config = mise::config::Config {
+        let mut config = mise::config::Config {
             http_port,
             origin: format!("http://localhost:{http_port}"),
             insecure_cookies: false,
             static_build_path: "../ui/build".to_string(),
             search_index_directory: index_path.clone(),
-            oidc: mise::config::Oidc {
+            oidc_providers: vec![mise::config::Oidc {
+                name: mise::config::DEFAULT_OIDC_PROVIDER.to_string(),
                 issuer_url: format!("http://[::]:{}", oidc_server.port),
                 client_id: "dev-client".to_string(),
                 client_secret: "secure-secret".to_string(),
-            },
+                access: mise::config::OidcAccess::default(),
+            }],
+            legacy_oidc_provider: None,
+            local_auth: None,
+            proxy_auth: None,
             sqlite: mise::config::Sqlite {
                 db_path: db_path.clone(),
                 session_db_path: session_db_path.clone(),
             },
+            cookie_signing_keys: vec![vec![3; 32]],
+            session_encryption_keys: vec![mise::config::SessionEncryptionKey {
+                id: "test".to_string(),
+                key: vec![7; 32],
+            }],
             image_backend: mise::config::ImageBackend::File(mise::config::ImageBackendFile {
                 directory: images_path.clone(),
             }),
+            image_cache: None,
+            image_processing: mise::config::ImageProcessing {
+                parallelism: 2,
+                queue_size: 4,
+                timeout_seconds: 30,
+                max_pixels: 1_000_000,
+            },
+            image_import: mise::config::ImageImport {
+                max_size_bytes: 1024 * 1024,
+                timeout_seconds: 5,
+                allow_private_addresses: false,
+            },
+            audit_log: mise::config::AuditLog {
+                retention_days: 365,
+            },
+            // tests make requests much faster than anyone would, they enable it when needed
+            rate_limit: None,
+            trusted_proxies: vec![],
         };
 
-        let oidc = oidc::Provider::new((&config).try_into().unwrap())
-            .await
-            .unwrap();
-
-        let sv_db_path = db_path.clone();
-        let sv_cache_path = session_db_path.clone();
-        let sv_images_path = images_path.clone();
-        let sv_index_path = index_path.clone();
-        tokio::task::spawn(async move {
-            let (_, connections) = mise::sqlite::datastore_handler(
-                &sv_db_path,
-                &mise::sqlite::DatastoreConfig {
-                    recipe_page_size: 2,
-                    recipe_dump_page_size: 10,
-                },
-            )
-            .expect("could not make datastore");
-            let session_store =
-                mise::sqlite::session_store(&sv_cache_path).expect("could not make session store");
-
-            let (background_result_sender, mut receiver) = tokio::sync::mpsc::channel(8);
-            tokio::task::spawn(async move {
-                let _ = receiver.recv().await;
-            });
-
-            let datastore = mise::datastore::Pool::new(connections);
-            let sb = Backend::new(&sv_index_path, datastore.clone()).unwrap();
-
-            let server = mise::http::Server::new(
-                config,
-                datastore,
-                mise::session_store::SessionStore::new(session_store, background_result_sender),
-                oidc,
-                ImageStore::new(Box::from(
-                    file::ImageBackend::new(&sv_images_path)
-                        .await
-                        .expect("could not make image backend"),
-                )),
-                ImageProcessor::new()
-                    .await
-                    .expect("could not init image processor"),
-                sb,
-            );
-            if let Err(err) = server.start().await {
-                println!("Failed to start http server: {:?}", err);
-            }
-        });
+        configure(&mut config);
 
-        wait_for(format!("http://localhost:{http_port}/health-check")).await?;
+        serve(config.clone(), images_path.clone(), presigning).await?;
 
         let client = reqwest::ClientBuilder::new().cookie_store(false).build()?;
 
@@ -187,13 +281,62 @@ impl Harness {
             session_db_path,
             images_path,
             index_path,
+            config,
+            presigning,
             client,
             base_url: format!("http://localhost:{http_port}"),
             session_id: None,
         })
     }
 
+    // Starts another server with changed configuration on the same databases and images, and sends
+    // further requests to it, as if the server was restarted.
+    pub async fn restart(
+        &mut self,
+        configure: impl FnOnce(&mut mise::config::Config),
+    ) -> Result<()> {
+        self.http_port = open_port()?;
+        self.base_url = format!("http://localhost:{}", self.http_port);
+        self.config.http_port = self.http_port;
+        self.config.origin.clone_from(&self.base_url);
+        // the index of the first server is still open
+        self.config.search_index_directory = format!("{}-restarted", self.index_path);
+        configure(&mut self.config);
+
+        serve(
+            self.config.clone(),
+            self.images_path.clone(),
+            self.presigning,
+        )
+        .await
+    }
+
     pub async fn authenticate(&mut self, username: &str) -> Result<()> {
+        self.try_authenticate(username).await?;
+        if self.session_id.is_none() {
+            return Err(anyhow!("{username} could not sign in"));
+        }
+
+        Ok(())
+    }
+
+    // Signs in through the provider, returning the response that completed the flow.
+    pub async fn try_authenticate(&mut self, username: &str) -> Result<reqwest::Response> {
+        self.sign_in("/auth/init", username).await
+    }
+
+    // Signs in through the provider with the given name.
+    pub async fn authenticate_with(&mut self, provider: &str, username: &str) -> Result<()> {
+        self.sign_in(&format!("/auth/init?provider={provider}"), username)
+            .await?;
+        if self.session_id.is_none() {
+            return Err(anyhow!("{username} could not sign in with {provider}"));
+        }
+
+        Ok(())
+    }
+
+    async fn sign_in(&mut self, init_path: &str, username: &str) -> Result<reqwest::Response> {
         let jar = reqwest_cookie_store::CookieStoreMutex::new(
             reqwest_cookie_store::CookieStore::new(None),
         );
@@ -203,26 +346,65 @@ impl Harness {
             .build()?;
 
         let r = auth_client
-            .get(format!("http://localhost:{}/auth/init", self.http_port))
+            .get(format!("http://localhost:{}{init_path}", self.http_port))
             .send()
             .await?;
 
         let login_url = format!("{}&username={username}", r.url().as_str());
 
-        auth_client.get(&login_url).send().await?;
+        let response = auth_client.get(&login_url).send().await?;
 
-        let session_id = {
+        self.session_id = {
             let store = jar.lock().unwrap();
             store
                 .get("localhost", "/", "id")
-                .unwrap()
-                .value()
-                .to_string()
+                .map(|cookie| cookie.value().to_string())
         };
 
-        self.session_id = Some(session_id);
+        Ok(response)
+    }
 
-        Ok(())
+    // Signs in with a local password, returning the response.
+    pub async fn sign_in_locally(
+        &mut self,
+        username: &str,
+        password: &str,
+    ) -> Result<reqwest::Response> {
+        let response = self
+            .client
+            .post(format!("{}/auth/local", self.base_url))
+            .json(&requests::LocalSignIn {
+                username: username.into(),
+                password: password.into(),
+            })
+            .send()
+            .await?;
+
+        self.session_id = response
+            .cookies()
+            .find(|cookie| cookie.name() == "id")
+            .map(|cookie| cookie.value().to_string());
+
+        Ok(response)
+    }
+
+    // Drops the session cookie, so requests only carry the credentials added to them.
+    pub fn sign_out(&mut self) {
+        self.session_id = None;
+    }
+
+    // The origin the server is configured with, which pages of the server send requests from.
+    pub fn origin(&self) -> &str {
+        &self.base_url
+    }
+
+    pub fn session_id(&self) -> Option<String> {
+        self.session_id.clone()
+    }
+
+    // Sends requests with the cookie of an earlier session.
+    pub fn resume(&mut self, session_id: Option<String>) {
+        self.session_id = session_id;
     }
 
     pub fn get(&self, path: &str) -> reqwest::RequestBuilder {
@@ -243,6 +425,12 @@ impl Harness {
         self.add_auth(builder)
     }
 
+    pub fn delete(&self, path: &str) -> reqwest::RequestBuilder {
+        let builder = self.client.delete(format!("{}{path}", self.base_url));
+
+        self.add_auth(builder)
+    }
+
     fn add_auth(&self, builder: reqwest::RequestBuilder) -> reqwest::RequestBuilder {
         if let Some(session_id) = &self.session_id {
             builder.header("cookie", format!("id={session_id}"))
@@ -264,15 +452,19 @@ impl Harness {
         Ok(response.json::<responses::CreateTag>().await?.data)
     }
 
+    pub async fn upload_image(&self, image: Vec<u8>) -> Result<reqwest::Response> {
+        let body =
+            reqwest::multipart::Form::new().part("file", reqwest::multipart::Part::bytes(image));
+
+        Ok(self.post("/api/v1/images").multipart(body).send().await?)
+    }
+
     pub async fn create_image(&self) -> Result<String> {
         let base64_engine = base64::engine::general_purpose::STANDARD;
 
-        let body = reqwest::multipart::Form::new().part(
-            "file",
-            reqwest::multipart::Part::bytes(base64_engine.decode(JPEG.as_bytes())?),
-        );
-
-        let response = self.post("/api/v1/images").multipart(body).send().await?;
+        let response = self
+            .upload_image(base64_engine.decode(JPEG.as_bytes())?)
+            .await?;
         assert_eq!(StatusCode::OK, response.status());
 
         let id = response.json::<responses::CreateImage>().await?.data;
@@ -281,11 +473,22 @@ impl Harness {
 }
 
 pub async fn harness() -> Result<Harness> {
-    Harness::new().await
+    Harness::new(|_| {}, false).await
+}
+
+pub async fn with_config(configure: impl FnOnce(&mut mise::config::Config)) -> Result<Harness> {
+    Harness::new(configure, false).await
 }
 
 pub async fn with_auth() -> Result<Harness> {
-    let mut harness = Harness::new().await?;
+    let mut harness = Harness::new(|_| {}, false).await?;
+    harness.authenticate("user").await?;
+    Ok(harness)
+}
+
+// Signed in, with an image backend that sends clients to presigned urls.
+pub async fn with_presigned_urls() -> Result<Harness> {
+    let mut harness = Harness::new(|_| {}, true).await?;
     harness.authenticate("user").await?;
     Ok(harness)
 }
@@ -0,0 +1,153 @@
+use super::{
+    requests, responses,
+    setup::{self, Harness},
+};
+use anyhow::Result;
+use reqwest::StatusCode;
+
+async fn create_recipe(harness: &Harness, image_id: Option<String>) -> Result<String> {
+    let response = harness
+        .post("/api/v1/recipes")
+        .json(&requests::CreateRecipe {
+            title: "Chicken Parm".into(),
+            image_id,
+            images: vec![],
+            ingredients: requests::IngredientBlock::new(&[(None, &["One chicken"])]),
+            instructions: requests::InstructionBlock::new(&[(None, &["Broil the chicken"])]),
+            notes: None,
+            tag_ids: vec![],
+        })
+        .send()
+        .await?;
+    assert_eq!(StatusCode::OK, response.status());
+
+    Ok(response.json::<responses::CreateRecipe>().await?.data)
+}
+
+async fn create_share(harness: &Harness, recipe_id: &str) -> Result<responses::CreatedShare> {
+    let response = harness
+        .post(&format!("/api/v1/recipes/{recipe_id}/shares"))
+        .json(&requests::CreateShare { expires_at: None })
+        .send()
+        .await?;
+    assert_eq!(StatusCode::OK, response.status());
+
+    Ok(response.json::<responses::CreateShare>().await?.data)
+}
+
+#[tokio::test]
+async fn can_open_shared_recipe_without_signing_in() -> Result<()> {
+    let mut harness = setup::harness().await?;
+    harness.authenticate("thomas").await?;
+    let image_id = harness.create_image().await?;
+    let recipe_id = create_recipe(&harness, Some(image_id.clone())).await?;
+    let share = create_share(&harness, &recipe_id).await?;
+
+    let response = harness
+        .get(&format!("/api/v1/recipes/{recipe_id}/shares"))
+        .send()
+        .await?;
+    assert_eq!(StatusCode::OK, response.status());
+    let shares = response.json::<responses::ListShares>().await?.data;
+    assert_eq!(1, shares.len());
+    assert_eq!(share.id, shares[0].id);
+    assert!(shares[0].expires_at.is_none());
+
+    harness.sign_out();
+
+    let response = harness
+        .get(&format!("/api/v1/shared/{}", share.token))
+        .send()
+        .await?;
+    assert_eq!(StatusCode::OK, response.status());
+    let recipe = response.json::<responses::GetRecipe>().await?.data;
+    assert_eq!(recipe_id, recipe.id);
+    assert_eq!("Chicken Parm", recipe.title);
+
+    let response = harness
+        .get(&format!("/api/v1/shared/{}/images/{image_id}", share.token))
+        .send()
+        .await?;
+    assert_eq!(StatusCode::OK, response.status());
+    assert_eq!("image/jpeg", response.headers()["content-type"]);
+
+    // the link does not sign anyone in
+    let response = harness
+        .get(&format!("/api/v1/recipes/{recipe_id}"))
+        .send()
+        .await?;
+    assert_eq!(StatusCode::UNAUTHORIZED, response.status());
+
+    let response = harness.get("/api/v1/shared/unknown").send().await?;
+    assert_eq!(StatusCode::NOT_FOUND, response.status());
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn share_only_exposes_images_of_the_recipe() -> Result<()> {
+    let mut harness = setup::harness().await?;
+    harness.authenticate("thomas").await?;
+    let image_id = harness.create_image().await?;
+    let recipe_id = create_recipe(&harness, None).await?;
+    let share = create_share(&harness, &recipe_id).await?;
+
+    harness.sign_out();
+
+    let response = harness
+        .get(&format!("/api/v1/shared/{}/images/{image_id}", share.token))
+        .send()
+        .await?;
+    assert_eq!(StatusCode::NOT_FOUND, response.status());
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn revoked_shares_cannot_be_opened() -> Result<()> {
+    let mut harness = setup::harness().await?;
+    harness.authenticate("thomas").await?;
+    let recipe_id = create_recipe(&harness, None).await?;
+    let share = create_share(&harness, &recipe_id).await?;
+
+    let response = harness
+        .delete(&format!("/api/v1/recipes/{recipe_id}/shares/{}", share.id))
+        .send()
+        .await?;
+    assert_eq!(StatusCode::NO_CONTENT, response.status());
+
+    let response = harness
+        .get(&format!("/api/v1/shared/{}", share.token))
+        .send()
+        .await?;
+    assert_eq!(StatusCode::NOT_FOUND, response.status());
+
+    // nor can a share expire right away
+    let response = harness
+        .post(&format!("/api/v1/recipes/{recipe_id}/shares"))
+        .json(&requests::CreateShare {
+            expires_at: Some("2020-01-01T00:00:00Z".into()),
+        })
+        .send()
+        .await?;
+    assert_eq!(StatusCode::UNPROCESSABLE_ENTITY, response.status());
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn cannot_share_recipe_of_other_household() -> Result<()> {
+    let mut harness = setup::harness().await?;
+    harness.authenticate("thomas").await?;
+    let recipe_id = create_recipe(&harness, None).await?;
+
+    harness.authenticate("barley").await?;
+    let response = harness
+        .post(&format!("/api/v1/recipes/{recipe_id}/shares"))
+        .json(&requests::CreateShare { expires_at: None })
+        .send()
+        .await?;
+    assert_eq!(StatusCode::NOT_FOUND, response.status());
+
+    Ok(())
+}
@@ -0,0 +1,193 @@
+use super::{
+    requests, responses,
+    setup::{self, Harness},
+};
+use anyhow::Result;
+use reqwest::StatusCode;
+
+async fn create_token(
+    harness: &Harness,
+    read_only: Option<bool>,
+) -> Result<responses::CreatedToken> {
+    let response = harness
+        .post("/api/v1/auth/tokens")
+        .json(&requests::CreateToken {
+            name: "Home Assistant".into(),
+            read_only,
+            expires_at: None,
+        })
+        .send()
+        .await?;
+    assert_eq!(StatusCode::OK, response.status());
+
+    Ok(response.json::<responses::CreateToken>().await?.data)
+}
+
+#[tokio::test]
+async fn can_use_token_instead_of_session() -> Result<()> {
+    let mut harness = setup::harness().await?;
+    harness.authenticate("thomas").await?;
+    let created = create_token(&harness, None).await?;
+
+    harness.sign_out();
+
+    let response = harness.get("/api/v1/tags").send().await?;
+    assert_eq!(StatusCode::UNAUTHORIZED, response.status());
+
+    let response = harness
+        .get("/api/v1/tags")
+        .bearer_auth(&created.token)
+        .send()
+        .await?;
+    assert_eq!(StatusCode::OK, response.status());
+
+    let response = harness
+        .post("/api/v1/tags")
+        .bearer_auth(&created.token)
+        .json(&requests::CreateTag {
+            name: "Main Dish".into(),
+        })
+        .send()
+        .await?;
+    assert_eq!(StatusCode::OK, response.status());
+
+    // the use is recorded
+    let response = harness
+        .get("/api/v1/auth/tokens")
+        .bearer_auth(&created.token)
+        .send()
+        .await?;
+    assert_eq!(StatusCode::OK, response.status());
+    let tokens = response.json::<responses::ListTokens>().await?.data;
+    assert_eq!(1, tokens.len());
+    assert_eq!(created.id, tokens[0].id);
+    assert_eq!("Home Assistant", tokens[0].name);
+    assert!(!tokens[0].read_only);
+    assert!(tokens[0].expires_at.is_none());
+    assert!(tokens[0].last_used_at.is_some());
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn read_only_tokens_cannot_change_anything() -> Result<()> {
+    let mut harness = setup::harness().await?;
+    harness.authenticate("thomas").await?;
+    let created = create_token(&harness, Some(true)).await?;
+
+    harness.sign_out();
+
+    let response = harness
+        .get("/api/v1/tags")
+        .bearer_auth(&created.token)
+        .send()
+        .await?;
+    assert_eq!(StatusCode::OK, response.status());
+
+    let response = harness
+        .post("/api/v1/tags")
+        .bearer_auth(&created.token)
+        .json(&requests::CreateTag {
+            name: "Main Dish".into(),
+        })
+        .send()
+        .await?;
+    assert_eq!(StatusCode::FORBIDDEN, response.status());
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn revoked_tokens_are_rejected() -> Result<()> {
+    let mut harness = setup::harness().await?;
+    harness.authenticate("thomas").await?;
+    let created = create_token(&harness, None).await?;
+
+    let response = harness
+        .delete(&format!("/api/v1/auth/tokens/{}", created.id))
+        .send()
+        .await?;
+    assert_eq!(StatusCode::NO_CONTENT, response.status());
+
+    harness.sign_out();
+    let response = harness
+        .get("/api/v1/tags")
+        .bearer_auth(&created.token)
+        .send()
+        .await?;
+    assert_eq!(StatusCode::UNAUTHORIZED, response.status());
+
+    let response = harness
+        .get("/api/v1/tags")
+        .bearer_auth("mise_unknown")
+        .send()
+        .await?;
+    assert_eq!(StatusCode::UNAUTHORIZED, response.status());
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn cannot_revoke_tokens_of_other_users() -> Result<()> {
+    let mut harness = setup::harness().await?;
+    harness.authenticate("thomas").await?;
+    let created = create_token(&harness, None).await?;
+
+    harness.authenticate("barley").await?;
+    let response = harness
+        .delete(&format!("/api/v1/auth/tokens/{}", created.id))
+        .send()
+        .await?;
+    assert_eq!(StatusCode::NOT_FOUND, response.status());
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn cannot_create_expired_token() -> Result<()> {
+    let mut harness = setup::harness().await?;
+    harness.authenticate("thomas").await?;
+
+    let response = harness
+        .post("/api/v1/auth/tokens")
+        .json(&requests::CreateToken {
+            name: "Home Assistant".into(),
+            read_only: None,
+            expires_at: Some("2020-01-01T00:00:00Z".into()),
+        })
+        .send()
+        .await?;
+    assert_eq!(StatusCode::UNPROCESSABLE_ENTITY, response.status());
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn tokens_cannot_manage_tokens() -> Result<()> {
+    let mut harness = setup::harness().await?;
+    harness.authenticate("thomas").await?;
+    let created = create_token(&harness, None).await?;
+
+    harness.sign_out();
+
+    let response = harness
+        .post("/api/v1/auth/tokens")
+        .bearer_auth(&created.token)
+        .json(&requests::CreateToken {
+            name: "Forever".into(),
+            read_only: None,
+            expires_at: None,
+        })
+        .send()
+        .await?;
+    assert_eq!(StatusCode::FORBIDDEN, response.status());
+
+    let response = harness
+        .delete(&format!("/api/v1/auth/tokens/{}", created.id))
+        .bearer_auth(&created.token)
+        .send()
+        .await?;
+    assert_eq!(StatusCode::FORBIDDEN, response.status());
+
+    Ok(())
+}
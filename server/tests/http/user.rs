@@ -0,0 +1,125 @@
+use super::{
+    requests, responses,
+    setup::{self, Harness},
+};
+use anyhow::Result;
+use reqwest::StatusCode;
+
+async fn me(harness: &Harness) -> Result<responses::Me> {
+    let response = harness.get("/api/v1/auth/me").send().await?;
+    assert_eq!(StatusCode::OK, response.status());
+
+    Ok(response.json::<responses::GetMe>().await?.data)
+}
+
+#[tokio::test]
+async fn can_get_profile() -> Result<()> {
+    let harness = setup::with_auth().await?;
+
+    let me = me(&harness).await?;
+
+    assert_eq!("admin", me.role);
+    assert_eq!(None, me.avatar_image_id);
+    assert_eq!(1, me.households.len());
+    assert_eq!(me.household_id, me.households[0].id);
+    assert!(me.households[0].active);
+    assert_eq!(
+        responses::Preferences {
+            units: None,
+            default_servings: None,
+        },
+        me.preferences
+    );
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn can_update_profile() -> Result<()> {
+    let harness = setup::with_auth().await?;
+    let provider_name = me(&harness).await?.name;
+    let image_id = harness.create_image().await?;
+
+    let response = harness
+        .put("/api/v1/auth/me")
+        .json(&requests::UpdateMe {
+            name: Some(" Tom ".into()),
+            avatar_image_id: Some(image_id.clone()),
+            preferences: requests::Preferences {
+                units: Some("metric".into()),
+                default_servings: Some(2),
+            },
+        })
+        .send()
+        .await?;
+    assert_eq!(StatusCode::OK, response.status());
+    let updated = response.json::<responses::GetMe>().await?.data;
+
+    assert_eq!("Tom", updated.name);
+    assert_eq!(Some(image_id), updated.avatar_image_id);
+    assert_eq!(
+        responses::Preferences {
+            units: Some("metric".into()),
+            default_servings: Some(2),
+        },
+        updated.preferences
+    );
+    assert_eq!(updated, me(&harness).await?);
+
+    let response = harness
+        .put("/api/v1/auth/me")
+        .json(&requests::UpdateMe {
+            name: None,
+            avatar_image_id: None,
+            preferences: requests::Preferences {
+                units: None,
+                default_servings: None,
+            },
+        })
+        .send()
+        .await?;
+    assert_eq!(StatusCode::OK, response.status());
+
+    assert_eq!(provider_name, me(&harness).await?.name);
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn cannot_update_profile_with_invalid_values() -> Result<()> {
+    let harness = setup::with_auth().await?;
+
+    let invalid = [
+        requests::UpdateMe {
+            name: Some("  ".into()),
+            avatar_image_id: None,
+            preferences: requests::Preferences {
+                units: None,
+                default_servings: None,
+            },
+        },
+        requests::UpdateMe {
+            name: None,
+            avatar_image_id: Some("01J0000000000000000000000A".into()),
+            preferences: requests::Preferences {
+                units: None,
+                default_servings: None,
+            },
+        },
+        requests::UpdateMe {
+            name: None,
+            avatar_image_id: None,
+            preferences: requests::Preferences {
+                units: None,
+                default_servings: Some(0),
+            },
+        },
+    ];
+
+    for request in invalid {
+        let response = harness.put("/api/v1/auth/me").json(&request).send().await?;
+        assert_eq!(StatusCode::UNPROCESSABLE_ENTITY, response.status());
+    }
+
+    Ok(())
+}
@@ -0,0 +1,270 @@
+use anyhow::Result;
+use futures::StreamExt;
+use mise::{
+    config, file, image_cache,
+    imagestore::{self, ImageStore},
+};
+
+use super::common::Directory;
+
+struct Cache {
+    cache_dir: Directory,
+    backend_dir: Directory,
+    store: ImageStore,
+}
+
+async fn cache(max_size_bytes: u64) -> Result<Cache> {
+    let cache_dir = Directory::new();
+    let backend_dir = Directory::new();
+
+    let store = ImageStore::new(Box::from(
+        image_cache::ImageBackend::new(
+            Box::from(file::ImageBackend::new(&backend_dir.path).await?),
+            &config::ImageCache {
+                directory: cache_dir.path.clone(),
+                max_size_bytes,
+            },
+        )
+        .await?,
+    ));
+
+    Ok(Cache {
+        cache_dir,
+        backend_dir,
+        store,
+    })
+}
+
+impl Cache {
+    // removes an image from the backend, so it can only be served from the cache
+    fn remove_from_backend(&self, path: &str) -> Result<()> {
+        std::fs::remove_file(format!("{}/{path}", self.backend_dir.path))?;
+        Ok(())
+    }
+}
+
+async fn read(store: &ImageStore, path: &str) -> Result<Vec<u8>, imagestore::Error> {
+    store.get(path, None).await?.into_bytes().await
+}
+
+#[tokio::test]
+async fn serves_image_from_cache() -> Result<()> {
+    let cache = cache(1024).await?;
+    cache.store.upload("a.jpg", b"image a".to_vec()).await?;
+
+    assert_eq!(b"image a".to_vec(), read(&cache.store, "a.jpg").await?);
+
+    cache.remove_from_backend("a.jpg")?;
+
+    assert_eq!(b"image a".to_vec(), read(&cache.store, "a.jpg").await?);
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn can_get_range_of_image() -> Result<()> {
+    let cache = cache(1024).await?;
+    cache.store.upload("a.jpg", b"image a".to_vec()).await?;
+
+    // a range of an image that is not cached is read from the backend, without caching the image
+    let object = cache
+        .store
+        .get(
+            "a.jpg",
+            Some(imagestore::Range::Bounded { start: 1, end: 3 }),
+        )
+        .await?;
+    assert_eq!(7, object.size);
+    assert_eq!(b"mag".to_vec(), object.into_bytes().await?);
+    assert_eq!(0, std::fs::read_dir(&cache.cache_dir.path)?.count());
+
+    // once read whole, ranges are served from the cache
+    read(&cache.store, "a.jpg").await?;
+    cache.remove_from_backend("a.jpg")?;
+
+    let object = cache
+        .store
+        .get("a.jpg", Some(imagestore::Range::Suffix { length: 1 }))
+        .await?;
+    assert_eq!(7, object.size);
+    assert_eq!(b"a".to_vec(), object.into_bytes().await?);
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn evicts_least_recently_used_image() -> Result<()> {
+    let cache = cache(20).await?;
+    cache.store.upload("a.jpg", b"image a".to_vec()).await?;
+    cache.store.upload("b.jpg", b"image b".to_vec()).await?;
+    cache.store.upload("c.jpg", b"image c".to_vec()).await?;
+
+    read(&cache.store, "a.jpg").await?;
+    read(&cache.store, "b.jpg").await?;
+    read(&cache.store, "a.jpg").await?;
+    // pushes the cache over its limit, b is the least recently used
+    read(&cache.store, "c.jpg").await?;
+
+    cache.remove_from_backend("a.jpg")?;
+    cache.remove_from_backend("b.jpg")?;
+    cache.remove_from_backend("c.jpg")?;
+
+    assert_eq!(b"image a".to_vec(), read(&cache.store, "a.jpg").await?);
+    assert_eq!(b"image c".to_vec(), read(&cache.store, "c.jpg").await?);
+    let result = read(&cache.store, "b.jpg").await;
+    if !matches!(result, Err(imagestore::Error::NotFound(_))) {
+        panic!("expected b.jpg to be evicted.");
+    }
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn does_not_cache_images_larger_than_cache() -> Result<()> {
+    let cache = cache(4).await?;
+    cache.store.upload("a.jpg", b"image a".to_vec()).await?;
+
+    assert_eq!(b"image a".to_vec(), read(&cache.store, "a.jpg").await?);
+
+    cache.remove_from_backend("a.jpg")?;
+
+    let result = read(&cache.store, "a.jpg").await;
+    if !matches!(result, Err(imagestore::Error::NotFound(_))) {
+        panic!("expected a.jpg to not be cached.");
+    }
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn keeps_cached_images_across_restarts() -> Result<()> {
+    let cache_dir = Directory::new();
+    let backend_dir = Directory::new();
+    let config = config::ImageCache {
+        directory: cache_dir.path.clone(),
+        max_size_bytes: 1024,
+    };
+
+    let store = ImageStore::new(Box::from(
+        image_cache::ImageBackend::new(
+            Box::from(file::ImageBackend::new(&backend_dir.path).await?),
+            &config,
+        )
+        .await?,
+    ));
+    store.upload("a.jpg", b"image a".to_vec()).await?;
+    read(&store, "a.jpg").await?;
+    std::fs::remove_file(format!("{}/a.jpg", backend_dir.path))?;
+
+    let store = ImageStore::new(Box::from(
+        image_cache::ImageBackend::new(
+            Box::from(file::ImageBackend::new(&backend_dir.path).await?),
+            &config,
+        )
+        .await?,
+    ));
+
+    assert_eq!(b"image a".to_vec(), read(&store, "a.jpg").await?);
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn can_check_image_exists() -> Result<()> {
+    let cache = cache(1024).await?;
+    cache.store.upload("a.jpg", b"image a".to_vec()).await?;
+
+    // before it was ever read, the backend is checked
+    assert!(cache.store.exists("a.jpg").await?);
+    assert!(!cache.store.exists("b.jpg").await?);
+
+    read(&cache.store, "a.jpg").await?;
+    cache.remove_from_backend("a.jpg")?;
+
+    assert!(cache.store.exists("a.jpg").await?);
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn can_delete_cached_image() -> Result<()> {
+    let cache = cache(1024).await?;
+    cache.store.upload("a.jpg", b"image a".to_vec()).await?;
+    read(&cache.store, "a.jpg").await?;
+
+    cache.store.delete("a.jpg").await?;
+
+    assert!(!cache.store.exists("a.jpg").await?);
+    assert!(matches!(
+        read(&cache.store, "a.jpg").await,
+        Err(imagestore::Error::NotFound(_))
+    ));
+
+    // deleting an image that is not stored succeeds
+    cache.store.delete("b.jpg").await?;
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn does_not_cache_partially_read_image() -> Result<()> {
+    let cache = cache(1024 * 1024).await?;
+    cache.store.upload("a.jpg", vec![1; 64 * 1024]).await?;
+
+    let mut object = cache.store.get("a.jpg", None).await?;
+    assert!(object.stream.next().await.is_some());
+    drop(object);
+
+    cache.remove_from_backend("a.jpg")?;
+
+    let result = read(&cache.store, "a.jpg").await;
+    if !matches!(result, Err(imagestore::Error::NotFound(_))) {
+        panic!("expected a.jpg to not be cached.");
+    }
+
+    // the partially written file is removed in the background
+    for _ in 0..100 {
+        if std::fs::read_dir(&cache.cache_dir.path)?.count() == 0 {
+            break;
+        }
+        tokio::time::sleep(std::time::Duration::from_millis(10)).await;
+    }
+    assert_eq!(0, std::fs::read_dir(&cache.cache_dir.path)?.count());
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn only_removes_files_it_cached() -> Result<()> {
+    let cache_dir = Directory::new();
+    let backend_dir = Directory::new();
+    std::fs::create_dir(&cache_dir.path)?;
+    std::fs::write(format!("{}/other.jpg", cache_dir.path), b"not cached")?;
+    std::fs::write(format!("{}/other.tmp", cache_dir.path), b"not cached")?;
+
+    let store = ImageStore::new(Box::from(
+        image_cache::ImageBackend::new(
+            Box::from(file::ImageBackend::new(&backend_dir.path).await?),
+            &config::ImageCache {
+                directory: cache_dir.path.clone(),
+                max_size_bytes: 8,
+            },
+        )
+        .await?,
+    ));
+    store.upload("a.jpg", b"image a".to_vec()).await?;
+    store.upload("b.jpg", b"image b".to_vec()).await?;
+    read(&store, "a.jpg").await?;
+    read(&store, "b.jpg").await?;
+
+    assert_eq!(
+        b"not cached".to_vec(),
+        std::fs::read(format!("{}/other.jpg", cache_dir.path))?
+    );
+    assert_eq!(
+        b"not cached".to_vec(),
+        std::fs::read(format!("{}/other.tmp", cache_dir.path))?
+    );
+
+    Ok(())
+}
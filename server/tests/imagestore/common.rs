@@ -0,0 +1,26 @@
+use rand::Rng;
+
+// A temporary directory that is removed when dropped.
+pub struct Directory {
+    pub path: String,
+}
+
+impl Directory {
+    pub fn new() -> Self {
+        let name: String = rand::rng()
+            .sample_iter(&rand::distr::Alphanumeric)
+            .take(8)
+            .map(char::from)
+            .collect();
+
+        Directory {
+            path: format!("/tmp/{name}-mise-images"),
+        }
+    }
+}
+
+impl Drop for Directory {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_dir_all(&self.path);
+    }
+}
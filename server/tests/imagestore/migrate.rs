@@ -0,0 +1,156 @@
+use anyhow::Result;
+use mise::{
+    core::{self, image::MigrateOutcome},
+    datastore, domain, file,
+    imagestore::ImageStore,
+};
+
+use super::common::Directory;
+use crate::datastore::{
+    common::{CreatesDatastore, HoldsDatastore},
+    sqlite::SqliteCreator,
+};
+
+async fn store() -> Result<(Directory, ImageStore)> {
+    let directory = Directory::new();
+    let backend = file::ImageBackend::new(&directory.path).await?;
+
+    Ok((directory, ImageStore::new(Box::from(backend))))
+}
+
+async fn household(datastore: &datastore::Pool) -> Result<domain::household::Id> {
+    let user = datastore
+        .upsert_user_by_identity(domain::RegisteringUser {
+            potential_id: domain::user::Id::new().into(),
+            issuer: "https://issuer.example.com".into(),
+            subject: "user-1".into(),
+            name: "user".into(),
+            max_role: None,
+        })
+        .await?;
+
+    Ok(datastore.get_active_membership(user.id).await?.household_id)
+}
+
+async fn image(datastore: &datastore::Pool, store: &ImageStore, content: &str) -> Result<String> {
+    let id = domain::image::Id::new();
+    datastore
+        .create_image(&household(datastore).await?, &id, &sha256::digest(content))
+        .await?;
+    store
+        .upload(&format!("{id}-original.jpg"), content.as_bytes().to_vec())
+        .await?;
+
+    Ok(id.into())
+}
+
+async fn migrate(
+    datastore: &datastore::Pool,
+    from: &ImageStore,
+    to: &ImageStore,
+) -> Result<core::image::MigrateSummary> {
+    Ok(core::image::migrate(datastore, from, to, |_| {}).await?)
+}
+
+#[tokio::test]
+async fn copies_all_images() -> Result<()> {
+    let holder = SqliteCreator {}.new();
+    let datastore = holder.get();
+    let (_from_dir, from) = store().await?;
+    let (_to_dir, to) = store().await?;
+
+    let mut ids = vec![];
+    for i in 0..3 {
+        ids.push(image(&datastore, &from, &format!("image {i}")).await?);
+    }
+
+    let mut progress = vec![];
+    let summary = core::image::migrate(&datastore, &from, &to, |p| {
+        progress.push((
+            p.position,
+            p.total,
+            matches!(p.outcome, MigrateOutcome::Copied { bytes: 7 }),
+        ));
+    })
+    .await?;
+
+    assert_eq!(3, summary.copied);
+    assert_eq!(0, summary.already_copied);
+    assert!(summary.missing_from_source.is_empty());
+    assert_eq!(vec![(1, 3, true), (2, 3, true), (3, 3, true)], progress);
+
+    for (i, id) in ids.iter().enumerate() {
+        let copied = to
+            .get(&format!("{id}-original.jpg"), None)
+            .await?
+            .into_bytes()
+            .await?;
+        assert_eq!(format!("image {i}").into_bytes(), copied);
+    }
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn can_resume_migration() -> Result<()> {
+    let holder = SqliteCreator {}.new();
+    let datastore = holder.get();
+    let (_from_dir, from) = store().await?;
+    let (to_dir, to) = store().await?;
+
+    let id = image(&datastore, &from, "image 1").await?;
+    image(&datastore, &from, "image 2").await?;
+    image(&datastore, &from, "image 3").await?;
+
+    migrate(&datastore, &from, &to).await?;
+
+    // lose one of the copied images
+    std::fs::remove_file(format!("{}/{id}-original.jpg", to_dir.path))?;
+
+    let summary = migrate(&datastore, &from, &to).await?;
+
+    assert_eq!(1, summary.copied);
+    assert_eq!(2, summary.already_copied);
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn fails_if_destination_has_different_content() -> Result<()> {
+    let holder = SqliteCreator {}.new();
+    let datastore = holder.get();
+    let (_from_dir, from) = store().await?;
+    let (_to_dir, to) = store().await?;
+
+    let id = image(&datastore, &from, "image 1").await?;
+    to.upload(&format!("{id}-original.jpg"), b"something else".to_vec())
+        .await?;
+
+    let result = migrate(&datastore, &from, &to).await;
+    if let Ok(_) = result {
+        panic!("result is Ok, expected error.");
+    }
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn reports_images_missing_from_source() -> Result<()> {
+    let holder = SqliteCreator {}.new();
+    let datastore = holder.get();
+    let (_from_dir, from) = store().await?;
+    let (_to_dir, to) = store().await?;
+
+    image(&datastore, &from, "image 1").await?;
+    let missing_id = domain::image::Id::new();
+    datastore
+        .create_image(&household(&datastore).await?, &missing_id, "hash")
+        .await?;
+
+    let summary = migrate(&datastore, &from, &to).await?;
+
+    assert_eq!(1, summary.copied);
+    assert_eq!(vec![missing_id], summary.missing_from_source);
+
+    Ok(())
+}
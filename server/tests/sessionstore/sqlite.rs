@@ -1,6 +1,9 @@
 use anyhow::Result;
 use chrono::{TimeDelta, Utc};
-use mise::{domain, session_store::BackgroundResultMessage};
+use mise::{
+    config, domain,
+    session_store::{BackgroundResultMessage, Cipher},
+};
 use rand::Rng;
 use rusqlite::{Connection, params};
 use tokio::sync::{mpsc, oneshot};
@@ -29,6 +32,36 @@ fn new() -> (TestPool, mpsc::Sender<mise::session_store::Message>) {
     (TestPool { path: file_path }, sender)
 }
 
+fn cipher(key_ids: &[&str]) -> Cipher {
+    let keys: Vec<config::SessionEncryptionKey> = key_ids
+        .iter()
+        .map(|id| config::SessionEncryptionKey {
+            id: id.to_string(),
+            key: sha256::digest(*id).as_bytes()[..32].to_vec(),
+        })
+        .collect();
+
+    Cipher::new(&keys).unwrap()
+}
+
+fn new_session(key: &str, user_id: &str) -> domain::Session {
+    let now = Utc::now();
+    domain::Session {
+        key: key.into(),
+        id: format!("id-{key}"),
+        user_id: user_id.into(),
+        provider: "default".into(),
+        refresh_token: "token".into(),
+        revalidate_at: now.checked_add_signed(TimeDelta::seconds(20)).unwrap(),
+        expires_at: now.checked_add_signed(TimeDelta::seconds(20)).unwrap(),
+        created_at: now,
+        last_used_at: now,
+        user_agent: Some("Firefox".into()),
+        client: None,
+        household_id: None,
+    }
+}
+
 #[tokio::test]
 async fn prunes_expired_sessions() -> Result<()> {
     let (pool, sender) = new();
@@ -46,11 +79,12 @@ async fn prunes_expired_sessions() -> Result<()> {
         expires_at: Utc::now()
             .checked_add_signed(TimeDelta::seconds(20))
             .unwrap(),
+        ..new_session("1", "1")
     };
 
     let _ = sender
         .send(mise::session_store::Message::Set {
-            session,
+            record: cipher(&["1"]).seal(&session)?,
             respond_to: tx,
         })
         .await;
@@ -67,11 +101,12 @@ async fn prunes_expired_sessions() -> Result<()> {
         expires_at: Utc::now()
             .checked_add_signed(TimeDelta::seconds(-20))
             .unwrap(),
+        ..new_session("2", "1")
     };
 
     let _ = sender
         .send(mise::session_store::Message::Set {
-            session,
+            record: cipher(&["1"]).seal(&session)?,
             respond_to: tx,
         })
         .await;
@@ -86,7 +121,8 @@ async fn prunes_expired_sessions() -> Result<()> {
 
     // startup session store
     let (background_result_sender, mut receiver) = tokio::sync::mpsc::channel(8);
-    let store = mise::session_store::SessionStore::new(sender, background_result_sender);
+    let store =
+        mise::session_store::SessionStore::new(sender, background_result_sender, cipher(&["1"]));
 
     // wait for session prune
     while let Some(msg) = receiver.recv().await {
@@ -130,7 +166,8 @@ async fn prunes_refresh_locks() -> Result<()> {
 
     // startup session store
     let (background_result_sender, mut receiver) = tokio::sync::mpsc::channel(8);
-    let _ = mise::session_store::SessionStore::new(sender, background_result_sender);
+    let _ =
+        mise::session_store::SessionStore::new(sender, background_result_sender, cipher(&["1"]));
 
     // wait for session prune
     while let Some(msg) = receiver.recv().await {
@@ -151,3 +188,220 @@ async fn prunes_refresh_locks() -> Result<()> {
 
     Ok(())
 }
+
+#[tokio::test]
+async fn can_list_and_revoke_sessions_of_user() -> Result<()> {
+    let (_pool, sender) = new();
+    let (background_result_sender, _receiver) = tokio::sync::mpsc::channel(8);
+    let store =
+        mise::session_store::SessionStore::new(sender, background_result_sender, cipher(&["1"]));
+
+    store.set(new_session("1", "alice")).await?;
+    store.set(new_session("2", "alice")).await?;
+    store.set(new_session("3", "alice")).await?;
+    store.set(new_session("4", "bob")).await?;
+    // a refreshed session keeps its id under a new key
+    store
+        .set(domain::Session {
+            key: "1-refreshed".into(),
+            ..new_session("1", "alice")
+        })
+        .await?;
+
+    let mut ids: Vec<String> = store
+        .list_for_user("alice".into())
+        .await?
+        .into_iter()
+        .map(|session| session.id)
+        .collect();
+    ids.sort();
+    assert_eq!(vec!["id-1", "id-2", "id-3"], ids);
+
+    // cannot revoke another user's session
+    assert!(
+        store
+            .delete_for_user("alice".into(), "id-4".into())
+            .await
+            .is_err()
+    );
+
+    // revoking removes every key of the session
+    store.delete_for_user("alice".into(), "id-1".into()).await?;
+    assert!(store.get(domain::SessionKey("1".into())).await.is_err());
+    assert!(
+        store
+            .get(domain::SessionKey("1-refreshed".into()))
+            .await
+            .is_err()
+    );
+
+    store
+        .delete_others_for_user("alice".into(), "id-2".into())
+        .await?;
+    let ids: Vec<String> = store
+        .list_for_user("alice".into())
+        .await?
+        .into_iter()
+        .map(|session| session.id)
+        .collect();
+    assert_eq!(vec!["id-2"], ids);
+
+    // other users are unaffected
+    assert_eq!(1, store.list_for_user("bob".into()).await?.len());
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn stores_only_sealed_sessions_and_hashed_keys() -> Result<()> {
+    let (pool, sender) = new();
+    let (background_result_sender, _receiver) = tokio::sync::mpsc::channel(8);
+    let store =
+        mise::session_store::SessionStore::new(sender, background_result_sender, cipher(&["1"]));
+
+    store
+        .set(domain::Session {
+            refresh_token: "secret-refresh-token".into(),
+            ..new_session("secret-key", "alice")
+        })
+        .await?;
+
+    let conn = Connection::open(&pool.path)?;
+    let (key_hash, sealed): (String, Vec<u8>) =
+        conn.query_row("SELECT key_hash, sealed FROM sessions", [], |row| {
+            Ok((row.get(0)?, row.get(1)?))
+        })?;
+    assert_ne!("secret-key", key_hash);
+    assert!(
+        !sealed
+            .windows("secret-refresh-token".len())
+            .any(|window| window == b"secret-refresh-token")
+    );
+
+    let session = store.get(domain::SessionKey("secret-key".into())).await?;
+    assert_eq!("secret-refresh-token", session.refresh_token);
+    assert_eq!(Some("Firefox".into()), session.user_agent);
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn can_open_sessions_sealed_with_previous_key() -> Result<()> {
+    let (_pool, sender) = new();
+
+    let (background_result_sender, _receiver) = tokio::sync::mpsc::channel(8);
+    let old_store = mise::session_store::SessionStore::new(
+        sender.clone(),
+        background_result_sender,
+        cipher(&["old"]),
+    );
+    old_store.set(new_session("1", "alice")).await?;
+
+    // the new key is added in front of the old one
+    let (background_result_sender, _receiver) = tokio::sync::mpsc::channel(8);
+    let rotated_store = mise::session_store::SessionStore::new(
+        sender.clone(),
+        background_result_sender,
+        cipher(&["new", "old"]),
+    );
+    let session = rotated_store.get(domain::SessionKey("1".into())).await?;
+    assert_eq!("token", session.refresh_token);
+
+    // sessions sealed with a removed key can no longer be used
+    let (background_result_sender, _receiver) = tokio::sync::mpsc::channel(8);
+    let new_store =
+        mise::session_store::SessionStore::new(sender, background_result_sender, cipher(&["new"]));
+    assert!(matches!(
+        new_store.get(domain::SessionKey("1".into())).await,
+        Err(mise::session_store::Error::NotFound(_))
+    ));
+
+    // sealing it again moves it to the new key
+    rotated_store.set(session).await?;
+    let session = new_store.get(domain::SessionKey("1".into())).await?;
+    assert_eq!("token", session.refresh_token);
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn lists_sessions_without_those_sealed_with_removed_key() -> Result<()> {
+    let (pool, sender) = new();
+
+    let (background_result_sender, _receiver) = tokio::sync::mpsc::channel(8);
+    let old_store = mise::session_store::SessionStore::new(
+        sender.clone(),
+        background_result_sender,
+        cipher(&["old"]),
+    );
+    old_store.set(new_session("1", "alice")).await?;
+
+    let (background_result_sender, _receiver) = tokio::sync::mpsc::channel(8);
+    let new_store =
+        mise::session_store::SessionStore::new(sender, background_result_sender, cipher(&["new"]));
+    new_store.set(new_session("2", "alice")).await?;
+
+    let ids: Vec<String> = new_store
+        .list_for_user("alice".into())
+        .await?
+        .into_iter()
+        .map(|session| session.id)
+        .collect();
+    assert_eq!(vec!["id-2"], ids);
+
+    // the session that can no longer be opened is removed
+    let conn = Connection::open(&pool.path)?;
+    let count: usize = conn.query_row("SELECT count(*) FROM sessions", [], |x| x.get(0))?;
+    assert_eq!(1, count);
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn touching_session_seals_it_with_current_key() -> Result<()> {
+    let (_pool, sender) = new();
+
+    let (background_result_sender, _receiver) = tokio::sync::mpsc::channel(8);
+    let old_store = mise::session_store::SessionStore::new(
+        sender.clone(),
+        background_result_sender,
+        cipher(&["old"]),
+    );
+    old_store.set(new_session("1", "alice")).await?;
+
+    let (background_result_sender, _receiver) = tokio::sync::mpsc::channel(8);
+    let rotated_store = mise::session_store::SessionStore::new(
+        sender.clone(),
+        background_result_sender,
+        cipher(&["new", "old"]),
+    );
+    let session = rotated_store.get(domain::SessionKey("1".into())).await?;
+    rotated_store.touch(&session).await?;
+
+    let (background_result_sender, _receiver) = tokio::sync::mpsc::channel(8);
+    let new_store =
+        mise::session_store::SessionStore::new(sender, background_result_sender, cipher(&["new"]));
+    let session = new_store.get(domain::SessionKey("1".into())).await?;
+    assert_eq!("token", session.refresh_token);
+
+    Ok(())
+}
+
+#[test]
+fn generates_session_encryption_key_once() -> Result<()> {
+    let path = format!("/tmp/{}-mise-test.key", ulid::Ulid::new());
+
+    let key = mise::session_store::generated_key(&path)?;
+    assert_eq!(mise::session_store::GENERATED_KEY_ID, key.id);
+    assert_eq!(32, key.key.len());
+
+    // kept across restarts
+    assert_eq!(key.key, mise::session_store::generated_key(&path)?.key);
+
+    std::fs::write(&path, "too short")?;
+    assert!(mise::session_store::generated_key(&path).is_err());
+
+    std::fs::remove_file(&path)?;
+
+    Ok(())
+}
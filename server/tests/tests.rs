@@ -1,5 +1,5 @@
 mod datastore {
-    mod common;
+    pub mod common;
     pub mod sqlite;
 }
 
@@ -7,12 +7,24 @@ mod sessionstore {
     mod sqlite;
 }
 
+mod imagestore {
+    mod cache;
+    mod common;
+    mod migrate;
+}
+
 mod http {
+    mod audit;
     mod auth;
+    mod household;
     mod image;
+    mod rate_limit;
     mod recipe;
     mod requests;
     mod responses;
     mod setup;
+    mod share;
     mod tag;
+    mod token;
+    mod user;
 }